@@ -1,6 +1,15 @@
-use std::{net::IpAddr, path::PathBuf};
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+};
 
-use crate::{ClientMode, TestType};
+use crate::constants::{
+    DEFAULT_HISTORY_DB, DEFAULT_UDP_MAX_SESSIONS, DEFAULT_UDP_SESSION_TIMEOUT,
+    DEFAULT_UDP_SOURCE_RATE_LIMIT,
+};
+use crate::history::{HistoryFilter, TrendBucket};
+use crate::report::{Bitrate, Latency, Percent};
+use crate::{BitrateMode, ClientMode, CongestionAlgorithm, OutputMode, TestType, UdpMode};
 use clap::Subcommand;
 
 #[derive(Subcommand, Debug)]
@@ -54,7 +63,7 @@ pub enum Commands {
         #[clap(group = "protocol")]
         http3: bool,
 
-        /// Export results to file (JSON, CBOR, or HTML depending on extension)
+        /// Export results to file (JSON, CBOR, HTML, JUnit XML, TAP, CSV or Prometheus depending on extension)
         #[arg(short, long)]
         export: Option<PathBuf>,
 
@@ -62,7 +71,8 @@ pub enum Commands {
         #[arg(short, long, default_value = "1")]
         connections: usize,
 
-        /// Test type (download, upload, bidirectional, simultaneous, latency)
+        /// Test type (download, upload, bidirectional, simultaneous, latency, latency-under-load,
+        /// responsiveness)
         #[arg(long = "type", default_value = "bidirectional")]
         test_type: TestType,
 
@@ -71,9 +81,84 @@ pub enum Commands {
         #[arg(long = "sizes", num_args = 0.., value_delimiter = ',')]
         test_sizes: Vec<usize>,
 
+        /// Limit uploads to this rate instead of sending as fast as possible (e.g., 50M, 1.5G)
+        #[arg(long, value_name = "BITRATE")]
+        bitrate: Option<Bitrate>,
+
+        /// Whether --bitrate applies to all connections together or to each of them
+        #[arg(long, value_enum, default_value_t = BitrateMode::Aggregate, requires = "bitrate")]
+        bitrate_mode: BitrateMode,
+
+        /// How UDP tests send datagrams: stp (congestion controlled) or cbr (constant
+        /// --bitrate, reporting loss and jitter)
+        #[arg(long, value_enum, default_value_t = UdpMode::Stp, requires_if("cbr", "bitrate"))]
+        udp_mode: UdpMode,
+
+        /// Congestion control algorithm of UDP uploads in stp mode (bbr, cubic, newreno, none)
+        #[arg(long = "cc", value_enum, default_value_t = CongestionAlgorithm::Bbr)]
+        congestion_control: CongestionAlgorithm,
+
+        /// Send and receive UDP datagrams one per syscall instead of in batches with
+        /// sendmmsg/recvmmsg and segmentation offload (Linux)
+        #[arg(long)]
+        udp_standard_io: bool,
+
         /// Maximum chunk size. Effective only for HTTP/1.1 tests.
         #[arg(long)]
         chunk_size: Option<usize>,
+
+        /// Interval in seconds between throughput reports (e.g., 0.5). Set to 0 to disable.
+        #[arg(short, long, default_value = "1")]
+        interval: f64,
+
+        /// Output mode while tests are running (progress, intervals, json-stream)
+        #[arg(long, value_enum, default_value_t = OutputMode::Progress)]
+        output: OutputMode,
+
+        /// Write newline-delimited JSON events to stdout instead of human-readable output.
+        /// Shorthand for `--output json-stream`.
+        #[arg(long, conflicts_with = "output")]
+        json_stream: bool,
+
+        /// Fail if the average download throughput of any payload size is below this
+        /// (e.g., 500Mbps, 1.5Gbit/s, 100MB/s)
+        #[arg(long, value_name = "BITRATE")]
+        min_download: Option<Bitrate>,
+
+        /// Fail if the average upload throughput of any payload size is below this
+        #[arg(long, value_name = "BITRATE")]
+        min_upload: Option<Bitrate>,
+
+        /// Fail if the average latency is above this (e.g., 20ms, 0.5s)
+        #[arg(long, value_name = "LATENCY")]
+        max_latency: Option<Latency>,
+
+        /// Fail if the 99th percentile latency is above this (e.g., 20ms)
+        #[arg(long, value_name = "LATENCY")]
+        max_p99_latency: Option<Latency>,
+
+        /// Fail if the share of dropped latency probes is above this (e.g., 0.5%)
+        #[arg(long, value_name = "PERCENT")]
+        max_loss: Option<Percent>,
+
+        /// JSON file with thresholds, using the same names as the options above
+        /// (e.g., {"min_download": "500Mbps"}). Options given on the command line take precedence.
+        #[arg(long)]
+        thresholds: Option<PathBuf>,
+
+        /// Run the test this many times and summarize the results (mean, standard deviation,
+        /// min/max and 95% confidence interval)
+        #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+        repeat: u32,
+
+        /// Pause in seconds between repeated runs (e.g., 0.5)
+        #[arg(long, default_value = "0")]
+        pause: f64,
+
+        /// Also store the report of every test in this SQLite history database
+        /// (defaults to speed-cli.db if given without a path)
+        #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = DEFAULT_HISTORY_DB)]
+        db: Option<PathBuf>,
     },
 
     /// Run as server
@@ -111,6 +196,24 @@ pub enum Commands {
         #[arg(long)]
         udp_port: Option<u16>,
 
+        /// Seconds after which the UDP server evicts a session its client stopped sending on
+        #[arg(long, default_value_t = DEFAULT_UDP_SESSION_TIMEOUT.as_secs(), value_parser = clap::value_parser!(u64).range(1..))]
+        udp_session_timeout: u64,
+
+        /// Most sessions the UDP server keeps at once. Further clients are refused.
+        #[arg(long, default_value_t = DEFAULT_UDP_MAX_SESSIONS)]
+        udp_max_sessions: usize,
+
+        /// Packets per second the UDP server handles from each source IP address that their
+        /// session isn't validated at, such as the INITs opening sessions
+        #[arg(long, default_value_t = DEFAULT_UDP_SOURCE_RATE_LIMIT, value_parser = clap::value_parser!(u32).range(1..))]
+        udp_rate_limit: u32,
+
+        /// Send and receive UDP datagrams one per syscall instead of in batches with
+        /// sendmmsg/recvmmsg and segmentation offload (Linux)
+        #[arg(long)]
+        udp_standard_io: bool,
+
         /// Listen port for HTTP server
         #[arg(long)]
         http_port: Option<u16>,
@@ -128,16 +231,171 @@ pub enum Commands {
         /// If not specified, defaults to `key.pem` in the current directory.
         #[arg(long)]
         key: Option<PathBuf>,
+
+        /// Serve Prometheus metrics of all servers at `/metrics` on this port
+        #[arg(long)]
+        metrics_port: Option<u16>,
+    },
+
+    /// Run a test plan: a sequence of client tests described in a TOML or YAML file
+    Run {
+        /// Path to the test plan (TOML, YAML or JSON)
+        plan: PathBuf,
+
+        /// Export the suite report to file (JSON, CBOR, HTML, JUnit XML, TAP, CSV or Prometheus
+        /// depending on extension)
+        #[arg(short, long)]
+        export: Option<PathBuf>,
+
+        /// Label of the suite report as KEY=VALUE (e.g., site=lab). Can be repeated.
+        /// Overrides labels of the same key in the plan.
+        #[arg(long = "label", value_name = "KEY=VALUE", value_parser = parse_label)]
+        labels: Vec<(String, String)>,
+
+        /// Tag of the suite report (e.g., nightly). Can be repeated.
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// Output mode while tests are running (progress, intervals, json-stream)
+        #[arg(long, value_enum, default_value_t = OutputMode::Progress)]
+        output: OutputMode,
+
+        /// Also store the report of every test in this SQLite history database
+        /// (defaults to speed-cli.db if given without a path)
+        #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = DEFAULT_HISTORY_DB)]
+        db: Option<PathBuf>,
+    },
+
+    /// Run a test plan periodically until interrupted, keeping the report of every test
+    Monitor {
+        /// Path to the test plan (TOML, YAML or JSON)
+        plan: PathBuf,
+
+        /// Minutes between the starts of consecutive runs (e.g., 15, 0.5)
+        #[arg(long, default_value = "15")]
+        every: f64,
+
+        /// Directory to keep the report of every test in, as CBOR files
+        #[arg(long, default_value = "speed-cli-history")]
+        history: PathBuf,
+
+        /// Number of most recent test reports the rolling summary covers
+        #[arg(long, default_value = "10", value_parser = clap::value_parser!(u32).range(1..))]
+        window: u32,
+
+        /// Serve the rolling summary at / and the latest report at /latest on this address
+        /// (e.g., 0.0.0.0:9100)
+        #[arg(long)]
+        listen: Option<SocketAddr>,
+
+        /// Output mode while tests are running (progress, intervals, json-stream)
+        #[arg(long, value_enum, default_value_t = OutputMode::Progress)]
+        output: OutputMode,
+
+        /// Also store the report of every test in this SQLite history database
+        /// (defaults to speed-cli.db if given without a path)
+        #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = DEFAULT_HISTORY_DB)]
+        db: Option<PathBuf>,
+    },
+
+    /// Query the SQLite history database of test reports
+    History {
+        /// Path to the history database
+        #[arg(long, default_value = DEFAULT_HISTORY_DB, global = true)]
+        db: PathBuf,
+
+        #[command(subcommand)]
+        command: HistoryCommands,
     },
 
     /// Print previously saved results
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Report {
-        /// Path to the results file (JSON or CBOR)
-        #[arg(short, long)]
-        file: PathBuf,
+        /// Path to the results file (JSON or CBOR), holding a test or suite report
+        #[arg(short, long, required = true)]
+        file: Option<PathBuf>,
 
         /// Export results to HTML
         #[arg(long)]
         export_html: Option<PathBuf>,
+
+        /// Export results to file (JSON, CBOR, HTML, JUnit XML, TAP, CSV or Prometheus depending on extension)
+        #[arg(short, long)]
+        export: Option<PathBuf>,
+
+        #[command(subcommand)]
+        command: Option<ReportCommands>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ReportCommands {
+    /// Compare two or more saved results against the first one
+    Compare {
+        /// Paths to the results files (JSON or CBOR), either all test reports or all suite
+        /// reports. The first one is the baseline.
+        #[arg(num_args = 2.., required = true)]
+        files: Vec<PathBuf>,
+
+        /// Export the comparison to file (JSON or HTML depending on extension)
+        #[arg(short, long)]
+        export: Option<PathBuf>,
     },
 }
+
+#[derive(Subcommand, Debug)]
+pub enum HistoryCommands {
+    /// List the most recent reports, newest first
+    List {
+        #[command(flatten)]
+        filter: HistoryFilter,
+
+        /// Maximum number of reports to list
+        #[arg(short = 'n', long, default_value = "20")]
+        limit: usize,
+    },
+
+    /// Print a stored report
+    Show {
+        /// ID of the report, as shown by `history list`
+        id: i64,
+
+        /// Export the report to file (JSON, CBOR, HTML, JUnit XML, TAP, CSV or Prometheus depending on extension)
+        #[arg(short, long)]
+        export: Option<PathBuf>,
+    },
+
+    /// Show how the metrics of the matching reports changed over time
+    Trend {
+        #[command(flatten)]
+        filter: HistoryFilter,
+
+        /// Only metrics of this direction (download, upload or latency)
+        #[arg(long)]
+        direction: Option<String>,
+
+        /// Width of the time buckets to average over
+        #[arg(long, value_enum, default_value_t = TrendBucket::Day)]
+        by: TrendBucket,
+    },
+
+    /// Delete the matching reports
+    Prune {
+        #[command(flatten)]
+        filter: HistoryFilter,
+
+        /// Only reports of tests that started more than this many days ago
+        #[arg(long, value_name = "DAYS")]
+        older_than: Option<u32>,
+    },
+}
+
+/// Parses a `KEY=VALUE` label
+fn parse_label(label: &str) -> Result<(String, String), String> {
+    match label.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("expected KEY=VALUE, got `{label}`")),
+    }
+}
@@ -1,13 +1,46 @@
+use std::time::Duration;
+
 pub const DEFAULT_TCP_PORT: u16 = 5201;
 pub const DEFAULT_UDP_PORT: u16 = 5201;
 pub const DEFAULT_HTTP_PORT: u16 = 8080;
 pub const DEFAULT_HTTPS_PORT: u16 = 8443;
 
+/// Time after which the UDP server evicts a session its client stopped sending on.
+pub const DEFAULT_UDP_SESSION_TIMEOUT: Duration = Duration::from_secs(30);
+/// Most sessions the UDP server keeps at once.
+pub const DEFAULT_UDP_MAX_SESSIONS: usize = 1024;
+/// Packets per second the UDP server handles from each source address that their session
+/// isn't validated at, e.g. the INITs opening sessions.
+pub const DEFAULT_UDP_SOURCE_RATE_LIMIT: u32 = 100;
+/// Highest bitrate in bits per second the UDP server streams a constant-bitrate download
+/// at. Faster requests are clamped to it.
+pub const DEFAULT_UDP_MAX_BITRATE: f64 = 10e9; // 10Gbit/s
+/// Longest test the UDP server runs a session for. Longer requests are clamped to it.
+pub const DEFAULT_UDP_MAX_TEST_DURATION: Duration = Duration::from_secs(3600);
+
 pub const DEFAULT_TCP_PAYLOAD_SIZES: &[usize] = &[1024, 8192, 65536]; // 1KB, 8KB, 64KB
-pub const DEFAULT_UDP_PAYLOAD_SIZES: &[usize] = &[1024, 8192, 65536]; // 1KB, 8KB, 64KB
+pub const DEFAULT_UDP_PAYLOAD_SIZES: &[usize] = &[1024, 8192, 32768]; // 1KB, 8KB, 32KB, each fitting in a datagram
 pub const DEFAULT_HTTP_PAYLOAD_SIZES: &[usize] =
     &[1024 * 1024, 10 * 1024 * 1024, 100 * 1024 * 1024]; // 1MB, 10MB, 100MB
 /// Maximum allowed upload size for HTTP requests.
 pub const MAX_HTTP_UPLOAD_SIZE: usize = 100 * 1024 * 1024 * 1024; // 100GiB
 
+/// Default width of the per-interval buckets in throughput results.
+pub const DEFAULT_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest time idle latency is measured for before loading the link in
+/// latency-under-load tests.
+pub const MAX_IDLE_LATENCY_DURATION: Duration = Duration::from_secs(5);
+
+/// Size of the large download of responsiveness tests, enough to keep a connection busy
+/// for the whole test.
+pub const RPM_LARGE_DOWNLOAD_SIZE: usize = 8 * 1024 * 1024 * 1024; // 8GiB
+
 pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024; // 1MB
+
+/// Exit code of the client when the result violates one of the given thresholds.
+/// Distinct from the exit code of other errors so CI jobs can tell them apart.
+pub const THRESHOLD_VIOLATION_EXIT_CODE: i32 = 3;
+
+/// Path of the SQLite history database when none is given.
+pub const DEFAULT_HISTORY_DB: &str = "speed-cli.db";
@@ -0,0 +1,558 @@
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use colored::*;
+use rusqlite::types::Value;
+use rusqlite::{Connection, OptionalExtension, params, params_from_iter};
+use thiserror::Error;
+
+use crate::report::{MetricUnit, Sample, SuiteReport, TestReport};
+use crate::{ClientMode, TestType};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS reports (
+    id INTEGER PRIMARY KEY,
+    run_id TEXT,
+    start_time TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    server TEXT NOT NULL,
+    protocol TEXT NOT NULL,
+    test_type TEXT NOT NULL,
+    version TEXT NOT NULL,
+    thresholds_passed INTEGER,
+    config TEXT NOT NULL,
+    report BLOB NOT NULL
+);
+CREATE INDEX IF NOT EXISTS reports_start_time ON reports (start_time);
+CREATE TABLE IF NOT EXISTS metrics (
+    report_id INTEGER NOT NULL REFERENCES reports (id) ON DELETE CASCADE,
+    direction TEXT NOT NULL,
+    payload_size INTEGER,
+    metric TEXT NOT NULL,
+    unit TEXT NOT NULL,
+    value REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS metrics_report_id ON metrics (report_id);
+";
+
+#[derive(Debug, Error)]
+pub enum HistoryError {
+    #[error("Database error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("JSON serialization error: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("CBOR serialization error: {0}")]
+    CborSer(#[from] ciborium::ser::Error<std::io::Error>),
+    #[error("CBOR parsing error: {0}")]
+    CborDe(#[from] ciborium::de::Error<std::io::Error>),
+    #[error("No report with ID {0}")]
+    NotFound(i64),
+}
+
+/// Criteria that select reports of the history database. Unset criteria match every report.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct HistoryFilter {
+    /// Only reports of tests against this server (hostname or IP address)
+    #[arg(long)]
+    pub server: Option<String>,
+
+    /// Only reports of tests with this protocol
+    #[arg(long, value_enum)]
+    pub protocol: Option<ClientMode>,
+
+    /// Only reports of tests of this type
+    #[arg(long = "type")]
+    pub test_type: Option<TestType>,
+
+    /// Only reports of tests that started at or after this date (e.g., 2025-06-01 or
+    /// 2025-06-01T12:00:00Z)
+    #[arg(long, value_parser = parse_date)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only reports of tests that started before this date
+    #[arg(long, value_parser = parse_date)]
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl HistoryFilter {
+    pub fn is_empty(&self) -> bool {
+        self.server.is_none()
+            && self.protocol.is_none()
+            && self.test_type.is_none()
+            && self.since.is_none()
+            && self.until.is_none()
+    }
+
+    /// Returns the SQL condition on the `reports` table and its parameters
+    fn condition(&self) -> (String, Vec<Value>) {
+        let mut conditions = vec!["1".to_string()];
+        let mut values = Vec::new();
+        let mut add = |condition: &str, value: String| {
+            conditions.push(condition.to_string());
+            values.push(Value::Text(value));
+        };
+
+        if let Some(server) = &self.server {
+            add("reports.server = ?", server.clone());
+        }
+        if let Some(protocol) = &self.protocol {
+            add("reports.protocol = ?", protocol.to_string());
+        }
+        if let Some(test_type) = &self.test_type {
+            add("reports.test_type = ?", test_type.to_string());
+        }
+        if let Some(since) = &self.since {
+            add("reports.start_time >= ?", format_time(since));
+        }
+        if let Some(until) = &self.until {
+            add("reports.start_time < ?", format_time(until));
+        }
+
+        (conditions.join(" AND "), values)
+    }
+}
+
+/// Parses a date (midnight UTC) or an RFC 3339 date and time
+fn parse_date(date: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Ok(date.to_utc());
+    }
+    chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|date| date.and_time(chrono::NaiveTime::MIN).and_utc())
+        .map_err(|_| format!("expected YYYY-MM-DD or an RFC 3339 date and time, got `{date}`"))
+}
+
+/// Formats times so that they sort chronologically as text
+fn format_time(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn parse_time(time: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| time.to_utc())
+        .unwrap_or_default()
+}
+
+/// Summary of a report in the history database
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub id: i64,
+    /// ID of the suite run the report belongs to, if any
+    pub run_id: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub server: String,
+    pub protocol: String,
+    pub test_type: String,
+    /// Whether the report passed its thresholds, if it had any
+    pub thresholds_passed: Option<bool>,
+    /// Throughput and latency values of the report
+    pub samples: Vec<(String, MetricUnit, f64)>,
+}
+
+impl Display for HistoryEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>6}  {}  {:<7} {:<14} {}",
+            self.id.to_string().bright_white().bold(),
+            self.start_time
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+                .yellow(),
+            self.protocol,
+            self.test_type,
+            self.server.green()
+        )?;
+        match self.thresholds_passed {
+            Some(true) => write!(f, "  {}", "PASSED".green().bold())?,
+            Some(false) => write!(f, "  {}", "VIOLATED".yellow().bold())?,
+            None => {}
+        }
+        if let Some(run_id) = &self.run_id {
+            write!(f, "  {}", format!("run {run_id}").dimmed())?;
+        }
+        for (name, unit, value) in &self.samples {
+            write!(f, "\n        {name}: {}", unit.format(*value))?;
+        }
+        Ok(())
+    }
+}
+
+/// Width of the buckets of a trend
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum TrendBucket {
+    Hour,
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl TrendBucket {
+    /// SQLite `strftime` format that maps a time to its bucket
+    fn format(&self) -> &'static str {
+        match self {
+            TrendBucket::Hour => "%Y-%m-%d %H:00",
+            TrendBucket::Day => "%Y-%m-%d",
+            TrendBucket::Week => "%Y-W%W",
+            TrendBucket::Month => "%Y-%m",
+        }
+    }
+}
+
+/// Aggregate of a metric over the reports of one bucket of a trend
+#[derive(Debug, Clone)]
+pub struct TrendPoint {
+    pub bucket: String,
+    pub protocol: String,
+    pub direction: String,
+    pub payload_size: Option<usize>,
+    pub metric: String,
+    pub unit: MetricUnit,
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    pub count: usize,
+}
+
+impl TrendPoint {
+    /// Describes the metric, e.g. "tcp upload (1 KiB) Throughput"
+    pub fn series(&self) -> String {
+        match self.payload_size {
+            Some(size) => format!(
+                "{} {} ({}) {}",
+                self.protocol,
+                self.direction,
+                crate::utils::format::format_bytes(size),
+                self.metric
+            ),
+            None => format!("{} {} {}", self.protocol, self.direction, self.metric),
+        }
+    }
+}
+
+/// SQLite database that keeps every test report: its configuration, the values of its
+/// metrics for querying, and the complete report including raw measurements as CBOR
+pub struct HistoryDb {
+    conn: Connection,
+}
+
+impl HistoryDb {
+    /// Opens the database at `path`, creating it if needed
+    pub fn open(path: &Path) -> Result<Self, HistoryError> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Stores `report` and returns its ID. `run_id` links reports of the same suite run.
+    pub fn insert(
+        &mut self,
+        report: &TestReport,
+        run_id: Option<&str>,
+    ) -> Result<i64, HistoryError> {
+        let mut blob = Vec::new();
+        ciborium::into_writer(report, &mut blob)?;
+        let config = serde_json::to_string(&report.config)?;
+
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO reports (run_id, start_time, timestamp, server, protocol, test_type,
+                version, thresholds_passed, config, report)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                run_id,
+                format_time(&report.start_time),
+                format_time(&report.timestamp),
+                report.config.server(),
+                report.config.protocol(),
+                report.config.test_type().to_string(),
+                report.version,
+                report
+                    .thresholds
+                    .as_ref()
+                    .map(|thresholds| thresholds.passed()),
+                config,
+                blob,
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+
+        for sample in Sample::from_report(report) {
+            tx.execute(
+                "INSERT INTO metrics (report_id, direction, payload_size, metric, unit, value)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    id,
+                    sample.direction,
+                    sample.payload_size.map(|size| size as i64),
+                    sample.metric,
+                    unit_name(sample.unit)?,
+                    sample.value,
+                ],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(id)
+    }
+
+    /// Stores the report of every step of `suite` that ran, and returns how many were stored
+    pub fn insert_suite(&mut self, suite: &SuiteReport) -> Result<usize, HistoryError> {
+        let mut count = 0;
+        for report in suite.steps.iter().filter_map(|step| step.report.as_ref()) {
+            self.insert(report, Some(&suite.run_id))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Lists the most recent reports matching `filter`, newest first
+    pub fn list(
+        &self,
+        filter: &HistoryFilter,
+        limit: usize,
+    ) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let (condition, mut values) = filter.condition();
+        values.push(Value::Integer(limit.try_into().unwrap_or(i64::MAX)));
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT id, run_id, start_time, server, protocol, test_type, thresholds_passed
+             FROM reports WHERE {condition} ORDER BY start_time DESC, id DESC LIMIT ?"
+        ))?;
+        let mut entries = statement
+            .query_map(params_from_iter(values), |row| {
+                Ok(HistoryEntry {
+                    id: row.get(0)?,
+                    run_id: row.get(1)?,
+                    start_time: parse_time(&row.get::<_, String>(2)?),
+                    server: row.get(3)?,
+                    protocol: row.get(4)?,
+                    test_type: row.get(5)?,
+                    thresholds_passed: row.get(6)?,
+                    samples: Vec::new(),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut statement = self.conn.prepare(
+            "SELECT direction, payload_size, metric, unit, value FROM metrics
+             WHERE report_id = ? ORDER BY rowid",
+        )?;
+        for entry in &mut entries {
+            let rows = statement
+                .query_map([entry.id], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<i64>>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, f64>(4)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            for (direction, payload_size, metric, unit, value) in rows {
+                let name = match payload_size {
+                    Some(size) => format!(
+                        "{direction} ({}) {metric}",
+                        crate::utils::format::format_bytes(size as u64)
+                    ),
+                    None => format!("{direction} {metric}"),
+                };
+                entry.samples.push((name, parse_unit(unit)?, value));
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Loads the complete report with the given ID
+    pub fn get(&self, id: i64) -> Result<TestReport, HistoryError> {
+        let blob: Vec<u8> = self
+            .conn
+            .query_row("SELECT report FROM reports WHERE id = ?", [id], |row| {
+                row.get(0)
+            })
+            .optional()?
+            .ok_or(HistoryError::NotFound(id))?;
+        Ok(ciborium::from_reader(&blob[..])?)
+    }
+
+    /// Aggregates the metrics of the reports matching `filter` per bucket of time, oldest
+    /// bucket first. `direction` (download, upload or latency) limits the metrics.
+    pub fn trend(
+        &self,
+        filter: &HistoryFilter,
+        bucket: TrendBucket,
+        direction: Option<&str>,
+    ) -> Result<Vec<TrendPoint>, HistoryError> {
+        let (condition, mut values) = filter.condition();
+        values.insert(0, Value::Text(bucket.format().to_string()));
+        let direction_condition = match direction {
+            Some(direction) => {
+                values.push(Value::Text(direction.to_string()));
+                "AND metrics.direction = ?"
+            }
+            None => "",
+        };
+
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT strftime(?, reports.start_time) AS bucket, reports.protocol,
+                metrics.direction, metrics.payload_size, metrics.metric, metrics.unit,
+                AVG(metrics.value), MIN(metrics.value), MAX(metrics.value), COUNT(*)
+             FROM metrics JOIN reports ON reports.id = metrics.report_id
+             WHERE {condition} {direction_condition}
+             GROUP BY reports.protocol, metrics.direction, metrics.payload_size,
+                metrics.metric, metrics.unit, bucket
+             ORDER BY reports.protocol, metrics.direction, metrics.payload_size,
+                metrics.metric, bucket"
+        ))?;
+        let rows = statement
+            .query_map(params_from_iter(values), |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, f64>(6)?,
+                    row.get::<_, f64>(7)?,
+                    row.get::<_, f64>(8)?,
+                    row.get::<_, i64>(9)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(
+                |(
+                    bucket,
+                    protocol,
+                    direction,
+                    payload_size,
+                    metric,
+                    unit,
+                    mean,
+                    min,
+                    max,
+                    count,
+                )| {
+                    Ok(TrendPoint {
+                        bucket,
+                        protocol,
+                        direction,
+                        payload_size: payload_size.map(|size| size as usize),
+                        metric,
+                        unit: parse_unit(unit)?,
+                        mean,
+                        min,
+                        max,
+                        count: count as usize,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// Deletes the reports matching `filter` and returns how many were deleted
+    pub fn prune(&mut self, filter: &HistoryFilter) -> Result<usize, HistoryError> {
+        let (condition, values) = filter.condition();
+        let deleted = self.conn.execute(
+            &format!("DELETE FROM reports WHERE {condition}"),
+            params_from_iter(values),
+        )?;
+        if deleted > 0 {
+            // Give the space of the raw measurements back to the file system
+            self.conn.execute_batch("VACUUM;")?;
+        }
+        Ok(deleted)
+    }
+}
+
+fn unit_name(unit: MetricUnit) -> Result<String, HistoryError> {
+    match serde_json::to_value(unit)? {
+        serde_json::Value::String(name) => Ok(name),
+        value => Ok(value.to_string()),
+    }
+}
+
+fn parse_unit(name: String) -> Result<MetricUnit, HistoryError> {
+    Ok(serde_json::from_value(serde_json::Value::String(name))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{
+        NetworkTestResult, TcpTestConfig, ThroughputMeasurement, ThroughputResult,
+    };
+    use std::time::Duration;
+
+    fn report(start_time: &str, bytes: u64) -> TestReport {
+        let mut result = NetworkTestResult::new_tcp();
+        result.add_upload(
+            1024,
+            ThroughputResult {
+                totals: [ThroughputMeasurement::new(bytes, Duration::from_secs(1))]
+                    .iter()
+                    .collect(),
+                total_duration: Duration::from_secs(1),
+                intervals: Vec::new(),
+                connection_intervals: Default::default(),
+                timestamp: Utc::now(),
+            },
+            None,
+        );
+        let config = TcpTestConfig::new(
+            "10.0.0.2".to_string(),
+            None,
+            1,
+            1,
+            TestType::Upload,
+            [1024],
+            Duration::from_secs(1),
+        );
+        TestReport::from((parse_date(start_time).unwrap(), config, result))
+    }
+
+    #[test]
+    fn test_query_history() {
+        let mut db = HistoryDb::open(Path::new(":memory:")).unwrap();
+        db.insert(&report("2025-06-01T10:00:00Z", 1000), None)
+            .unwrap();
+        db.insert(&report("2025-06-01T14:00:00Z", 3000), None)
+            .unwrap();
+        let id = db
+            .insert(&report("2025-06-02T10:00:00Z", 5000), None)
+            .unwrap();
+
+        let filter = HistoryFilter {
+            server: Some("10.0.0.2".to_string()),
+            since: Some(parse_date("2025-06-01T12:00:00Z").unwrap()),
+            ..Default::default()
+        };
+        let entries = db.list(&filter, 10).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].id, id);
+        assert_eq!(entries[0].samples.len(), 1);
+
+        let trend = db
+            .trend(&HistoryFilter::default(), TrendBucket::Day, Some("upload"))
+            .unwrap();
+        assert_eq!(trend.len(), 2);
+        assert_eq!(trend[0].bucket, "2025-06-01");
+        assert_eq!((trend[0].mean, trend[0].count), (16000.0, 2));
+
+        assert_eq!(db.get(id).unwrap().config.server(), "10.0.0.2");
+
+        let filter = HistoryFilter {
+            until: Some(parse_date("2025-06-02").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(db.prune(&filter).unwrap(), 2);
+        assert_eq!(db.list(&HistoryFilter::default(), 10).unwrap().len(), 1);
+        assert!(matches!(db.get(1), Err(HistoryError::NotFound(1))));
+    }
+}
@@ -2,51 +2,82 @@ use colored::*;
 use eyre::Result;
 use std::fs;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
 use tracing::trace;
-use tracing_subscriber::{EnvFilter, fmt, prelude::*};
+use tracing_subscriber::{EnvFilter, fmt, fmt::writer::BoxMakeWriter, prelude::*};
 
 use clap::Parser;
-use cli::{Cli, Commands};
+use cli::{Cli, Commands, HistoryCommands, ReportCommands};
 use performance::http::server::{HttpServerConfig, run_http_server};
-use performance::tcp::client::run_tcp_client;
-use performance::udp::client::run_udp_client;
+use performance::run_client_test;
 
 pub use utils::types::*;
 
 use crate::constants::{
-    DEFAULT_HTTP_PORT, DEFAULT_HTTPS_PORT, DEFAULT_TCP_PORT, DEFAULT_UDP_PORT, MAX_HTTP_UPLOAD_SIZE,
+    DEFAULT_HTTP_PORT, DEFAULT_HTTPS_PORT, DEFAULT_TCP_PORT, DEFAULT_UDP_MAX_BITRATE,
+    DEFAULT_UDP_MAX_TEST_DURATION, DEFAULT_UDP_PORT, MAX_HTTP_UPLOAD_SIZE,
+    THRESHOLD_VIOLATION_EXIT_CODE,
 };
+use crate::history::{HistoryDb, HistoryError};
+use crate::monitor::{MonitorOptions, run_monitor};
+use crate::performance::http::server::HttpServerMetrics;
 use crate::performance::http::server::{HttpsServerConfig, TlsConfig, run_https_server};
-use crate::performance::http::{HttpVersion, client::run_http_test};
-use crate::performance::tcp::server::run_tcp_server;
-use crate::performance::udp::server::run_udp_server;
-use crate::report::{HttpTestConfig, TcpTestConfig, TestReport, UdpTestConfig};
-use crate::utils::export::{export_report, export_report_html};
+use crate::performance::metrics::{MetricsRegistry, run_metrics_server};
+use crate::performance::tcp::server::build_tcp_server;
+use crate::performance::udp::server::{UdpServerConfig, UdpServerMetrics, run_udp_server};
+use crate::plan::{ResolvedStep, run_plan, run_steps};
+use crate::report::{
+    RepeatStatistic, ReportComparison, ReportFile, SuiteReport, TestConfig, Thresholds,
+};
+use crate::utils::export::{
+    SUITE_EXTENSIONS, check_extension, export_comparison, export_report, export_report_html,
+    export_suite_report, export_suite_report_html,
+};
 use crate::utils::file::can_write;
-use crate::utils::import::{import_report_cbor, import_report_json};
+use crate::utils::import::{
+    import_plan, import_report, import_report_cbor, import_report_json, import_thresholds,
+};
+use crate::utils::output::{StreamEvent, emit, is_json_stream, set_output_mode, status};
 use crate::utils::progress::with_progress_counter;
 
 mod cli;
 mod constants;
+mod history;
+mod monitor;
 mod performance;
+mod plan;
 mod renderer;
 mod report;
 mod utils;
 
-/// Creates an optimized Tokio runtime for network performance testing
-fn create_optimized_runtime() -> tokio::runtime::Runtime {
-    tokio::runtime::Builder::new_multi_thread()
-        .worker_threads(num_cpus::get())
-        .thread_name("speed-cli-worker")
-        .enable_all()
-        .thread_stack_size(2 * 1024 * 1024) // 2MB stack size
-        .build()
-        .expect("Failed to create optimized Tokio runtime")
-}
-
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<()> {
-    // Initialize tracing subscriber for logging
+    // Start parsing
+    let cli = Cli::parse();
+
+    // The output mode decides where logs can go, so it is set before logging is initialized
+    match &cli.command {
+        Commands::Client {
+            output,
+            json_stream,
+            ..
+        } => set_output_mode(if *json_stream {
+            OutputMode::JsonStream
+        } else {
+            *output
+        }),
+        Commands::Run { output, .. } | Commands::Monitor { output, .. } => set_output_mode(*output),
+        _ => {}
+    }
+
+    // Initialize tracing subscriber for logging.
+    // Logs go to stderr while stdout carries the JSON event stream.
+    let writer = if is_json_stream() {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
     let filter_layer = EnvFilter::try_from_default_env()
         .or_else(|_| EnvFilter::try_new("info"))
         .unwrap();
@@ -55,15 +86,13 @@ async fn main() -> Result<()> {
         .with_thread_ids(true)
         .with_thread_names(true)
         .with_file(true)
-        .with_line_number(true);
+        .with_line_number(true)
+        .with_writer(writer);
     tracing_subscriber::registry()
         .with(filter_layer)
         .with(fmt_layer)
         .init();
 
-    // Start parsing
-    let cli = Cli::parse();
-
     trace!("Parsed CLI arguments: {:#?}", cli);
 
     match cli.command {
@@ -82,7 +111,24 @@ async fn main() -> Result<()> {
             connections,
             test_type,
             test_sizes,
+            bitrate,
+            bitrate_mode,
+            udp_mode,
+            congestion_control,
+            udp_standard_io,
             chunk_size,
+            interval,
+            output: _,
+            json_stream: _,
+            min_download,
+            min_upload,
+            max_latency,
+            max_p99_latency,
+            max_loss,
+            thresholds,
+            repeat,
+            pause,
+            db,
         } => {
             // Assert that exactly one specific protocol is enabled (no more, no less)
             // Count enabled protocols
@@ -112,6 +158,26 @@ async fn main() -> Result<()> {
                 }
             });
 
+            let interval = Duration::try_from_secs_f64(interval)
+                .map_err(|e| eyre::eyre!("Invalid report interval {interval}: {e}"))?;
+            let pause = Duration::try_from_secs_f64(pause)
+                .map_err(|e| eyre::eyre!("Invalid pause {pause}: {e}"))?;
+
+            let mut thresholds_file = Thresholds::default();
+            if let Some(thresholds) = &thresholds {
+                thresholds_file = import_thresholds(thresholds).await.map_err(|e| {
+                    eyre::eyre!("Invalid thresholds file {}: {e}", thresholds.display())
+                })?;
+            }
+            let thresholds = Thresholds {
+                min_download,
+                min_upload,
+                max_latency,
+                max_p99_latency,
+                max_loss,
+            }
+            .or(thresholds_file);
+
             // Verify export file path is writable
             if let Some(export) = &export {
                 if let Some(parent) = export.parent() {
@@ -123,62 +189,62 @@ async fn main() -> Result<()> {
                         export.display()
                     ));
                 }
+                if repeat > 1 {
+                    check_extension(export, SUITE_EXTENSIONS)?;
+                }
             }
 
-            let report: TestReport = match mode {
-                ClientMode::TCP => {
-                    let config = TcpTestConfig::new(
-                        server,
-                        port,
-                        duration,
-                        connections,
-                        test_type,
-                        test_sizes,
-                    );
-
-                    run_tcp_client(config).await?
-                }
-                ClientMode::UDP => {
-                    let config = UdpTestConfig::new(
-                        server,
-                        port,
-                        duration,
-                        connections,
-                        test_type,
-                        test_sizes,
-                    );
-
-                    run_udp_client(config).await?
-                }
-                ClientMode::HTTP1 | ClientMode::HTTP2 | ClientMode::H2C | ClientMode::HTTP3 => {
-                    // For HTTP modes, we need to determine the HTTP version
-                    let http_version = match mode {
-                        ClientMode::HTTP1 => HttpVersion::HTTP1,
-                        ClientMode::HTTP2 => HttpVersion::HTTP2,
-                        ClientMode::H2C => HttpVersion::H2C,
-                        ClientMode::HTTP3 => HttpVersion::HTTP3,
-                        _ => unreachable!(),
-                    };
-
-                    let config = HttpTestConfig::new(
-                        server,
-                        port,
-                        duration,
-                        connections,
-                        test_type,
-                        test_sizes,
-                        chunk_size,
-                        http_version,
-                    );
-
-                    run_http_test(config).await?
-                }
-            };
+            let config = TestConfig::new(
+                mode,
+                server,
+                port,
+                duration,
+                connections,
+                test_type,
+                test_sizes,
+                chunk_size,
+                interval,
+            )
+            .with_bitrate(bitrate.map(|bitrate| TargetBitrate {
+                bitrate,
+                mode: bitrate_mode,
+            }))
+            .with_udp_mode(udp_mode)
+            .with_congestion_control(congestion_control)
+            .with_udp_standard_io(udp_standard_io);
+
+            if repeat > 1 {
+                let steps = (1..=repeat)
+                    .map(|run| ResolvedStep {
+                        name: format!("Run {run}"),
+                        config: config.clone(),
+                        thresholds: thresholds.clone(),
+                    })
+                    .collect();
+                let mut report =
+                    run_steps(None, Default::default(), Vec::new(), steps, pause).await;
+                report.statistics = RepeatStatistic::from_reports(
+                    report.steps.iter().filter_map(|step| step.report.as_ref()),
+                );
+
+                status!("{}", "Client test completed.".green().bold());
+                return finish_suite(&report, export.as_deref(), db.as_deref()).await;
+            }
+
+            let mut report = run_client_test(config).await?;
 
-            println!("{}", "Client test completed.".green().bold());
+            status!("{}", "Client test completed.".green().bold());
+
+            if !thresholds.is_empty() {
+                report.thresholds = Some(thresholds.evaluate(&report.result));
+            }
 
             // Print test report
-            println!("{report:#}");
+            if is_json_stream() {
+                emit(&StreamEvent::Report { report: &report });
+            } else {
+                println!("{report:#}");
+            }
 
             // If export file is specified, write results
             if let Some(export) = &export {
@@ -188,13 +254,194 @@ async fn main() -> Result<()> {
                 )
                 .await
                 {
-                    Ok(_) => println!(
+                    Ok(_) => status!(
                         "{}",
                         format!("Results exported to {}", export.to_string_lossy()).cyan()
                     ),
                     Err(e) => eprintln!("Error exporting results: {e}"),
                 }
             }
+
+            if let Some(db) = &db {
+                record_history(db, |history| history.insert(&report, None).map(|_| ()));
+            }
+
+            if let Some(evaluation) = report.thresholds.as_ref().filter(|e| !e.passed()) {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Threshold violations ({} of {}):",
+                        evaluation.violations().count(),
+                        evaluation.checks.len()
+                    )
+                    .red()
+                    .bold()
+                );
+                for check in evaluation.violations() {
+                    eprintln!("  {check}");
+                }
+                std::process::exit(THRESHOLD_VIOLATION_EXIT_CODE);
+            }
+        }
+
+        Commands::Run {
+            plan,
+            export,
+            labels,
+            tags,
+            output: _,
+            db,
+        } => {
+            let mut test_plan = import_plan(&plan)
+                .await
+                .map_err(|e| eyre::eyre!("Invalid test plan {}: {e}", plan.display()))?;
+            test_plan.labels.extend(labels);
+            for tag in tags {
+                if !test_plan.tags.contains(&tag) {
+                    test_plan.tags.push(tag);
+                }
+            }
+
+            // Verify export file path is writable
+            if let Some(export) = &export {
+                if let Some(parent) = export.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                if !can_write(export)? {
+                    return Err(eyre::eyre!(
+                        "Export file is not writable: {}",
+                        export.display()
+                    ));
+                }
+                check_extension(export, SUITE_EXTENSIONS)?;
+            }
+
+            let report = run_plan(&test_plan)
+                .await
+                .map_err(|e| eyre::eyre!("Invalid test plan {}: {e}", plan.display()))?;
+
+            status!("{}", "Test plan completed.".green().bold());
+
+            finish_suite(&report, export.as_deref(), db.as_deref()).await?;
+        }
+
+        Commands::Monitor {
+            plan,
+            every,
+            history,
+            window,
+            listen,
+            output: _,
+            db,
+        } => {
+            let test_plan = import_plan(&plan)
+                .await
+                .map_err(|e| eyre::eyre!("Invalid test plan {}: {e}", plan.display()))?;
+            let interval = Duration::try_from_secs_f64(every * 60.0)
+                .ok()
+                .filter(|interval| !interval.is_zero())
+                .ok_or_else(|| eyre::eyre!("Invalid monitoring interval {every}"))?;
+
+            status!(
+                "{}",
+                format!("Monitoring every {every} minutes. Press Ctrl+C to stop.")
+                    .blue()
+                    .bold()
+            );
+            run_monitor(
+                &test_plan,
+                MonitorOptions {
+                    every: interval,
+                    history,
+                    window: window as usize,
+                    listen,
+                    db,
+                },
+            )
+            .await?;
+        }
+
+        Commands::History { db, command } => {
+            let mut history = HistoryDb::open(&db)
+                .map_err(|e| eyre::eyre!("Cannot open history database {}: {e}", db.display()))?;
+
+            match command {
+                HistoryCommands::List { filter, limit } => {
+                    let entries = history.list(&filter, limit)?;
+                    if entries.is_empty() {
+                        println!("No reports found.");
+                    }
+                    for entry in entries {
+                        println!("{entry}");
+                    }
+                }
+
+                HistoryCommands::Show { id, export } => {
+                    let report = history.get(id)?;
+                    println!("{report:#}");
+
+                    if let Some(export) = &export {
+                        match with_progress_counter(
+                            "Exporting test results",
+                            export_report(&report, export),
+                        )
+                        .await
+                        {
+                            Ok(_) => status!(
+                                "{}",
+                                format!("Results exported to {}", export.to_string_lossy()).cyan()
+                            ),
+                            Err(e) => eprintln!("Error exporting results: {e}"),
+                        }
+                    }
+                }
+
+                HistoryCommands::Trend {
+                    filter,
+                    direction,
+                    by,
+                } => {
+                    let points = history.trend(&filter, by, direction.as_deref())?;
+                    if points.is_empty() {
+                        println!("No reports found.");
+                    }
+
+                    let mut series = None;
+                    for point in points {
+                        let name = point.series();
+                        if series.as_ref() != Some(&name) {
+                            println!("{}", name.bright_white().bold());
+                            series = Some(name);
+                        }
+                        println!(
+                            "  {:<16} mean {}, min {}, max {}, n={}",
+                            point.bucket.yellow(),
+                            point.unit.format(point.mean).green(),
+                            point.unit.format(point.min),
+                            point.unit.format(point.max),
+                            point.count
+                        );
+                    }
+                }
+
+                HistoryCommands::Prune {
+                    mut filter,
+                    older_than,
+                } => {
+                    if let Some(days) = older_than {
+                        let cutoff = chrono::Utc::now() - chrono::Duration::days(days.into());
+                        filter.until = Some(filter.until.map_or(cutoff, |until| until.min(cutoff)));
+                    }
+                    if filter.is_empty() {
+                        return Err(eyre::eyre!(
+                            "Refusing to delete every report. Select reports to prune, e.g. with --older-than or --until."
+                        ));
+                    }
+
+                    let deleted = history.prune(&filter)?;
+                    println!("Deleted {deleted} reports from {}", db.display());
+                }
+            }
         }
 
         Commands::Server {
@@ -206,10 +453,15 @@ async fn main() -> Result<()> {
             bind,
             tcp_port,
             udp_port,
+            udp_session_timeout,
+            udp_max_sessions,
+            udp_rate_limit,
+            udp_standard_io,
             http_port,
             https_port,
             cert,
             key,
+            metrics_port,
         } => {
             let enable_tcp = tcp || all;
             let enable_udp = udp || all;
@@ -226,22 +478,44 @@ async fn main() -> Result<()> {
             println!("{}", "Starting server mode...".blue().bold());
 
             let mut handles: Vec<(&str, tokio::task::JoinHandle<_>)> = vec![];
+            let mut registry = MetricsRegistry::default();
 
             // Setup TCP
             if enable_tcp {
                 let tcp_addr = SocketAddr::new(bind, tcp_port.unwrap_or(DEFAULT_TCP_PORT));
-                handles.push(("TCP", tokio::spawn(run_tcp_server(tcp_addr))));
+                let server = build_tcp_server();
+                registry.register("tcp", server.get_metrics());
+                handles.push((
+                    "TCP",
+                    tokio::spawn(async move { server.run(tcp_addr).await }),
+                ));
             }
 
             // Setup UDP
             if enable_udp {
                 let udp_addr = SocketAddr::new(bind, udp_port.unwrap_or(DEFAULT_UDP_PORT));
-                handles.push(("UDP", tokio::spawn(run_udp_server(udp_addr))));
+                let metrics = UdpServerMetrics::new();
+                registry.register("udp", metrics.clone());
+                handles.push((
+                    "UDP",
+                    tokio::spawn(run_udp_server(UdpServerConfig {
+                        bind_addr: udp_addr,
+                        session_timeout: Duration::from_secs(udp_session_timeout),
+                        max_sessions: udp_max_sessions,
+                        source_rate_limit: udp_rate_limit,
+                        max_bitrate: DEFAULT_UDP_MAX_BITRATE,
+                        max_test_duration: DEFAULT_UDP_MAX_TEST_DURATION,
+                        standard_io: udp_standard_io,
+                        metrics,
+                    })),
+                ));
             }
 
             // Setup HTTP server modes (i.e. HTTP/1.1 without TLS, h2c)
             if enable_http {
                 let http_addr = SocketAddr::new(bind, http_port.unwrap_or(DEFAULT_HTTP_PORT));
+                let metrics = HttpServerMetrics::new();
+                registry.register("http", metrics.clone());
 
                 handles.push((
                     "HTTP",
@@ -249,6 +523,7 @@ async fn main() -> Result<()> {
                         bind_addr: http_addr,
                         enable_cors: true,
                         max_upload_size: MAX_HTTP_UPLOAD_SIZE,
+                        metrics,
                     })),
                 ));
             }
@@ -281,17 +556,30 @@ async fn main() -> Result<()> {
                     }
                 };
 
+                let metrics = HttpServerMetrics::new();
+                registry.register("https", metrics.clone());
+
                 handles.push((
                     "HTTPS",
                     tokio::spawn(run_https_server(HttpsServerConfig {
                         bind_addr: https_addr,
                         enable_cors: true,
                         max_upload_size: MAX_HTTP_UPLOAD_SIZE,
+                        metrics,
                         tls_config,
                     })),
                 ));
             }
 
+            // Setup metrics endpoint
+            if let Some(metrics_port) = metrics_port {
+                let metrics_addr = SocketAddr::new(bind, metrics_port);
+                handles.push((
+                    "Metrics",
+                    tokio::spawn(run_metrics_server(metrics_addr, registry)),
+                ));
+            }
+
             // Log servers to be startup
             println!(
                 "{}",
@@ -331,7 +619,74 @@ async fn main() -> Result<()> {
             }
         }
 
-        Commands::Report { file, export_html } => {
+        Commands::Report {
+            command: Some(ReportCommands::Compare { files, export }),
+            ..
+        } => {
+            let mut reports = Vec::with_capacity(files.len());
+            let mut suites = Vec::with_capacity(files.len());
+            for file in &files {
+                if !file.is_file() {
+                    return Err(eyre::eyre!(
+                        "Report file does not exist: {}",
+                        file.display()
+                    ));
+                }
+                let report = with_progress_counter(
+                    &format!("Loading report from {}", file.display()),
+                    import_report(file),
+                )
+                .await?;
+                let label = file.file_name().map_or_else(
+                    || file.display().to_string(),
+                    |name| name.to_string_lossy().into_owned(),
+                );
+                match report {
+                    ReportFile::Test(report) => reports.push((label, *report)),
+                    ReportFile::Suite(report) => suites.push((label, *report)),
+                }
+            }
+
+            let comparison = match (reports.is_empty(), suites.is_empty()) {
+                (_, true) => ReportComparison::new(&reports),
+                (true, _) => ReportComparison::new_suites(&suites),
+                _ => {
+                    return Err(eyre::eyre!(
+                        "Test reports can only be compared with test reports, and suite reports with suite reports"
+                    ));
+                }
+            };
+            println!("{comparison}");
+            if comparison.has_regressions() {
+                println!(
+                    "{}",
+                    "Regressions detected compared to the baseline".red().bold()
+                );
+            }
+
+            if let Some(export_file) = export {
+                match with_progress_counter(
+                    "Exporting comparison",
+                    export_comparison(&comparison, &export_file),
+                )
+                .await
+                {
+                    Ok(_) => println!(
+                        "{}",
+                        format!("Comparison exported to {}", export_file.display()).cyan()
+                    ),
+                    Err(e) => eprintln!("Error exporting comparison: {e}"),
+                }
+            }
+        }
+
+        Commands::Report {
+            file,
+            export_html,
+            export,
+            ..
+        } => {
+            let file = file.expect("clap requires --file without a subcommand");
             // Validate file exists and is readable
             if !file.exists() {
                 return Err(eyre::eyre!(
@@ -342,96 +697,172 @@ async fn main() -> Result<()> {
             if !file.is_file() {
                 return Err(eyre::eyre!("Report path is not a file: {}", file.display()));
             }
-            if let Some(ext) = file.extension() {
-                match ext.to_string_lossy().as_ref() {
-                    "json" => {
-                        let report = with_progress_counter(
-                            "Loading report from JSON file",
-                            import_report_json(&file),
-                        )
-                        .await?;
-
-                        match export_html {
-                            None => {
-                                // Print report in stdout
-                                println!("{report:#}");
-                            }
-                            Some(html_file) => {
-                                // Export to HTML
-                                match with_progress_counter(
-                                    "Exporting report to HTML",
-                                    export_report_html(&report, &html_file),
-                                )
-                                .await
-                                {
-                                    Ok(_) => println!(
-                                        "{}",
-                                        format!("HTML report exported to {}", html_file.display())
-                                            .cyan()
-                                    ),
-                                    Err(e) => eprintln!("Error exporting to HTML: {e}"),
-                                }
-                            }
-                        }
+            let Some(ext) = file.extension() else {
+                return Err(eyre::eyre!(
+                    "Report file must have an extension: {}",
+                    file.display()
+                ));
+            };
+            let report = match ext.to_string_lossy().as_ref() {
+                "json" => {
+                    with_progress_counter(
+                        "Loading report from JSON file",
+                        import_report_json(&file),
+                    )
+                    .await?
+                }
+                "cbor" => {
+                    with_progress_counter(
+                        "Loading report from CBOR file",
+                        import_report_cbor(&file),
+                    )
+                    .await?
+                }
+                "html" => {
+                    return Err(eyre::eyre!(
+                        "HTML report format should be opened via a web browser: {}",
+                        file.display()
+                    ));
+                }
+                _ => match with_progress_counter(
+                    "Loading report from file (assuming JSON format)",
+                    import_report_json(&file),
+                )
+                .await
+                {
+                    Ok(report) => report,
+                    Err(e) => {
+                        eprintln!("Error parsing report (assumed to be JSON): {e}");
+                        return Ok(());
                     }
-                    "cbor" => {
-                        let report = with_progress_counter(
-                            "Loading report from CBOR file",
-                            import_report_cbor(&file),
-                        )
-                        .await?;
-
-                        match export_html {
-                            None => {
-                                // Print report in stdout
-                                println!("{report:#}");
-                            }
-                            Some(html_file) => {
-                                // Export to HTML
-                                match with_progress_counter(
-                                    "Exporting report to HTML",
-                                    export_report_html(&report, &html_file),
-                                )
-                                .await
-                                {
-                                    Ok(_) => println!(
-                                        "{}",
-                                        format!("HTML report exported to {}", html_file.display())
-                                            .cyan()
-                                    ),
-                                    Err(e) => eprintln!("Error exporting to HTML: {e}"),
-                                }
-                            }
+                },
+            };
+
+            if export_html.is_none() && export.is_none() {
+                // Print report in stdout
+                println!("{report:#}");
+            }
+
+            if let Some(html_file) = export_html {
+                // Export to HTML
+                match with_progress_counter("Exporting report to HTML", async {
+                    match &report {
+                        ReportFile::Test(report) => export_report_html(report, &html_file).await,
+                        ReportFile::Suite(report) => {
+                            export_suite_report_html(report, &html_file).await
                         }
                     }
-                    "html" => {
-                        return Err(eyre::eyre!(
-                            "HTML report format should be opened via a web browser: {}",
-                            file.display()
-                        ));
+                })
+                .await
+                {
+                    Ok(_) => println!(
+                        "{}",
+                        format!("HTML report exported to {}", html_file.display()).cyan()
+                    ),
+                    Err(e) => eprintln!("Error exporting to HTML: {e}"),
+                }
+            }
+
+            if let Some(export) = export {
+                match with_progress_counter("Exporting report", async {
+                    match &report {
+                        ReportFile::Test(report) => export_report(report, &export).await,
+                        ReportFile::Suite(report) => export_suite_report(report, &export).await,
                     }
-                    _ => match with_progress_counter(
-                        "Loading report from file (assuming JSON format)",
-                        import_report_json(&file),
-                    )
-                    .await
-                    {
-                        Ok(report) => {
-                            println!("{report:#}");
-                        }
-                        Err(e) => {
-                            eprintln!("Error parsing report (assumed to be JSON): {e}");
-                        }
-                    },
+                })
+                .await
+                {
+                    Ok(_) => println!(
+                        "{}",
+                        format!("Report exported to {}", export.display()).cyan()
+                    ),
+                    Err(e) => eprintln!("Error exporting report: {e}"),
                 }
-            } else {
-                return Err(eyre::eyre!(
-                    "Report file must have an extension: {}",
-                    file.display()
-                ));
             }
         }
     }
 
     Ok(())
 }
+
+/// Prints, exports and stores the report of a suite, then fails if any of its steps
+/// failed to run or exits with [`THRESHOLD_VIOLATION_EXIT_CODE`] if any violated its thresholds
+async fn finish_suite(
+    report: &SuiteReport,
+    export: Option<&Path>,
+    db: Option<&Path>,
+) -> Result<()> {
+    if is_json_stream() {
+        emit(&StreamEvent::SuiteReport { report });
+    } else {
+        println!("{report}");
+    }
+
+    if let Some(export) = export {
+        match with_progress_counter(
+            "Exporting test results",
+            export_suite_report(report, export),
+        )
+        .await
+        {
+            Ok(_) => status!(
+                "{}",
+                format!("Results exported to {}", export.to_string_lossy()).cyan()
+            ),
+            Err(e) => eprintln!("Error exporting results: {e}"),
+        }
+    }
+
+    if let Some(db) = db {
+        record_history(db, |history| history.insert_suite(report).map(|_| ()));
+    }
+
+    let failed = report.failed_steps().count();
+    if failed > 0 {
+        return Err(eyre::eyre!(
+            "{failed} of {} steps failed to run",
+            report.steps.len()
+        ));
+    }
+
+    let violating: Vec<_> = report.violating_steps().collect();
+    if !violating.is_empty() {
+        eprintln!(
+            "{}",
+            format!(
+                "Threshold violations in {} of {} steps:",
+                violating.len(),
+                report.steps.len()
+            )
+            .red()
+            .bold()
+        );
+        for step in violating {
+            let Some(evaluation) = step
+                .report
+                .as_ref()
+                .and_then(|report| report.thresholds.as_ref())
+            else {
+                continue;
+            };
+            for check in evaluation.violations() {
+                eprintln!("  {}: {check}", step.name);
+            }
+        }
+        std::process::exit(THRESHOLD_VIOLATION_EXIT_CODE);
+    }
+
+    Ok(())
+}
+
+/// Stores results in the history database at `db`. Failing to store them doesn't fail
+/// the test, just like failing to export them.
+fn record_history(db: &Path, record: impl FnOnce(&mut HistoryDb) -> Result<(), HistoryError>) {
+    match HistoryDb::open(db).and_then(|mut history| record(&mut history)) {
+        Ok(()) => status!(
+            "{}",
+            format!("Results stored in {}", db.to_string_lossy()).cyan()
+        ),
+        Err(e) => eprintln!("Error storing results in {}: {e}", db.display()),
+    }
+}
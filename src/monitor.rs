@@ -0,0 +1,411 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{Json, Router, extract::State, http::StatusCode, response::IntoResponse, routing::get};
+use chrono::{DateTime, Utc};
+use colored::*;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+use crate::history::{HistoryDb, HistoryError};
+use crate::plan::{PlanError, TestPlan, run_plan};
+use crate::report::{RepeatStatistic, ReportFile, Sample, SuiteReport, TestReport};
+use crate::utils::export::{ExportError, export_report_cbor};
+use crate::utils::import::import_report_cbor;
+use crate::utils::output::{StreamEvent, emit, is_json_stream, status};
+
+/// Options of the `monitor` command
+#[derive(Debug, Clone)]
+pub struct MonitorOptions {
+    /// Time between the starts of consecutive runs
+    pub every: Duration,
+    /// Directory that keeps the report of every test
+    pub history: PathBuf,
+    /// Number of most recent reports the rolling summary covers
+    pub window: usize,
+    /// Address to serve the latest results on, if any
+    pub listen: Option<SocketAddr>,
+    /// SQLite history database to also store every test report in, if any
+    pub db: Option<PathBuf>,
+}
+
+#[derive(Debug, Error)]
+pub enum MonitorError {
+    #[error("{0}")]
+    Plan(#[from] PlanError),
+    #[error("Cannot open history directory {}: {source}", path.display())]
+    History { path: PathBuf, source: io::Error },
+    #[error("Cannot serve results: {0}")]
+    Serve(io::Error),
+    #[error("Cannot open history database {}: {source}", path.display())]
+    Database { path: PathBuf, source: HistoryError },
+}
+
+/// Directory of CBOR test reports, one file per test, named after the time the test
+/// completed so that the files sort chronologically
+#[derive(Debug, Clone)]
+pub struct HistoryStore {
+    dir: PathBuf,
+}
+
+impl HistoryStore {
+    /// Opens the store in `dir`, creating the directory if needed
+    pub async fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        tokio::fs::create_dir_all(&dir).await?;
+        Ok(Self { dir })
+    }
+
+    /// Writes `report` to a new file of the store and returns its path. `step` tells apart
+    /// reports of the same run.
+    pub async fn append(&self, report: &TestReport, step: usize) -> Result<PathBuf, ExportError> {
+        let name = format!(
+            "{}-{step}.cbor",
+            report.timestamp.format("%Y%m%dT%H%M%S%.3fZ")
+        );
+        let path = self.dir.join(name);
+        // Write to a temporary file first, so an interrupted write never leaves a
+        // truncated report behind
+        let partial = path.with_extension("cbor.partial");
+        export_report_cbor(report, &partial).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(path)
+    }
+
+    /// Loads the `count` most recent test reports of the store, oldest first. Files that
+    /// can't be read are skipped with a warning.
+    pub async fn load_recent(&self, count: usize) -> Vec<TestReport> {
+        let mut paths = match self.paths().await {
+            Ok(paths) => paths,
+            Err(e) => {
+                warn!("Cannot list history directory {}: {e}", self.dir.display());
+                return Vec::new();
+            }
+        };
+        paths.sort();
+
+        let mut reports = Vec::new();
+        for path in paths.iter().rev() {
+            if reports.len() == count {
+                break;
+            }
+            match import_report_cbor(path).await {
+                Ok(ReportFile::Test(report)) => reports.push(*report),
+                // Suite reports aren't written by the monitor, so they don't belong here
+                Ok(ReportFile::Suite(_)) => {}
+                Err(e) => warn!("Skipping unreadable history file {}: {e}", path.display()),
+            }
+        }
+        reports.reverse();
+        reports
+    }
+
+    async fn paths(&self) -> io::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "cbor") {
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+/// Samples of the most recent test reports, up to a fixed number of reports. Only the
+/// samples are kept since raw measurements can take megabytes per report.
+#[derive(Debug, Clone)]
+pub struct RollingHistory {
+    reports: VecDeque<Vec<Sample>>,
+    capacity: usize,
+}
+
+impl RollingHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            reports: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Adds the samples of `report`, evicting the oldest report if the history is full
+    pub fn push(&mut self, report: &TestReport) {
+        if self.reports.len() == self.capacity {
+            self.reports.pop_front();
+        }
+        self.reports.push_back(Sample::from_report(report));
+    }
+
+    pub fn len(&self) -> usize {
+        self.reports.len()
+    }
+
+    /// Summarizes the reports per protocol, direction and payload size
+    pub fn summary(&self) -> Vec<RepeatStatistic> {
+        RepeatStatistic::from_samples(self.reports.iter().flatten())
+    }
+}
+
+/// State of the monitor, as served over HTTP
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitorStatus {
+    pub started: DateTime<Utc>,
+    /// Runs completed since the monitor started
+    pub runs: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_run: Option<DateTime<Utc>>,
+    /// Number of test reports the summary covers
+    pub window: usize,
+    /// Rolling summary of the most recent test reports
+    pub summary: Vec<RepeatStatistic>,
+    /// Report of the latest run
+    #[serde(skip)]
+    pub latest: Option<SuiteReport>,
+}
+
+type SharedStatus = Arc<RwLock<MonitorStatus>>;
+
+/// Runs `plan` every `options.every` until SIGINT or SIGTERM, appending each test report
+/// to the history store and keeping a rolling summary of the most recent reports. A run
+/// in progress when the signal arrives is abandoned.
+pub async fn run_monitor(plan: &TestPlan, options: MonitorOptions) -> Result<(), MonitorError> {
+    // Surface mistakes in the plan before the first run
+    plan.resolve()?;
+
+    let store = HistoryStore::open(&options.history)
+        .await
+        .map_err(|source| MonitorError::History {
+            path: options.history.clone(),
+            source,
+        })?;
+    let mut db = match &options.db {
+        Some(path) => Some(
+            HistoryDb::open(path).map_err(|source| MonitorError::Database {
+                path: path.clone(),
+                source,
+            })?,
+        ),
+        None => None,
+    };
+    let mut history = RollingHistory::new(options.window);
+    for report in store.load_recent(options.window).await {
+        history.push(&report);
+    }
+    if history.len() > 0 {
+        status!(
+            "{}",
+            format!(
+                "Loaded {} reports from {}",
+                history.len(),
+                store.dir().display()
+            )
+            .cyan()
+        );
+    }
+
+    let shared = Arc::new(RwLock::new(MonitorStatus {
+        started: Utc::now(),
+        runs: 0,
+        last_run: None,
+        next_run: None,
+        window: history.len(),
+        summary: history.summary(),
+        latest: None,
+    }));
+
+    if let Some(addr) = options.listen {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(MonitorError::Serve)?;
+        let app = Router::new()
+            .route("/", get(status_handler))
+            .route("/latest", get(latest_handler))
+            .with_state(shared.clone());
+        info!("Monitor results served on {}", addr);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+    }
+
+    let monitor = async {
+        let mut ticker = tokio::time::interval(options.every);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            let report = run_plan(plan).await?;
+
+            for (index, step) in report.steps.iter().enumerate() {
+                let Some(test) = &step.report else {
+                    continue;
+                };
+                if let Err(e) = store.append(test, index + 1).await {
+                    status!("{}", format!("Error writing history: {e}").red());
+                }
+                history.push(test);
+            }
+            if let Some(db) = &mut db
+                && let Err(e) = db.insert_suite(&report)
+            {
+                status!("{}", format!("Error writing history database: {e}").red());
+            }
+            let summary = history.summary();
+
+            let mut shared = shared.write().await;
+            shared.runs += 1;
+            shared.last_run = Some(report.timestamp);
+            shared.next_run = chrono::Duration::from_std(options.every)
+                .ok()
+                .and_then(|every| report.start_time.checked_add_signed(every));
+            shared.window = history.len();
+
+            if is_json_stream() {
+                emit(&StreamEvent::SuiteReport { report: &report });
+            } else {
+                print_run(shared.runs, &report, &summary);
+            }
+
+            shared.summary = summary;
+            shared.latest = Some(report);
+        }
+    };
+
+    tokio::select! {
+        result = monitor => result,
+        _ = shutdown_signal() => {
+            status!("{}", "Monitor stopped.".green().bold());
+            Ok(())
+        }
+    }
+}
+
+fn print_run(run: u64, report: &SuiteReport, summary: &[RepeatStatistic]) {
+    let failed = report.failed_steps().count();
+    let violating = report.violating_steps().count();
+    let outcome = if failed > 0 {
+        format!("{failed} of {} steps failed", report.steps.len()).red()
+    } else if violating > 0 {
+        format!(
+            "{violating} of {} steps violated thresholds",
+            report.steps.len()
+        )
+        .yellow()
+    } else {
+        "passed".green()
+    };
+    println!(
+        "{} {}: {outcome}",
+        report.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+        format!("Run {run}").bright_white().bold()
+    );
+    for statistic in summary {
+        println!("  {statistic}");
+    }
+}
+
+/// Completes when the process receives SIGINT (Ctrl+C) or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+async fn status_handler(State(status): State<SharedStatus>) -> Json<MonitorStatus> {
+    Json(status.read().await.clone())
+}
+
+async fn latest_handler(State(status): State<SharedStatus>) -> impl IntoResponse {
+    match &status.read().await.latest {
+        Some(report) => Json(report.clone()).into_response(),
+        None => (StatusCode::NOT_FOUND, "No run has completed yet").into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestType;
+    use crate::report::{LatencyMeasurement, LatencyResult, NetworkTestResult, TcpTestConfig};
+
+    fn report() -> TestReport {
+        let config = TcpTestConfig::new(
+            "127.0.0.1".to_string(),
+            None,
+            1,
+            1,
+            TestType::LatencyOnly,
+            [],
+            Duration::from_secs(1),
+        );
+        let mut result = NetworkTestResult::new_tcp();
+        result.latency = Some(LatencyResult {
+            measurements: vec![LatencyMeasurement {
+                rtt_ms: Some(10.0),
+                elapsed_time: Duration::ZERO,
+            }],
+            timestamp: Utc::now(),
+        });
+        TestReport::from((Utc::now(), config, result))
+    }
+
+    #[test]
+    fn test_rolling_history_evicts_oldest() {
+        let mut history = RollingHistory::new(2);
+        for _ in 0..3 {
+            history.push(&report());
+        }
+        assert_eq!(history.len(), 2);
+        let summary = history.summary();
+        assert_eq!(summary[0].metric, "Average RTT");
+        assert!(
+            summary
+                .iter()
+                .all(|statistic| statistic.summary.samples == 2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_history_store_round_trip() {
+        let dir = std::env::temp_dir().join(format!("speed-cli-history-{}", std::process::id()));
+        let store = HistoryStore::open(&dir).await.unwrap();
+        for step in 1..=3 {
+            store.append(&report(), step).await.unwrap();
+        }
+
+        tokio::fs::write(dir.join("99999999T000000.000Z-1.cbor"), b"truncated")
+            .await
+            .unwrap();
+
+        let reports = store.load_recent(2).await;
+        assert_eq!(reports.len(), 2);
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
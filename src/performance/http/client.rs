@@ -1,34 +1,46 @@
+use bytes::Bytes;
 use chrono::Utc;
 use colored::Colorize as _;
 use eyre::{Context, Result};
-use futures::stream::StreamExt;
+use futures::stream::{self, StreamExt};
 use humansize::ToF64;
 
 use rand::{prelude::*, rng};
 use reqwest::{Client, ClientBuilder};
 use rustls::crypto::{CryptoProvider, aws_lc_rs};
 use std::{
-    sync::Once,
+    sync::{Arc, Once, OnceLock},
     time::{Duration, Instant},
 };
 use tokio::time::sleep;
 use tracing::trace;
 
 use crate::{
-    TestType,
-    performance::http::HttpVersion,
+    TargetBitrate, TestType,
+    performance::{
+        ensure_data_transferred,
+        http::{HttpVersion, UploadReceipt, rpm::run_responsiveness_test},
+        idle_latency_duration, load_payload_size,
+        pacing::{SharedPacer, connection_pacers},
+    },
     report::{
-        ConnectionError, HttpTestConfig, LatencyMeasurement, LatencyResult, NetworkTestResult,
-        TestReport, ThroughputMeasurement, ThroughputResult,
+        ConnectionError, HttpTestConfig, LatencyMeasurement, LatencyResult, LoadedLatencyResult,
+        NetworkTestResult, SERVER_INTERVAL, ServerMeasurement, TestConfig, TestReport,
+        ThroughputMeasurement, ThroughputResult,
     },
     utils::{
         format::format_bytes,
         instrumentation::{
-            LatencyStatsCollector, ProgressBarType, ThroughputStatsCollector, create_progress_bar,
+            LatencyStatsCollector, ProgressBarType, ThroughputEvent, ThroughputStatsCollector,
+            create_progress_bar,
         },
+        output::{StreamEvent, TestPhase, emit, status},
     },
 };
 
+/// Size of the slices of rate-limited upload bodies, small enough to pace smoothly
+const PACED_SLICE_SIZE: usize = 64 * 1024;
+
 static CRYPTO_PROVIDER_INIT: Once = Once::new();
 
 fn ensure_crypto_provider() {
@@ -40,7 +52,7 @@ fn ensure_crypto_provider() {
 // TODO: Need to optimize HTTPS (e.g. HTTP/2) tests for throughput
 
 pub async fn run_http_test(config: HttpTestConfig) -> Result<TestReport> {
-    println!(
+    status!(
         "{}",
         format!(
             "Starting {} speed test to server {}...",
@@ -52,6 +64,10 @@ pub async fn run_http_test(config: HttpTestConfig) -> Result<TestReport> {
     );
 
     let start_time = Utc::now();
+    emit(&StreamEvent::TestStarted {
+        start_time,
+        config: &TestConfig::from(config.clone()),
+    });
 
     let mut result = NetworkTestResult::new_http();
 
@@ -63,65 +79,114 @@ pub async fn run_http_test(config: HttpTestConfig) -> Result<TestReport> {
             result.latency =
                 measure_http_latency(&client, &config.server_url, config.duration).await?;
         }
+        TestType::LatencyUnderLoad => {
+            let payload_size = load_payload_size(&config.payload_sizes)?;
+            result.latency = measure_http_latency(
+                &client,
+                &config.server_url,
+                idle_latency_duration(config.duration),
+            )
+            .await?;
+
+            status!("Measuring HTTP latency under download load...");
+            let (latency, download) = tokio::join!(
+                measure_http_latency(&client, &config.server_url, config.duration),
+                run_download_test(
+                    &client,
+                    &config.server_url,
+                    config.parallel_connections,
+                    payload_size,
+                    config.chunk_size,
+                    config.duration,
+                    config.interval,
+                )
+            );
+            let mut loaded = LoadedLatencyResult {
+                download: latency?,
+                upload: None,
+            };
+            let (download, server) = download?;
+            result.add_download(payload_size, download, server);
+
+            status!("Measuring HTTP latency under upload load...");
+            let (latency, upload) = tokio::join!(
+                measure_http_latency(&client, &config.server_url, config.duration),
+                run_upload_test(
+                    &client,
+                    &config.server_url,
+                    config.parallel_connections,
+                    payload_size,
+                    config.chunk_size,
+                    config.duration,
+                    config.interval,
+                    config.bitrate,
+                )
+            );
+            loaded.upload = latency?;
+            let (upload, server) = upload?;
+            result.add_upload(payload_size, upload, server);
+            result.loaded_latency = Some(loaded);
+        }
+        TestType::Responsiveness => {
+            result.responsiveness = Some(run_responsiveness_test(&client, &config).await?);
+        }
         TestType::Download => {
             for payload_size in &config.payload_sizes {
-                result.download.insert(
+                let (download, server) = run_download_test(
+                    &client,
+                    &config.server_url,
+                    config.parallel_connections,
                     *payload_size,
-                    run_download_test(
-                        &client,
-                        &config.server_url,
-                        config.parallel_connections,
-                        *payload_size,
-                        config.chunk_size,
-                        config.duration,
-                    )
-                    .await?,
-                );
+                    config.chunk_size,
+                    config.duration,
+                    config.interval,
+                )
+                .await?;
+                result.add_download(*payload_size, download, server);
             }
         }
         TestType::Upload => {
             for payload_size in &config.payload_sizes {
-                result.upload.insert(
+                let (upload, server) = run_upload_test(
+                    &client,
+                    &config.server_url,
+                    config.parallel_connections,
                     *payload_size,
-                    run_upload_test(
-                        &client,
-                        &config.server_url,
-                        config.parallel_connections,
-                        *payload_size,
-                        config.chunk_size,
-                        config.duration,
-                    )
-                    .await?,
-                );
+                    config.chunk_size,
+                    config.duration,
+                    config.interval,
+                    config.bitrate,
+                )
+                .await?;
+                result.add_upload(*payload_size, upload, server);
             }
         }
         TestType::Bidirectional => {
             // Run download and upload sequentially
             for payload_size in &config.payload_sizes {
-                result.download.insert(
+                let (download, server) = run_download_test(
+                    &client,
+                    &config.server_url,
+                    config.parallel_connections,
                     *payload_size,
-                    run_download_test(
-                        &client,
-                        &config.server_url,
-                        config.parallel_connections,
-                        *payload_size,
-                        config.chunk_size,
-                        config.duration,
-                    )
-                    .await?,
-                );
-                result.upload.insert(
+                    config.chunk_size,
+                    config.duration,
+                    config.interval,
+                )
+                .await?;
+                result.add_download(*payload_size, download, server);
+                let (upload, server) = run_upload_test(
+                    &client,
+                    &config.server_url,
+                    config.parallel_connections,
                     *payload_size,
-                    run_upload_test(
-                        &client,
-                        &config.server_url,
-                        config.parallel_connections,
-                        *payload_size,
-                        config.chunk_size,
-                        config.duration,
-                    )
-                    .await?,
-                );
+                    config.chunk_size,
+                    config.duration,
+                    config.interval,
+                    config.bitrate,
+                )
+                .await?;
+                result.add_upload(*payload_size, upload, server);
             }
         }
         TestType::Simultaneous => {
@@ -135,6 +200,7 @@ pub async fn run_http_test(config: HttpTestConfig) -> Result<TestReport> {
                         *payload_size,
                         config.chunk_size,
                         config.duration,
+                        config.interval,
                     ),
                     run_upload_test(
                         &client,
@@ -143,11 +209,15 @@ pub async fn run_http_test(config: HttpTestConfig) -> Result<TestReport> {
                         *payload_size,
                         config.chunk_size,
                         config.duration,
+                        config.interval,
+                        config.bitrate,
                     )
                 );
 
-                result.download.insert(*payload_size, download_result?);
-                result.upload.insert(*payload_size, upload_result?);
+                let (download, download_server) = download_result?;
+                let (upload, upload_server) = upload_result?;
+                result.add_download(*payload_size, download, download_server);
+                result.add_upload(*payload_size, upload, upload_server);
             }
         }
     }
@@ -155,7 +225,7 @@ pub async fn run_http_test(config: HttpTestConfig) -> Result<TestReport> {
     Ok((start_time, config, result).into())
 }
 
-async fn create_http_client(version: &HttpVersion) -> Result<Client> {
+pub(super) async fn create_http_client(version: &HttpVersion) -> Result<Client> {
     // Ensure crypto provider is initialized before creating TLS client
     ensure_crypto_provider();
 
@@ -197,7 +267,7 @@ async fn measure_http_latency(
     let url = format!("{server_url}/latency");
     let mut measurements = Vec::new();
 
-    println!("Measuring HTTP latency for {duration:?}...");
+    status!("Measuring HTTP latency for {duration:?}...");
 
     // Create progress bar for latency measurement
     let progress_bar = create_progress_bar(ProgressBarType::Latency, duration);
@@ -206,6 +276,7 @@ async fn measure_http_latency(
     // Set up instrumentation
     let (stats_collector, tx) = LatencyStatsCollector::new(progress_bar.clone(), start, duration);
 
+    let mut last_error = None;
     while start.elapsed() < duration {
         let request_start = Instant::now();
         match client.head(&url).send().await {
@@ -231,6 +302,7 @@ async fn measure_http_latency(
                 let _ = tx.send(measurement);
 
                 trace!("HTTP request error while measuring latency: {e}");
+                last_error = Some(e);
             }
         }
 
@@ -249,6 +321,11 @@ async fn measure_http_latency(
     if measurements.is_empty() {
         return Ok(None);
     }
+    if let Some(e) = last_error
+        && measurements.iter().all(|m| m.rtt_ms.is_none())
+    {
+        eyre::bail!("HTTP latency test could not reach {url}: {e}");
+    }
 
     Ok(Some(LatencyResult {
         measurements,
@@ -263,8 +340,9 @@ async fn run_download_test(
     payload_size: usize,
     chunk_size: usize,
     duration: Duration,
-) -> Result<ThroughputResult> {
-    println!(
+    interval: Duration,
+) -> Result<(ThroughputResult, Option<ServerMeasurement>)> {
+    status!(
         "Starting download test with {} payload size and {} parallel connections...",
         format_bytes(payload_size).yellow(),
         parallel_connections.to_string().yellow()
@@ -273,12 +351,16 @@ async fn run_download_test(
     // Create progress bar
     let progress_bar = create_progress_bar(ProgressBarType::Download, duration);
 
-    let mut measurements = Vec::new();
     let start_time = Instant::now();
 
     // Set up instrumentation
-    let (stats_collector, tx) =
-        ThroughputStatsCollector::new(progress_bar.clone(), start_time, duration);
+    let (stats_collector, tx) = ThroughputStatsCollector::new(
+        progress_bar.clone(),
+        TestPhase::Download { payload_size },
+        start_time,
+        duration,
+        interval,
+    );
 
     let mut tasks = Vec::new();
 
@@ -288,15 +370,17 @@ async fn run_download_test(
         let server_url = server_url.to_string();
 
         let task = tokio::spawn(async move {
-            let mut local_measurements = Vec::new();
             while start_time.elapsed() < duration {
                 let download_start = Instant::now();
                 match download_chunk(&client, &server_url, i, payload_size, chunk_size).await {
                     Ok(bytes) => {
                         let measurement =
                             ThroughputMeasurement::new(bytes, download_start.elapsed());
-                        local_measurements.push(measurement.clone());
-                        let _ = tx.send(measurement);
+                        let _ = tx.send(ThroughputEvent::Measurement {
+                            connection: i,
+                            offset: start_time.elapsed(),
+                            measurement,
+                        });
                     }
                     Err(e) => {
                         let measurements = ThroughputMeasurement::Failure {
@@ -304,15 +388,18 @@ async fn run_download_test(
                             duration: download_start.elapsed(),
                             retry_count: 0, // No retries in this case
                         };
-                        local_measurements.push(measurements.clone());
-                        let _ = tx.send(measurements);
+                        let _ = tx.send(ThroughputEvent::Measurement {
+                            connection: i,
+                            offset: start_time.elapsed(),
+                            measurement: measurements,
+                        });
 
-                        break;
+                        return Err(e);
                     }
                 }
             }
 
-            local_measurements
+            Ok(())
         });
 
         tasks.push(task);
@@ -324,11 +411,11 @@ async fn run_download_test(
     // Drop the sender to signal stats collector to finish
     drop(tx);
 
+    let mut errors = Vec::new();
     for result in results {
         match result {
-            Ok(task_measurements) => {
-                measurements.extend(task_measurements);
-            }
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => errors.push(e),
             Err(e) => {
                 panic!("Task error: {e}");
             }
@@ -336,19 +423,28 @@ async fn run_download_test(
     }
 
     // Wait for stats collector to complete and get measurements
-    measurements = stats_collector
+    let (totals, intervals, connection_intervals) = stats_collector
         .finish(progress_bar, "Download complete".to_string())
         .await;
 
+    ensure_data_transferred("HTTP download", &totals, errors.into_iter().next())?;
+
     let end_time = Instant::now();
 
-    Ok(ThroughputResult {
-        measurements,
-        total_duration: end_time.duration_since(start_time),
-        timestamp: chrono::Utc::now(),
-    })
+    // The download endpoint streams a fixed-size body, so the server has nothing to add
+    Ok((
+        ThroughputResult {
+            totals,
+            total_duration: end_time.duration_since(start_time),
+            intervals,
+            connection_intervals,
+            timestamp: chrono::Utc::now(),
+        },
+        None,
+    ))
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_upload_test(
     client: &Client,
     server_url: &str,
@@ -356,8 +452,10 @@ async fn run_upload_test(
     payload_size: usize,
     chunk_size: usize,
     duration: Duration,
-) -> Result<ThroughputResult> {
-    println!(
+    interval: Duration,
+    bitrate: Option<TargetBitrate>,
+) -> Result<(ThroughputResult, Option<ServerMeasurement>)> {
+    status!(
         "Starting upload test with {} payload size and {} parallel connections...",
         format_bytes(payload_size).yellow(),
         parallel_connections.to_string().yellow()
@@ -366,7 +464,6 @@ async fn run_upload_test(
     // Create progress bar
     let progress_bar = create_progress_bar(ProgressBarType::Upload, duration);
 
-    let mut measurements = Vec::new();
     let start_time = Instant::now();
 
     // Generate random upload data at the size of chunk_size
@@ -378,26 +475,71 @@ async fn run_upload_test(
     debug_assert!(chunk_data.len() == chunk_size, "Chunk data size mismatch");
 
     // Set up instrumentation
-    let (stats_collector, tx) =
-        ThroughputStatsCollector::new(progress_bar.clone(), start_time, duration);
+    let (stats_collector, tx) = ThroughputStatsCollector::new(
+        progress_bar.clone(),
+        TestPhase::Upload { payload_size },
+        start_time,
+        duration,
+        interval,
+    );
 
+    let pacers = connection_pacers(bitrate, parallel_connections);
+    // Server time the first upload of the test started, shared by all connections
+    let server_start = Arc::new(OnceLock::new());
     let mut tasks = Vec::new();
 
-    for i in 0..parallel_connections {
+    for (i, pacer) in pacers.into_iter().enumerate() {
         let client = client.clone();
         let tx = tx.clone();
         let server_url = server_url.to_string();
         let chunk_data = chunk_data.clone();
+        let server_start = server_start.clone();
 
         let task = tokio::spawn(async move {
-            let mut local_measurements = Vec::new();
+            // Server-side view of this connection, dropped if the server does not report receipts
+            let mut server_measurement = Some(ServerMeasurement {
+                connections: 1,
+                ..ServerMeasurement::new(SERVER_INTERVAL)
+            });
+            let mut first_error = None;
+
             while start_time.elapsed() < duration {
                 let upload_start = Instant::now();
-                match upload_chunk(&client, &server_url, payload_size, chunk_data.clone()).await {
-                    Ok(bytes) => {
+                match upload_chunk(
+                    &client,
+                    &server_url,
+                    payload_size,
+                    chunk_data.clone(),
+                    pacer.as_ref(),
+                )
+                .await
+                {
+                    Ok((bytes, receipt)) => {
                         let measurement = ThroughputMeasurement::new(bytes, upload_start.elapsed());
-                        local_measurements.push(measurement.clone());
-                        let _ = tx.send(measurement);
+                        let _ = tx.send(ThroughputEvent::Measurement {
+                            connection: i,
+                            offset: start_time.elapsed(),
+                            measurement,
+                        });
+
+                        // Bucketed by when the server finished reading the upload by its own
+                        // clock, relative to the first upload it started reading
+                        match (server_measurement.as_mut(), receipt) {
+                            (
+                                Some(server),
+                                Some(UploadReceipt {
+                                    bytes_received,
+                                    started_at_us: Some(started_at),
+                                    finished_at_us: Some(finished_at),
+                                    ..
+                                }),
+                            ) => {
+                                let server_start = *server_start.get_or_init(|| started_at);
+                                let offset = finished_at.saturating_sub(server_start).max(0);
+                                server.record(Duration::from_micros(offset as u64), bytes_received);
+                            }
+                            _ => server_measurement = None,
+                        }
                     }
                     Err(e) => {
                         let measurement = ThroughputMeasurement::new_error(
@@ -405,13 +547,21 @@ async fn run_upload_test(
                             upload_start.elapsed(),
                             0,
                         );
-                        local_measurements.push(measurement.clone());
-                        let _ = tx.send(measurement);
+                        let _ = tx.send(ThroughputEvent::Measurement {
+                            connection: i,
+                            offset: start_time.elapsed(),
+                            measurement,
+                        });
+                        first_error.get_or_insert(e);
                     }
                 }
             }
 
-            local_measurements
+            // Servers that don't time their reads leave nothing to compute a rate from
+            (
+                server_measurement.filter(|server| !server.duration.is_zero()),
+                first_error,
+            )
         });
 
         tasks.push(task);
@@ -423,10 +573,19 @@ async fn run_upload_test(
     // Drop the sender to signal stats collector to finish
     drop(tx);
 
+    let mut server_measurement = Some(ServerMeasurement::new(SERVER_INTERVAL));
+    let mut errors = Vec::new();
     for result in results {
         match result {
-            Ok(task_measurements) => {
-                measurements.extend(task_measurements);
+            Ok((task_server_measurement, task_error)) => {
+                errors.extend(task_error);
+                server_measurement = match (server_measurement, task_server_measurement) {
+                    (Some(mut total), Some(task)) => {
+                        total.merge(&task);
+                        Some(total)
+                    }
+                    _ => None,
+                };
             }
             Err(e) => {
                 panic!("Task error: {e}");
@@ -435,17 +594,24 @@ async fn run_upload_test(
     }
 
     // Wait for stats collector to complete and get measurements
-    measurements = stats_collector
+    let (totals, intervals, connection_intervals) = stats_collector
         .finish(progress_bar, "Upload complete".to_string())
         .await;
 
+    ensure_data_transferred("HTTP upload", &totals, errors.into_iter().next())?;
+
     let end_time = Instant::now();
 
-    Ok(ThroughputResult {
-        measurements,
-        total_duration: end_time.duration_since(start_time),
-        timestamp: chrono::Utc::now(),
-    })
+    Ok((
+        ThroughputResult {
+            totals,
+            total_duration: end_time.duration_since(start_time),
+            intervals,
+            connection_intervals,
+            timestamp: chrono::Utc::now(),
+        },
+        server_measurement,
+    ))
 }
 
 /// Download a chunk of data from the server
@@ -480,15 +646,40 @@ async fn download_chunk(
     Ok(total_bytes)
 }
 
+/// Request body that releases `data` in slices as fast as `pacer` allows
+fn paced_body(data: Vec<u8>, pacer: SharedPacer) -> reqwest::Body {
+    let data = Bytes::from(data);
+    let slices = (0..data.len())
+        .step_by(PACED_SLICE_SIZE)
+        .map(move |start| data.slice(start..data.len().min(start + PACED_SLICE_SIZE)));
+    reqwest::Body::wrap_stream(stream::iter(slices).then(move |slice| {
+        let pacer = pacer.clone();
+        async move {
+            pacer.wait(slice.len()).await;
+            Ok::<_, std::io::Error>(slice)
+        }
+    }))
+}
+
+/// Uploads `payload_size` bytes in chunks. Returns the bytes sent and, if the server
+/// acknowledged every chunk with an [`UploadReceipt`], the bytes it reports having received
+/// and when it started reading the first chunk and finished reading the last one.
 async fn upload_chunk(
     client: &Client,
     server_url: &str,
     payload_size: usize,
     chunk_data: Vec<u8>,
-) -> Result<u64> {
+    pacer: Option<&SharedPacer>,
+) -> Result<(u64, Option<UploadReceipt>)> {
     let chunk_size = chunk_data.len();
     let total_bytes_to_send = payload_size;
     let mut total_bytes_sent = 0u64;
+    let mut total_received = Some(UploadReceipt {
+        bytes_received: 0,
+        duration_ms: 0.0,
+        started_at_us: None,
+        finished_at_us: None,
+    });
 
     // Calculate how many chunks we need to send
     let num_chunks = total_bytes_to_send.div_ceil(chunk_size); // Ceiling division
@@ -509,7 +700,10 @@ async fn upload_chunk(
             .header("Content-Type", "application/octet-stream")
             .header("X-Chunk-Index", chunk_index.to_string())
             .header("X-Total-Chunks", num_chunks.to_string())
-            .body(chunk_to_send)
+            .body(match pacer {
+                Some(pacer) => paced_body(chunk_to_send, pacer.clone()),
+                None => chunk_to_send.into(),
+            })
             .send()
             .await?;
 
@@ -519,7 +713,54 @@ async fn upload_chunk(
         }
 
         total_bytes_sent += current_chunk_size as u64;
+
+        let receipt = response.json::<UploadReceipt>().await.ok();
+        total_received = total_received
+            .zip(receipt)
+            .map(|(total, receipt)| UploadReceipt {
+                bytes_received: total.bytes_received + receipt.bytes_received,
+                duration_ms: total.duration_ms + receipt.duration_ms,
+                started_at_us: total.started_at_us.or(receipt.started_at_us),
+                finished_at_us: receipt.finished_at_us,
+            });
     }
 
-    Ok(total_bytes_sent)
+    Ok((total_bytes_sent, total_received))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_closed_port_fails() {
+        // Nothing listens on the port once the listener is dropped
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let server_url = format!("http://127.0.0.1:{port}");
+        let client = Client::new();
+        let duration = Duration::from_millis(200);
+
+        let error = run_download_test(&client, &server_url, 2, 1024, 1024, duration, duration)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("transferred no data"), "{error}");
+
+        let error = run_upload_test(
+            &client,
+            &server_url,
+            1,
+            1024,
+            1024,
+            duration,
+            duration,
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("transferred no data"), "{error}");
+    }
 }
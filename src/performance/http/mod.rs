@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::fmt;
 
 pub mod client;
+mod rpm;
 pub mod server;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -28,6 +29,44 @@ impl HttpVersion {
     }
 }
 
+/// JSON body returned by the server's `/upload` endpoint
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct UploadReceipt {
+    /// Bytes the server read from the request body
+    pub bytes_received: u64,
+    /// Time the server spent reading the request body, in milliseconds
+    #[serde(default)]
+    pub duration_ms: f64,
+    /// Time the server started reading the request body by its own clock, in microseconds
+    /// since the Unix epoch. Missing from older servers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at_us: Option<i64>,
+    /// Time the server finished reading the request body by its own clock, in microseconds
+    /// since the Unix epoch. Missing from older servers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at_us: Option<i64>,
+}
+
+/// JSON body returned by the server's `/rpm/config` endpoint, in the format of Apple's
+/// `networkQuality` configuration so either side can be used with the other's tools
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpmConfig {
+    pub version: u32,
+    pub urls: RpmUrls,
+}
+
+/// Endpoints of a responsiveness test. The names follow `networkQuality`, which uses them
+/// for plain HTTP URLs as well.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpmUrls {
+    /// Small object fetched by the probes
+    pub small_https_download_url: String,
+    /// Download large enough to keep a connection busy for the whole test
+    pub large_https_download_url: String,
+    /// Endpoint that discards uploaded data
+    pub https_upload_url: String,
+}
+
 impl fmt::Display for HttpVersion {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
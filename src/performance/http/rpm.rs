@@ -0,0 +1,532 @@
+//! Responsiveness test following the IETF "Responsiveness under Working Conditions"
+//! draft. Load-generating connections are added until goodput saturates, while probes
+//! time round trips on new connections (foreign probes) and on the loaded connections
+//! themselves (self probes).
+
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use eyre::{Context, OptionExt as _, Result};
+use futures::{StreamExt as _, stream};
+use http_body_util::{BodyExt as _, Empty};
+use hyper::Request;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use rand::{prelude::*, rng};
+use reqwest::Client;
+use rustls::{ClientConfig, pki_types::ServerName};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    sync::mpsc,
+    task::JoinHandle,
+};
+use tokio_rustls::TlsConnector;
+use tracing::trace;
+use url::Url;
+
+use crate::{
+    performance::http::{HttpVersion, RpmConfig, RpmUrls, client::create_http_client},
+    report::{HttpTestConfig, ResponsivenessPhase, ResponsivenessResult},
+    utils::{
+        instrumentation::{ProgressBarType, create_progress_bar},
+        output::status,
+        tls::insecure_client_config,
+    },
+};
+
+/// Interval at which load-generating connections are added and saturation is checked
+const INTERVAL: Duration = Duration::from_secs(1);
+/// Number of intervals the moving averages span
+const MOVING_AVERAGE_DISTANCE: usize = 4;
+/// Standard deviation of the moving averages, relative to the latest one, below which
+/// they are considered stable
+const STABILITY_TOLERANCE: f64 = 0.05;
+/// Maximum number of load-generating connections
+const MAX_LOAD_CONNECTIONS: usize = 16;
+/// Time between probes
+const PROBE_INTERVAL: Duration = Duration::from_millis(100);
+/// Fraction of the fastest probe results the trimmed means keep
+const TRIMMED_MEAN_KEEP: f64 = 0.95;
+/// Size of the chunks the load-generating uploads are made of
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    Download,
+    Upload,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Download => write!(f, "download"),
+            Direction::Upload => write!(f, "upload"),
+        }
+    }
+}
+
+/// Round trips timed by a probe, in milliseconds
+#[derive(Debug, Clone, Copy)]
+enum Probe {
+    /// Probe on a new connection
+    Foreign {
+        tcp: Option<f64>,
+        tls: Option<f64>,
+        http: f64,
+    },
+    /// Probe on one of the load-generating connections
+    InConnection { http: f64 },
+}
+
+/// Probe results of a number of intervals
+#[derive(Debug, Default)]
+struct ProbeSet {
+    tcp: Vec<f64>,
+    tls: Vec<f64>,
+    http_foreign: Vec<f64>,
+    http_self: Vec<f64>,
+}
+
+impl ProbeSet {
+    fn add(&mut self, probe: Probe) {
+        match probe {
+            Probe::Foreign { tcp, tls, http } => {
+                self.tcp.extend(tcp);
+                self.tls.extend(tls);
+                self.http_foreign.push(http);
+            }
+            Probe::InConnection { http } => self.http_self.push(http),
+        }
+    }
+
+    /// Merges the probes of the last intervals the moving averages span
+    fn recent(intervals: &[ProbeSet]) -> Self {
+        let mut set = Self::default();
+        for interval in &intervals[intervals.len().saturating_sub(MOVING_AVERAGE_DISTANCE)..] {
+            set.tcp.extend(&interval.tcp);
+            set.tls.extend(&interval.tls);
+            set.http_foreign.extend(&interval.http_foreign);
+            set.http_self.extend(&interval.http_self);
+        }
+        set
+    }
+
+    /// Round-trips per minute: foreign and self probes weigh half each, and the foreign
+    /// half is split evenly between the TCP, TLS and HTTP round trips that were measured
+    fn rpm(&self) -> Option<f64> {
+        let foreign: Vec<f64> = [&self.tcp, &self.tls, &self.http_foreign]
+            .into_iter()
+            .filter_map(|values| trimmed_mean(values))
+            .collect();
+        if foreign.is_empty() {
+            return None;
+        }
+        let round_trip = (mean(&foreign) + trimmed_mean(&self.http_self)?) / 2.0;
+        Some(60_000.0 / round_trip)
+    }
+}
+
+/// Connection kept busy with a download or upload until it is dropped
+struct LoadConnection {
+    client: Client,
+    task: JoinHandle<()>,
+}
+
+impl LoadConnection {
+    async fn open(
+        version: HttpVersion,
+        urls: &RpmUrls,
+        direction: Direction,
+        bytes: Arc<AtomicU64>,
+    ) -> Result<Self> {
+        // Each client keeps its own HTTP/2 or HTTP/3 connection to the server
+        let client = create_http_client(&version).await?;
+        let task = match direction {
+            Direction::Download => tokio::spawn(keep_downloading(
+                client.clone(),
+                urls.large_https_download_url.clone(),
+                bytes,
+            )),
+            Direction::Upload => tokio::spawn(keep_uploading(
+                client.clone(),
+                urls.https_upload_url.clone(),
+                bytes,
+            )),
+        };
+        Ok(Self { client, task })
+    }
+}
+
+impl Drop for LoadConnection {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Measures responsiveness while downloads and then uploads saturate the link
+pub async fn run_responsiveness_test(
+    client: &Client,
+    config: &HttpTestConfig,
+) -> Result<ResponsivenessResult> {
+    if matches!(config.http_version, HttpVersion::HTTP1) {
+        eyre::bail!("Responsiveness tests require HTTP/2 or HTTP/3");
+    }
+
+    let rpm_config: RpmConfig = client
+        .get(format!("{}/rpm/config", config.server_url))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .context("Failed to fetch the responsiveness test configuration")?
+        .json()
+        .await
+        .context("Invalid responsiveness test configuration")?;
+
+    let mut result = ResponsivenessResult::default();
+    for direction in [Direction::Download, Direction::Upload] {
+        let phase = run_phase(
+            config.http_version,
+            &rpm_config.urls,
+            direction,
+            config.duration,
+        )
+        .await?;
+        match direction {
+            Direction::Download => result.download = Some(phase),
+            Direction::Upload => result.upload = Some(phase),
+        }
+    }
+    Ok(result)
+}
+
+/// Adds load-generating connections in `direction` until goodput saturates, and probes
+/// until responsiveness is stable or `max_duration` has passed
+async fn run_phase(
+    version: HttpVersion,
+    urls: &RpmUrls,
+    direction: Direction,
+    max_duration: Duration,
+) -> Result<ResponsivenessPhase> {
+    status!("Measuring responsiveness under {direction} load for up to {max_duration:?}...");
+
+    let small_url =
+        Url::parse(&urls.small_https_download_url).context("Invalid URL of the small object")?;
+    let tls = matches!(version, HttpVersion::HTTP2)
+        .then(|| Arc::new(insecure_client_config(vec![b"h2".to_vec()])));
+
+    let progress_bar = create_progress_bar(
+        match direction {
+            Direction::Download => ProgressBarType::Download,
+            Direction::Upload => ProgressBarType::Upload,
+        },
+        max_duration,
+    );
+
+    let bytes = Arc::new(AtomicU64::new(0));
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut load = Vec::new();
+    let mut intervals: Vec<ProbeSet> = Vec::new();
+    let mut goodput = Vec::new();
+    let mut goodput_averages = Vec::new();
+    let mut rpm_averages = Vec::new();
+    let mut transferred = 0;
+    let mut stable = false;
+
+    let mut probe_timer = tokio::time::interval(PROBE_INTERVAL);
+    let start = Instant::now();
+    while start.elapsed() < max_duration {
+        if !is_stable(&goodput_averages) && load.len() < MAX_LOAD_CONNECTIONS {
+            load.push(LoadConnection::open(version, urls, direction, bytes.clone()).await?);
+        }
+
+        let index = intervals.len();
+        intervals.push(ProbeSet::default());
+        let interval_start = Instant::now();
+        while interval_start.elapsed() < INTERVAL {
+            probe_timer.tick().await;
+
+            let (tx_foreign, url, tls) = (tx.clone(), small_url.clone(), tls.clone());
+            tokio::spawn(async move {
+                match foreign_probe(version, &url, tls).await {
+                    Ok(probe) => {
+                        let _ = tx_foreign.send((index, probe));
+                    }
+                    Err(e) => trace!("Foreign probe failed: {e:#}"),
+                }
+            });
+
+            let connection: &LoadConnection = load.choose(&mut rng()).expect("load is not empty");
+            let (tx_self, client, url) = (tx.clone(), connection.client.clone(), small_url.clone());
+            tokio::spawn(async move {
+                match timed_get(&client, &url).await {
+                    Ok(http) => {
+                        let _ = tx_self.send((index, Probe::InConnection { http }));
+                    }
+                    Err(e) => trace!("Self probe failed: {e:#}"),
+                }
+            });
+        }
+
+        while let Ok((index, probe)) = rx.try_recv() {
+            intervals[index].add(probe);
+        }
+        let total = bytes.load(Ordering::Relaxed);
+        goodput.push((total - transferred) as f64 * 8.0 / interval_start.elapsed().as_secs_f64());
+        transferred = total;
+        goodput_averages.push(moving_average(&goodput));
+        if let Some(rpm) = ProbeSet::recent(&intervals).rpm() {
+            rpm_averages.push(rpm);
+        }
+
+        progress_bar.set_position(start.elapsed().as_secs());
+        progress_bar.set_message(format!(
+            "{} connections, {:.0} RPM",
+            load.len(),
+            rpm_averages.last().copied().unwrap_or_default()
+        ));
+
+        if is_stable(&goodput_averages) && is_stable(&rpm_averages) {
+            stable = true;
+            break;
+        }
+    }
+    let duration = start.elapsed();
+    let load_connections = load.len();
+    drop(load);
+    progress_bar.finish_with_message("Responsiveness measurement complete");
+
+    let probes = ProbeSet::recent(&intervals);
+    Ok(ResponsivenessPhase {
+        rpm: probes
+            .rpm()
+            .ok_or_eyre("No responsiveness probe completed")?,
+        goodput_bps: moving_average(&goodput),
+        load_connections,
+        stable,
+        duration,
+        tcp_connect_ms: trimmed_mean(&probes.tcp),
+        tls_handshake_ms: trimmed_mean(&probes.tls),
+        http_foreign_ms: trimmed_mean(&probes.http_foreign),
+        http_self_ms: trimmed_mean(&probes.http_self),
+        foreign_probes: probes.http_foreign.len(),
+        self_probes: probes.http_self.len(),
+    })
+}
+
+/// Times the TCP connect, TLS handshake and request/response of a new connection
+async fn foreign_probe(
+    version: HttpVersion,
+    url: &Url,
+    tls: Option<Arc<ClientConfig>>,
+) -> Result<Probe> {
+    if matches!(version, HttpVersion::HTTP3) {
+        return quic_foreign_probe(url).await;
+    }
+
+    let host = url.host_str().ok_or_eyre("URL has no host")?;
+    let port = url.port_or_known_default().ok_or_eyre("URL has no port")?;
+    let address = tokio::net::lookup_host((host.trim_matches(['[', ']']), port))
+        .await?
+        .next()
+        .ok_or_eyre("Host did not resolve")?;
+
+    let start = Instant::now();
+    let stream = TcpStream::connect(address).await?;
+    let tcp = elapsed_ms(start);
+    stream.set_nodelay(true)?;
+
+    match tls {
+        None => Ok(Probe::Foreign {
+            tcp: Some(tcp),
+            tls: None,
+            http: http2_round_trip(stream, url).await?,
+        }),
+        Some(tls) => {
+            let server_name = ServerName::try_from(host.trim_matches(['[', ']']).to_string())?;
+            let start = Instant::now();
+            let stream = TlsConnector::from(tls).connect(server_name, stream).await?;
+            let tls = elapsed_ms(start);
+            Ok(Probe::Foreign {
+                tcp: Some(tcp),
+                tls: Some(tls),
+                http: http2_round_trip(stream, url).await?,
+            })
+        }
+    }
+}
+
+/// The HTTP client does not expose the QUIC handshake, so it is estimated as the extra
+/// time the first request on a new connection takes over a second one
+async fn quic_foreign_probe(url: &Url) -> Result<Probe> {
+    let client = create_http_client(&HttpVersion::HTTP3).await?;
+    let first = timed_get(&client, url).await?;
+    let http = timed_get(&client, url).await?;
+    Ok(Probe::Foreign {
+        tcp: None,
+        tls: Some((first - http).max(0.0)),
+        http,
+    })
+}
+
+/// Times a single HTTP/2 request/response on a new connection over `stream`
+async fn http2_round_trip<S>(stream: S, url: &Url) -> Result<f64>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream)).await?;
+    tokio::spawn(connection);
+
+    let request = Request::get(url.as_str()).body(Empty::<Bytes>::new())?;
+    let start = Instant::now();
+    let response = sender.send_request(request).await?;
+    response.into_body().collect().await?;
+    Ok(elapsed_ms(start))
+}
+
+/// Times a GET of `url`, including reading the response body
+async fn timed_get(client: &Client, url: &Url) -> Result<f64> {
+    let start = Instant::now();
+    client
+        .get(url.clone())
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    Ok(elapsed_ms(start))
+}
+
+/// Downloads `url` over and over, counting the bytes received
+async fn keep_downloading(client: Client, url: String, bytes: Arc<AtomicU64>) {
+    loop {
+        let response = match client.get(&url).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                trace!("Load-generating download failed: {e}");
+                return;
+            }
+        };
+        let mut body = response.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            match chunk {
+                Ok(chunk) => {
+                    bytes.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                }
+                // Requests time out eventually, so start over with a new one
+                Err(e) if e.is_timeout() => break,
+                Err(e) => {
+                    trace!("Load-generating download failed: {e}");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Uploads an endless body to `url`, counting the bytes sent
+async fn keep_uploading(client: Client, url: String, bytes: Arc<AtomicU64>) {
+    let chunk = {
+        let mut data = vec![0u8; UPLOAD_CHUNK_SIZE];
+        rng().fill_bytes(&mut data);
+        Bytes::from(data)
+    };
+
+    loop {
+        let (bytes, chunk) = (bytes.clone(), chunk.clone());
+        let body = stream::repeat_with(move || {
+            bytes.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            Ok::<_, std::io::Error>(chunk.clone())
+        });
+        match client
+            .post(&url)
+            .body(reqwest::Body::wrap_stream(body))
+            .send()
+            .await
+        {
+            Err(e) if !e.is_timeout() => {
+                trace!("Load-generating upload failed: {e}");
+                return;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Mean of the fastest [`TRIMMED_MEAN_KEEP`] of `values`
+fn trimmed_mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let keep = (sorted.len() as f64 * TRIMMED_MEAN_KEEP).ceil() as usize;
+    Some(mean(&sorted[..keep]))
+}
+
+/// Mean of the last values the moving averages span
+fn moving_average(values: &[f64]) -> f64 {
+    mean(&values[values.len().saturating_sub(MOVING_AVERAGE_DISTANCE)..])
+}
+
+/// Returns whether the last moving averages deviate from the latest one by less than the
+/// stability tolerance
+fn is_stable(averages: &[f64]) -> bool {
+    let Some(window) = averages.last_chunk::<MOVING_AVERAGE_DISTANCE>() else {
+        return false;
+    };
+    let average = mean(window);
+    let variance = window
+        .iter()
+        .map(|value| (value - average).powi(2))
+        .sum::<f64>()
+        / window.len() as f64;
+    variance.sqrt() <= STABILITY_TOLERANCE * window[window.len() - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rpm_weights_foreign_and_self_probes() {
+        let mut probes = ProbeSet::default();
+        for _ in 0..19 {
+            probes.add(Probe::Foreign {
+                tcp: Some(10.0),
+                tls: Some(20.0),
+                http: 30.0,
+            });
+            probes.add(Probe::InConnection { http: 40.0 });
+        }
+        // Outliers beyond the fastest 95% are trimmed
+        probes.add(Probe::InConnection { http: 4000.0 });
+
+        // (mean(10, 20, 30) + 40) / 2 = 30 ms per round trip
+        assert_eq!(probes.rpm(), Some(2000.0));
+        assert_eq!(trimmed_mean(&probes.http_self), Some(40.0));
+        assert_eq!(ProbeSet::default().rpm(), None);
+    }
+
+    #[test]
+    fn test_stability_needs_a_full_window() {
+        assert!(!is_stable(&[100.0, 100.0, 100.0]));
+        assert!(is_stable(&[10.0, 100.0, 101.0, 99.0, 100.0]));
+        assert!(!is_stable(&[50.0, 80.0, 100.0, 120.0]));
+    }
+}
@@ -1,25 +1,31 @@
 use axum::{
     Json, Router,
     body::Body,
-    extract::{DefaultBodyLimit, Query},
+    extract::{DefaultBodyLimit, Query, Request, State},
     http::{Method, StatusCode, header},
+    middleware::{self, Next},
     response::{IntoResponse, Response},
     routing::{get, post},
 };
 use axum_server::tls_rustls::RustlsConfig;
 use bytes::Bytes;
+use chrono::Utc;
 use eyre::Result;
 use futures::StreamExt as _;
 use futures::stream;
 use rustls::crypto::{CryptoProvider, aws_lc_rs};
 use serde::{Deserialize, Serialize};
 use std::sync::LazyLock as SyncLazy;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
 use std::{net::SocketAddr, path::PathBuf, sync::Arc, sync::Once};
 use tower_http::cors::{Any, CorsLayer};
 
+use crate::performance::http::{RpmConfig, RpmUrls, UploadReceipt};
+use crate::performance::metrics::{ServerCounters, ServerMetrics};
 use crate::utils::tls::get_self_signed_cert;
 
-use crate::constants::DEFAULT_CHUNK_SIZE;
+use crate::constants::{DEFAULT_CHUNK_SIZE, RPM_LARGE_DOWNLOAD_SIZE};
 
 /// Static buffer for download operations to avoid allocations
 static ZERO_BUFFER: SyncLazy<Arc<Bytes>> = SyncLazy::new(|| {
@@ -35,6 +41,35 @@ fn ensure_crypto_provider() {
     });
 }
 
+/// Server metrics for monitoring
+#[derive(Debug, Default)]
+pub struct HttpServerMetrics {
+    pub total_requests: AtomicU64,
+    pub active_requests: AtomicUsize,
+    pub total_bytes_received: AtomicU64,
+    pub total_bytes_sent: AtomicU64,
+    /// Requests that failed or were answered with an error status
+    pub request_errors: AtomicU64,
+}
+
+impl HttpServerMetrics {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+}
+
+impl ServerMetrics for HttpServerMetrics {
+    fn counters(&self) -> ServerCounters {
+        ServerCounters {
+            total_connections: self.total_requests.load(Ordering::Relaxed),
+            active_connections: self.active_requests.load(Ordering::Relaxed) as u64,
+            bytes_received: self.total_bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.total_bytes_sent.load(Ordering::Relaxed),
+            errors: self.request_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct HttpServerConfig {
     /// Bind address
@@ -43,6 +78,8 @@ pub struct HttpServerConfig {
     pub enable_cors: bool,
     /// Max upload size in bytes
     pub max_upload_size: usize,
+    /// Metrics updated by the server
+    pub metrics: Arc<HttpServerMetrics>,
 }
 
 #[derive(Debug, Clone)]
@@ -53,6 +90,8 @@ pub struct HttpsServerConfig {
     pub enable_cors: bool,
     /// Max upload size in bytes
     pub max_upload_size: usize,
+    /// Metrics updated by the server
+    pub metrics: Arc<HttpServerMetrics>,
 
     /// TLS config
     /// If not provided, a self-signed certificate will be generated
@@ -69,7 +108,12 @@ pub struct TlsConfig {
 
 /// Runs the HTTP server.
 pub async fn run_http_server(config: HttpServerConfig) -> Result<()> {
-    let app = create_router(config.enable_cors, config.max_upload_size);
+    let app = create_router(
+        "http",
+        config.enable_cors,
+        config.max_upload_size,
+        config.metrics,
+    );
 
     tracing::info!("HTTP server listening on {}", config.bind_addr);
     let listener = tokio::net::TcpListener::bind(config.bind_addr).await?;
@@ -84,7 +128,12 @@ pub async fn run_https_server(config: HttpsServerConfig) -> Result<()> {
     // Ensure crypto provider is initialized before using TLS
     ensure_crypto_provider();
 
-    let app = create_router(config.enable_cors, config.max_upload_size);
+    let app = create_router(
+        "https",
+        config.enable_cors,
+        config.max_upload_size,
+        config.metrics,
+    );
     let tls_config = match config.tls_config {
         Some(tls_config) => {
             RustlsConfig::from_pem_file(tls_config.cert_path, tls_config.key_path).await?
@@ -102,14 +151,31 @@ pub async fn run_https_server(config: HttpsServerConfig) -> Result<()> {
     Ok(())
 }
 
-fn create_router(enable_cors: bool, max_upload_size: usize) -> Router {
+fn create_router(
+    scheme: &'static str,
+    enable_cors: bool,
+    max_upload_size: usize,
+    metrics: Arc<HttpServerMetrics>,
+) -> Router {
     let mut router = Router::new()
         .route("/download", get(download_handler))
         .route("/upload", post(upload_handler))
         .route("/latency", get(latency_handler).head(latency_handler))
         .route("/info", get(info_handler))
         .route("/health", get(health_handler))
-        .layer(DefaultBodyLimit::max(max_upload_size));
+        .route(
+            "/rpm/config",
+            get(move |request: Request| rpm_config_handler(scheme, request)),
+        )
+        .route("/rpm/small", get(rpm_small_handler))
+        .route("/rpm/large", get(rpm_large_handler))
+        .route("/rpm/slurp", post(upload_handler).put(upload_handler))
+        .layer(DefaultBodyLimit::max(max_upload_size))
+        .layer(middleware::from_fn_with_state(
+            metrics.clone(),
+            track_requests,
+        ))
+        .with_state(metrics);
 
     if enable_cors {
         router = router.layer(
@@ -142,6 +208,24 @@ fn create_router(enable_cors: bool, max_upload_size: usize) -> Router {
     router
 }
 
+/// Counts requests in the server metrics
+async fn track_requests(
+    State(metrics): State<Arc<HttpServerMetrics>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    metrics.total_requests.fetch_add(1, Ordering::Relaxed);
+    metrics.active_requests.fetch_add(1, Ordering::Relaxed);
+
+    let response = next.run(request).await;
+
+    metrics.active_requests.fetch_sub(1, Ordering::Relaxed);
+    if response.status().is_client_error() || response.status().is_server_error() {
+        metrics.request_errors.fetch_add(1, Ordering::Relaxed);
+    }
+    response
+}
+
 #[derive(Deserialize)]
 struct DownloadQuery {
     size: usize,
@@ -153,10 +237,20 @@ fn default_chunk_size() -> usize {
     DEFAULT_CHUNK_SIZE
 }
 
-async fn download_handler(Query(query): Query<DownloadQuery>) -> impl IntoResponse {
+async fn download_handler(
+    State(metrics): State<Arc<HttpServerMetrics>>,
+    Query(query): Query<DownloadQuery>,
+) -> impl IntoResponse {
+    zeros_response(metrics, query.size, query.chunk_size)
+}
+
+/// Streams `total_size` zero bytes in chunks of `chunk_size`
+fn zeros_response(
+    metrics: Arc<HttpServerMetrics>,
+    total_size: usize,
+    chunk_size: usize,
+) -> Response {
     // Use the static buffer to avoid allocations
-    let total_size = query.size;
-    let chunk_size = query.chunk_size;
     let chunks = total_size.div_ceil(chunk_size); // Round up division
 
     let buffer_ref = Arc::clone(&ZERO_BUFFER);
@@ -166,6 +260,9 @@ async fn download_handler(Query(query): Query<DownloadQuery>) -> impl IntoRespon
         let bytes_sent = i * chunk_size;
         let remaining_bytes = total_size.saturating_sub(bytes_sent);
         let current_chunk_size = chunk_size.min(remaining_bytes);
+        metrics
+            .total_bytes_sent
+            .fetch_add(current_chunk_size as u64, Ordering::Relaxed);
 
         // If the chunk size is larger than our buffer, we need to repeat the buffer
         if current_chunk_size <= buffer_ref.len() {
@@ -189,30 +286,83 @@ async fn download_handler(Query(query): Query<DownloadQuery>) -> impl IntoRespon
     Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "application/octet-stream")
-        .header(header::CONTENT_LENGTH, query.size.to_string())
+        .header(header::CONTENT_LENGTH, total_size.to_string())
         .body(body)
         .unwrap()
 }
 
-async fn upload_handler(body: Body) -> impl IntoResponse {
+async fn upload_handler(
+    State(metrics): State<Arc<HttpServerMetrics>>,
+    body: Body,
+) -> impl IntoResponse {
+    let start = Instant::now();
+    let started_at = Utc::now();
     let mut body_reader = body.into_data_stream();
     let mut total_bytes = 0;
     while let Some(chunk) = body_reader.next().await {
         match chunk {
             Ok(data) => {
                 total_bytes += data.len();
+                metrics
+                    .total_bytes_received
+                    .fetch_add(data.len() as u64, Ordering::Relaxed);
                 // Immediately drop data to minimize memory pressure
                 drop(data); // Explicit but just in case
             }
-            Err(_) => break,
+            Err(_) => {
+                metrics.request_errors.fetch_add(1, Ordering::Relaxed);
+                break;
+            }
         }
     }
     (
         StatusCode::OK,
-        Json(serde_json::json!({ "bytes_received": total_bytes })),
+        Json(UploadReceipt {
+            bytes_received: total_bytes as u64,
+            duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+            started_at_us: Some(started_at.timestamp_micros()),
+            finished_at_us: Some(Utc::now().timestamp_micros()),
+        }),
     )
 }
 
+/// Serves the configuration of responsiveness tests, pointing at this server as it was
+/// addressed by the client
+async fn rpm_config_handler(scheme: &'static str, request: Request) -> Response {
+    let authority = request
+        .uri()
+        .authority()
+        .map(|authority| authority.to_string())
+        .or_else(|| {
+            let host = request.headers().get(header::HOST)?;
+            host.to_str().ok().map(str::to_string)
+        });
+    let Some(authority) = authority else {
+        return (StatusCode::BAD_REQUEST, "Missing Host header").into_response();
+    };
+
+    let base_url = format!("{scheme}://{authority}/rpm");
+    let config = RpmConfig {
+        version: 1,
+        urls: RpmUrls {
+            small_https_download_url: format!("{base_url}/small"),
+            large_https_download_url: format!("{base_url}/large"),
+            https_upload_url: format!("{base_url}/slurp"),
+        },
+    };
+    (StatusCode::OK, Json(config)).into_response()
+}
+
+/// Serves the small object fetched by the probes of responsiveness tests
+async fn rpm_small_handler(State(metrics): State<Arc<HttpServerMetrics>>) -> Response {
+    zeros_response(metrics, 1, DEFAULT_CHUNK_SIZE)
+}
+
+/// Serves a download that outlasts any responsiveness test
+async fn rpm_large_handler(State(metrics): State<Arc<HttpServerMetrics>>) -> Response {
+    zeros_response(metrics, RPM_LARGE_DOWNLOAD_SIZE, DEFAULT_CHUNK_SIZE)
+}
+
 async fn latency_handler() -> impl IntoResponse {
     (StatusCode::OK, "OK")
 }
@@ -228,7 +378,17 @@ async fn info_handler() -> impl IntoResponse {
     let info = ServerInfo {
         server_name: "Rust Hyper/Axum Server".to_string(),
         version: "1.0.0".to_string(),
-        available_endpoints: vec!["/download", "/upload", "/latency", "/info", "/health"],
+        available_endpoints: vec![
+            "/download",
+            "/upload",
+            "/latency",
+            "/info",
+            "/health",
+            "/rpm/config",
+            "/rpm/small",
+            "/rpm/large",
+            "/rpm/slurp",
+        ],
     };
     (StatusCode::OK, Json(info))
 }
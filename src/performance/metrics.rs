@@ -0,0 +1,157 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::{Router, extract::State, http::header, response::IntoResponse, routing::get};
+use eyre::Result;
+use tracing::info;
+
+use crate::utils::prometheus::{MetricType, write_header, write_sample};
+
+/// Point-in-time values of the counters every server keeps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServerCounters {
+    /// Connections (TCP), clients (UDP) or requests (HTTP) handled since startup
+    pub total_connections: u64,
+    /// Connections, clients or requests currently being handled
+    pub active_connections: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub errors: u64,
+}
+
+/// Implemented by the metrics of each server so they can be exposed together
+pub trait ServerMetrics: Send + Sync {
+    fn counters(&self) -> ServerCounters;
+}
+
+/// Name, help, type and value of a metric family derived from the server counters
+type CounterFamily = (
+    &'static str,
+    &'static str,
+    MetricType,
+    fn(&ServerCounters) -> u64,
+);
+
+/// Metrics of all servers running in this process, labeled by protocol
+#[derive(Default, Clone)]
+pub struct MetricsRegistry {
+    servers: Vec<(&'static str, Arc<dyn ServerMetrics>)>,
+}
+
+impl MetricsRegistry {
+    pub fn register(&mut self, protocol: &'static str, metrics: Arc<dyn ServerMetrics>) {
+        self.servers.push((protocol, metrics));
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let counters: Vec<_> = self
+            .servers
+            .iter()
+            .map(|(protocol, metrics)| (*protocol, metrics.counters()))
+            .collect();
+
+        let families: [CounterFamily; 5] = [
+            (
+                "speed_cli_server_connections_total",
+                "Connections (TCP), clients (UDP) or requests (HTTP) handled since startup",
+                MetricType::Counter,
+                |c| c.total_connections,
+            ),
+            (
+                "speed_cli_server_active_connections",
+                "Connections, clients or requests currently being handled",
+                MetricType::Gauge,
+                |c| c.active_connections,
+            ),
+            (
+                "speed_cli_server_received_bytes_total",
+                "Bytes received from clients since startup",
+                MetricType::Counter,
+                |c| c.bytes_received,
+            ),
+            (
+                "speed_cli_server_sent_bytes_total",
+                "Bytes sent to clients since startup",
+                MetricType::Counter,
+                |c| c.bytes_sent,
+            ),
+            (
+                "speed_cli_server_errors_total",
+                "Connection, packet or request errors since startup",
+                MetricType::Counter,
+                |c| c.errors,
+            ),
+        ];
+
+        let mut buffer = Vec::new();
+        for (name, help, metric_type, value) in families {
+            // Writing to a Vec can't fail
+            let _ = write_header(&mut buffer, name, help, metric_type);
+            for (protocol, counters) in &counters {
+                let _ = write_sample(
+                    &mut buffer,
+                    name,
+                    &[("protocol", protocol)],
+                    value(counters) as f64,
+                );
+            }
+        }
+
+        String::from_utf8(buffer).expect("metrics should be valid UTF-8")
+    }
+}
+
+/// Serves the metrics of all servers at `/metrics` for Prometheus to scrape
+pub async fn run_metrics_server(addr: SocketAddr, registry: MetricsRegistry) -> Result<()> {
+    let app = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .with_state(Arc::new(registry));
+
+    info!("Metrics server listening on {}", addr);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+async fn metrics_handler(State(registry): State<Arc<MetricsRegistry>>) -> impl IntoResponse {
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        registry.render(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedMetrics(ServerCounters);
+
+    impl ServerMetrics for FixedMetrics {
+        fn counters(&self) -> ServerCounters {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_render_labels_protocols() {
+        let mut registry = MetricsRegistry::default();
+        registry.register(
+            "tcp",
+            Arc::new(FixedMetrics(ServerCounters {
+                bytes_received: 1024,
+                ..Default::default()
+            })),
+        );
+        registry.register("udp", Arc::new(FixedMetrics(ServerCounters::default())));
+
+        let text = registry.render();
+        assert!(text.contains("# TYPE speed_cli_server_received_bytes_total counter\n"));
+        assert!(text.contains("speed_cli_server_received_bytes_total{protocol=\"tcp\"} 1024\n"));
+        assert!(text.contains("speed_cli_server_received_bytes_total{protocol=\"udp\"} 0\n"));
+    }
+}
@@ -1,3 +1,54 @@
 pub mod http;
+pub mod metrics;
+pub mod pacing;
 pub mod tcp;
 pub mod udp;
+
+use std::time::Duration;
+
+use eyre::Result;
+use indexmap::IndexSet;
+
+use crate::constants::MAX_IDLE_LATENCY_DURATION;
+use crate::report::{TestConfig, TestReport, ThroughputTotals};
+
+/// Runs the client test described by `config` against its server
+pub async fn run_client_test(config: TestConfig) -> Result<TestReport> {
+    match config {
+        TestConfig::Tcp(config) => tcp::client::run_tcp_client(config).await,
+        TestConfig::Udp(config) => udp::client::run_udp_client(config).await,
+        TestConfig::Http(config) => http::client::run_http_test(config).await,
+    }
+}
+
+/// Fails a throughput test that transferred no data at all, which means the server couldn't
+/// be reached rather than that the link runs at 0 bits/s. `error` is the first failure seen.
+pub fn ensure_data_transferred(
+    test: &str,
+    totals: &ThroughputTotals,
+    error: Option<eyre::Report>,
+) -> Result<()> {
+    if totals.bytes > 0 {
+        return Ok(());
+    }
+
+    match error {
+        Some(error) => Err(eyre::eyre!("{test} transferred no data: {error}")),
+        None => Err(eyre::eyre!("{test} transferred no data")),
+    }
+}
+
+/// Returns how long to measure idle latency for in a latency-under-load test of `duration`
+pub fn idle_latency_duration(duration: Duration) -> Duration {
+    duration.min(MAX_IDLE_LATENCY_DURATION)
+}
+
+/// Returns the payload size that loads the link in a latency-under-load test: the largest
+/// of the configured sizes, since it saturates the link most easily
+pub fn load_payload_size(payload_sizes: &IndexSet<usize>) -> Result<usize> {
+    payload_sizes
+        .iter()
+        .max()
+        .copied()
+        .ok_or_else(|| eyre::eyre!("A latency-under-load test needs a payload size"))
+}
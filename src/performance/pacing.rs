@@ -0,0 +1,217 @@
+use parking_lot::Mutex;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::time::{Sleep, sleep};
+
+use crate::utils::types::{BitrateMode, TargetBitrate};
+
+/// Token bucket controlling the rate at which data is sent. Tokens are bytes that
+/// accumulate at the sending rate up to the burst size. A send may overdraw the bucket,
+/// and the next one waits until the debt is repaid, so a burst size of zero spaces out
+/// sends evenly.
+#[derive(Debug)]
+pub struct Pacer {
+    /// Target sending rate in bytes per second
+    sending_rate: f64,
+    /// Most bytes that can be sent back to back
+    burst: f64,
+    /// Tokens in the bucket as of `last_update`. Negative while in debt.
+    tokens: f64,
+    /// Time of the last scheduled send, which may lie in the future
+    last_update: Option<Instant>,
+}
+
+impl Pacer {
+    pub fn new(initial_rate: f64) -> Self {
+        Self::with_burst(initial_rate, 0)
+    }
+
+    /// Creates a pacer that lets up to `burst` bytes through back to back
+    pub fn with_burst(initial_rate: f64, burst: usize) -> Self {
+        Self {
+            sending_rate: initial_rate.max(1000.0), // Minimum 1KB/s
+            burst: burst as f64,
+            tokens: 0.0,
+            last_update: None,
+        }
+    }
+
+    /// Update the target sending rate
+    pub fn update_rate(&mut self, rate: f64) {
+        self.sending_rate = rate.max(1000.0);
+    }
+
+    /// Takes `packet_size` bytes from the bucket and returns how long to wait before
+    /// sending them, if at all
+    pub fn schedule_next_send(&mut self, packet_size: usize) -> Option<Duration> {
+        let now = Instant::now();
+
+        let (tokens, available_at) = match self.last_update {
+            // A full bucket for the first packet
+            None => (self.burst, now),
+            Some(last) if now >= last => {
+                let refill = (now - last).as_secs_f64() * self.sending_rate;
+                ((self.tokens + refill).min(self.burst), now)
+            }
+            // Earlier sends are still waiting for their turn
+            Some(last) => (self.tokens, last),
+        };
+
+        // Wait until any debt is repaid
+        let send_time = if tokens < 0.0 {
+            available_at + Duration::from_secs_f64(-tokens / self.sending_rate)
+        } else {
+            available_at
+        };
+        self.tokens = tokens.max(0.0) - packet_size as f64;
+        self.last_update = Some(send_time);
+
+        (send_time > now).then(|| send_time - now)
+    }
+
+    /// Waits until `packet_size` bytes may be sent
+    pub async fn wait(&mut self, packet_size: usize) {
+        PacedSend::new(self.schedule_next_send(packet_size)).await;
+    }
+
+    /// Get the current sending rate
+    #[allow(dead_code)]
+    pub fn get_rate(&self) -> f64 {
+        self.sending_rate
+    }
+}
+
+/// Pacer shared by the tasks sending through it, e.g. the connections of an upload
+/// limited to an aggregate bitrate
+#[derive(Debug, Clone)]
+pub struct SharedPacer(Arc<Mutex<Pacer>>);
+
+impl SharedPacer {
+    pub fn new(pacer: Pacer) -> Self {
+        Self(Arc::new(Mutex::new(pacer)))
+    }
+
+    /// Waits until `packet_size` bytes may be sent
+    pub async fn wait(&self, packet_size: usize) {
+        let wait = self.0.lock().schedule_next_send(packet_size);
+        PacedSend::new(wait).await;
+    }
+}
+
+impl TargetBitrate {
+    /// Returns the pacer of each of `connections` connections: the same one for all of them
+    /// if the bitrate is an aggregate, or one each otherwise
+    pub fn pacers(&self, connections: usize) -> Vec<SharedPacer> {
+        let pacer = || SharedPacer::new(Pacer::new(self.bitrate.0 / 8.0));
+        match self.mode {
+            BitrateMode::Aggregate => vec![pacer(); connections],
+            BitrateMode::PerConnection => (0..connections).map(|_| pacer()).collect(),
+        }
+    }
+}
+
+/// Returns the pacer of each of `connections` connections, if `bitrate` limits them
+pub fn connection_pacers(
+    bitrate: Option<TargetBitrate>,
+    connections: usize,
+) -> Vec<Option<SharedPacer>> {
+    match bitrate {
+        Some(bitrate) => bitrate.pacers(connections).into_iter().map(Some).collect(),
+        None => vec![None; connections],
+    }
+}
+
+/// Future that resolves when it's time to send the next packet
+pub struct PacedSend {
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl PacedSend {
+    pub fn new(wait_duration: Option<Duration>) -> Self {
+        Self {
+            sleep: wait_duration.map(|d| Box::pin(sleep(d))),
+        }
+    }
+}
+
+impl Future for PacedSend {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.sleep.as_mut() {
+            Some(sleep) => sleep.as_mut().poll(cx),
+            None => Poll::Ready(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pacer_initialization() {
+        let pacer = Pacer::new(10000.0); // 10KB/s
+        assert_eq!(pacer.get_rate(), 10000.0);
+    }
+
+    #[test]
+    fn test_pacer_rate_update() {
+        let mut pacer = Pacer::new(10000.0);
+        pacer.update_rate(20000.0);
+        assert_eq!(pacer.get_rate(), 20000.0);
+    }
+
+    #[test]
+    fn test_pacer_minimum_rate() {
+        let pacer = Pacer::new(100.0); // Below minimum
+        assert_eq!(pacer.get_rate(), 1000.0); // Should be clamped to minimum
+    }
+
+    #[test]
+    fn test_pacer_token_bucket() {
+        // 1 MB/s with room for two 1000 byte packets back to back
+        let mut pacer = Pacer::with_burst(1_000_000.0, 2000);
+        assert_eq!(pacer.schedule_next_send(1000), None);
+        assert_eq!(pacer.schedule_next_send(1000), None);
+        assert_eq!(pacer.schedule_next_send(1000), None);
+
+        // The third packet overdrew the bucket, so the next ones are spaced out
+        let wait = pacer.schedule_next_send(1000).unwrap();
+        assert!(wait <= Duration::from_millis(1) && wait > Duration::from_micros(900));
+        let wait = pacer.schedule_next_send(1000).unwrap();
+        assert!(wait <= Duration::from_millis(2) && wait > Duration::from_micros(1900));
+    }
+
+    #[test]
+    fn test_aggregate_pacers_are_shared() {
+        let bitrate = TargetBitrate {
+            bitrate: "8M".parse().unwrap(),
+            mode: BitrateMode::Aggregate,
+        };
+        let pacers = bitrate.pacers(2);
+        assert!(Arc::ptr_eq(&pacers[0].0, &pacers[1].0));
+
+        let bitrate = TargetBitrate {
+            mode: BitrateMode::PerConnection,
+            ..bitrate
+        };
+        let pacers = bitrate.pacers(2);
+        assert!(!Arc::ptr_eq(&pacers[0].0, &pacers[1].0));
+        assert_eq!(pacers[0].0.lock().get_rate(), 1_000_000.0);
+    }
+
+    #[tokio::test]
+    async fn test_paced_send() {
+        let paced_send = PacedSend::new(Some(Duration::from_millis(1)));
+        let start = Instant::now();
+        paced_send.await;
+        let elapsed = start.elapsed();
+
+        // Should have waited at least 1ms (with some tolerance for timing)
+        assert!(elapsed >= Duration::from_millis(1));
+    }
+}
@@ -6,27 +6,43 @@ use rand::{prelude::*, rng};
 use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
+use tokio::sync::mpsc::UnboundedSender;
 use tokio::time::sleep;
 use tracing::trace;
 
+use super::protocol::{self, HandshakeError, TcpTestKind, TestRequest};
 use crate::{
-    TestType,
+    TargetBitrate, TestType,
+    performance::{
+        ensure_data_transferred, idle_latency_duration, load_payload_size,
+        pacing::connection_pacers,
+    },
     report::{
-        ConnectionError, LatencyMeasurement, LatencyResult, NetworkTestResult, TcpTestConfig,
-        TestReport, ThroughputMeasurement, ThroughputResult,
+        ConnectionError, LatencyMeasurement, LatencyResult, LoadedLatencyResult, NetworkTestResult,
+        ServerMeasurement, TcpTestConfig, TestConfig, TestReport, ThroughputMeasurement,
+        ThroughputResult, ThroughputTotals,
     },
     utils::{
         format::format_bytes,
         instrumentation::{
-            LatencyStatsCollector, ProgressBarType, ThroughputStatsCollector, create_progress_bar,
+            LatencyStatsCollector, ProgressBarType, ThroughputEvent, ThroughputStatsCollector,
+            create_progress_bar,
         },
+        output::{StreamEvent, TestPhase, emit, status},
+        socket::tcp_total_retransmits,
     },
 };
 
+/// How often upload connections are checked for new retransmissions
+const RETRANSMIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Longest a download connection waits for the server to close it after the test duration
+const DOWNLOAD_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
 pub async fn run_tcp_client(config: TcpTestConfig) -> Result<TestReport> {
     let server_addr = format!("{}:{}", config.server, config.port);
 
-    println!(
+    status!(
         "{}",
         format!("Starting TCP test to server {}...", server_addr.cyan())
             .green()
@@ -34,68 +50,120 @@ pub async fn run_tcp_client(config: TcpTestConfig) -> Result<TestReport> {
     );
 
     let start_time = Utc::now();
+    emit(&StreamEvent::TestStarted {
+        start_time,
+        config: &TestConfig::from(config.clone()),
+    });
 
     let mut result = NetworkTestResult::new_tcp();
 
     match config.test_type {
+        TestType::Responsiveness => {
+            eyre::bail!("Responsiveness tests require HTTP/2 or HTTP/3");
+        }
         TestType::LatencyOnly => {
-            result.latency = measure_tcp_latency(&config).await?;
+            result.latency =
+                measure_tcp_latency(&config.server, config.port, config.duration).await?;
+        }
+        TestType::LatencyUnderLoad => {
+            let payload_size = load_payload_size(&config.payload_sizes)?;
+            result.latency = measure_tcp_latency(
+                &config.server,
+                config.port,
+                idle_latency_duration(config.duration),
+            )
+            .await?;
+
+            status!("Measuring TCP latency under download load...");
+            let (latency, download) = tokio::join!(
+                measure_tcp_latency(&config.server, config.port, config.duration),
+                run_download_test(
+                    &config.server,
+                    config.port,
+                    config.parallel_connections,
+                    payload_size,
+                    config.duration,
+                    config.interval,
+                )
+            );
+            let mut loaded = LoadedLatencyResult {
+                download: latency?,
+                upload: None,
+            };
+            let (download, server) = download?;
+            result.add_download(payload_size, download, server);
+
+            status!("Measuring TCP latency under upload load...");
+            let (latency, upload) = tokio::join!(
+                measure_tcp_latency(&config.server, config.port, config.duration),
+                run_upload_test(
+                    &config.server,
+                    config.port,
+                    config.parallel_connections,
+                    payload_size,
+                    config.duration,
+                    config.interval,
+                    config.bitrate,
+                )
+            );
+            loaded.upload = latency?;
+            let (upload, server) = upload?;
+            result.add_upload(payload_size, upload, server);
+            result.loaded_latency = Some(loaded);
         }
         TestType::Download => {
             for payload_size in &config.payload_sizes {
-                result.download.insert(
+                let (download, server) = run_download_test(
+                    &config.server,
+                    config.port,
+                    config.parallel_connections,
                     *payload_size,
-                    run_download_test(
-                        &config.server,
-                        config.port,
-                        config.parallel_connections,
-                        *payload_size,
-                        config.duration,
-                    )
-                    .await?,
-                );
+                    config.duration,
+                    config.interval,
+                )
+                .await?;
+                result.add_download(*payload_size, download, server);
             }
         }
         TestType::Upload => {
             for payload_size in &config.payload_sizes {
-                result.upload.insert(
+                let (upload, server) = run_upload_test(
+                    &config.server,
+                    config.port,
+                    config.parallel_connections,
                     *payload_size,
-                    run_upload_test(
-                        &config.server,
-                        config.port,
-                        config.parallel_connections,
-                        *payload_size,
-                        config.duration,
-                    )
-                    .await?,
-                );
+                    config.duration,
+                    config.interval,
+                    config.bitrate,
+                )
+                .await?;
+                result.add_upload(*payload_size, upload, server);
             }
         }
         TestType::Bidirectional => {
             // Run download and upload sequentially
             for payload_size in &config.payload_sizes {
-                result.download.insert(
+                let (download, server) = run_download_test(
+                    &config.server,
+                    config.port,
+                    config.parallel_connections,
                     *payload_size,
-                    run_download_test(
-                        &config.server,
-                        config.port,
-                        config.parallel_connections,
-                        *payload_size,
-                        config.duration,
-                    )
-                    .await?,
-                );
-                result.upload.insert(
+                    config.duration,
+                    config.interval,
+                )
+                .await?;
+                result.add_download(*payload_size, download, server);
+                let (upload, server) = run_upload_test(
+                    &config.server,
+                    config.port,
+                    config.parallel_connections,
                     *payload_size,
-                    run_upload_test(
-                        &config.server,
-                        config.port,
-                        config.parallel_connections,
-                        *payload_size,
-                        config.duration,
-                    )
-                    .await?,
-                );
+                    config.duration,
+                    config.interval,
+                    config.bitrate,
+                )
+                .await?;
+                result.add_upload(*payload_size, upload, server);
             }
         }
         TestType::Simultaneous => {
@@ -108,6 +176,7 @@ pub async fn run_tcp_client(config: TcpTestConfig) -> Result<TestReport> {
                         config.parallel_connections,
                         *payload_size,
                         config.duration,
+                        config.interval,
                     ),
                     run_upload_test(
                         &config.server,
@@ -115,11 +184,15 @@ pub async fn run_tcp_client(config: TcpTestConfig) -> Result<TestReport> {
                         config.parallel_connections,
                         *payload_size,
                         config.duration,
+                        config.interval,
+                        config.bitrate,
                     )
                 );
 
-                result.download.insert(*payload_size, download_result?);
-                result.upload.insert(*payload_size, upload_result?);
+                let (download, download_server) = download_result?;
+                let (upload, upload_server) = upload_result?;
+                result.add_download(*payload_size, download, download_server);
+                result.add_upload(*payload_size, upload, upload_server);
             }
         }
     }
@@ -128,12 +201,15 @@ pub async fn run_tcp_client(config: TcpTestConfig) -> Result<TestReport> {
 }
 
 /// Measure TCP latency by establishing connections and measuring round-trip time
-async fn measure_tcp_latency(config: &TcpTestConfig) -> Result<Option<LatencyResult>> {
-    let addr = format!("{}:{}", config.server, config.port);
-    let duration = config.duration;
+async fn measure_tcp_latency(
+    server: &str,
+    port: u16,
+    duration: Duration,
+) -> Result<Option<LatencyResult>> {
+    let addr = format!("{server}:{port}");
     let mut measurements = Vec::new();
 
-    println!("Measuring TCP latency for {duration:?}...");
+    status!("Measuring TCP latency for {duration:?}...");
 
     // Create progress bar for latency measurement
     let progress_bar = create_progress_bar(ProgressBarType::Latency, duration);
@@ -143,6 +219,7 @@ async fn measure_tcp_latency(config: &TcpTestConfig) -> Result<Option<LatencyRes
     // Set up instrumentation
     let (stats_collector, tx) = LatencyStatsCollector::new(progress_bar.clone(), start, duration);
 
+    let mut last_error = None;
     while start.elapsed() < duration {
         let connect_start = Instant::now();
         match TcpStream::connect(&addr).await {
@@ -171,6 +248,7 @@ async fn measure_tcp_latency(config: &TcpTestConfig) -> Result<Option<LatencyRes
                 let _ = tx.send(measurement);
 
                 trace!("TCP connection error while measuring latency: {e}");
+                last_error = Some(e);
             }
         }
 
@@ -189,6 +267,11 @@ async fn measure_tcp_latency(config: &TcpTestConfig) -> Result<Option<LatencyRes
     if measurements.is_empty() {
         return Ok(None);
     }
+    if let Some(e) = last_error
+        && measurements.iter().all(|m| m.rtt_ms.is_none())
+    {
+        eyre::bail!("TCP latency test could not connect to {addr}: {e}");
+    }
 
     Ok(Some(LatencyResult {
         measurements,
@@ -202,8 +285,9 @@ async fn run_download_test(
     parallel_connections: usize,
     payload_size: usize,
     duration: Duration,
-) -> Result<ThroughputResult> {
-    println!(
+    interval: Duration,
+) -> Result<(ThroughputResult, Option<ServerMeasurement>)> {
+    status!(
         "Starting TCP download test with {} payload size and {} parallel connections...",
         format_bytes(payload_size).yellow(),
         parallel_connections.to_string().yellow()
@@ -212,12 +296,24 @@ async fn run_download_test(
     // Create progress bar
     let progress_bar = create_progress_bar(ProgressBarType::Download, duration);
 
-    let mut measurements = Vec::new();
     let start_time = Instant::now();
 
     // Set up instrumentation
-    let (stats_collector, tx) =
-        ThroughputStatsCollector::new(progress_bar.clone(), start_time, duration);
+    let (stats_collector, tx) = ThroughputStatsCollector::new(
+        progress_bar.clone(),
+        TestPhase::Download { payload_size },
+        start_time,
+        duration,
+        interval,
+    );
+
+    let request = TestRequest {
+        test_id: rng().next_u64(),
+        kind: TcpTestKind::Download,
+        duration,
+        payload_size: u32::try_from(payload_size)
+            .map_err(|_| HandshakeError::OutOfRange("payload size"))?,
+    };
 
     let mut tasks = Vec::new();
 
@@ -227,53 +323,86 @@ async fn run_download_test(
 
         let task = tokio::spawn(async move {
             let addr = format!("{server}:{port}");
-            let mut local_measurements = Vec::new();
 
             match TcpStream::connect(&addr).await {
                 Ok(mut stream) => {
-                    // Send download command
-                    if let Err(e) = stream.write_all(b"D").await {
-                        eprintln!("Failed to send download command on connection {i}: {e}");
-                        return local_measurements;
+                    // Negotiate the test with the server
+                    if let Err(e) = protocol::client_handshake(&mut stream, &request).await {
+                        eprintln!("TCP handshake failed on connection {i}: {e}");
+                        let measurement = ThroughputMeasurement::new_error(
+                            ConnectionError::ConnectionFailed(format!("Handshake failed: {e}")),
+                            start_time.elapsed(),
+                            0,
+                        );
+                        let _ = tx.send(ThroughputEvent::Measurement {
+                            connection: i,
+                            offset: start_time.elapsed(),
+                            measurement,
+                        });
+                        return Err(e.into());
                     }
 
-                    // Give the server a moment to process the command
-                    tokio::time::sleep(Duration::from_millis(10)).await;
-
                     let mut buffer = vec![0u8; payload_size.min(8192)]; // Use smaller buffer sizes to avoid overwhelming
 
-                    while start_time.elapsed() < duration {
+                    // The server ends the test once the requested duration elapses on its
+                    // side, which starts after the handshake. Reading until it closes the
+                    // connection keeps the client from resetting it early.
+                    let deadline = tokio::time::Instant::from_std(start_time)
+                        + duration
+                        + DOWNLOAD_GRACE_PERIOD;
+                    loop {
                         let read_start = Instant::now();
-                        match stream.read(&mut buffer).await {
-                            Ok(0) => {
-                                // Server closed connection - this might be normal if server hit limits
-                                eprintln!("Server closed connection {i} (might be normal)");
+                        match tokio::time::timeout_at(deadline, stream.read(&mut buffer)).await {
+                            Ok(Ok(0)) => {
+                                trace!("Server closed download connection {i}");
                                 break;
                             }
-                            Ok(n) => {
+                            Ok(Ok(n)) => {
                                 let measurement =
                                     ThroughputMeasurement::new(n as u64, read_start.elapsed());
-                                local_measurements.push(measurement.clone());
-                                let _ = tx.send(measurement);
+                                let _ = tx.send(ThroughputEvent::Measurement {
+                                    connection: i,
+                                    offset: start_time.elapsed(),
+                                    measurement,
+                                });
                             }
-                            Err(e) => {
+                            Ok(Err(e)) => {
                                 let measurement = ThroughputMeasurement::new_error(
                                     ConnectionError::Unknown(e.to_string()),
                                     read_start.elapsed(),
                                     0,
                                 );
-                                local_measurements.push(measurement.clone());
-                                let _ = tx.send(measurement);
+                                let _ = tx.send(ThroughputEvent::Measurement {
+                                    connection: i,
+                                    offset: start_time.elapsed(),
+                                    measurement,
+                                });
+                                break;
+                            }
+                            Err(_) => {
+                                trace!("Server did not close download connection {i} in time");
+                                break;
                             }
                         }
                     }
                 }
                 Err(e) => {
                     eprintln!("TCP connection error on connection {i}: {e}");
+                    let measurement = ThroughputMeasurement::new_error(
+                        ConnectionError::ConnectionFailed(e.to_string()),
+                        start_time.elapsed(),
+                        0,
+                    );
+                    let _ = tx.send(ThroughputEvent::Measurement {
+                        connection: i,
+                        offset: start_time.elapsed(),
+                        measurement,
+                    });
+                    return Err(eyre::eyre!("Could not connect to {addr}: {e}"));
                 }
             }
 
-            local_measurements
+            Ok(())
         });
 
         tasks.push(task);
@@ -285,11 +414,11 @@ async fn run_download_test(
     // Drop the sender to signal stats collector to finish
     drop(tx);
 
+    let mut setup_errors = Vec::new();
     for result in results {
         match result {
-            Ok(task_measurements) => {
-                measurements.extend(task_measurements);
-            }
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => setup_errors.push(e),
             Err(e) => {
                 panic!("Task error: {e}");
             }
@@ -297,17 +426,26 @@ async fn run_download_test(
     }
 
     // Wait for stats collector to complete and get measurements
-    measurements = stats_collector
+    let (totals, intervals, connection_intervals) = stats_collector
         .finish(progress_bar, "Download complete".to_string())
         .await;
 
+    ensure_test_started(setup_errors, parallel_connections, &totals)?;
+
     let end_time = Instant::now();
 
-    Ok(ThroughputResult {
-        measurements,
-        total_duration: end_time.duration_since(start_time),
-        timestamp: chrono::Utc::now(),
-    })
+    let server_measurement = fetch_server_measurement(server, port, request.test_id).await;
+
+    Ok((
+        ThroughputResult {
+            totals,
+            total_duration: end_time.duration_since(start_time),
+            intervals,
+            connection_intervals,
+            timestamp: chrono::Utc::now(),
+        },
+        server_measurement,
+    ))
 }
 
 async fn run_upload_test(
@@ -316,8 +454,10 @@ async fn run_upload_test(
     parallel_connections: usize,
     payload_size: usize,
     duration: Duration,
-) -> Result<ThroughputResult> {
-    println!(
+    interval: Duration,
+    bitrate: Option<TargetBitrate>,
+) -> Result<(ThroughputResult, Option<ServerMeasurement>)> {
+    status!(
         "Starting TCP upload test with {} payload size and {} parallel connections...",
         format_bytes(payload_size).yellow(),
         parallel_connections.to_string().yellow()
@@ -326,7 +466,6 @@ async fn run_upload_test(
     // Create progress bar
     let progress_bar = create_progress_bar(ProgressBarType::Upload, duration);
 
-    let mut measurements = Vec::new();
     let start_time = Instant::now();
 
     // Generate upload data
@@ -337,29 +476,59 @@ async fn run_upload_test(
     };
 
     // Set up instrumentation
-    let (stats_collector, tx) =
-        ThroughputStatsCollector::new(progress_bar.clone(), start_time, duration);
+    let (stats_collector, tx) = ThroughputStatsCollector::new(
+        progress_bar.clone(),
+        TestPhase::Upload { payload_size },
+        start_time,
+        duration,
+        interval,
+    );
+
+    let request = TestRequest {
+        test_id: rng().next_u64(),
+        kind: TcpTestKind::Upload,
+        duration,
+        payload_size: u32::try_from(payload_size)
+            .map_err(|_| HandshakeError::OutOfRange("payload size"))?,
+    };
 
+    let pacers = connection_pacers(bitrate, parallel_connections);
     let mut tasks = Vec::new();
 
-    for i in 0..parallel_connections {
+    for (i, pacer) in pacers.into_iter().enumerate() {
         let server = server.to_string();
         let data = upload_data.clone();
         let tx = tx.clone();
 
         let task = tokio::spawn(async move {
             let addr = format!("{server}:{port}");
-            let mut local_measurements = Vec::new();
 
             match TcpStream::connect(&addr).await {
                 Ok(mut stream) => {
-                    // Send upload command
-                    if let Err(e) = stream.write_all(b"U").await {
-                        eprintln!("Failed to send upload command on connection {i}: {e}");
-                        return local_measurements;
+                    // Negotiate the test with the server
+                    if let Err(e) = protocol::client_handshake(&mut stream, &request).await {
+                        eprintln!("TCP handshake failed on connection {i}: {e}");
+                        let measurement = ThroughputMeasurement::new_error(
+                            ConnectionError::ConnectionFailed(format!("Handshake failed: {e}")),
+                            start_time.elapsed(),
+                            0,
+                        );
+                        let _ = tx.send(ThroughputEvent::Measurement {
+                            connection: i,
+                            offset: start_time.elapsed(),
+                            measurement,
+                        });
+                        return Err(e.into());
                     }
 
+                    // Only count retransmissions that happen during the test itself
+                    let mut retransmits = tcp_total_retransmits(&stream).unwrap_or(0);
+                    let mut last_retransmit_poll = Instant::now();
+
                     while start_time.elapsed() < duration {
+                        if let Some(pacer) = &pacer {
+                            pacer.wait(data.len()).await;
+                        }
                         let write_start = Instant::now();
                         match stream.write_all(&data).await {
                             Ok(_) => {
@@ -367,10 +536,24 @@ async fn run_upload_test(
                                     data.len() as u64,
                                     write_start.elapsed(),
                                 );
-                                local_measurements.push(measurement.clone());
 
                                 // Send to stats collector (non-blocking)
-                                let _ = tx.send(measurement);
+                                let _ = tx.send(ThroughputEvent::Measurement {
+                                    connection: i,
+                                    offset: start_time.elapsed(),
+                                    measurement,
+                                });
+
+                                if last_retransmit_poll.elapsed() >= RETRANSMIT_POLL_INTERVAL {
+                                    report_retransmits(
+                                        &stream,
+                                        i,
+                                        start_time,
+                                        &mut retransmits,
+                                        &tx,
+                                    );
+                                    last_retransmit_poll = Instant::now();
+                                }
                             }
                             Err(e) => {
                                 let measurement = ThroughputMeasurement::new_error(
@@ -380,19 +563,34 @@ async fn run_upload_test(
                                     write_start.elapsed(),
                                     0,
                                 );
-                                local_measurements.push(measurement.clone());
-                                let _ = tx.send(measurement);
+                                let _ = tx.send(ThroughputEvent::Measurement {
+                                    connection: i,
+                                    offset: start_time.elapsed(),
+                                    measurement,
+                                });
                                 break;
                             }
                         }
                     }
+                    report_retransmits(&stream, i, start_time, &mut retransmits, &tx);
                 }
                 Err(e) => {
                     eprintln!("TCP connection error on connection {i}: {e}");
+                    let measurement = ThroughputMeasurement::new_error(
+                        ConnectionError::ConnectionFailed(e.to_string()),
+                        start_time.elapsed(),
+                        0,
+                    );
+                    let _ = tx.send(ThroughputEvent::Measurement {
+                        connection: i,
+                        offset: start_time.elapsed(),
+                        measurement,
+                    });
+                    return Err(eyre::eyre!("Could not connect to {addr}: {e}"));
                 }
             }
 
-            local_measurements
+            Ok(())
         });
 
         tasks.push(task);
@@ -404,11 +602,11 @@ async fn run_upload_test(
     // Drop the sender to signal stats collector to finish
     drop(tx);
 
+    let mut setup_errors = Vec::new();
     for result in results {
         match result {
-            Ok(task_measurements) => {
-                measurements.extend(task_measurements);
-            }
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => setup_errors.push(e),
             Err(e) => {
                 panic!("Task error: {e}");
             }
@@ -416,15 +614,150 @@ async fn run_upload_test(
     }
 
     // Wait for stats collector to complete and get measurements
-    measurements = stats_collector
+    let (totals, intervals, connection_intervals) = stats_collector
         .finish(progress_bar, "Upload complete".to_string())
         .await;
 
+    ensure_test_started(setup_errors, parallel_connections, &totals)?;
+
     let end_time = Instant::now();
 
-    Ok(ThroughputResult {
-        measurements,
-        total_duration: end_time.duration_since(start_time),
-        timestamp: chrono::Utc::now(),
-    })
+    let server_measurement = fetch_server_measurement(server, port, request.test_id).await;
+
+    Ok((
+        ThroughputResult {
+            totals,
+            total_duration: end_time.duration_since(start_time),
+            intervals,
+            connection_intervals,
+            timestamp: chrono::Utc::now(),
+        },
+        server_measurement,
+    ))
+}
+
+/// Reports the segments retransmitted on `stream` since the last report to the stats collector
+fn report_retransmits(
+    stream: &TcpStream,
+    connection: usize,
+    start_time: Instant,
+    reported: &mut u32,
+    tx: &UnboundedSender<ThroughputEvent>,
+) {
+    if let Some(total) = tcp_total_retransmits(stream)
+        && total > *reported
+    {
+        let _ = tx.send(ThroughputEvent::Retransmits {
+            connection,
+            offset: start_time.elapsed(),
+            count: (total - *reported) as u64,
+        });
+        *reported = total;
+    }
+}
+
+/// Fails the test if no connection could connect and complete the control handshake, since
+/// that means the server is unreachable, incompatible or refused the test rather than a
+/// transient failure. Also fails it if the connections that did start transferred nothing.
+fn ensure_test_started(
+    mut setup_errors: Vec<eyre::Report>,
+    parallel_connections: usize,
+    totals: &ThroughputTotals,
+) -> Result<()> {
+    if !setup_errors.is_empty() && setup_errors.len() == parallel_connections {
+        let error = setup_errors.swap_remove(0);
+        return Err(eyre::eyre!("TCP test could not be started: {error}"));
+    }
+
+    ensure_data_transferred("TCP test", totals, setup_errors.into_iter().next())
+}
+
+/// Asks the server for its own measurement of a finished test.
+/// A server that cannot answer does not fail the test; the report then only has client-side results.
+async fn fetch_server_measurement(
+    server: &str,
+    port: u16,
+    test_id: u64,
+) -> Option<ServerMeasurement> {
+    let query = async {
+        let mut stream = TcpStream::connect(format!("{server}:{port}")).await?;
+        protocol::request_summary(&mut stream, test_id).await
+    };
+
+    match query.await {
+        Ok(measurement) => Some(measurement),
+        Err(e) => {
+            eprintln!("Could not fetch server-side results: {e}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::performance::{metrics::ServerMetrics, tcp::server::TcpServerBuilder};
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_closed_port_fails() {
+        // Nothing listens on the port once the listener is dropped
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let duration = Duration::from_millis(200);
+
+        let error = run_download_test("127.0.0.1", port, 2, 1024, duration, duration)
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("could not be started"),
+            "{error}"
+        );
+
+        let error = run_upload_test("127.0.0.1", port, 1, 1024, duration, duration, None)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Could not connect"), "{error}");
+
+        assert!(
+            measure_tcp_latency("127.0.0.1", port, duration)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clean_download_has_no_server_errors() {
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let server = Arc::new(TcpServerBuilder::new().build());
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.run(("127.0.0.1", port)).await }
+        });
+
+        // Retry until the server listens, refused connections never reach it
+        let duration = Duration::from_millis(500);
+        let mut attempts = 0;
+        let (result, _) = loop {
+            match run_download_test("127.0.0.1", port, 2, 1024, duration, duration).await {
+                Ok(result) => break result,
+                Err(error) if attempts < 50 => {
+                    trace!("Server not ready yet: {error}");
+                    attempts += 1;
+                    sleep(Duration::from_millis(20)).await;
+                }
+                Err(error) => panic!("{error}"),
+            }
+        };
+        assert!(result.totals.bytes > 0);
+        // The summary is only sent once every connection of the test has closed
+        assert_eq!(server.get_metrics().counters().errors, 0);
+        server.shutdown().await.unwrap();
+    }
 }
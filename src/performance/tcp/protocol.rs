@@ -0,0 +1,660 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::fmt;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::report::ServerMeasurement;
+
+/// Magic bytes at the start of every TCP control frame
+pub const PROTOCOL_MAGIC: [u8; 4] = *b"SPDT";
+
+/// Version of the TCP control protocol spoken by this build
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Maximum time either side waits for the other side's handshake frame
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum time the client waits for the server to answer a results query.
+/// The server may hold the answer until all connections of the test have closed.
+pub const SUMMARY_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Control frame header.
+/// Fixed 9-byte header: magic (4), version (2), frame type (1), body length (2).
+/// The header layout must never change between protocol versions so that mismatched
+/// peers can still read each other's version and fail cleanly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub version: u16,
+    pub frame_type: FrameType,
+    pub body_len: u16,
+}
+
+impl FrameHeader {
+    pub const SIZE: usize = 9;
+
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_slice(&PROTOCOL_MAGIC);
+        buf.put_u16(self.version);
+        buf.put_u8(self.frame_type as u8);
+        buf.put_u16(self.body_len);
+    }
+
+    pub fn decode(mut buf: Bytes) -> Result<Self, HandshakeError> {
+        if buf.len() < Self::SIZE {
+            return Err(HandshakeError::Malformed("frame header too short"));
+        }
+
+        let mut magic = [0u8; 4];
+        buf.copy_to_slice(&mut magic);
+        if magic != PROTOCOL_MAGIC {
+            return Err(HandshakeError::BadMagic(magic));
+        }
+
+        let version = buf.get_u16();
+        let frame_type = FrameType::try_from(buf.get_u8())?;
+        let body_len = buf.get_u16();
+
+        Ok(Self {
+            version,
+            frame_type,
+            body_len,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FrameType {
+    /// Client -> server test request
+    Request = 1,
+    /// Server -> client accept/reject response
+    Response = 2,
+    /// Server -> client summary of a finished test
+    Summary = 3,
+}
+
+impl TryFrom<u8> for FrameType {
+    type Error = HandshakeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(FrameType::Request),
+            2 => Ok(FrameType::Response),
+            3 => Ok(FrameType::Summary),
+            other => Err(HandshakeError::UnexpectedFrame(other)),
+        }
+    }
+}
+
+/// Kind of TCP test requested by the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TcpTestKind {
+    /// Server sends data to the client
+    Download = 1,
+    /// Client sends data to the server
+    Upload = 2,
+    /// Client asks for the server-side measurements of a finished test
+    Results = 3,
+}
+
+impl TryFrom<u8> for TcpTestKind {
+    type Error = HandshakeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(TcpTestKind::Download),
+            2 => Ok(TcpTestKind::Upload),
+            3 => Ok(TcpTestKind::Results),
+            _ => Err(HandshakeError::Malformed("unknown test kind")),
+        }
+    }
+}
+
+impl fmt::Display for TcpTestKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TcpTestKind::Download => write!(f, "download"),
+            TcpTestKind::Upload => write!(f, "upload"),
+            TcpTestKind::Results => write!(f, "results"),
+        }
+    }
+}
+
+/// Test request sent by the client to open a test connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TestRequest {
+    /// Identifier shared by all parallel connections of the same test
+    pub test_id: u64,
+    pub kind: TcpTestKind,
+    /// Requested test duration. The server stops the test once it elapses.
+    pub duration: Duration,
+    /// Payload size in bytes used by the client for this test
+    pub payload_size: u32,
+}
+
+impl TestRequest {
+    pub const BODY_SIZE: usize = 21;
+
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u64(self.test_id);
+        buf.put_u8(self.kind as u8);
+        buf.put_u64(self.duration.as_millis() as u64);
+        buf.put_u32(self.payload_size);
+    }
+
+    pub fn decode(mut buf: Bytes) -> Result<Self, HandshakeError> {
+        if buf.len() < Self::BODY_SIZE {
+            return Err(HandshakeError::Malformed("test request too short"));
+        }
+
+        Ok(Self {
+            test_id: buf.get_u64(),
+            kind: TcpTestKind::try_from(buf.get_u8())?,
+            duration: Duration::from_millis(buf.get_u64()),
+            payload_size: buf.get_u32(),
+        })
+    }
+}
+
+/// Reason a server refused a test request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RejectReason {
+    UnsupportedVersion = 1,
+    InvalidRequest = 2,
+    ServerBusy = 3,
+}
+
+impl TryFrom<u8> for RejectReason {
+    type Error = HandshakeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(RejectReason::UnsupportedVersion),
+            2 => Ok(RejectReason::InvalidRequest),
+            3 => Ok(RejectReason::ServerBusy),
+            _ => Err(HandshakeError::Malformed("unknown reject reason")),
+        }
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::UnsupportedVersion => write!(f, "unsupported protocol version"),
+            RejectReason::InvalidRequest => write!(f, "invalid request"),
+            RejectReason::ServerBusy => write!(f, "server busy"),
+        }
+    }
+}
+
+/// Server reply to a [`TestRequest`].
+/// The body layout (status byte, message length, message) is shared by all protocol versions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestResponse {
+    Accepted,
+    Rejected {
+        reason: RejectReason,
+        message: String,
+    },
+}
+
+impl TestResponse {
+    pub fn rejected(reason: RejectReason, message: impl Into<String>) -> Self {
+        Self::Rejected {
+            reason,
+            message: message.into(),
+        }
+    }
+
+    pub fn encode(&self, buf: &mut BytesMut) {
+        match self {
+            TestResponse::Accepted => {
+                buf.put_u8(0);
+                buf.put_u16(0);
+            }
+            TestResponse::Rejected { reason, message } => {
+                let message = &message.as_bytes()[..message.len().min(u16::MAX as usize)];
+                buf.put_u8(*reason as u8);
+                buf.put_u16(message.len() as u16);
+                buf.put_slice(message);
+            }
+        }
+    }
+
+    pub fn decode(mut buf: Bytes) -> Result<Self, HandshakeError> {
+        if buf.len() < 3 {
+            return Err(HandshakeError::Malformed("test response too short"));
+        }
+
+        let status = buf.get_u8();
+        let message_len = buf.get_u16() as usize;
+        if status == 0 {
+            return Ok(TestResponse::Accepted);
+        }
+        if buf.len() < message_len {
+            return Err(HandshakeError::Malformed("truncated reject message"));
+        }
+
+        Ok(TestResponse::Rejected {
+            reason: RejectReason::try_from(status)?,
+            message: String::from_utf8_lossy(&buf[..message_len]).into_owned(),
+        })
+    }
+}
+
+/// Fixed part of a summary frame body: bytes (8), duration in microseconds (8),
+/// connections (4), interval in milliseconds (4), interval count (2).
+/// It is followed by one byte count (8) per interval.
+const SUMMARY_FIXED_SIZE: usize = 26;
+
+/// Most intervals that fit in a single summary frame; later intervals are dropped
+const MAX_SUMMARY_INTERVALS: usize = (u16::MAX as usize - SUMMARY_FIXED_SIZE) / 8;
+
+/// Encodes the body of a [`FrameType::Summary`] frame
+pub fn encode_summary(
+    measurement: &ServerMeasurement,
+    buf: &mut BytesMut,
+) -> Result<(), HandshakeError> {
+    let intervals =
+        &measurement.intervals[..measurement.intervals.len().min(MAX_SUMMARY_INTERVALS)];
+    let duration = u64::try_from(measurement.duration.as_micros())
+        .map_err(|_| HandshakeError::OutOfRange("summary duration"))?;
+    let interval = u32::try_from(measurement.interval.as_millis())
+        .map_err(|_| HandshakeError::OutOfRange("summary interval"))?;
+
+    buf.put_u64(measurement.bytes);
+    buf.put_u64(duration);
+    buf.put_u32(measurement.connections);
+    buf.put_u32(interval);
+    buf.put_u16(intervals.len() as u16);
+    for bytes in intervals {
+        buf.put_u64(*bytes);
+    }
+    Ok(())
+}
+
+/// Decodes the body of a [`FrameType::Summary`] frame
+pub fn decode_summary(mut buf: Bytes) -> Result<ServerMeasurement, HandshakeError> {
+    if buf.len() < SUMMARY_FIXED_SIZE {
+        return Err(HandshakeError::Malformed("test summary too short"));
+    }
+
+    let bytes = buf.get_u64();
+    let duration = Duration::from_micros(buf.get_u64());
+    let connections = buf.get_u32();
+    let interval = Duration::from_millis(buf.get_u32() as u64);
+    let count = buf.get_u16() as usize;
+    if buf.len() < count * 8 {
+        return Err(HandshakeError::Malformed("truncated test summary"));
+    }
+    let intervals = (0..count).map(|_| buf.get_u64()).collect();
+
+    Ok(ServerMeasurement {
+        bytes,
+        duration,
+        interval,
+        intervals,
+        connections,
+        datagrams: None,
+    })
+}
+
+#[derive(Debug, Error)]
+pub enum HandshakeError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("peer does not speak the speed-cli TCP protocol (got magic {0:02x?})")]
+    BadMagic([u8; 4]),
+    #[error("protocol version mismatch: peer speaks v{peer}, this build speaks v{local}")]
+    UnsupportedVersion { peer: u16, local: u16 },
+    #[error("unexpected frame type {0}")]
+    UnexpectedFrame(u8),
+    #[error("malformed frame: {0}")]
+    Malformed(&'static str),
+    #[error("{0} does not fit in a frame")]
+    OutOfRange(&'static str),
+    #[error("server rejected the test ({reason}): {message}")]
+    Rejected {
+        reason: RejectReason,
+        message: String,
+    },
+    #[error("connection closed during handshake (is the server running a compatible speed-cli?)")]
+    Closed,
+    #[error("timed out waiting for handshake")]
+    Timeout,
+}
+
+/// Writes a single control frame with the current protocol version
+pub async fn write_frame<W>(
+    writer: &mut W,
+    frame_type: FrameType,
+    body: &[u8],
+) -> Result<(), HandshakeError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let body_len =
+        u16::try_from(body.len()).map_err(|_| HandshakeError::Malformed("frame body too large"))?;
+
+    let mut buf = BytesMut::with_capacity(FrameHeader::SIZE + body.len());
+    FrameHeader {
+        version: PROTOCOL_VERSION,
+        frame_type,
+        body_len,
+    }
+    .encode(&mut buf);
+    buf.put_slice(body);
+
+    writer.write_all(&buf).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Reads a single control frame. The version is returned as-is and must be checked by the caller.
+pub async fn read_frame<R>(reader: &mut R) -> Result<(FrameHeader, Bytes), HandshakeError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header_buf = [0u8; FrameHeader::SIZE];
+    match reader.read_exact(&mut header_buf).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            return Err(HandshakeError::Closed);
+        }
+        Err(e) => return Err(e.into()),
+    }
+    let header = FrameHeader::decode(Bytes::copy_from_slice(&header_buf))?;
+
+    let mut body = vec![0u8; header.body_len as usize];
+    match reader.read_exact(&mut body).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            return Err(HandshakeError::Closed);
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok((header, Bytes::from(body)))
+}
+
+/// Server side: reads the client's test request, rejecting unknown protocol versions
+pub async fn read_request<R>(reader: &mut R) -> Result<TestRequest, HandshakeError>
+where
+    R: AsyncRead + Unpin,
+{
+    let (header, body) = read_frame(reader).await?;
+    if header.version != PROTOCOL_VERSION {
+        return Err(HandshakeError::UnsupportedVersion {
+            peer: header.version,
+            local: PROTOCOL_VERSION,
+        });
+    }
+    if header.frame_type != FrameType::Request {
+        return Err(HandshakeError::UnexpectedFrame(header.frame_type as u8));
+    }
+
+    TestRequest::decode(body)
+}
+
+/// Server side: sends the accept/reject response
+pub async fn write_response<W>(
+    writer: &mut W,
+    response: &TestResponse,
+) -> Result<(), HandshakeError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut body = BytesMut::new();
+    response.encode(&mut body);
+    write_frame(writer, FrameType::Response, &body).await
+}
+
+/// Server side: sends the summary of a finished test
+pub async fn write_summary<W>(
+    writer: &mut W,
+    measurement: &ServerMeasurement,
+) -> Result<(), HandshakeError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut body = BytesMut::new();
+    encode_summary(measurement, &mut body)?;
+    write_frame(writer, FrameType::Summary, &body).await
+}
+
+/// Client side: asks the server for its measurements of the test identified by `test_id`
+pub async fn request_summary<S>(
+    stream: &mut S,
+    test_id: u64,
+) -> Result<ServerMeasurement, HandshakeError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = TestRequest {
+        test_id,
+        kind: TcpTestKind::Results,
+        duration: Duration::ZERO,
+        payload_size: 0,
+    };
+    let mut body = BytesMut::with_capacity(TestRequest::BODY_SIZE);
+    request.encode(&mut body);
+
+    let exchange = async {
+        write_frame(stream, FrameType::Request, &body).await?;
+        read_frame(stream).await
+    };
+    let (header, body) = tokio::time::timeout(SUMMARY_TIMEOUT, exchange)
+        .await
+        .map_err(|_| HandshakeError::Timeout)??;
+
+    if header.version != PROTOCOL_VERSION {
+        return Err(HandshakeError::UnsupportedVersion {
+            peer: header.version,
+            local: PROTOCOL_VERSION,
+        });
+    }
+
+    match header.frame_type {
+        FrameType::Summary => decode_summary(body),
+        FrameType::Response => match TestResponse::decode(body)? {
+            TestResponse::Rejected { reason, message } => {
+                Err(HandshakeError::Rejected { reason, message })
+            }
+            TestResponse::Accepted => {
+                Err(HandshakeError::UnexpectedFrame(FrameType::Response as u8))
+            }
+        },
+        other => Err(HandshakeError::UnexpectedFrame(other as u8)),
+    }
+}
+
+/// Client side: sends the test request and waits for the server to accept it
+pub async fn client_handshake<S>(
+    stream: &mut S,
+    request: &TestRequest,
+) -> Result<(), HandshakeError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut body = BytesMut::with_capacity(TestRequest::BODY_SIZE);
+    request.encode(&mut body);
+
+    let exchange = async {
+        write_frame(stream, FrameType::Request, &body).await?;
+        read_frame(stream).await
+    };
+    let (header, body) = tokio::time::timeout(HANDSHAKE_TIMEOUT, exchange)
+        .await
+        .map_err(|_| HandshakeError::Timeout)??;
+
+    if header.frame_type != FrameType::Response {
+        return Err(HandshakeError::UnexpectedFrame(header.frame_type as u8));
+    }
+
+    match TestResponse::decode(body)? {
+        TestResponse::Accepted if header.version == PROTOCOL_VERSION => Ok(()),
+        TestResponse::Accepted => Err(HandshakeError::UnsupportedVersion {
+            peer: header.version,
+            local: PROTOCOL_VERSION,
+        }),
+        TestResponse::Rejected { reason, message } => {
+            Err(HandshakeError::Rejected { reason, message })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_request() -> TestRequest {
+        TestRequest {
+            test_id: 0xDEADBEEF,
+            kind: TcpTestKind::Upload,
+            duration: Duration::from_secs(10),
+            payload_size: 65536,
+        }
+    }
+
+    #[test]
+    fn test_request_encode_decode() {
+        let request = sample_request();
+        let mut buf = BytesMut::new();
+        request.encode(&mut buf);
+        assert_eq!(buf.len(), TestRequest::BODY_SIZE);
+
+        let decoded = TestRequest::decode(buf.freeze()).unwrap();
+        assert_eq!(request, decoded);
+    }
+
+    #[test]
+    fn test_response_encode_decode() {
+        for response in [
+            TestResponse::Accepted,
+            TestResponse::rejected(RejectReason::InvalidRequest, "duration too long"),
+        ] {
+            let mut buf = BytesMut::new();
+            response.encode(&mut buf);
+            assert_eq!(TestResponse::decode(buf.freeze()).unwrap(), response);
+        }
+    }
+
+    #[test]
+    fn test_summary_encode_decode() {
+        let mut measurement = ServerMeasurement::new(Duration::from_secs(1));
+        measurement.connections = 4;
+        measurement.record(Duration::from_millis(250), 1000);
+        measurement.record(Duration::from_millis(1750), 3000);
+
+        let mut buf = BytesMut::new();
+        encode_summary(&measurement, &mut buf).unwrap();
+        assert_eq!(decode_summary(buf.freeze()).unwrap(), measurement);
+    }
+
+    #[test]
+    fn test_summary_interval_out_of_range() {
+        let measurement = ServerMeasurement::new(Duration::from_millis(u32::MAX as u64 + 1));
+        let result = encode_summary(&measurement, &mut BytesMut::new());
+        assert!(matches!(
+            result,
+            Err(HandshakeError::OutOfRange("summary interval"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_request_summary() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let mut measurement = ServerMeasurement::new(Duration::from_secs(1));
+        measurement.record(Duration::from_millis(500), 4096);
+
+        let expected = measurement.clone();
+        tokio::spawn(async move {
+            let request = read_request(&mut server).await.unwrap();
+            assert_eq!(request.kind, TcpTestKind::Results);
+            assert_eq!(request.test_id, 7);
+            write_summary(&mut server, &measurement).await.unwrap();
+        });
+
+        assert_eq!(request_summary(&mut client, 7).await.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_bad_magic_rejected() {
+        let result = FrameHeader::decode(Bytes::from_static(b"D\0\0\0\0\0\0\0\0"));
+        assert!(matches!(result, Err(HandshakeError::BadMagic(_))));
+    }
+
+    #[tokio::test]
+    async fn test_handshake_accepted() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let request = sample_request();
+
+        let server_task = tokio::spawn(async move {
+            let received = read_request(&mut server).await.unwrap();
+            write_response(&mut server, &TestResponse::Accepted)
+                .await
+                .unwrap();
+            received
+        });
+
+        client_handshake(&mut client, &request).await.unwrap();
+        assert_eq!(server_task.await.unwrap(), request);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_version_mismatch() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+
+        // Hand-craft a request from a future protocol version
+        let mut frame = BytesMut::new();
+        FrameHeader {
+            version: PROTOCOL_VERSION + 1,
+            frame_type: FrameType::Request,
+            body_len: 0,
+        }
+        .encode(&mut frame);
+        client.write_all(&frame).await.unwrap();
+
+        let result = read_request(&mut server).await;
+        assert!(matches!(
+            result,
+            Err(HandshakeError::UnsupportedVersion { peer, local })
+                if peer == PROTOCOL_VERSION + 1 && local == PROTOCOL_VERSION
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_handshake_rejected() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+
+        tokio::spawn(async move {
+            let _ = read_request(&mut server).await;
+            let response = TestResponse::rejected(RejectReason::ServerBusy, "try again later");
+            write_response(&mut server, &response).await.unwrap();
+        });
+
+        let result = client_handshake(&mut client, &sample_request()).await;
+        assert!(matches!(
+            result,
+            Err(HandshakeError::Rejected {
+                reason: RejectReason::ServerBusy,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_handshake_closed_by_legacy_server() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        drop(server);
+
+        let result = client_handshake(&mut client, &sample_request()).await;
+        assert!(matches!(
+            result,
+            Err(HandshakeError::Closed) | Err(HandshakeError::Io(_))
+        ));
+    }
+}
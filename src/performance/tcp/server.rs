@@ -1,5 +1,6 @@
 use colored::*;
 use eyre::{Context, Result};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
@@ -7,13 +8,28 @@ use tokio::io::AsyncReadExt;
 use tokio::net::ToSocketAddrs;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::{Semaphore, broadcast};
-use tokio::time::timeout;
+use tokio::time::{sleep_until, timeout};
 use tracing::{debug, error, info, instrument, warn};
 
+use super::protocol::{
+    self, HANDSHAKE_TIMEOUT, HandshakeError, PROTOCOL_VERSION, RejectReason, TcpTestKind,
+    TestRequest, TestResponse,
+};
+use crate::performance::metrics::{ServerCounters, ServerMetrics};
+use crate::report::{SERVER_INTERVAL, ServerMeasurement};
 use crate::utils::format::{format_bytes, format_throughput};
 
 // TODO: Try pushing this to 100gig connection
 
+/// Extra time an upload connection stays open after the requested duration
+const UPLOAD_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// Longest a results query waits for the remaining connections of its test to finish
+const SUMMARY_WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Finished tests whose results were never queried are forgotten after this long
+const TEST_RECORD_TTL: Duration = Duration::from_secs(600);
+
 #[derive(Debug, Clone)]
 pub struct TcpServerConfig {
     /// Maximum number of concurrent connections
@@ -28,6 +44,8 @@ pub struct TcpServerConfig {
     pub report_interval: Duration,
     /// Maximum bytes per connection before auto-disconnect
     pub max_bytes_per_connection: Option<u64>,
+    /// Longest test duration a client may request
+    pub max_test_duration: Duration,
 }
 
 impl Default for TcpServerConfig {
@@ -39,6 +57,7 @@ impl Default for TcpServerConfig {
             buffer_size: 131072, // 128KB buffer for better high-speed performance
             report_interval: Duration::from_secs(5),
             max_bytes_per_connection: Some(1_000_000_000_000), // 1TB limit for high-speed tests
+            max_test_duration: Duration::from_secs(3600),
         }
     }
 }
@@ -59,6 +78,7 @@ impl TcpServerMetrics {
     }
 
     /// Log current metrics
+    #[allow(dead_code)]
     pub fn log_summary(&self) {
         let total_conns = self.total_connections.load(Ordering::Relaxed);
         let active_conns = self.active_connections.load(Ordering::Relaxed);
@@ -77,6 +97,93 @@ impl TcpServerMetrics {
     }
 }
 
+impl ServerMetrics for TcpServerMetrics {
+    fn counters(&self) -> ServerCounters {
+        ServerCounters {
+            total_connections: self.total_connections.load(Ordering::Relaxed),
+            active_connections: self.active_connections.load(Ordering::Relaxed) as u64,
+            bytes_received: self.total_bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.total_bytes_sent.load(Ordering::Relaxed),
+            errors: self.connection_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Server-side record of a test, shared by all of its parallel connections
+#[derive(Debug)]
+struct TestRecord {
+    /// Time the first connection of the test was accepted
+    start: Instant,
+    /// Connections that were accepted but have not finished yet
+    open_connections: usize,
+    measurement: ServerMeasurement,
+    last_update: Instant,
+}
+
+/// Tracks server-side measurements of running and recently finished tests by test id,
+/// so that the client can query them once the test is over
+#[derive(Debug, Default)]
+struct TestRegistry {
+    tests: std::sync::Mutex<HashMap<u64, TestRecord>>,
+}
+
+impl TestRegistry {
+    /// Registers a new connection for the test and returns the time the test started
+    fn open(&self, test_id: u64) -> Instant {
+        let now = Instant::now();
+        let mut tests = self.tests.lock().unwrap();
+
+        // Forget tests whose results were never collected
+        tests.retain(|_, record| {
+            record.open_connections > 0 || now - record.last_update < TEST_RECORD_TTL
+        });
+
+        let record = tests.entry(test_id).or_insert_with(|| TestRecord {
+            start: now,
+            open_connections: 0,
+            measurement: ServerMeasurement::new(SERVER_INTERVAL),
+            last_update: now,
+        });
+        record.open_connections += 1;
+        record.last_update = now;
+        record.start
+    }
+
+    /// Merges the measurement of a finished connection into its test
+    fn close(&self, test_id: u64, measurement: &ServerMeasurement) {
+        let mut tests = self.tests.lock().unwrap();
+        if let Some(record) = tests.get_mut(&test_id) {
+            record.open_connections = record.open_connections.saturating_sub(1);
+            record.measurement.merge(measurement);
+            record.last_update = Instant::now();
+        }
+    }
+
+    /// Waits for all connections of the test to finish, then removes and returns its measurement.
+    /// Returns whatever was collected so far if connections are still open after `wait`.
+    async fn take_summary(&self, test_id: u64, wait: Duration) -> Option<ServerMeasurement> {
+        let deadline = Instant::now() + wait;
+
+        loop {
+            {
+                let mut tests = self.tests.lock().unwrap();
+                let record = tests.get(&test_id)?;
+                if record.open_connections == 0 || Instant::now() >= deadline {
+                    if record.open_connections > 0 {
+                        warn!(
+                            "Reporting test {:016x} with {} connections still open",
+                            test_id, record.open_connections
+                        );
+                    }
+                    return tests.remove(&test_id).map(|record| record.measurement);
+                }
+            }
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
+
 /// Production TCP server with proper resource management and monitoring
 pub struct TcpServer {
     config: TcpServerConfig,
@@ -84,6 +191,7 @@ pub struct TcpServer {
     connection_semaphore: Arc<Semaphore>,
     shutdown_tx: broadcast::Sender<()>,
     metrics: Arc<TcpServerMetrics>,
+    registry: Arc<TestRegistry>,
 }
 
 impl TcpServer {
@@ -95,6 +203,7 @@ impl TcpServer {
             active_connections: Arc::new(AtomicUsize::new(0)),
             shutdown_tx,
             metrics: TcpServerMetrics::new(),
+            registry: Arc::new(TestRegistry::default()),
         }
     }
 
@@ -106,6 +215,7 @@ impl TcpServer {
         self.metrics.clone()
     }
 
+    #[allow(dead_code)]
     pub async fn shutdown(&self) -> Result<()> {
         info!("Initiating TCP server shutdown...");
 
@@ -178,6 +288,7 @@ impl TcpServer {
                                         permit,
                                         shutdown_rx: self.get_shutdown_receiver(),
                                         metrics: self.metrics.clone(),
+                                        registry: self.registry.clone(),
                                     },
                                 );
 
@@ -209,20 +320,17 @@ impl TcpServer {
     }
 }
 
-// TODO: Remove this vv
-/// Legacy function for backward compatibility - now uses the builder pattern
-pub async fn run_tcp_server(addr: impl ToSocketAddrs + std::fmt::Debug + Clone) -> Result<()> {
-    // Use the builder pattern with optimized settings for high-throughput testing
-    let server = TcpServerBuilder::new()
+/// Builds a TCP server with settings optimized for high-throughput testing
+pub fn build_tcp_server() -> TcpServer {
+    TcpServerBuilder::new()
         .max_connections(1000)
         .connection_timeout(Duration::from_secs(300))
         .read_timeout(Duration::from_secs(30))
         .buffer_size(131072) // 128KB
         .report_interval(Duration::from_secs(5))
         .max_bytes_per_connection(Some(1_000_000_000_000)) // 1TB
-        .build();
-
-    server.run(addr).await
+        .max_test_duration(Duration::from_secs(3600))
+        .build()
 }
 
 /// Builder for TcpServer with sensible defaults
@@ -267,6 +375,11 @@ impl TcpServerBuilder {
         self
     }
 
+    pub fn max_test_duration(mut self, duration: Duration) -> Self {
+        self.config.max_test_duration = duration;
+        self
+    }
+
     pub fn build(self) -> TcpServer {
         TcpServer::new(self.config)
     }
@@ -284,6 +397,7 @@ struct TcpHandlerContext {
     pub permit: tokio::sync::OwnedSemaphorePermit,
     pub shutdown_rx: broadcast::Receiver<()>,
     pub metrics: Arc<TcpServerMetrics>,
+    pub registry: Arc<TestRegistry>,
 }
 
 /// Production-grade TCP connection handler with comprehensive monitoring and safety features
@@ -297,6 +411,13 @@ struct ProductionTcpHandler {
     shutdown_rx: broadcast::Receiver<()>,
     stats: ConnectionStats,
     metrics: Arc<TcpServerMetrics>,
+    registry: Arc<TestRegistry>,
+    /// Test this connection belongs to, once the handshake has registered it
+    test_id: Option<u64>,
+    /// Start of the test this connection belongs to, shared with its other connections
+    test_start: Instant,
+    /// Bytes transferred by this connection, merged into the test record when it finishes
+    measurement: ServerMeasurement,
 }
 
 #[derive(Debug)]
@@ -367,6 +488,13 @@ impl ProductionTcpHandler {
             shutdown_rx: context.shutdown_rx,
             stats: ConnectionStats::new(),
             metrics: context.metrics,
+            registry: context.registry,
+            test_id: None,
+            test_start: Instant::now(),
+            measurement: ServerMeasurement {
+                connections: 1,
+                ..ServerMeasurement::new(SERVER_INTERVAL)
+            },
         }
     }
 
@@ -382,70 +510,58 @@ impl ProductionTcpHandler {
         let mut buffer = vec![0u8; self.config.buffer_size];
         let mut shutdown_rx = self.shutdown_rx.resubscribe();
 
-        // First, read the command byte to determine if this is upload or download
-        let command = match timeout(
-            Duration::from_secs(5),
-            self.socket.read_exact(&mut buffer[..1]),
-        )
-        .await
-        {
-            Ok(Ok(_)) => buffer[0],
-            Ok(Err(e)) => {
-                error!("Failed to read command byte: {}", e);
-                self.metrics
-                    .connection_errors
-                    .fetch_add(1, Ordering::Relaxed);
-                return Err(e.into());
+        // Perform the control handshake to learn what the client wants to test
+        let result = match self.handshake().await {
+            Ok(Some(request)) if request.kind == TcpTestKind::Results => {
+                self.handle_results(request.test_id).await
             }
-            Err(_) => {
-                warn!("Timeout waiting for command byte");
-                self.metrics
-                    .connection_errors
-                    .fetch_add(1, Ordering::Relaxed);
-                return Err(eyre::eyre!("Command timeout"));
-            }
-        };
+            Ok(Some(request)) => {
+                let res = match request.kind {
+                    TcpTestKind::Upload => {
+                        self.handle_upload(&mut buffer, &mut shutdown_rx, request.duration)
+                            .await
+                    }
+                    TcpTestKind::Download => {
+                        self.handle_download(&mut buffer, &mut shutdown_rx, request.duration)
+                            .await
+                    }
+                    TcpTestKind::Results => unreachable!("results queries are handled above"),
+                };
 
-        let result = match command {
-            b'U' => {
-                let res = self.handle_upload(&mut buffer, &mut shutdown_rx).await;
-                // Log final statistics for upload
+                // Log final statistics for the test
                 let (total_bytes, duration, throughput_mbps) = self.stats.get_summary();
                 let status = if res.is_ok() { "completed" } else { "failed" };
+                let (label, verb) = match request.kind {
+                    TcpTestKind::Upload => ("Upload", "received"),
+                    TcpTestKind::Download | TcpTestKind::Results => ("Download", "sent"),
+                };
                 info!(
-                    "Upload connection {} {}: {} received in {:.2}s ({})",
+                    "{} connection {} (test {:016x}) {}: {} {} in {:.2}s ({})",
+                    label,
                     self.connection_id,
+                    request.test_id,
                     status,
                     format_bytes(total_bytes).yellow(),
+                    verb,
                     duration.as_secs_f64(),
                     format_throughput(throughput_mbps).green()
                 );
                 res
             }
-            b'D' => {
-                let res = self.handle_download(&mut buffer, &mut shutdown_rx).await;
-                // Log final statistics for download
-                let (total_bytes, duration, throughput_mbps) = self.stats.get_summary();
-                let status = if res.is_ok() { "completed" } else { "failed" };
-                info!(
-                    "Download connection {} {}: {} sent in {:.2}s ({})",
-                    self.connection_id,
-                    status,
-                    format_bytes(total_bytes).yellow(),
-                    duration.as_secs_f64(),
-                    format_throughput(throughput_mbps).green()
-                );
-                res
-            }
-            _ => {
-                warn!("Unknown command byte: {}", command);
+            Ok(None) => Ok(()),
+            Err(e) => {
                 self.metrics
                     .connection_errors
                     .fetch_add(1, Ordering::Relaxed);
-                Err(eyre::eyre!("Unknown command"))
+                Err(e.into())
             }
         };
 
+        // Hand this connection's measurement over to its test
+        if let Some(test_id) = self.test_id {
+            self.registry.close(test_id, &self.measurement);
+        }
+
         // Update active connection count and metrics
         let remaining = self.active_connections.fetch_sub(1, Ordering::Relaxed) - 1;
         self.metrics
@@ -456,6 +572,101 @@ impl ProductionTcpHandler {
         result
     }
 
+    /// Reads and validates the client's test request.
+    /// Returns `Ok(None)` if the client closed the connection without sending anything
+    /// (e.g. a TCP connect latency probe).
+    async fn handshake(&mut self) -> Result<Option<TestRequest>, HandshakeError> {
+        let request =
+            match timeout(HANDSHAKE_TIMEOUT, protocol::read_request(&mut self.socket)).await {
+                Ok(Ok(request)) => request,
+                Ok(Err(HandshakeError::Closed)) => {
+                    debug!("Client closed connection before handshake");
+                    return Ok(None);
+                }
+                Ok(Err(e @ HandshakeError::UnsupportedVersion { peer, .. })) => {
+                    warn!("Rejecting client speaking protocol v{}", peer);
+                    let response = TestResponse::rejected(
+                        RejectReason::UnsupportedVersion,
+                        format!("server speaks protocol v{PROTOCOL_VERSION}, client sent v{peer}"),
+                    );
+                    let _ = protocol::write_response(&mut self.socket, &response).await;
+                    return Err(e);
+                }
+                Ok(Err(e)) => {
+                    warn!("Invalid handshake: {}", e);
+                    return Err(e);
+                }
+                Err(_) => {
+                    warn!("Timeout waiting for handshake");
+                    return Err(HandshakeError::Timeout);
+                }
+            };
+
+        // Results queries carry no test parameters and get a summary instead of a response
+        if request.kind == TcpTestKind::Results {
+            return Ok(Some(request));
+        }
+
+        if request.duration.is_zero() || request.duration > self.config.max_test_duration {
+            let response = TestResponse::rejected(
+                RejectReason::InvalidRequest,
+                format!(
+                    "requested duration {:?} must be between 0s and {:?}",
+                    request.duration, self.config.max_test_duration
+                ),
+            );
+            protocol::write_response(&mut self.socket, &response).await?;
+            return Err(HandshakeError::Rejected {
+                reason: RejectReason::InvalidRequest,
+                message: "requested duration out of range".to_string(),
+            });
+        }
+
+        // Register before accepting so that a results query can never miss this connection
+        self.test_start = self.registry.open(request.test_id);
+        self.test_id = Some(request.test_id);
+
+        protocol::write_response(&mut self.socket, &TestResponse::Accepted).await?;
+        debug!(
+            "Accepted {} test {:016x} for {:?} ({} payload)",
+            request.kind,
+            request.test_id,
+            request.duration,
+            format_bytes(request.payload_size)
+        );
+
+        Ok(Some(request))
+    }
+
+    /// Answers a results query with the server-side measurement of a finished test
+    async fn handle_results(&mut self, test_id: u64) -> Result<()> {
+        match self
+            .registry
+            .take_summary(test_id, SUMMARY_WAIT_TIMEOUT)
+            .await
+        {
+            Some(measurement) => {
+                debug!(
+                    "Sending summary of test {:016x}: {} over {} connections",
+                    test_id,
+                    format_bytes(measurement.bytes),
+                    measurement.connections
+                );
+                protocol::write_summary(&mut self.socket, &measurement).await?;
+            }
+            None => {
+                warn!("Results requested for unknown test {:016x}", test_id);
+                let response = TestResponse::rejected(
+                    RejectReason::InvalidRequest,
+                    format!("unknown test {test_id:016x}"),
+                );
+                protocol::write_response(&mut self.socket, &response).await?;
+            }
+        }
+
+        Ok(())
+    }
+
     async fn configure_socket(&mut self) -> Result<()> {
         // Configure socket for high-throughput scenarios
 
@@ -472,9 +683,13 @@ impl ProductionTcpHandler {
         &mut self,
         buffer: &mut [u8],
         shutdown_rx: &mut broadcast::Receiver<()>,
+        duration: Duration,
     ) -> Result<()> {
         info!("Handling upload request");
 
+        // Give the client a short grace period to finish sending after the test duration
+        let deadline = tokio::time::Instant::now() + duration + UPLOAD_GRACE_PERIOD;
+
         loop {
             tokio::select! {
                 // Handle incoming data with timeout for read operations
@@ -487,6 +702,7 @@ impl ProductionTcpHandler {
                         }
                         Ok(Ok(n)) => {
                             self.stats.add_bytes(n as u64);
+                            self.measurement.record(self.test_start.elapsed(), n as u64);
 
                             // Update server metrics
                             self.metrics.total_bytes_received.fetch_add(n as u64, Ordering::Relaxed);
@@ -526,6 +742,12 @@ impl ProductionTcpHandler {
                     }
                 }
 
+                // Enforce the requested test duration
+                _ = sleep_until(deadline) => {
+                    info!("Upload test duration elapsed, closing connection");
+                    break Ok(());
+                }
+
                 // Handle shutdown signal
                 _ = shutdown_rx.recv() => {
                     info!("Received shutdown signal during upload");
@@ -547,11 +769,14 @@ impl ProductionTcpHandler {
         &mut self,
         buffer: &mut [u8],
         shutdown_rx: &mut broadcast::Receiver<()>,
+        duration: Duration,
     ) -> Result<()> {
         use tokio::io::AsyncWriteExt;
 
         info!("Handling download request");
 
+        let deadline = tokio::time::Instant::now() + duration;
+
         // Fill buffer with random data for download
         buffer.fill(0x42); // Fill with a pattern for testing
 
@@ -568,6 +793,7 @@ impl ProductionTcpHandler {
                             let bytes_sent = buffer.len() as u64;
                             total_sent += bytes_sent;
                             self.stats.add_bytes(bytes_sent);
+                            self.measurement.record(self.test_start.elapsed(), bytes_sent);
 
                             // Update server metrics
                             self.metrics.total_bytes_sent.fetch_add(bytes_sent, Ordering::Relaxed);
@@ -603,6 +829,13 @@ impl ProductionTcpHandler {
                     }
                 }
 
+                // Enforce the requested test duration
+                _ = sleep_until(deadline) => {
+                    info!("Download test duration elapsed, closing connection");
+                    let _ = self.socket.shutdown().await;
+                    break Ok(());
+                }
+
                 // Handle shutdown signal
                 _ = shutdown_rx.recv() => {
                     info!("Received shutdown signal during download");
@@ -0,0 +1,370 @@
+use bytes::Bytes;
+use colored::Colorize as _;
+use eyre::Result;
+use rand::RngCore;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+
+use super::client::{StpClient, Transfer};
+use super::io::RecvBatch;
+use super::protocol::{
+    MAX_BITRATE, MAX_PAYLOAD_SIZE, PacketType, SessionKind, SessionParams, SessionState, StpHeader,
+    StpPacket, current_timestamp_micros,
+};
+use crate::{
+    TargetBitrate,
+    performance::pacing::Pacer,
+    report::{ConnectionError, DatagramStats, ThroughputMeasurement, ThroughputResult},
+    utils::{
+        format::{format_bytes, format_throughput},
+        instrumentation::{ProgressBarType, ThroughputStatsCollector, create_progress_bar},
+        output::{TestPhase, status},
+    },
+};
+
+/// Sending time a constant-bitrate stream may catch up on at once after the timer fired
+/// late. Timers have a resolution of about a millisecond, so without any burst, streams
+/// that need datagrams closer together than that fall short of their bitrate.
+const CBR_MAX_BURST: Duration = Duration::from_millis(5);
+
+/// Returns the pacer of a constant-bitrate stream of `datagram_size`-byte datagrams at
+/// `bitrate` bits per second
+pub fn cbr_pacer(bitrate: f64, datagram_size: usize) -> Pacer {
+    let rate = bitrate / 8.0;
+    let burst = (rate * CBR_MAX_BURST.as_secs_f64()) as usize;
+    Pacer::with_burst(rate, burst.max(datagram_size))
+}
+
+/// Tracks the delivery of a sequence-numbered datagram stream: loss, duplicates, reordering
+/// and the RFC 3550 interarrival jitter. Sequence numbers are the STP packet numbers and
+/// transit times come from the STP timestamps. As in RFC 3550, loss is counted from the
+/// first datagram received, so the packets of the handshake don't count as lost. The clocks of sender and
+/// receiver need not be synchronized since jitter only depends on differences of transit
+/// times.
+#[derive(Debug, Default)]
+pub struct DatagramReceiver {
+    received: u64,
+    duplicates: u64,
+    out_of_order: u64,
+    lowest: Option<u64>,
+    highest: u64,
+    seen: HashSet<u64>,
+    /// Transit time of the previous datagram in microseconds
+    last_transit: Option<i64>,
+    /// Jitter estimate in microseconds
+    jitter: f64,
+}
+
+impl DatagramReceiver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a datagram with `header` that arrived at `arrival` (microseconds since the
+    /// Unix epoch)
+    pub fn on_datagram(&mut self, header: &StpHeader, arrival: u64) {
+        if !self.seen.insert(header.packet_number) {
+            self.duplicates += 1;
+            return;
+        }
+        self.received += 1;
+
+        if header.packet_number < self.highest {
+            self.out_of_order += 1;
+        }
+        self.highest = self.highest.max(header.packet_number);
+        let lowest = self.lowest.get_or_insert(header.packet_number);
+        *lowest = (*lowest).min(header.packet_number);
+
+        // J(i) = J(i-1) + (|D(i-1,i)| - J(i-1)) / 16
+        let transit = arrival as i64 - header.timestamp as i64;
+        if let Some(last_transit) = self.last_transit {
+            let difference = (transit - last_transit).abs() as f64;
+            self.jitter += (difference - self.jitter) / 16.0;
+        }
+        self.last_transit = Some(transit);
+    }
+
+    pub fn stats(&self) -> DatagramStats {
+        DatagramStats {
+            received: self.received,
+            lost: self.lowest.map_or(0, |lowest| {
+                (self.highest - lowest + 1).saturating_sub(self.received)
+            }),
+            duplicates: self.duplicates,
+            out_of_order: self.out_of_order,
+            jitter: Duration::from_secs_f64(self.jitter / 1_000_000.0),
+        }
+    }
+}
+
+/// Returns the target bitrate of a constant-bitrate test, checking that the server accepts
+/// it
+fn cbr_bitrate(bitrate: Option<TargetBitrate>) -> Result<TargetBitrate> {
+    let bitrate =
+        bitrate.ok_or_else(|| eyre::eyre!("Constant-bitrate UDP tests require a bitrate"))?;
+    if !(bitrate.bitrate.0 > 0.0 && bitrate.bitrate.0 <= MAX_BITRATE) {
+        eyre::bail!(
+            "Constant-bitrate UDP tests require a bitrate above 0 and of at most {}",
+            format_throughput(MAX_BITRATE / 1_000_000.0)
+        );
+    }
+    Ok(bitrate)
+}
+
+/// Sends datagrams to the server at a constant bitrate, which counts the STP headers,
+/// without congestion control or retransmissions. The server reports the loss and jitter
+/// of the datagrams along with its measurement.
+pub async fn run_cbr_upload(
+    server: &str,
+    port: u16,
+    payload_size: usize,
+    duration: Duration,
+    interval: Duration,
+    bitrate: Option<TargetBitrate>,
+    standard_io: bool,
+) -> Result<Transfer> {
+    let bitrate = cbr_bitrate(bitrate)?;
+    status!(
+        "Starting UDP constant-bitrate upload test at {} with {} payload size...",
+        bitrate.to_string().yellow(),
+        format_bytes(payload_size).yellow()
+    );
+
+    let mut upload_data = vec![0u8; payload_size];
+    rand::rng().fill_bytes(&mut upload_data);
+    let payload = Bytes::from(upload_data);
+
+    let mut client = StpClient::new(&format!("{server}:{port}"), standard_io).await?;
+    client
+        .handshake(SessionParams {
+            kind: SessionKind::CbrUpload,
+            payload_size,
+            bitrate: bitrate.bitrate.0,
+            duration,
+        })
+        .await?;
+
+    let progress_bar = create_progress_bar(ProgressBarType::Upload, duration);
+    let start_time = Instant::now();
+    let (stats_collector, tx) = ThroughputStatsCollector::new(
+        progress_bar.clone(),
+        TestPhase::Upload { payload_size },
+        start_time,
+        duration,
+        interval,
+    );
+
+    let mut pacer = cbr_pacer(bitrate.bitrate.0, StpHeader::SIZE + payload.len());
+    while start_time.elapsed() < duration {
+        pacer.wait(StpHeader::SIZE + payload.len()).await;
+
+        let packet = client.connection.packet(PacketType::Data, payload.clone());
+        let write_start = Instant::now();
+        match client.socket.send(&packet.encode()).await {
+            Ok(_) => {
+                let measurement =
+                    ThroughputMeasurement::new(payload.len() as u64, write_start.elapsed());
+                let _ = tx.send((start_time.elapsed(), measurement).into());
+            }
+            Err(e) => {
+                let measurement = ThroughputMeasurement::new_error(
+                    ConnectionError::TransferFailed(format!("UDP send error: {e}")),
+                    write_start.elapsed(),
+                    0,
+                );
+                let _ = tx.send((start_time.elapsed(), measurement).into());
+                break;
+            }
+        }
+    }
+
+    drop(tx);
+    let (totals, intervals, connection_intervals) = stats_collector
+        .finish(progress_bar, "Upload complete".to_string())
+        .await;
+    let end_time = Instant::now();
+
+    let mut server_measurement = match client.close().await {
+        Ok(measurement) => Some(measurement),
+        Err(e) => {
+            eprintln!("Could not fetch server-side results: {e}");
+            None
+        }
+    };
+    let datagrams = server_measurement
+        .as_mut()
+        .and_then(|measurement| measurement.datagrams.take());
+
+    Ok((
+        ThroughputResult {
+            totals,
+            total_duration: end_time.duration_since(start_time),
+            intervals,
+            connection_intervals,
+            timestamp: chrono::Utc::now(),
+        },
+        server_measurement,
+        datagrams,
+        client.socket.path(),
+    ))
+}
+
+/// Asks the server to send datagrams at a constant bitrate and measures their loss and
+/// jitter as they arrive
+pub async fn run_cbr_download(
+    server: &str,
+    port: u16,
+    payload_size: usize,
+    duration: Duration,
+    interval: Duration,
+    bitrate: Option<TargetBitrate>,
+    standard_io: bool,
+) -> Result<Transfer> {
+    let bitrate = cbr_bitrate(bitrate)?;
+    status!(
+        "Starting UDP constant-bitrate download test at {} with {} payload size...",
+        bitrate.to_string().yellow(),
+        format_bytes(payload_size).yellow()
+    );
+
+    let mut client = StpClient::new(&format!("{server}:{port}"), standard_io).await?;
+    client
+        .handshake(SessionParams {
+            kind: SessionKind::CbrDownload,
+            payload_size,
+            bitrate: bitrate.bitrate.0,
+            duration,
+        })
+        .await?;
+
+    let progress_bar = create_progress_bar(ProgressBarType::Download, duration);
+    let start_time = Instant::now();
+    let (stats_collector, tx) = ThroughputStatsCollector::new(
+        progress_bar.clone(),
+        TestPhase::Download { payload_size },
+        start_time,
+        duration,
+        interval,
+    );
+
+    let mut receiver = DatagramReceiver::new();
+    let mut batch = RecvBatch::new(&client.socket, StpHeader::SIZE + MAX_PAYLOAD_SIZE);
+    'receive: while let Some(remaining) = duration.checked_sub(start_time.elapsed()) {
+        let received = match timeout(remaining, client.socket.recv_batch(&mut batch)).await {
+            Ok(Ok(received)) => received,
+            Ok(Err(e)) => {
+                let measurement = ThroughputMeasurement::new_error(
+                    ConnectionError::Unknown(format!("Socket receive error: {e}")),
+                    Duration::ZERO,
+                    0,
+                );
+                let _ = tx.send((start_time.elapsed(), measurement).into());
+                break;
+            }
+            // The test is over
+            Err(_) => break,
+        };
+        let arrival = current_timestamp_micros();
+        let read_start = Instant::now();
+
+        for (data, _) in received {
+            let Some(packet) = StpPacket::decode(data) else {
+                continue;
+            };
+            if client.answer_challenge(&packet).await
+                || !client.connection.on_received(&packet.header)
+            {
+                continue;
+            }
+            if client.connection.state == SessionState::Reset {
+                let measurement = ThroughputMeasurement::new_error(
+                    ConnectionError::TransferFailed("Server reset the session".to_string()),
+                    read_start.elapsed(),
+                    0,
+                );
+                let _ = tx.send((start_time.elapsed(), measurement).into());
+                break 'receive;
+            }
+
+            // Skip repeated INIT-ACKs
+            if packet.header.packet_type == PacketType::Data {
+                receiver.on_datagram(&packet.header, arrival);
+                let measurement =
+                    ThroughputMeasurement::new(packet.payload.len() as u64, read_start.elapsed());
+                let _ = tx.send((start_time.elapsed(), measurement).into());
+            }
+        }
+    }
+
+    drop(tx);
+    let (totals, intervals, connection_intervals) = stats_collector
+        .finish(progress_bar, "Download complete".to_string())
+        .await;
+    let end_time = Instant::now();
+
+    let server_measurement = match client.close().await {
+        Ok(measurement) => Some(measurement),
+        Err(e) => {
+            eprintln!("Could not fetch server-side results: {e}");
+            None
+        }
+    };
+
+    Ok((
+        ThroughputResult {
+            totals,
+            total_duration: end_time.duration_since(start_time),
+            intervals,
+            connection_intervals,
+            timestamp: chrono::Utc::now(),
+        },
+        server_measurement,
+        Some(receiver.stats()),
+        client.socket.path(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(packet_number: u64, timestamp: u64) -> StpHeader {
+        StpHeader {
+            packet_type: PacketType::Data,
+            session_id: 1,
+            packet_number,
+            timestamp,
+            latest_ack: 0,
+            ack_timestamp_echo: 0,
+        }
+    }
+
+    #[test]
+    fn test_loss_reordering_and_duplicates() {
+        let mut receiver = DatagramReceiver::new();
+        for packet_number in [1, 2, 4, 3, 3, 6] {
+            receiver.on_datagram(&header(packet_number, 0), 0);
+        }
+
+        let stats = receiver.stats();
+        assert_eq!(stats.received, 5);
+        assert_eq!(stats.lost, 1);
+        assert_eq!(stats.duplicates, 1);
+        assert_eq!(stats.out_of_order, 1);
+        assert_eq!(stats.jitter, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_jitter_follows_rfc3550() {
+        let mut receiver = DatagramReceiver::new();
+        // Sent every 20 ms with a clock offset of 1 s; the third one is delayed by 16 ms
+        let offset = 1_000_000;
+        receiver.on_datagram(&header(1, 0), offset);
+        receiver.on_datagram(&header(2, 20_000), offset + 20_000);
+        receiver.on_datagram(&header(3, 40_000), offset + 56_000);
+
+        assert_eq!(receiver.stats().jitter, Duration::from_micros(1000));
+    }
+}
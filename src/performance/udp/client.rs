@@ -8,36 +8,60 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::net::UdpSocket;
+use tokio::sync::mpsc::UnboundedSender;
 use tokio::time::{sleep, timeout};
 use tracing::trace;
 
-use super::congestion::{BbrCongestionControl, CongestionControl};
-use super::pacing::{PacedSend, Pacer};
+use super::cbr::{DatagramReceiver, run_cbr_download, run_cbr_upload};
+use super::congestion::{BbrCongestionControl, CongestionControl, new_congestion_control};
+use super::io::{DatagramSocket, RecvBatch};
 use super::protocol::{
-    ConnectionState, InFlightPacket, LossRecovery, StpPacket, calculate_rtt,
+    AckTracker, ConnectionState, InFlightPacket, LossRecovery, MAX_PAYLOAD_SIZE, PacketType,
+    SessionKind, SessionParams, SessionState, StpHeader, StpPacket, calculate_rtt,
     current_timestamp_micros,
 };
 use crate::{
-    TestType,
+    CongestionAlgorithm, TargetBitrate, TestType, UdpMode,
+    performance::{
+        idle_latency_duration, load_payload_size,
+        pacing::{Pacer, SharedPacer, connection_pacers},
+    },
     report::{
-        ConnectionError, LatencyMeasurement, LatencyResult, NetworkTestResult, TestReport,
-        ThroughputMeasurement, ThroughputResult, UdpTestConfig,
+        ConnectionError, DatagramStats, LatencyMeasurement, LatencyResult, LoadedLatencyResult,
+        NetworkTestResult, ServerMeasurement, TestConfig, TestReport, ThroughputMeasurement,
+        ThroughputResult, UdpIoPath, UdpTestConfig,
     },
     utils::{
         format::format_bytes,
         instrumentation::{
-            LatencyStatsCollector, ProgressBarType, ThroughputStatsCollector, create_progress_bar,
+            LatencyStatsCollector, ProgressBarType, ThroughputEvent, ThroughputStatsCollector,
+            create_progress_bar,
         },
+        output::{StreamEvent, TestPhase, emit, status},
     },
 };
 
 // TODO: Verify upload, download, latency modes all work correctly
 // TODO: Improve the STP implementation performance
 
+/// Client and server measurements of a download or upload, along with the delivery of the
+/// datagrams as seen by the receiver and the I/O path of the client
+pub(super) type Transfer = (
+    ThroughputResult,
+    Option<ServerMeasurement>,
+    Option<DatagramStats>,
+    UdpIoPath,
+);
+
+/// Number of times an INIT or CLOSE is sent before giving up
+const CONTROL_ATTEMPTS: usize = 3;
+
 /// STP Client for bandwidth measurement
 pub struct StpClient {
-    socket: UdpSocket,
-    connection: ConnectionState,
+    pub(super) socket: DatagramSocket,
+    pub(super) connection: ConnectionState,
+    /// Parameters of the session, repeated in the INITs answering the server's challenges
+    params: Option<SessionParams>,
     congestion_control: Box<dyn CongestionControl + Send>,
     loss_recovery: LossRecovery,
     pacer: Pacer,
@@ -54,18 +78,26 @@ pub struct StpClient {
 }
 
 impl StpClient {
-    pub async fn new(server_addr: &str) -> Result<Self> {
+    /// Connects a socket to `server_addr`, sending and receiving datagrams one per syscall
+    /// if `standard_io` is set and in batches where the platform allows otherwise
+    pub async fn new(server_addr: &str, standard_io: bool) -> Result<Self> {
         let socket = UdpSocket::bind("0.0.0.0:0").await?;
         socket.connect(server_addr).await?;
+        let socket = if standard_io {
+            DatagramSocket::standard(socket)
+        } else {
+            DatagramSocket::new(socket, false)
+        };
 
         let peer_addr = server_addr.parse()?;
-        let connection = ConnectionState::new(peer_addr);
+        let connection = ConnectionState::new(peer_addr, rand::random::<u64>().max(1));
         let congestion_control = Box::new(BbrCongestionControl::new());
         let initial_rate = congestion_control.get_sending_rate();
 
         Ok(Self {
             socket,
             connection,
+            params: None,
             congestion_control,
             loss_recovery: LossRecovery::new(),
             pacer: Pacer::new(initial_rate),
@@ -78,53 +110,91 @@ impl StpClient {
         })
     }
 
-    /// Send data with STP protocol
-    pub async fn send_data(&mut self, payload: Bytes) -> Result<()> {
-        let packet_number = self.connection.next_packet_number();
-        let packet = StpPacket::new(
-            packet_number,
-            self.connection.last_received_packet,
-            self.connection.last_received_timestamp,
-            payload.clone(),
-        );
+    /// Replaces the congestion control algorithm, which defaults to BBR
+    pub fn with_congestion_control(mut self, algorithm: CongestionAlgorithm) -> Self {
+        self.congestion_control = new_congestion_control(algorithm);
+        self.pacer
+            .update_rate(self.congestion_control.get_sending_rate());
+        self
+    }
+
+    /// Send `count` data packets carrying `payload` with STP protocol, in as few syscalls as
+    /// the socket allows
+    pub async fn send_data(&mut self, payload: Bytes, count: usize) -> Result<()> {
+        let packets: Vec<StpPacket> = (0..count)
+            .map(|_| self.connection.packet(PacketType::Data, payload.clone()))
+            .collect();
 
         // Store timestamp for RTT calculation
         {
             let mut timestamps = self.ack_timestamps.lock();
-            timestamps.insert(packet_number, packet.header.timestamp);
+            for packet in &packets {
+                timestamps.insert(packet.header.packet_number, packet.header.timestamp);
+            }
         }
 
         // Pace the sending
-        let wait_duration = self.pacer.schedule_next_send(packet.payload.len() + 32);
-        if let Some(duration) = wait_duration {
-            PacedSend::new(Some(duration)).await;
-        }
+        self.pacer
+            .wait(count * (payload.len() + StpHeader::SIZE))
+            .await;
 
-        // Send packet
-        let encoded = packet.encode();
-        self.socket.send(&encoded).await?;
+        // Send packets
+        let encoded: Vec<Bytes> = packets.iter().map(StpPacket::encode).collect();
+        self.socket.send_batch(&encoded, None).await?;
 
         // Update statistics and congestion control
         let now = Instant::now();
-        self.bytes_sent += encoded.len() as u64;
-        self.packets_sent += 1;
-
-        self.congestion_control.on_packet_sent(encoded.len(), now);
+        for (packet, encoded) in packets.iter().zip(encoded) {
+            self.bytes_sent += encoded.len() as u64;
+            self.packets_sent += 1;
+            self.congestion_control.on_packet_sent(encoded.len(), now);
+
+            // Track in-flight packet
+            let in_flight =
+                InFlightPacket::new(packet.header.packet_number, encoded.len(), encoded);
+            self.loss_recovery.on_packet_sent(in_flight);
+        }
         self.pacer
             .update_rate(self.congestion_control.get_sending_rate());
 
-        // Track in-flight packet
-        let in_flight = InFlightPacket::new(packet_number, encoded.len(), encoded);
-        self.loss_recovery.on_packet_sent(in_flight);
-
         Ok(())
     }
 
-    /// Process received ACK packet
-    pub async fn process_ack(&mut self, data: &[u8]) -> Result<()> {
-        if let Some(packet) = StpPacket::decode(Bytes::copy_from_slice(data)) {
-            self.connection.update_from_received(&packet.header);
+    /// Answers the server's challenge if `packet` is one, returning whether it was. The server
+    /// challenges the client when it opens a session and after its address changed, and
+    /// sends it nothing else until it echoed the cookie from its address. A failed send is
+    /// like a lost packet: the server challenges again.
+    pub async fn answer_challenge(&mut self, packet: &StpPacket) -> bool {
+        let Some(cookie) = packet.cookie() else {
+            return false;
+        };
+        if packet.header.session_id != self.connection.session_id {
+            return false;
+        }
+
+        if let Some(params) = self.params {
+            let init = self
+                .connection
+                .packet(PacketType::Init, params.encode_with_cookie(cookie));
+            let _ = self.socket.send(&init.encode()).await;
+        }
+        true
+    }
+
+    /// Process received ACK packet, returning the number of packets retransmitted as a result
+    pub async fn process_ack(&mut self, data: &[u8]) -> Result<u64> {
+        let mut retransmits = 0;
+        let Some(packet) = StpPacket::decode(Bytes::copy_from_slice(data)) else {
+            return Ok(0);
+        };
+        if self.answer_challenge(&packet).await || !self.connection.on_received(&packet.header) {
+            return Ok(0);
+        }
+        if self.connection.state == SessionState::Reset {
+            eyre::bail!("server reset the session");
+        }
 
+        if let Some(frame) = packet.ack_frame() {
             let now = Instant::now();
 
             // Calculate RTT if we have the timestamp
@@ -135,8 +205,7 @@ impl StpClient {
             };
 
             // Process acknowledgment
-            let (acked_packets, lost_packets) =
-                self.loss_recovery.on_ack_received(packet.header.latest_ack);
+            let (acked_packets, lost_packets) = self.loss_recovery.on_ack_frame(&frame);
 
             // Update statistics for acked packets
             for acked in &acked_packets {
@@ -172,6 +241,7 @@ impl StpClient {
                         InFlightPacket::new(new_packet_number, encoded.len(), encoded);
                     retransmit_in_flight.retransmitted = true;
                     self.loss_recovery.on_packet_sent(retransmit_in_flight);
+                    retransmits += 1;
                 }
             }
 
@@ -180,7 +250,86 @@ impl StpClient {
                 .update_rate(self.congestion_control.get_sending_rate());
         }
 
-        Ok(())
+        Ok(retransmits)
+    }
+
+    /// Opens a session running the test described by `params` and waits for the server to
+    /// accept it, answering its challenge on the way. INIT is repeated since either it or
+    /// the INIT-ACK may be lost.
+    pub async fn handshake(&mut self, params: SessionParams) -> Result<()> {
+        let mut buffer = vec![0u8; 65536];
+        self.params = Some(params);
+
+        for _ in 0..CONTROL_ATTEMPTS {
+            let init = self.connection.packet(PacketType::Init, params.encode());
+            self.socket.send(&init.encode()).await?;
+
+            let deadline = Instant::now() + Duration::from_secs(1);
+            while let Ok(Ok(size)) = timeout(
+                deadline.saturating_duration_since(Instant::now()),
+                self.socket.recv(&mut buffer),
+            )
+            .await
+            {
+                let Some(packet) = StpPacket::decode(Bytes::copy_from_slice(&buffer[..size]))
+                else {
+                    continue;
+                };
+                if self.answer_challenge(&packet).await
+                    || !self.connection.on_received(&packet.header)
+                {
+                    continue;
+                }
+                match self.connection.state {
+                    SessionState::Established => return Ok(()),
+                    SessionState::Reset => eyre::bail!("server refused the session"),
+                    _ => {}
+                }
+            }
+        }
+
+        eyre::bail!("server did not accept the session")
+    }
+
+    /// Closes the session, returning the server's measurement of the test carried by its
+    /// CLOSE. CLOSE is repeated since either it or the reply may be lost.
+    pub async fn close(&mut self) -> Result<ServerMeasurement> {
+        // The reply carries per-interval counts and can be larger than a data packet
+        let mut buffer = vec![0u8; 65536];
+
+        for _ in 0..CONTROL_ATTEMPTS {
+            let close = self.connection.packet(PacketType::Close, Bytes::new());
+            self.socket.send(&close.encode()).await?;
+
+            // Skip any data or ACKs still in flight until the reply shows up
+            let deadline = Instant::now() + Duration::from_secs(2);
+            while let Ok(Ok(size)) = timeout(
+                deadline.saturating_duration_since(Instant::now()),
+                self.socket.recv(&mut buffer),
+            )
+            .await
+            {
+                let Some(packet) = StpPacket::decode(Bytes::copy_from_slice(&buffer[..size]))
+                else {
+                    continue;
+                };
+                if self.answer_challenge(&packet).await
+                    || !self.connection.on_received(&packet.header)
+                {
+                    continue;
+                }
+                match self.connection.state {
+                    SessionState::Closed => return Ok(serde_json::from_slice(&packet.payload)?),
+                    SessionState::Reset => eyre::bail!("server reset the session"),
+                    _ => {}
+                }
+            }
+        }
+
+        // Don't leave the session open on the server
+        let reset = self.connection.packet(PacketType::Reset, Bytes::new());
+        let _ = self.socket.send(&reset.encode()).await;
+        eyre::bail!("server did not answer the close")
     }
 
     /// Get current throughput statistics
@@ -206,7 +355,20 @@ impl StpClient {
 pub async fn run_udp_client(config: UdpTestConfig) -> Result<TestReport> {
     let server_addr = format!("{}:{}", config.server, config.port);
 
-    println!(
+    // The server refuses sessions whose payloads don't fit in a datagram
+    if let Some(payload_size) = config
+        .payload_sizes
+        .iter()
+        .find(|payload_size| **payload_size > MAX_PAYLOAD_SIZE)
+    {
+        eyre::bail!(
+            "UDP payloads must be at most {} to fit in a datagram, got {}",
+            format_bytes(MAX_PAYLOAD_SIZE),
+            format_bytes(*payload_size)
+        );
+    }
+
+    status!(
         "{}",
         format!("Starting UDP test to server {}...", server_addr.cyan())
             .green()
@@ -214,92 +376,77 @@ pub async fn run_udp_client(config: UdpTestConfig) -> Result<TestReport> {
     );
 
     let start_time = Utc::now();
+    emit(&StreamEvent::TestStarted {
+        start_time,
+        config: &TestConfig::from(config.clone()),
+    });
 
     let mut result = NetworkTestResult::new_udp();
 
+    let duration = Duration::from_secs(config.duration);
     match config.test_type {
+        TestType::Responsiveness => {
+            eyre::bail!("Responsiveness tests require HTTP/2 or HTTP/3");
+        }
         TestType::LatencyOnly => {
-            result.latency = measure_udp_latency(&config).await?;
+            result.latency = measure_udp_latency(&config.server, config.port, duration).await?;
+        }
+        TestType::LatencyUnderLoad => {
+            let payload_size = load_payload_size(&config.payload_sizes)?;
+            result.latency =
+                measure_udp_latency(&config.server, config.port, idle_latency_duration(duration))
+                    .await?;
+
+            status!("Measuring UDP latency under download load...");
+            let (latency, download) = tokio::join!(
+                measure_udp_latency(&config.server, config.port, duration),
+                run_download(&config, payload_size, duration)
+            );
+            let mut loaded = LoadedLatencyResult {
+                download: latency?,
+                upload: None,
+            };
+            add_download(&mut result, payload_size, download?);
+
+            status!("Measuring UDP latency under upload load...");
+            let (latency, upload) = tokio::join!(
+                measure_udp_latency(&config.server, config.port, duration),
+                run_upload(&config, payload_size, duration)
+            );
+            loaded.upload = latency?;
+            add_upload(&mut result, payload_size, upload?);
+            result.loaded_latency = Some(loaded);
         }
         TestType::Download => {
             for payload_size in &config.payload_sizes {
-                result.download.insert(
-                    *payload_size,
-                    run_download_test(
-                        &config.server,
-                        config.port,
-                        config.parallel_streams,
-                        *payload_size,
-                        Duration::from_secs(config.duration),
-                    )
-                    .await?,
-                );
+                let download = run_download(&config, *payload_size, duration).await?;
+                add_download(&mut result, *payload_size, download);
             }
         }
         TestType::Upload => {
             for payload_size in &config.payload_sizes {
-                result.upload.insert(
-                    *payload_size,
-                    run_upload_test(
-                        &config.server,
-                        config.port,
-                        config.parallel_streams,
-                        *payload_size,
-                        Duration::from_secs(config.duration),
-                    )
-                    .await?,
-                );
+                let upload = run_upload(&config, *payload_size, duration).await?;
+                add_upload(&mut result, *payload_size, upload);
             }
         }
         TestType::Bidirectional => {
             // Run download and upload sequentially
             for payload_size in &config.payload_sizes {
-                result.download.insert(
-                    *payload_size,
-                    run_download_test(
-                        &config.server,
-                        config.port,
-                        config.parallel_streams,
-                        *payload_size,
-                        Duration::from_secs(config.duration),
-                    )
-                    .await?,
-                );
-                result.upload.insert(
-                    *payload_size,
-                    run_upload_test(
-                        &config.server,
-                        config.port,
-                        config.parallel_streams,
-                        *payload_size,
-                        Duration::from_secs(config.duration),
-                    )
-                    .await?,
-                );
+                let download = run_download(&config, *payload_size, duration).await?;
+                add_download(&mut result, *payload_size, download);
+                let upload = run_upload(&config, *payload_size, duration).await?;
+                add_upload(&mut result, *payload_size, upload);
             }
         }
         TestType::Simultaneous => {
             // Run download and upload concurrently
             for payload_size in &config.payload_sizes {
-                let (download_result, upload_result) = tokio::join!(
-                    run_download_test(
-                        &config.server,
-                        config.port,
-                        config.parallel_streams,
-                        *payload_size,
-                        Duration::from_secs(config.duration),
-                    ),
-                    run_upload_test(
-                        &config.server,
-                        config.port,
-                        config.parallel_streams,
-                        *payload_size,
-                        Duration::from_secs(config.duration),
-                    )
+                let (download, upload) = tokio::join!(
+                    run_download(&config, *payload_size, duration),
+                    run_upload(&config, *payload_size, duration)
                 );
-
-                result.download.insert(*payload_size, download_result?);
-                result.upload.insert(*payload_size, upload_result?);
+                add_download(&mut result, *payload_size, download?);
+                add_upload(&mut result, *payload_size, upload?);
             }
         }
     }
@@ -307,13 +454,109 @@ pub async fn run_udp_client(config: UdpTestConfig) -> Result<TestReport> {
     Ok((start_time, config, result).into())
 }
 
+/// Runs a download in the mode of `config`
+async fn run_download(
+    config: &UdpTestConfig,
+    payload_size: usize,
+    duration: Duration,
+) -> Result<Transfer> {
+    match config.mode {
+        UdpMode::Stp => {
+            run_download_test(
+                &config.server,
+                config.port,
+                config.parallel_streams,
+                payload_size,
+                duration,
+                config.interval,
+                config.standard_io,
+            )
+            .await
+        }
+        UdpMode::Cbr => {
+            run_cbr_download(
+                &config.server,
+                config.port,
+                payload_size,
+                duration,
+                config.interval,
+                config.bitrate,
+                config.standard_io,
+            )
+            .await
+        }
+    }
+}
+
+/// Runs an upload in the mode of `config`
+async fn run_upload(
+    config: &UdpTestConfig,
+    payload_size: usize,
+    duration: Duration,
+) -> Result<Transfer> {
+    match config.mode {
+        UdpMode::Stp => {
+            run_upload_test(
+                &config.server,
+                config.port,
+                config.parallel_streams,
+                payload_size,
+                duration,
+                config.interval,
+                config.bitrate,
+                config.congestion_control,
+                config.standard_io,
+            )
+            .await
+        }
+        UdpMode::Cbr => {
+            run_cbr_upload(
+                &config.server,
+                config.port,
+                payload_size,
+                duration,
+                config.interval,
+                config.bitrate,
+                config.standard_io,
+            )
+            .await
+        }
+    }
+}
+
+fn add_download(result: &mut NetworkTestResult, payload_size: usize, transfer: Transfer) {
+    let (download, server, datagrams, udp_io) = transfer;
+    result.add_download(payload_size, download, server);
+    if let Some(datagrams) = datagrams {
+        result.datagrams.download.insert(payload_size, datagrams);
+    }
+    add_udp_io(result, udp_io);
+}
+
+fn add_upload(result: &mut NetworkTestResult, payload_size: usize, transfer: Transfer) {
+    let (upload, server, datagrams, udp_io) = transfer;
+    result.add_upload(payload_size, upload, server);
+    if let Some(datagrams) = datagrams {
+        result.datagrams.upload.insert(payload_size, datagrams);
+    }
+    add_udp_io(result, udp_io);
+}
+
+/// Records the I/O path of a transfer, keeping the slowest one if a socket had to fall back
+fn add_udp_io(result: &mut NetworkTestResult, udp_io: UdpIoPath) {
+    result.udp_io = Some(result.udp_io.map_or(udp_io, |path| path.min(udp_io)));
+}
+
 /// Measure UDP latency using simple UDP packets
-async fn measure_udp_latency(config: &UdpTestConfig) -> Result<Option<LatencyResult>> {
-    let addr = format!("{}:{}", config.server, config.port);
-    let duration = Duration::from_secs(config.duration);
+async fn measure_udp_latency(
+    server: &str,
+    port: u16,
+    duration: Duration,
+) -> Result<Option<LatencyResult>> {
+    let addr = format!("{server}:{port}");
     let mut measurements = Vec::new();
 
-    println!("Measuring UDP latency for {duration:?}...");
+    status!("Measuring UDP latency for {duration:?}...");
 
     // Create progress bar for latency measurement
     let progress_bar = create_progress_bar(ProgressBarType::Latency, duration);
@@ -327,7 +570,8 @@ async fn measure_udp_latency(config: &UdpTestConfig) -> Result<Option<LatencyRes
         let connect_start = Instant::now();
 
         // Create STP client for latency measurement
-        let mut client = match StpClient::new(&addr).await {
+        // Probes are single datagrams either way
+        let mut client = match StpClient::new(&addr, true).await {
             Ok(c) => c,
             Err(_) => {
                 let measurement = LatencyMeasurement {
@@ -341,12 +585,7 @@ async fn measure_udp_latency(config: &UdpTestConfig) -> Result<Option<LatencyRes
         };
 
         // Send an STP ping packet
-        let ping_packet = StpPacket::new(
-            client.connection.next_packet_number(),
-            0,
-            0,
-            Bytes::from("PING"),
-        );
+        let ping_packet = client.connection.packet(PacketType::Ping, Bytes::new());
 
         match client.socket.send(&ping_packet.encode()).await {
             Ok(_) => {
@@ -354,8 +593,9 @@ async fn measure_udp_latency(config: &UdpTestConfig) -> Result<Option<LatencyRes
                 let mut buffer = [0u8; 2048];
                 match timeout(Duration::from_millis(1000), client.socket.recv(&mut buffer)).await {
                     Ok(Ok(size)) => {
-                        if let Some(_response_packet) =
+                        if let Some(response_packet) =
                             StpPacket::decode(Bytes::copy_from_slice(&buffer[..size]))
+                            && response_packet.header.packet_type == PacketType::Pong
                         {
                             let rtt = connect_start.elapsed().as_secs_f64() * 1000.0;
                             let measurement = LatencyMeasurement {
@@ -425,102 +665,241 @@ async fn measure_udp_latency(config: &UdpTestConfig) -> Result<Option<LatencyRes
     }))
 }
 
+/// Opens `streams` sessions running the test described by `params`, each on its own socket
+/// so that they are independent flows
+async fn connect_streams(
+    addr: &str,
+    streams: usize,
+    params: SessionParams,
+    standard_io: bool,
+) -> Result<Vec<StpClient>> {
+    futures::future::try_join_all((0..streams).map(|_| async move {
+        let mut client = StpClient::new(addr, standard_io).await?;
+        client.handshake(params).await?;
+        Ok::<_, eyre::Report>(client)
+    }))
+    .await
+}
+
+/// Returns the slowest I/O path the sockets of `clients` took
+fn io_path(clients: &[StpClient]) -> UdpIoPath {
+    clients
+        .iter()
+        .map(|client| client.socket.path())
+        .min()
+        .unwrap_or(UdpIoPath::Standard)
+}
+
+/// Closes the sessions of `clients`, returning the server's measurements of them merged
+/// into one
+async fn close_streams(clients: &mut [StpClient]) -> Option<ServerMeasurement> {
+    let results = futures::future::join_all(clients.iter_mut().map(StpClient::close)).await;
+
+    let mut server_measurement: Option<ServerMeasurement> = None;
+    for result in results {
+        match result {
+            Ok(measurement) => match &mut server_measurement {
+                Some(merged) => merged.merge(&measurement),
+                None => server_measurement = Some(measurement),
+            },
+            Err(e) => eprintln!("Could not fetch server-side results: {e}"),
+        }
+    }
+    server_measurement
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn run_download_test(
     server: &str,
     port: u16,
-    _parallel_connections: usize,
+    parallel_streams: usize,
     payload_size: usize,
     duration: Duration,
-) -> Result<ThroughputResult> {
-    println!(
-        "Starting UDP download test with {} payload size...",
-        format_bytes(payload_size).yellow()
+    interval: Duration,
+    standard_io: bool,
+) -> Result<Transfer> {
+    status!(
+        "Starting UDP download test with {} payload size and {} parallel streams...",
+        format_bytes(payload_size).yellow(),
+        parallel_streams.to_string().yellow()
     );
 
+    // Open the download sessions; the server starts sending once it accepted them
+    let addr = format!("{server}:{port}");
+    let clients = connect_streams(
+        &addr,
+        parallel_streams,
+        SessionParams {
+            kind: SessionKind::Download,
+            payload_size,
+            bitrate: 0.0,
+            duration,
+        },
+        standard_io,
+    )
+    .await?;
+
     // Create progress bar
     let progress_bar = create_progress_bar(ProgressBarType::Download, duration);
 
-    let mut measurements = Vec::new();
     let start_time = Instant::now();
 
     // Set up instrumentation
-    let (stats_collector, tx) =
-        ThroughputStatsCollector::new(progress_bar.clone(), start_time, duration);
-
-    let addr = format!("{server}:{port}");
-    let mut client = StpClient::new(&addr).await?;
-
-    // Send a download command to the server first, including the payload size
-    let download_cmd_data = format!("DOWNLOAD:{}", payload_size);
-    let download_cmd = StpPacket::new(
-        client.connection.next_packet_number(),
-        0,
-        0,
-        Bytes::from(download_cmd_data),
+    let (stats_collector, tx) = ThroughputStatsCollector::new(
+        progress_bar.clone(),
+        TestPhase::Download { payload_size },
+        start_time,
+        duration,
+        interval,
     );
-    let encoded = download_cmd.encode();
-    // println!(
-    //     "Sending DOWNLOAD command to server... (size: {} bytes)",
-    //     encoded.len()
-    // );
-    client.socket.send(&encoded).await?;
-    // println!("DOWNLOAD command sent, waiting for response...");
-
-    let mut recv_buffer = vec![0u8; 2048];
+
+    let tasks: Vec<_> = clients
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut client)| {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let datagrams = receive_download(&mut client, i, start_time, duration, &tx).await;
+                (client, datagrams)
+            })
+        })
+        .collect();
+
+    // Wait for all streams to complete concurrently
+    let results = futures::future::join_all(tasks).await;
+
+    // Drop the sender to signal stats collector to finish
+    drop(tx);
+
+    // Wait for stats collector to complete and get measurements
+    let (totals, intervals, connection_intervals) = stats_collector
+        .finish(progress_bar, "Download complete".to_string())
+        .await;
+
+    let end_time = Instant::now();
+
+    let mut clients = Vec::new();
+    let mut datagrams = DatagramStats::default();
+    for result in results {
+        let (client, stream_datagrams) = result?;
+        datagrams.merge(&stream_datagrams);
+        clients.push(client);
+    }
+    let udp_io = io_path(&clients);
+    let server_measurement = close_streams(&mut clients).await;
+
+    Ok((
+        ThroughputResult {
+            totals,
+            total_duration: end_time.duration_since(start_time),
+            intervals,
+            connection_intervals,
+            timestamp: chrono::Utc::now(),
+        },
+        server_measurement,
+        (datagrams.received > 0).then_some(datagrams),
+        udp_io,
+    ))
+}
+
+/// Receives and acknowledges the data of the download session of `client` until `duration`
+/// elapsed, reporting it as `connection`. Returns the delivery of its datagrams.
+async fn receive_download(
+    client: &mut StpClient,
+    connection: usize,
+    start_time: Instant,
+    duration: Duration,
+    tx: &UnboundedSender<ThroughputEvent>,
+) -> DatagramStats {
+    let send = |measurement| {
+        let _ = tx.send(ThroughputEvent::Measurement {
+            connection,
+            offset: start_time.elapsed(),
+            measurement,
+        });
+    };
+
+    let mut batch = RecvBatch::new(&client.socket, 2048);
     let mut last_successful_receive = Instant::now();
     let mut timeout_count = 0;
+    let mut acks = AckTracker::new();
+    let mut datagrams = DatagramReceiver::new();
 
-    while start_time.elapsed() < duration {
+    'receive: while start_time.elapsed() < duration {
         // Try to receive data (non-blocking with short timeout)
         match timeout(
             Duration::from_millis(50),
-            client.socket.recv(&mut recv_buffer),
+            client.socket.recv_batch(&mut batch),
         )
         .await
         {
-            Ok(Ok(size)) => {
+            Ok(Ok(received)) => {
+                let arrival = current_timestamp_micros();
                 let read_start = Instant::now();
                 last_successful_receive = read_start;
                 timeout_count = 0; // Reset timeout counter on successful receive
 
-                // Process received packet
-                if let Some(packet) =
-                    StpPacket::decode(Bytes::copy_from_slice(&recv_buffer[..size]))
-                {
-                    // Send ACK
-                    let ack_packet = StpPacket::ack_only(
+                let mut ack_packets = Vec::new();
+                for (data, _) in received {
+                    // Process received packet
+                    let Some(packet) = StpPacket::decode(data) else {
+                        // Invalid packet received - log as error
+                        send(ThroughputMeasurement::new_error(
+                            ConnectionError::TransferFailed(
+                                "Invalid STP packet received".to_string(),
+                            ),
+                            read_start.elapsed(),
+                            0,
+                        ));
+                        continue;
+                    };
+                    if client.answer_challenge(&packet).await
+                        || !client.connection.on_received(&packet.header)
+                    {
+                        continue;
+                    }
+                    if client.connection.state == SessionState::Reset {
+                        send(ThroughputMeasurement::new_error(
+                            ConnectionError::TransferFailed("Server reset the session".to_string()),
+                            read_start.elapsed(),
+                            0,
+                        ));
+                        break 'receive;
+                    }
+
+                    // Every packet of the server counts towards loss and reordering
+                    acks.on_packet_received(packet.header.packet_number);
+                    datagrams.on_datagram(&packet.header, arrival);
+
+                    // Only data is acknowledged and measured
+                    if packet.header.packet_type != PacketType::Data {
+                        continue;
+                    }
+
+                    let ack_packet = StpPacket::ack(
+                        client.connection.session_id,
                         client.connection.next_packet_number(),
-                        packet.header.packet_number,
+                        &acks.frame(),
                         packet.header.timestamp,
                     );
-                    let _ = client.socket.send(&ack_packet.encode()).await;
+                    ack_packets.push(ack_packet.encode());
 
-                    let measurement = ThroughputMeasurement::new(
+                    send(ThroughputMeasurement::new(
                         packet.payload.len() as u64,
                         read_start.elapsed(),
-                    );
-                    measurements.push(measurement.clone());
-                    let _ = tx.send(measurement);
-                } else {
-                    // Invalid packet received - log as error
-                    let error_measurement = ThroughputMeasurement::new_error(
-                        ConnectionError::TransferFailed("Invalid STP packet received".to_string()),
-                        read_start.elapsed(),
-                        0,
-                    );
-                    measurements.push(error_measurement.clone());
-                    let _ = tx.send(error_measurement);
+                    ));
                 }
+
+                // The ACKs of a batch go out together
+                let _ = client.socket.send_batch(&ack_packets, None).await;
             }
             Ok(Err(e)) => {
                 // Socket error - log as error
-                let error_measurement = ThroughputMeasurement::new_error(
+                send(ThroughputMeasurement::new_error(
                     ConnectionError::Unknown(format!("Socket receive error: {}", e)),
                     last_successful_receive.elapsed(),
                     0,
-                );
-                measurements.push(error_measurement.clone());
-                let _ = tx.send(error_measurement);
+                ));
                 break; // Exit on socket error
             }
             Err(_) => {
@@ -530,16 +909,14 @@ async fn run_download_test(
 
                 if time_since_last_data > Duration::from_secs(2) {
                     // Log timeout as an error measurement
-                    let error_measurement = ThroughputMeasurement::new_error(
+                    send(ThroughputMeasurement::new_error(
                         ConnectionError::Timeout(format!(
                             "No data received for {:.1}s",
                             time_since_last_data.as_secs_f32()
                         )),
                         time_since_last_data,
                         timeout_count,
-                    );
-                    measurements.push(error_measurement.clone());
-                    let _ = tx.send(error_measurement);
+                    ));
 
                     // Reset timeout tracking
                     last_successful_receive = Instant::now();
@@ -548,44 +925,46 @@ async fn run_download_test(
                 continue;
             }
         }
-
-        // Small delay to prevent busy waiting
-        tokio::time::sleep(Duration::from_micros(500)).await;
     }
 
-    // Drop the sender to signal stats collector to finish
-    drop(tx);
-
-    // Wait for stats collector to complete and get measurements
-    measurements = stats_collector
-        .finish(progress_bar, "Download complete".to_string())
-        .await;
-
-    let end_time = Instant::now();
-
-    Ok(ThroughputResult {
-        measurements,
-        total_duration: end_time.duration_since(start_time),
-        timestamp: chrono::Utc::now(),
-    })
+    datagrams.stats()
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_upload_test(
     server: &str,
     port: u16,
-    _parallel_connections: usize,
+    parallel_streams: usize,
     payload_size: usize,
     duration: Duration,
-) -> Result<ThroughputResult> {
-    println!(
-        "Starting UDP upload test with {} payload size...",
-        format_bytes(payload_size).yellow()
+    interval: Duration,
+    bitrate: Option<TargetBitrate>,
+    congestion_control: CongestionAlgorithm,
+    standard_io: bool,
+) -> Result<Transfer> {
+    status!(
+        "Starting UDP upload test with {} payload size and {} parallel streams...",
+        format_bytes(payload_size).yellow(),
+        parallel_streams.to_string().yellow()
     );
 
+    let addr = format!("{server}:{port}");
+    let clients = connect_streams(
+        &addr,
+        parallel_streams,
+        SessionParams {
+            kind: SessionKind::Upload,
+            payload_size,
+            bitrate: 0.0,
+            duration,
+        },
+        standard_io,
+    )
+    .await?;
+
     // Create progress bar
     let progress_bar = create_progress_bar(ProgressBarType::Upload, duration);
 
-    let mut measurements = Vec::new();
     let start_time = Instant::now();
 
     // Generate upload data
@@ -594,70 +973,153 @@ async fn run_upload_test(
     let payload = Bytes::from(upload_data);
 
     // Set up instrumentation
-    let (stats_collector, tx) =
-        ThroughputStatsCollector::new(progress_bar.clone(), start_time, duration);
+    let (stats_collector, tx) = ThroughputStatsCollector::new(
+        progress_bar.clone(),
+        TestPhase::Upload { payload_size },
+        start_time,
+        duration,
+        interval,
+    );
 
-    let addr = format!("{server}:{port}");
-    let mut client = StpClient::new(&addr).await?;
+    // Each stream runs its own congestion controller
+    let pacers = connection_pacers(bitrate, parallel_streams);
+    let tasks: Vec<_> = clients
+        .into_iter()
+        .zip(pacers)
+        .enumerate()
+        .map(|(i, (client, pacer))| {
+            let mut client = client.with_congestion_control(congestion_control);
+            let payload = payload.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                send_upload(&mut client, i, start_time, duration, &tx, payload, pacer).await;
+                client
+            })
+        })
+        .collect();
 
-    let mut recv_buffer = vec![0u8; 2048];
+    // Wait for all streams to complete concurrently
+    let results = futures::future::join_all(tasks).await;
 
-    while start_time.elapsed() < duration {
-        // Send data if congestion control allows
-        let (bytes_sent, _, _, _, _sending_rate, _avg_rtt) = client.get_stats();
-        let bytes_in_flight = bytes_sent - client.bytes_acked;
+    // Drop the sender to signal stats collector to finish
+    drop(tx);
+
+    // Wait for stats collector to complete and get measurements
+    let (totals, intervals, connection_intervals) = stats_collector
+        .finish(progress_bar, "Upload complete".to_string())
+        .await;
+
+    let end_time = Instant::now();
+
+    let mut clients = results.into_iter().collect::<Result<Vec<_>, _>>()?;
+    let udp_io = io_path(&clients);
+    let mut server_measurement = close_streams(&mut clients).await;
+    let datagrams = server_measurement
+        .as_mut()
+        .and_then(|measurement| measurement.datagrams.take());
+
+    Ok((
+        ThroughputResult {
+            totals,
+            total_duration: end_time.duration_since(start_time),
+            intervals,
+            connection_intervals,
+            timestamp: chrono::Utc::now(),
+        },
+        server_measurement,
+        datagrams,
+        udp_io,
+    ))
+}
+
+/// Sends `payload` over the upload session of `client` as fast as its congestion control
+/// and `pacer` allow until `duration` elapsed, reporting it as `connection`
+async fn send_upload(
+    client: &mut StpClient,
+    connection: usize,
+    start_time: Instant,
+    duration: Duration,
+    tx: &UnboundedSender<ThroughputEvent>,
+    payload: Bytes,
+    pacer: Option<SharedPacer>,
+) {
+    let send = |measurement| {
+        let _ = tx.send(ThroughputEvent::Measurement {
+            connection,
+            offset: start_time.elapsed(),
+            measurement,
+        });
+    };
+
+    let mut batch = RecvBatch::new(&client.socket, 2048);
+    let packet_size = StpHeader::SIZE + payload.len();
 
-        if client.congestion_control.can_send(bytes_in_flight as usize) {
+    'send: while start_time.elapsed() < duration {
+        // Send as many packets as congestion control allows, up to a batch
+        let (bytes_sent, _, _, _, _sending_rate, _avg_rtt) = client.get_stats();
+        let bytes_in_flight = (bytes_sent - client.bytes_acked) as usize;
+        let count = (0..client.socket.batch_size())
+            .take_while(|count| {
+                client
+                    .congestion_control
+                    .can_send(bytes_in_flight + count * packet_size)
+            })
+            .count();
+
+        if count > 0 {
+            if let Some(pacer) = &pacer {
+                pacer.wait(count * payload.len()).await;
+            }
             let write_start = Instant::now();
-            match client.send_data(payload.clone()).await {
+            match client.send_data(payload.clone(), count).await {
                 Ok(_) => {
-                    let measurement =
-                        ThroughputMeasurement::new(payload.len() as u64, write_start.elapsed());
-                    measurements.push(measurement.clone());
-
                     // Send to stats collector (non-blocking)
-                    let _ = tx.send(measurement);
+                    send(ThroughputMeasurement::new(
+                        (count * payload.len()) as u64,
+                        write_start.elapsed(),
+                    ));
                 }
                 Err(e) => {
-                    let measurement = ThroughputMeasurement::new_error(
+                    send(ThroughputMeasurement::new_error(
                         ConnectionError::TransferFailed(format!("UDP send error: {e}")),
                         write_start.elapsed(),
                         0,
-                    );
-                    measurements.push(measurement.clone());
-                    let _ = tx.send(measurement);
+                    ));
                     break;
                 }
             }
         }
 
-        // Try to receive ACKs (non-blocking)
-        if let Ok(Ok((size, _))) = timeout(
+        // Try to receive the ACKs that arrived (non-blocking)
+        if let Ok(Ok(received)) = timeout(
             Duration::from_millis(1),
-            client.socket.recv_from(&mut recv_buffer),
+            client.socket.recv_batch(&mut batch),
         )
         .await
         {
-            let _ = client.process_ack(&recv_buffer[..size]).await;
+            for (data, _) in received {
+                match client.process_ack(&data).await {
+                    Ok(0) => {}
+                    Ok(retransmits) => {
+                        let _ = tx.send(ThroughputEvent::Retransmits {
+                            connection,
+                            offset: start_time.elapsed(),
+                            count: retransmits,
+                        });
+                    }
+                    Err(e) => {
+                        send(ThroughputMeasurement::new_error(
+                            ConnectionError::TransferFailed(e.to_string()),
+                            Duration::ZERO,
+                            0,
+                        ));
+                        break 'send;
+                    }
+                }
+            }
         }
 
         // Small delay to prevent busy waiting
         tokio::time::sleep(Duration::from_micros(100)).await;
     }
-
-    // Drop the sender to signal stats collector to finish
-    drop(tx);
-
-    // Wait for stats collector to complete and get measurements
-    measurements = stats_collector
-        .finish(progress_bar, "Upload complete".to_string())
-        .await;
-
-    let end_time = Instant::now();
-
-    Ok(ThroughputResult {
-        measurements,
-        total_duration: end_time.duration_since(start_time),
-        timestamp: chrono::Utc::now(),
-    })
 }
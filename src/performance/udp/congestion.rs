@@ -1,8 +1,31 @@
 use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 
+use crate::CongestionAlgorithm;
+
 // TODO: Check the logic for correctness here...
 
+/// Segment size the window-based algorithms count in, matching the STP packet size
+const MAX_SEGMENT_SIZE: usize = 1400;
+/// Initial congestion window of the window-based algorithms (RFC 6928)
+const INITIAL_WINDOW: usize = 10 * MAX_SEGMENT_SIZE;
+/// Smallest congestion window of the window-based algorithms
+const MIN_WINDOW: usize = 2 * MAX_SEGMENT_SIZE;
+/// Largest congestion window, the same as BBR's
+const MAX_WINDOW: usize = 1024 * 1024;
+/// RTT assumed by the window-based algorithms until the first ACK arrives
+const INITIAL_RTT: Duration = Duration::from_millis(100);
+
+/// Creates the congestion controller implementing `algorithm`
+pub fn new_congestion_control(algorithm: CongestionAlgorithm) -> Box<dyn CongestionControl + Send> {
+    match algorithm {
+        CongestionAlgorithm::Bbr => Box::new(BbrCongestionControl::new()),
+        CongestionAlgorithm::Cubic => Box::new(CubicCongestionControl::new()),
+        CongestionAlgorithm::NewReno => Box::new(NewRenoCongestionControl::new()),
+        CongestionAlgorithm::None => Box::new(NoCongestionControl),
+    }
+}
+
 /// Trait for congestion control algorithms
 pub trait CongestionControl {
     /// Called when a packet is sent
@@ -18,6 +41,7 @@ pub trait CongestionControl {
     fn get_sending_rate(&self) -> f64;
 
     /// Get current congestion window in bytes
+    #[allow(dead_code)]
     fn get_cwnd(&self) -> usize;
 
     /// Check if we can send more data
@@ -336,6 +360,239 @@ impl Default for BbrCongestionControl {
     }
 }
 
+/// Smoothed RTT of the window-based algorithms (RFC 6298)
+#[derive(Debug, Default)]
+struct RttEstimator {
+    smoothed: Option<Duration>,
+}
+
+impl RttEstimator {
+    fn update(&mut self, rtt: Duration) {
+        self.smoothed = Some(match self.smoothed {
+            None => rtt,
+            Some(smoothed) => smoothed.mul_f64(0.875) + rtt.mul_f64(0.125),
+        });
+    }
+
+    fn smoothed(&self) -> Duration {
+        self.smoothed.unwrap_or(INITIAL_RTT)
+    }
+}
+
+/// Sending rate of a window-based algorithm: the congestion window per smoothed RTT,
+/// paced faster than that during slow start so the window can keep doubling, as Linux does
+fn window_pacing_rate(cwnd: usize, ssthresh: usize, rtt: &RttEstimator) -> f64 {
+    let gain = if cwnd < ssthresh { 2.0 } else { 1.25 };
+    gain * cwnd as f64 / rtt.smoothed().as_secs_f64().max(1e-6)
+}
+
+/// NewReno congestion control (RFC 5681, RFC 6582): slow start, then one segment more per
+/// RTT, halving the window once per round trip with losses
+#[derive(Debug)]
+pub struct NewRenoCongestionControl {
+    cwnd: usize,
+    ssthresh: usize,
+    rtt: RttEstimator,
+    /// Losses until then belong to the same congestion event and don't shrink the window again
+    recovery_until: Option<Instant>,
+}
+
+impl NewRenoCongestionControl {
+    pub fn new() -> Self {
+        Self {
+            cwnd: INITIAL_WINDOW,
+            ssthresh: usize::MAX,
+            rtt: RttEstimator::default(),
+            recovery_until: None,
+        }
+    }
+}
+
+impl Default for NewRenoCongestionControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CongestionControl for NewRenoCongestionControl {
+    fn on_packet_sent(&mut self, _bytes_sent: usize, _now: Instant) {}
+
+    fn on_ack_received(&mut self, bytes_acked: usize, rtt: Duration, _now: Instant) {
+        self.rtt.update(rtt);
+
+        let increase = if self.cwnd < self.ssthresh {
+            bytes_acked
+        } else {
+            (MAX_SEGMENT_SIZE * bytes_acked / self.cwnd).max(1)
+        };
+        self.cwnd = (self.cwnd + increase).min(MAX_WINDOW);
+    }
+
+    fn on_packet_lost(&mut self, _bytes_lost: usize, now: Instant) {
+        if self.recovery_until.is_some_and(|until| now < until) {
+            return;
+        }
+
+        self.ssthresh = (self.cwnd / 2).max(MIN_WINDOW);
+        self.cwnd = self.ssthresh;
+        self.recovery_until = Some(now + self.rtt.smoothed());
+    }
+
+    fn get_sending_rate(&self) -> f64 {
+        window_pacing_rate(self.cwnd, self.ssthresh, &self.rtt).max(1000.0) // Minimum 1KB/s
+    }
+
+    fn get_cwnd(&self) -> usize {
+        self.cwnd
+    }
+
+    fn can_send(&self, bytes_in_flight: usize) -> bool {
+        bytes_in_flight < self.cwnd
+    }
+}
+
+/// CUBIC congestion control (RFC 9438): after a loss the window grows along a cubic
+/// function of the time since, plateauing around the window the loss happened at
+#[derive(Debug)]
+pub struct CubicCongestionControl {
+    cwnd: usize,
+    ssthresh: usize,
+    rtt: RttEstimator,
+    recovery_until: Option<Instant>,
+    /// Window before the last reduction, in segments
+    w_max: f64,
+    /// Start of the current congestion avoidance epoch
+    epoch_start: Option<Instant>,
+    /// Time the cubic function takes to grow back to `w_max`, in seconds
+    k: f64,
+    /// Window Reno would have in the current epoch, in segments
+    w_est: f64,
+}
+
+impl CubicCongestionControl {
+    const C: f64 = 0.4;
+    const BETA: f64 = 0.7;
+
+    pub fn new() -> Self {
+        Self {
+            cwnd: INITIAL_WINDOW,
+            ssthresh: usize::MAX,
+            rtt: RttEstimator::default(),
+            recovery_until: None,
+            w_max: 0.0,
+            epoch_start: None,
+            k: 0.0,
+            w_est: 0.0,
+        }
+    }
+
+    fn segments(&self) -> f64 {
+        self.cwnd as f64 / MAX_SEGMENT_SIZE as f64
+    }
+
+    fn congestion_avoidance(&mut self, bytes_acked: usize, now: Instant) {
+        let cwnd = self.segments();
+        let epoch_start = *self.epoch_start.get_or_insert_with(|| {
+            self.k = if self.w_max > cwnd {
+                ((self.w_max - cwnd) / Self::C).cbrt()
+            } else {
+                self.w_max = cwnd;
+                0.0
+            };
+            self.w_est = cwnd;
+            now
+        });
+
+        // Target one RTT ahead, growing by at most half the window per RTT
+        let t = (now - epoch_start + self.rtt.smoothed()).as_secs_f64();
+        let target = (Self::C * (t - self.k).powi(3) + self.w_max).clamp(cwnd, 1.5 * cwnd);
+        let acked = bytes_acked as f64 / MAX_SEGMENT_SIZE as f64;
+        let mut next = cwnd + (target - cwnd) * acked / cwnd;
+
+        // Reno-friendly region: grow at least as fast as Reno with the same reduction
+        let alpha = 3.0 * (1.0 - Self::BETA) / (1.0 + Self::BETA);
+        self.w_est += alpha * acked / cwnd;
+        next = next.max(self.w_est);
+
+        self.cwnd = ((next * MAX_SEGMENT_SIZE as f64) as usize).clamp(MIN_WINDOW, MAX_WINDOW);
+    }
+}
+
+impl Default for CubicCongestionControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CongestionControl for CubicCongestionControl {
+    fn on_packet_sent(&mut self, _bytes_sent: usize, _now: Instant) {}
+
+    fn on_ack_received(&mut self, bytes_acked: usize, rtt: Duration, now: Instant) {
+        self.rtt.update(rtt);
+
+        if self.cwnd < self.ssthresh {
+            self.cwnd = (self.cwnd + bytes_acked).min(MAX_WINDOW);
+        } else {
+            self.congestion_avoidance(bytes_acked, now);
+        }
+    }
+
+    fn on_packet_lost(&mut self, _bytes_lost: usize, now: Instant) {
+        if self.recovery_until.is_some_and(|until| now < until) {
+            return;
+        }
+
+        // Fast convergence: release bandwidth to newer flows if the window keeps shrinking
+        let cwnd = self.segments();
+        self.w_max = if cwnd < self.w_max {
+            cwnd * (1.0 + Self::BETA) / 2.0
+        } else {
+            cwnd
+        };
+        self.cwnd = ((self.cwnd as f64 * Self::BETA) as usize).max(MIN_WINDOW);
+        self.ssthresh = self.cwnd;
+        self.epoch_start = None;
+        self.recovery_until = Some(now + self.rtt.smoothed());
+    }
+
+    fn get_sending_rate(&self) -> f64 {
+        window_pacing_rate(self.cwnd, self.ssthresh, &self.rtt).max(1000.0) // Minimum 1KB/s
+    }
+
+    fn get_cwnd(&self) -> usize {
+        self.cwnd
+    }
+
+    fn can_send(&self, bytes_in_flight: usize) -> bool {
+        bytes_in_flight < self.cwnd
+    }
+}
+
+/// No congestion control: packets are sent as fast as possible, or at the target bitrate
+#[derive(Debug, Default)]
+pub struct NoCongestionControl;
+
+impl CongestionControl for NoCongestionControl {
+    fn on_packet_sent(&mut self, _bytes_sent: usize, _now: Instant) {}
+
+    fn on_ack_received(&mut self, _bytes_acked: usize, _rtt: Duration, _now: Instant) {}
+
+    fn on_packet_lost(&mut self, _bytes_lost: usize, _now: Instant) {}
+
+    fn get_sending_rate(&self) -> f64 {
+        // Finite so the pacer's arithmetic stays well-defined
+        f64::MAX
+    }
+
+    fn get_cwnd(&self) -> usize {
+        usize::MAX
+    }
+
+    fn can_send(&self, _bytes_in_flight: usize) -> bool {
+        true
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -366,4 +623,54 @@ mod tests {
 
         assert_eq!(filter.get_max_bw(), 2000.0);
     }
+
+    #[test]
+    fn test_newreno_halves_once_per_rtt() {
+        let mut reno = NewRenoCongestionControl::new();
+        let now = Instant::now();
+        let rtt = Duration::from_millis(10);
+
+        // Slow start grows the window by the bytes acknowledged
+        reno.on_ack_received(MAX_SEGMENT_SIZE, rtt, now);
+        assert_eq!(reno.get_cwnd(), INITIAL_WINDOW + MAX_SEGMENT_SIZE);
+
+        let before = reno.get_cwnd();
+        reno.on_packet_lost(MAX_SEGMENT_SIZE, now);
+        reno.on_packet_lost(MAX_SEGMENT_SIZE, now + Duration::from_millis(5));
+        assert_eq!(reno.get_cwnd(), before / 2);
+
+        // Congestion avoidance adds about a segment per window acknowledged
+        let cwnd = reno.get_cwnd();
+        reno.on_ack_received(cwnd, rtt, now + Duration::from_millis(20));
+        assert_eq!(reno.get_cwnd(), cwnd + MAX_SEGMENT_SIZE);
+    }
+
+    #[test]
+    fn test_cubic_reduces_and_recovers() {
+        let mut cubic = CubicCongestionControl::new();
+        let now = Instant::now();
+        let rtt = Duration::from_millis(10);
+        cubic.on_ack_received(90 * MAX_SEGMENT_SIZE, rtt, now);
+        assert_eq!(cubic.get_cwnd(), 100 * MAX_SEGMENT_SIZE);
+
+        cubic.on_packet_lost(MAX_SEGMENT_SIZE, now);
+        assert_eq!(cubic.get_cwnd(), 70 * MAX_SEGMENT_SIZE);
+
+        // The window grows back to where the loss happened after about K seconds
+        let mut time = now;
+        while time - now < Duration::from_secs(5) {
+            time += rtt;
+            let cwnd = cubic.get_cwnd();
+            cubic.on_ack_received(cwnd, rtt, time);
+        }
+        assert!(cubic.get_cwnd() > 100 * MAX_SEGMENT_SIZE);
+    }
+
+    #[test]
+    fn test_new_congestion_control() {
+        let none = new_congestion_control(CongestionAlgorithm::None);
+        assert!(none.can_send(usize::MAX - 1));
+        let reno = new_congestion_control(CongestionAlgorithm::NewReno);
+        assert!(!reno.can_send(INITIAL_WINDOW));
+    }
 }
@@ -0,0 +1,595 @@
+use bytes::{Bytes, BytesMut};
+use std::io;
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::net::UdpSocket;
+use tracing::debug;
+
+use crate::report::UdpIoPath;
+
+/// Most datagrams sent or received per syscall
+pub const BATCH_SIZE: usize = 32;
+
+/// Largest UDP datagram, and the largest buffer GRO coalesces datagrams into
+pub const MAX_DATAGRAM_SIZE: usize = 65_535;
+
+/// Most segments the kernel accepts in a single `UDP_SEGMENT` send
+const MAX_GSO_SEGMENTS: usize = 64;
+
+/// Most bytes sent in a single `UDP_SEGMENT` send, staying below the 64 KiB limit of an IP
+/// packet including its headers
+const MAX_GSO_BYTES: usize = 63 * 1024;
+
+/// UDP socket sending and receiving its datagrams in batches where the platform allows it.
+/// On Linux these are `sendmmsg`/`recvmmsg`, with `UDP_SEGMENT` (GSO) for runs of equally
+/// sized datagrams and optionally `UDP_GRO`; elsewhere there is one syscall per datagram.
+///
+/// It dereferences to the [`UdpSocket`] for single datagrams. With GRO enabled, a buffer
+/// received may hold several datagrams, so all datagrams must then be received through
+/// [`DatagramSocket::recv_batch`].
+#[derive(Debug)]
+pub struct DatagramSocket {
+    socket: UdpSocket,
+    /// Whether `sendmmsg` and `recvmmsg` are used
+    batched: bool,
+    /// Whether `UDP_SEGMENT` is used, cleared if the kernel or NIC refuse a segmented send
+    gso: AtomicBool,
+    /// Whether `UDP_GRO` is enabled
+    gro: bool,
+}
+
+impl Deref for DatagramSocket {
+    type Target = UdpSocket;
+
+    fn deref(&self) -> &UdpSocket {
+        &self.socket
+    }
+}
+
+impl DatagramSocket {
+    /// Wraps `socket`, enabling the offloads the platform supports. Receive offload is only
+    /// enabled if `gro` is set.
+    #[cfg(target_os = "linux")]
+    pub fn new(socket: UdpSocket, gro: bool) -> Self {
+        let gso = linux::supports_gso(&socket);
+        let gro = gro && linux::enable_gro(&socket);
+        Self {
+            socket,
+            batched: true,
+            gso: AtomicBool::new(gso),
+            gro,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new(socket: UdpSocket, _gro: bool) -> Self {
+        Self {
+            socket,
+            batched: false,
+            gso: AtomicBool::new(false),
+            gro: false,
+        }
+    }
+
+    /// Wraps `socket`, sending and receiving one datagram per syscall
+    pub fn standard(socket: UdpSocket) -> Self {
+        Self {
+            socket,
+            batched: false,
+            gso: AtomicBool::new(false),
+            gro: false,
+        }
+    }
+
+    /// Returns the I/O path the datagrams of the socket currently take
+    pub fn path(&self) -> UdpIoPath {
+        if !self.batched {
+            UdpIoPath::Standard
+        } else if self.gso.load(Ordering::Relaxed) || self.gro {
+            UdpIoPath::Offload
+        } else {
+            UdpIoPath::Batched
+        }
+    }
+
+    /// Returns the most datagrams worth sending at once
+    pub fn batch_size(&self) -> usize {
+        if self.batched { BATCH_SIZE } else { 1 }
+    }
+
+    /// Sends `datagrams` to `addr`, or to the connected peer if `None`, in as few syscalls
+    /// as possible
+    pub async fn send_batch(
+        &self,
+        datagrams: &[Bytes],
+        addr: Option<SocketAddr>,
+    ) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        if self.batched {
+            return self.send_batch_linux(datagrams, addr).await;
+        }
+
+        for datagram in datagrams {
+            match addr {
+                Some(addr) => self.socket.send_to(datagram, addr).await?,
+                None => self.socket.send(datagram).await?,
+            };
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn send_batch_linux(
+        &self,
+        mut datagrams: &[Bytes],
+        addr: Option<SocketAddr>,
+    ) -> io::Result<()> {
+        use std::os::fd::AsRawFd;
+        use tokio::io::Interest;
+
+        let fd = self.socket.as_raw_fd();
+        while !datagrams.is_empty() {
+            let segments = gso_segments(datagrams);
+            if segments > 1 && self.gso.load(Ordering::Relaxed) {
+                match self
+                    .socket
+                    .async_io(Interest::WRITABLE, || {
+                        linux::send_segmented(fd, &datagrams[..segments], addr)
+                    })
+                    .await
+                {
+                    Ok(()) => {
+                        datagrams = &datagrams[segments..];
+                        continue;
+                    }
+                    // NICs without checksum offload can't segment, and neither can
+                    // paths with a smaller MTU than the segments
+                    Err(e) if matches!(e.raw_os_error(), Some(libc::EIO | libc::EINVAL)) => {
+                        debug!("Disabling UDP GSO after a failed segmented send: {e}");
+                        self.gso.store(false, Ordering::Relaxed);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            let count = datagrams.len().min(BATCH_SIZE);
+            let sent = self
+                .socket
+                .async_io(Interest::WRITABLE, || {
+                    linux::send_mmsg(fd, &datagrams[..count], addr)
+                })
+                .await?;
+            datagrams = &datagrams[sent..];
+        }
+        Ok(())
+    }
+
+    /// Waits for datagrams and receives as many as are queued, up to a batch. The datagrams
+    /// share a single buffer and are returned along with their senders.
+    pub async fn recv_batch(&self, batch: &mut RecvBatch) -> io::Result<Vec<(Bytes, SocketAddr)>> {
+        #[cfg(target_os = "linux")]
+        if self.batched {
+            use std::os::fd::AsRawFd;
+            use tokio::io::Interest;
+
+            let fd = self.socket.as_raw_fd();
+            let received = self
+                .socket
+                .async_io(Interest::READABLE, || linux::recv_mmsg(fd, batch))
+                .await?;
+            return Ok(batch.split(&received));
+        }
+
+        let (size, addr) = self.socket.recv_from(&mut batch.buffer).await?;
+        Ok(batch.split(&[(size, addr, None)]))
+    }
+}
+
+/// Buffers of the datagrams received in a batch
+#[derive(Debug)]
+pub struct RecvBatch {
+    /// Consecutive slots of `slot_size` bytes, one per datagram
+    buffer: Vec<u8>,
+    slot_size: usize,
+}
+
+impl RecvBatch {
+    /// Allocates buffers for a batch of datagrams of up to `datagram_size` bytes on `socket`
+    pub fn new(socket: &DatagramSocket, datagram_size: usize) -> Self {
+        // Coalesced datagrams can fill a whole slot
+        let slot_size = if socket.gro {
+            MAX_DATAGRAM_SIZE
+        } else {
+            datagram_size
+        };
+        Self {
+            buffer: vec![0u8; slot_size * socket.batch_size()],
+            slot_size,
+        }
+    }
+
+    fn slots(&self) -> usize {
+        self.buffer.len() / self.slot_size
+    }
+
+    /// Copies the datagrams received into the first slots into a single buffer, splitting
+    /// them into their segments. Each entry is the size of a slot, the sender and the GRO
+    /// segment size, if coalesced.
+    fn split(&self, received: &[(usize, SocketAddr, Option<usize>)]) -> Vec<(Bytes, SocketAddr)> {
+        let mut buffer = BytesMut::with_capacity(received.iter().map(|(size, ..)| size).sum());
+        for (slot, (size, ..)) in received.iter().enumerate() {
+            let start = slot * self.slot_size;
+            buffer.extend_from_slice(&self.buffer[start..start + size]);
+        }
+        let mut buffer = buffer.freeze();
+
+        let mut datagrams = Vec::with_capacity(received.len());
+        for (size, addr, segment_size) in received {
+            let mut slot = buffer.split_to(*size);
+            let segment_size = segment_size.unwrap_or(*size).max(1);
+            while !slot.is_empty() {
+                datagrams.push((slot.split_to(segment_size.min(slot.len())), *addr));
+            }
+        }
+        datagrams
+    }
+}
+
+/// Returns how many of the leading `datagrams` can be sent as the segments of a single
+/// `UDP_SEGMENT` send: all but the last must be of the same size, the last may be shorter
+fn gso_segments(datagrams: &[Bytes]) -> usize {
+    let Some(segment_size) = datagrams.first().map(Bytes::len) else {
+        return 0;
+    };
+
+    let mut segments = 0;
+    let mut bytes = 0;
+    for datagram in datagrams.iter().take(MAX_GSO_SEGMENTS) {
+        if datagram.len() > segment_size || bytes + datagram.len() > MAX_GSO_BYTES {
+            break;
+        }
+        segments += 1;
+        bytes += datagram.len();
+        if datagram.len() < segment_size {
+            break;
+        }
+    }
+    segments.max(1)
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::RecvBatch;
+    use bytes::Bytes;
+    use std::io;
+    use std::mem;
+    use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6};
+    use std::os::fd::{AsRawFd, RawFd};
+    use tokio::net::UdpSocket;
+
+    /// Control message buffer, aligned for `cmsghdr`, large enough for a `UDP_SEGMENT` or
+    /// `UDP_GRO` control message
+    #[repr(C, align(8))]
+    struct ControlBuffer([u8; 32]);
+
+    impl ControlBuffer {
+        fn new() -> Self {
+            Self([0; 32])
+        }
+    }
+
+    /// Returns whether the kernel supports `UDP_SEGMENT` (Linux 4.18)
+    pub fn supports_gso(socket: &UdpSocket) -> bool {
+        let mut value: libc::c_int = 0;
+        let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+        // SAFETY: `value` is a c_int and `len` is its size
+        let ret = unsafe {
+            libc::getsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_UDP,
+                libc::UDP_SEGMENT,
+                &mut value as *mut libc::c_int as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        ret == 0
+    }
+
+    /// Enables `UDP_GRO` (Linux 5.0), returning whether the kernel accepted it
+    pub fn enable_gro(socket: &UdpSocket) -> bool {
+        let value: libc::c_int = 1;
+        // SAFETY: `value` is a c_int and the length is its size
+        let ret = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_UDP,
+                libc::UDP_GRO,
+                &value as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        ret == 0
+    }
+
+    fn to_raw(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+        // SAFETY: `sockaddr_storage` is plain old data, large enough for any address
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let len = match addr {
+            SocketAddr::V4(addr) => {
+                let raw = libc::sockaddr_in {
+                    sin_family: libc::AF_INET as libc::sa_family_t,
+                    sin_port: addr.port().to_be(),
+                    sin_addr: libc::in_addr {
+                        s_addr: u32::from_ne_bytes(addr.ip().octets()),
+                    },
+                    sin_zero: [0; 8],
+                };
+                // SAFETY: `sockaddr_in` fits in `sockaddr_storage`
+                unsafe { (&mut storage as *mut _ as *mut libc::sockaddr_in).write(raw) };
+                mem::size_of::<libc::sockaddr_in>()
+            }
+            SocketAddr::V6(addr) => {
+                let raw = libc::sockaddr_in6 {
+                    sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                    sin6_port: addr.port().to_be(),
+                    sin6_flowinfo: addr.flowinfo(),
+                    sin6_addr: libc::in6_addr {
+                        s6_addr: addr.ip().octets(),
+                    },
+                    sin6_scope_id: addr.scope_id(),
+                };
+                // SAFETY: `sockaddr_in6` fits in `sockaddr_storage`
+                unsafe { (&mut storage as *mut _ as *mut libc::sockaddr_in6).write(raw) };
+                mem::size_of::<libc::sockaddr_in6>()
+            }
+        };
+        (storage, len as libc::socklen_t)
+    }
+
+    fn from_raw(storage: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
+        match storage.ss_family as libc::c_int {
+            libc::AF_INET => {
+                // SAFETY: the family says the storage holds a `sockaddr_in`
+                let raw = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+                Ok(SocketAddr::V4(SocketAddrV4::new(
+                    raw.sin_addr.s_addr.to_ne_bytes().into(),
+                    u16::from_be(raw.sin_port),
+                )))
+            }
+            libc::AF_INET6 => {
+                // SAFETY: the family says the storage holds a `sockaddr_in6`
+                let raw = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+                Ok(SocketAddr::V6(SocketAddrV6::new(
+                    raw.sin6_addr.s6_addr.into(),
+                    u16::from_be(raw.sin6_port),
+                    raw.sin6_flowinfo,
+                    raw.sin6_scope_id,
+                )))
+            }
+            family => Err(io::Error::other(format!(
+                "unsupported address family {family}"
+            ))),
+        }
+    }
+
+    fn iovec(data: &[u8]) -> libc::iovec {
+        libc::iovec {
+            iov_base: data.as_ptr() as *mut libc::c_void,
+            iov_len: data.len(),
+        }
+    }
+
+    /// Sends `datagrams` of equal size, except for a shorter last one, in a single
+    /// `sendmsg` the kernel splits into segments
+    pub fn send_segmented(
+        fd: RawFd,
+        datagrams: &[Bytes],
+        addr: Option<SocketAddr>,
+    ) -> io::Result<()> {
+        let mut iovecs: Vec<libc::iovec> =
+            datagrams.iter().map(|datagram| iovec(datagram)).collect();
+        let mut name = addr.map(|addr| to_raw(&addr));
+        let mut control = ControlBuffer::new();
+
+        // SAFETY: all pointers in the header refer to buffers that outlive the call, and the
+        // control buffer is large enough for the single message written into it
+        let ret = unsafe {
+            let mut header: libc::msghdr = mem::zeroed();
+            if let Some((storage, len)) = &mut name {
+                header.msg_name = storage as *mut _ as *mut libc::c_void;
+                header.msg_namelen = *len;
+            }
+            header.msg_iov = iovecs.as_mut_ptr();
+            header.msg_iovlen = iovecs.len() as _;
+            header.msg_control = control.0.as_mut_ptr() as *mut libc::c_void;
+            header.msg_controllen = libc::CMSG_SPACE(mem::size_of::<u16>() as u32) as _;
+
+            let cmsg = libc::CMSG_FIRSTHDR(&header);
+            (*cmsg).cmsg_level = libc::SOL_UDP;
+            (*cmsg).cmsg_type = libc::UDP_SEGMENT;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<u16>() as u32) as _;
+            (libc::CMSG_DATA(cmsg) as *mut u16).write_unaligned(datagrams[0].len() as u16);
+
+            libc::sendmsg(fd, &header, 0)
+        };
+
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Sends `datagrams` with a single `sendmmsg`, returning how many were sent
+    pub fn send_mmsg(
+        fd: RawFd,
+        datagrams: &[Bytes],
+        addr: Option<SocketAddr>,
+    ) -> io::Result<usize> {
+        let mut iovecs: Vec<libc::iovec> =
+            datagrams.iter().map(|datagram| iovec(datagram)).collect();
+        let mut name = addr.map(|addr| to_raw(&addr));
+
+        // SAFETY: all pointers in the headers refer to buffers that outlive the call
+        let ret = unsafe {
+            let mut headers: Vec<libc::mmsghdr> = iovecs
+                .iter_mut()
+                .map(|iovec| {
+                    let mut header: libc::mmsghdr = mem::zeroed();
+                    if let Some((storage, len)) = &mut name {
+                        header.msg_hdr.msg_name = storage as *mut _ as *mut libc::c_void;
+                        header.msg_hdr.msg_namelen = *len;
+                    }
+                    header.msg_hdr.msg_iov = iovec;
+                    header.msg_hdr.msg_iovlen = 1;
+                    header
+                })
+                .collect();
+
+            libc::sendmmsg(fd, headers.as_mut_ptr(), headers.len() as _, 0)
+        };
+
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ret as usize)
+    }
+
+    /// Receives up to a batch of datagrams with a single `recvmmsg` without waiting,
+    /// returning the size, sender and GRO segment size of each slot filled
+    pub fn recv_mmsg(
+        fd: RawFd,
+        batch: &mut RecvBatch,
+    ) -> io::Result<Vec<(usize, SocketAddr, Option<usize>)>> {
+        let slots = batch.slots();
+        let slot_size = batch.slot_size;
+        // SAFETY: `sockaddr_storage` is plain old data
+        let mut names: Vec<libc::sockaddr_storage> =
+            (0..slots).map(|_| unsafe { mem::zeroed() }).collect();
+        let mut controls: Vec<ControlBuffer> = (0..slots).map(|_| ControlBuffer::new()).collect();
+        let mut iovecs: Vec<libc::iovec> = batch
+            .buffer
+            .chunks_mut(slot_size)
+            .map(|slot| libc::iovec {
+                iov_base: slot.as_mut_ptr() as *mut libc::c_void,
+                iov_len: slot.len(),
+            })
+            .collect();
+
+        // SAFETY: all pointers in the headers refer to buffers that outlive the call
+        let mut headers: Vec<libc::mmsghdr> = (0..slots)
+            .map(|slot| {
+                let mut header: libc::mmsghdr = unsafe { mem::zeroed() };
+                header.msg_hdr.msg_name = &mut names[slot] as *mut _ as *mut libc::c_void;
+                header.msg_hdr.msg_namelen =
+                    mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+                header.msg_hdr.msg_iov = &mut iovecs[slot];
+                header.msg_hdr.msg_iovlen = 1;
+                header.msg_hdr.msg_control = controls[slot].0.as_mut_ptr() as *mut libc::c_void;
+                header.msg_hdr.msg_controllen = controls[slot].0.len() as _;
+                header
+            })
+            .collect();
+
+        let ret = unsafe {
+            libc::recvmmsg(
+                fd,
+                headers.as_mut_ptr(),
+                slots as _,
+                libc::MSG_DONTWAIT,
+                std::ptr::null_mut(),
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        headers[..ret as usize]
+            .iter()
+            .zip(&names)
+            .map(|(header, name)| {
+                // SAFETY: the kernel filled the control messages of the header
+                let segment_size = unsafe {
+                    let mut cmsg = libc::CMSG_FIRSTHDR(&header.msg_hdr);
+                    let mut segment_size = None;
+                    while !cmsg.is_null() {
+                        if (*cmsg).cmsg_level == libc::SOL_UDP && (*cmsg).cmsg_type == libc::UDP_GRO
+                        {
+                            let size =
+                                (libc::CMSG_DATA(cmsg) as *const libc::c_int).read_unaligned();
+                            segment_size = Some(size as usize);
+                        }
+                        cmsg = libc::CMSG_NXTHDR(&header.msg_hdr, cmsg);
+                    }
+                    segment_size
+                };
+                Ok((header.msg_len as usize, from_raw(name)?, segment_size))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn datagrams(sizes: &[usize]) -> Vec<Bytes> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(i, size)| Bytes::from(vec![i as u8; *size]))
+            .collect()
+    }
+
+    #[test]
+    fn test_gso_segments() {
+        assert_eq!(gso_segments(&datagrams(&[1000; 10])), 10);
+        // A shorter datagram ends the run, a longer one is left out
+        assert_eq!(gso_segments(&datagrams(&[1000, 1000, 500, 1000])), 3);
+        assert_eq!(gso_segments(&datagrams(&[1000, 1000, 1500])), 2);
+        assert_eq!(gso_segments(&datagrams(&[1200; 100])), 53);
+        assert_eq!(gso_segments(&datagrams(&[100; 100])), MAX_GSO_SEGMENTS);
+        assert_eq!(gso_segments(&datagrams(&[])), 0);
+    }
+
+    #[tokio::test]
+    async fn test_batches_roundtrip() {
+        for batched in [true, false] {
+            let bind = || UdpSocket::bind("127.0.0.1:0");
+            let (sender, receiver) = (bind().await.unwrap(), bind().await.unwrap());
+            let (sender, receiver) = if batched {
+                (
+                    DatagramSocket::new(sender, false),
+                    DatagramSocket::new(receiver, true),
+                )
+            } else {
+                (
+                    DatagramSocket::standard(sender),
+                    DatagramSocket::standard(receiver),
+                )
+            };
+
+            let sent = datagrams(&[1200, 1200, 1200, 1200, 300, 50, 1200]);
+            sender
+                .send_batch(&sent, Some(receiver.local_addr().unwrap()))
+                .await
+                .unwrap();
+
+            let mut batch = RecvBatch::new(&receiver, 2048);
+            let mut received = Vec::new();
+            while received.len() < sent.len() {
+                let datagrams =
+                    tokio::time::timeout(Duration::from_secs(1), receiver.recv_batch(&mut batch))
+                        .await
+                        .unwrap()
+                        .unwrap();
+                for (data, addr) in datagrams {
+                    assert_eq!(addr, sender.local_addr().unwrap());
+                    received.push(data);
+                }
+            }
+            assert_eq!(received, sent);
+        }
+    }
+}
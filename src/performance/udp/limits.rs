@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Instant;
+
+/// Most source addresses tracked at once. Buckets that have refilled are dropped to make
+/// room, and beyond that packets of new sources are refused, so spoofed addresses can't
+/// grow the table without bounds.
+const MAX_TRACKED_SOURCES: usize = 65_536;
+
+/// Token bucket per source IP address, limiting the packets the server handles from
+/// addresses their session isn't validated at: the INITs opening sessions, the packets
+/// answered with a challenge or a RESET.
+/// Each source may send `rate` such packets per second, in bursts of up to a second's worth.
+#[derive(Debug)]
+pub struct SourceRateLimiter {
+    rate: f64,
+    buckets: HashMap<IpAddr, Bucket>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    last_update: Instant,
+}
+
+impl SourceRateLimiter {
+    pub fn new(rate: u32) -> Self {
+        Self {
+            rate: rate.max(1) as f64,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Takes a token from the bucket of `source`, returning false if it is empty
+    pub fn allow(&mut self, source: IpAddr, now: Instant) -> bool {
+        if !self.buckets.contains_key(&source) && self.buckets.len() >= MAX_TRACKED_SOURCES {
+            self.prune(now);
+            if self.buckets.len() >= MAX_TRACKED_SOURCES {
+                return false;
+            }
+        }
+
+        let rate = self.rate;
+        let bucket = self.buckets.entry(source).or_insert(Bucket {
+            tokens: rate,
+            last_update: now,
+        });
+        let refill = now
+            .saturating_duration_since(bucket.last_update)
+            .as_secs_f64()
+            * rate;
+        bucket.tokens = (bucket.tokens + refill).min(rate);
+        bucket.last_update = now;
+
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    /// Drops the buckets that have refilled, which behave like those of new sources
+    pub fn prune(&mut self, now: Instant) {
+        let rate = self.rate;
+        self.buckets.retain(|_, bucket| {
+            let refill = now
+                .saturating_duration_since(bucket.last_update)
+                .as_secs_f64()
+                * rate;
+            bucket.tokens + refill < rate
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_limits_each_source() {
+        let mut limiter = SourceRateLimiter::new(3);
+        let now = Instant::now();
+        let first: IpAddr = "192.0.2.1".parse().unwrap();
+        let second: IpAddr = "192.0.2.2".parse().unwrap();
+
+        for _ in 0..3 {
+            assert!(limiter.allow(first, now));
+        }
+        assert!(!limiter.allow(first, now));
+        assert!(limiter.allow(second, now));
+
+        // A third of a second refills one token
+        let later = now + Duration::from_millis(334);
+        assert!(limiter.allow(first, later));
+        assert!(!limiter.allow(first, later));
+
+        // Only the full bucket of the second source is dropped
+        limiter.prune(now + Duration::from_millis(400));
+        assert_eq!(limiter.buckets.len(), 1);
+        assert!(limiter.buckets.contains_key(&first));
+    }
+}
@@ -1,5 +1,7 @@
+pub mod cbr;
 pub mod client;
 pub mod congestion;
-pub mod pacing;
+pub mod io;
+pub mod limits;
 pub mod protocol;
 pub mod server;
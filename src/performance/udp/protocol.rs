@@ -1,12 +1,67 @@
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use std::collections::VecDeque;
 use std::net::SocketAddr;
+use std::ops::RangeInclusive;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+/// Most ranges an ACK frame carries. Older ranges are left out.
+pub const MAX_ACK_RANGES: usize = 32;
+
+/// Largest payload that fits in a single UDP datagram along with the STP header
+pub const MAX_PAYLOAD_SIZE: usize = 65_507 - StpHeader::SIZE;
+
+/// Highest bitrate in bits per second a session may ask for
+pub const MAX_BITRATE: f64 = 100e9;
+
+/// Longest test a session may ask for
+pub const MAX_DURATION: Duration = Duration::from_secs(24 * 3600);
+
+/// Type of an STP packet, which tells the receiver how to handle it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PacketType {
+    /// Opens a session, carrying its [`SessionParams`] followed by the cookie of the
+    /// server's challenge once the client answers it
+    Init = 1,
+    /// Accepts a session, or challenges the client with a cookie to echo in an INIT
+    InitAck = 2,
+    /// Test data
+    Data = 3,
+    /// Acknowledges packets with an [`AckFrame`]
+    Ack = 4,
+    /// Latency probe, answered with a PONG whether or not it belongs to a session
+    Ping = 5,
+    Pong = 6,
+    /// Ends a session. The server answers with a CLOSE carrying its measurement as JSON.
+    Close = 7,
+    /// Aborts a session, or tells the peer that its session is unknown
+    Reset = 8,
+}
+
+impl PacketType {
+    fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            1 => Self::Init,
+            2 => Self::InitAck,
+            3 => Self::Data,
+            4 => Self::Ack,
+            5 => Self::Ping,
+            6 => Self::Pong,
+            7 => Self::Close,
+            8 => Self::Reset,
+            _ => return None,
+        })
+    }
+}
+
 /// STP (Simple Transport Protocol) packet header
-/// Fixed 32-byte header for all packets
+/// Fixed 41-byte header for all packets
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct StpHeader {
+    pub packet_type: PacketType,
+    /// Random 64-bit identifier chosen by the client for the session. Sessions are told
+    /// apart by it rather than by address, so they survive NAT rebinding.
+    pub session_id: u64,
     /// Unique, monotonically increasing 64-bit packet number
     pub packet_number: u64,
     /// 64-bit microsecond timestamp set by sender
@@ -18,10 +73,18 @@ pub struct StpHeader {
 }
 
 impl StpHeader {
-    pub const SIZE: usize = 32;
+    pub const SIZE: usize = 41;
 
-    pub fn new(packet_number: u64, latest_ack: u64, ack_timestamp_echo: u64) -> Self {
+    pub fn new(
+        packet_type: PacketType,
+        session_id: u64,
+        packet_number: u64,
+        latest_ack: u64,
+        ack_timestamp_echo: u64,
+    ) -> Self {
         Self {
+            packet_type,
+            session_id,
             packet_number,
             timestamp: current_timestamp_micros(),
             latest_ack,
@@ -30,6 +93,8 @@ impl StpHeader {
     }
 
     pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u8(self.packet_type as u8);
+        buf.put_u64(self.session_id);
         buf.put_u64(self.packet_number);
         buf.put_u64(self.timestamp);
         buf.put_u64(self.latest_ack);
@@ -42,6 +107,8 @@ impl StpHeader {
         }
 
         Some(Self {
+            packet_type: PacketType::from_u8(buf.get_u8())?,
+            session_id: buf.get_u64(),
             packet_number: buf.get_u64(),
             timestamp: buf.get_u64(),
             latest_ack: buf.get_u64(),
@@ -58,22 +125,27 @@ pub struct StpPacket {
 }
 
 impl StpPacket {
-    pub fn new(
+    pub fn new(header: StpHeader, payload: Bytes) -> Self {
+        Self { header, payload }
+    }
+
+    /// Creates an ACK packet carrying `frame`. The header acknowledges the largest packet
+    /// number of the frame and echoes the timestamp of the packet that triggered the ACK.
+    pub fn ack(
+        session_id: u64,
         packet_number: u64,
-        latest_ack: u64,
+        frame: &AckFrame,
         ack_timestamp_echo: u64,
-        payload: Bytes,
     ) -> Self {
         Self {
-            header: StpHeader::new(packet_number, latest_ack, ack_timestamp_echo),
-            payload,
-        }
-    }
-
-    pub fn ack_only(packet_number: u64, latest_ack: u64, ack_timestamp_echo: u64) -> Self {
-        Self {
-            header: StpHeader::new(packet_number, latest_ack, ack_timestamp_echo),
-            payload: Bytes::new(),
+            header: StpHeader::new(
+                PacketType::Ack,
+                session_id,
+                packet_number,
+                frame.largest().unwrap_or(0),
+                ack_timestamp_echo,
+            ),
+            payload: frame.encode(),
         }
     }
 
@@ -95,31 +167,343 @@ impl StpPacket {
         Some(Self { header, payload })
     }
 
-    pub fn is_ack_only(&self) -> bool {
-        self.payload.is_empty()
+    /// Creates a packet answering `packet` without any session state: a PONG to a PING,
+    /// or a RESET to a packet of an unknown session
+    pub fn reply(packet: &StpPacket, packet_type: PacketType) -> Self {
+        Self::new(
+            StpHeader::new(
+                packet_type,
+                packet.header.session_id,
+                0,
+                packet.header.packet_number,
+                packet.header.timestamp,
+            ),
+            Bytes::new(),
+        )
+    }
+
+    /// Creates an INIT-ACK answering `packet` with `cookie`, which the client must echo in an
+    /// INIT from the same address before the server sends it anything else
+    pub fn challenge(packet: &StpPacket, cookie: u64) -> Self {
+        let mut challenge = Self::reply(packet, PacketType::InitAck);
+        challenge.payload = Bytes::copy_from_slice(&cookie.to_be_bytes());
+        challenge
+    }
+
+    /// Returns the cookie of the server's challenge, if this packet is one
+    pub fn cookie(&self) -> Option<u64> {
+        if self.header.packet_type != PacketType::InitAck {
+            return None;
+        }
+        Some(u64::from_be_bytes(self.payload.as_ref().try_into().ok()?))
+    }
+
+    /// Returns the ACK frame carried by this packet, if it is an ACK
+    pub fn ack_frame(&self) -> Option<AckFrame> {
+        if self.header.packet_type != PacketType::Ack {
+            return None;
+        }
+        AckFrame::decode(&self.payload)
+    }
+}
+
+/// Packet numbers acknowledged by an ACK packet, as ranges ordered from the highest down.
+/// Unlike a cumulative acknowledgement it tells reordered packets apart from lost ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AckFrame {
+    pub ranges: Vec<RangeInclusive<u64>>,
+}
+
+impl AckFrame {
+    /// Largest acknowledged packet number
+    pub fn largest(&self) -> Option<u64> {
+        self.ranges.first().map(|range| *range.end())
+    }
+
+    pub fn contains(&self, packet_number: u64) -> bool {
+        self.ranges
+            .iter()
+            .any(|range| range.contains(&packet_number))
+    }
+
+    /// Encodes the frame as the payload of an ACK packet: the number of ranges, then the
+    /// first and last packet number of each range
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(2 + self.ranges.len() * 16);
+        buf.put_u16(self.ranges.len() as u16);
+        for range in &self.ranges {
+            buf.put_u64(*range.start());
+            buf.put_u64(*range.end());
+        }
+        buf.freeze()
+    }
+
+    pub fn decode(mut buf: &[u8]) -> Option<Self> {
+        if buf.len() < 2 {
+            return None;
+        }
+        let count = buf.get_u16() as usize;
+        if buf.len() != count * 16 {
+            return None;
+        }
+
+        let ranges = (0..count)
+            .map(|_| {
+                let start = buf.get_u64();
+                let end = buf.get_u64();
+                (start <= end).then_some(start..=end)
+            })
+            .collect::<Option<_>>()?;
+        Some(Self { ranges })
+    }
+}
+
+/// Packet numbers received from the peer, kept as ranges to build ACK frames from
+#[derive(Debug, Default)]
+pub struct AckTracker {
+    /// Disjoint ranges ordered from the highest down
+    ranges: Vec<RangeInclusive<u64>>,
+}
+
+impl AckTracker {
+    /// Ranges kept beyond those sent in ACK frames, so reordered packets can still close gaps
+    const MAX_TRACKED_RANGES: usize = 4 * MAX_ACK_RANGES;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a received packet number. Returns false if it was received before.
+    pub fn on_packet_received(&mut self, packet_number: u64) -> bool {
+        // First range starting at or below the packet number
+        let index = self
+            .ranges
+            .iter()
+            .position(|range| *range.start() <= packet_number)
+            .unwrap_or(self.ranges.len());
+        if self
+            .ranges
+            .get(index)
+            .is_some_and(|range| range.contains(&packet_number))
+        {
+            return false;
+        }
+
+        let joins_below = self
+            .ranges
+            .get(index)
+            .is_some_and(|range| *range.end() + 1 == packet_number);
+        let joins_above = index > 0 && *self.ranges[index - 1].start() == packet_number + 1;
+        match (joins_below, joins_above) {
+            (true, true) => {
+                let below = self.ranges.remove(index);
+                self.ranges[index - 1] = *below.start()..=*self.ranges[index - 1].end();
+            }
+            (true, false) => {
+                self.ranges[index] = *self.ranges[index].start()..=packet_number;
+            }
+            (false, true) => {
+                self.ranges[index - 1] = packet_number..=*self.ranges[index - 1].end();
+            }
+            (false, false) => self.ranges.insert(index, packet_number..=packet_number),
+        }
+        self.ranges.truncate(Self::MAX_TRACKED_RANGES);
+
+        true
+    }
+
+    /// Returns the ACK frame acknowledging the most recent ranges
+    pub fn frame(&self) -> AckFrame {
+        AckFrame {
+            ranges: self.ranges.iter().take(MAX_ACK_RANGES).cloned().collect(),
+        }
+    }
+}
+
+/// Kind of test a session runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SessionKind {
+    /// Congestion-controlled upload, acknowledged by the server
+    Upload = 1,
+    /// Download sent by the server in bursts clocked by the client's ACKs
+    Download = 2,
+    /// Constant-bitrate upload. The server tracks loss and jitter of the data packets
+    /// without acknowledging them.
+    CbrUpload = 3,
+    /// Constant-bitrate download streamed by the server
+    CbrDownload = 4,
+}
+
+impl SessionKind {
+    fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            1 => Self::Upload,
+            2 => Self::Download,
+            3 => Self::CbrUpload,
+            4 => Self::CbrDownload,
+            _ => return None,
+        })
+    }
+
+    /// Whether the server sends the test data
+    pub fn is_download(self) -> bool {
+        matches!(self, Self::Download | Self::CbrDownload)
+    }
+}
+
+/// Test parameters carried by INIT packets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionParams {
+    pub kind: SessionKind,
+    pub payload_size: usize,
+    /// Bits per second of the datagrams, headers included, for constant-bitrate downloads
+    pub bitrate: f64,
+    pub duration: Duration,
+}
+
+impl SessionParams {
+    const SIZE: usize = 21;
+
+    /// Encodes the parameters as the payload of an INIT packet: the kind, the payload
+    /// size, the bitrate and the duration in milliseconds
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(Self::SIZE);
+        buf.put_u8(self.kind as u8);
+        buf.put_u32(self.payload_size as u32);
+        buf.put_f64(self.bitrate);
+        buf.put_u64(self.duration.as_millis() as u64);
+        buf.freeze()
+    }
+
+    /// Returns the parameters with the bitrate and duration lowered to at most
+    /// `max_bitrate` and `max_duration`
+    pub fn clamped(self, max_bitrate: f64, max_duration: Duration) -> Self {
+        Self {
+            bitrate: self.bitrate.min(max_bitrate),
+            duration: self.duration.min(max_duration),
+            ..self
+        }
+    }
+
+    /// Encodes the parameters followed by `cookie`, as the payload of an INIT answering the
+    /// server's challenge
+    pub fn encode_with_cookie(&self, cookie: u64) -> Bytes {
+        let mut buf = BytesMut::from(self.encode());
+        buf.put_u64(cookie);
+        buf.freeze()
+    }
+
+    /// Decodes the payload of an INIT: the parameters, and the cookie of the server's
+    /// challenge if the client answers it
+    pub fn decode_init(buf: &[u8]) -> Option<(Self, Option<u64>)> {
+        if buf.len() == Self::SIZE + 8 {
+            let (params, mut cookie) = buf.split_at(Self::SIZE);
+            return Some((Self::decode(params)?, Some(cookie.get_u64())));
+        }
+        Some((Self::decode(buf)?, None))
+    }
+
+    /// Decodes the parameters of an INIT, returning `None` unless they are valid: payloads
+    /// that fit in a datagram, a finite bitrate of at most [`MAX_BITRATE`] that is positive
+    /// for constant-bitrate tests, and a duration of at most [`MAX_DURATION`]
+    pub fn decode(mut buf: &[u8]) -> Option<Self> {
+        if buf.len() != Self::SIZE {
+            return None;
+        }
+
+        let params = Self {
+            kind: SessionKind::from_u8(buf.get_u8())?,
+            payload_size: buf.get_u32() as usize,
+            bitrate: buf.get_f64(),
+            duration: Duration::from_millis(buf.get_u64()),
+        };
+        params.is_valid().then_some(params)
+    }
+
+    fn is_valid(&self) -> bool {
+        let constant_bitrate =
+            matches!(self.kind, SessionKind::CbrUpload | SessionKind::CbrDownload);
+        self.payload_size <= MAX_PAYLOAD_SIZE
+            && (0.0..=MAX_BITRATE).contains(&self.bitrate)
+            && (self.bitrate > 0.0 || !constant_bitrate)
+            && self.duration <= MAX_DURATION
+    }
+}
+
+/// Lifecycle of a session, shared by the client and the server. The client opens a
+/// session with INIT and closes it with CLOSE; the server only ever answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    /// No packet exchanged yet. The server keeps new sessions idle until the client echoed
+    /// the cookie of its challenge.
+    Idle,
+    /// The client sent INIT and waits for INIT-ACK
+    Handshaking,
+    Established,
+    /// The client sent CLOSE and waits for the server's CLOSE
+    Closing,
+    Closed,
+    /// The peer aborted the session or didn't know it
+    Reset,
+}
+
+impl SessionState {
+    /// Returns the state after sending a packet of `packet_type`
+    pub fn on_sent(self, packet_type: PacketType) -> Self {
+        match (self, packet_type) {
+            (_, PacketType::Reset) => Self::Reset,
+            (Self::Idle, PacketType::Init) => Self::Handshaking,
+            (Self::Established, PacketType::Close) => Self::Closing,
+            (state, _) => state,
+        }
+    }
+
+    /// Returns the state after receiving a packet of `packet_type`, or `None` if the packet
+    /// isn't expected in this state and must be dropped
+    pub fn on_received(self, packet_type: PacketType) -> Option<Self> {
+        use PacketType::*;
+
+        match (self, packet_type) {
+            (_, Reset) => Some(Self::Reset),
+            // The server accepts a new session whose client echoed its cookie, or the
+            // client's INIT is repeated since the INIT-ACK was lost
+            (Self::Idle | Self::Established, Init) => Some(Self::Established),
+            (Self::Handshaking | Self::Established, InitAck) => Some(Self::Established),
+            (Self::Established, Data | Ack | Ping | Pong) => Some(Self::Established),
+            // Packets still in flight when the client closed the session
+            (Self::Closing, InitAck | Data | Ack | Pong) => Some(Self::Closing),
+            // The server's CLOSE answers the client's; a repeated CLOSE means the answer
+            // was lost
+            (Self::Established | Self::Closing | Self::Closed, Close) => Some(Self::Closed),
+            _ => None,
+        }
     }
 }
 
 /// Connection state for tracking peer
 #[derive(Debug)]
 pub struct ConnectionState {
+    /// Address the peer last sent from
     pub peer_addr: SocketAddr,
+    pub session_id: u64,
+    pub state: SessionState,
     pub local_packet_number: u64,
     pub peer_latest_ack: u64,
     pub last_received_packet: u64,
     pub last_received_timestamp: u64,
-    pub established: bool,
 }
 
 impl ConnectionState {
-    pub fn new(peer_addr: SocketAddr) -> Self {
+    pub fn new(peer_addr: SocketAddr, session_id: u64) -> Self {
         Self {
             peer_addr,
+            session_id,
+            state: SessionState::Idle,
             local_packet_number: 0,
             peer_latest_ack: 0,
             last_received_packet: 0,
             last_received_timestamp: 0,
-            established: false,
         }
     }
 
@@ -128,14 +512,35 @@ impl ConnectionState {
         self.local_packet_number
     }
 
-    pub fn update_from_received(&mut self, header: &StpHeader) {
+    /// Creates the next packet of the session, acknowledging the last packet received in
+    /// the header, and advances the session state
+    pub fn packet(&mut self, packet_type: PacketType, payload: Bytes) -> StpPacket {
+        let header = StpHeader::new(
+            packet_type,
+            self.session_id,
+            self.next_packet_number(),
+            self.last_received_packet,
+            self.last_received_timestamp,
+        );
+        self.state = self.state.on_sent(packet_type);
+        StpPacket::new(header, payload)
+    }
+
+    /// Advances the session state with a packet received from the peer. Returns false if
+    /// the packet belongs to another session or isn't expected in the current state.
+    pub fn on_received(&mut self, header: &StpHeader) -> bool {
+        if header.session_id != self.session_id {
+            return false;
+        }
+        let Some(state) = self.state.on_received(header.packet_type) else {
+            return false;
+        };
+
+        self.state = state;
         self.peer_latest_ack = header.latest_ack;
         self.last_received_packet = header.packet_number;
         self.last_received_timestamp = header.timestamp;
-
-        if !self.established {
-            self.established = true;
-        }
+        true
     }
 }
 
@@ -184,33 +589,35 @@ impl LossRecovery {
         self.in_flight.push_back(packet);
     }
 
-    pub fn on_ack_received(
-        &mut self,
-        acked_packet: u64,
-    ) -> (Vec<InFlightPacket>, Vec<InFlightPacket>) {
+    /// Processes an ACK frame, returning the packets it newly acknowledges and those now
+    /// considered lost. A packet is lost once a packet sent `loss_threshold` packets after
+    /// it is acknowledged, so reordering by fewer packets is tolerated, or once it has gone
+    /// unacknowledged for `loss_timeout`.
+    pub fn on_ack_frame(&mut self, frame: &AckFrame) -> (Vec<InFlightPacket>, Vec<InFlightPacket>) {
         let mut acked_packets = Vec::new();
         let mut lost_packets = Vec::new();
         let current_time = current_timestamp_micros();
 
-        self.largest_acked = self.largest_acked.max(acked_packet);
+        if let Some(largest) = frame.largest() {
+            self.largest_acked = self.largest_acked.max(largest);
+        }
 
         // Remove acked packets and detect losses
         self.in_flight.retain(|packet| {
-            if packet.packet_number <= acked_packet {
+            if frame.contains(packet.packet_number) {
                 acked_packets.push(packet.clone());
-                false // Remove from in_flight
+                return false;
+            }
+
+            let reordering_exceeded =
+                packet.packet_number + self.loss_threshold <= self.largest_acked;
+            let timed_out = current_time.saturating_sub(packet.sent_time)
+                > self.loss_timeout.as_micros() as u64;
+            if reordering_exceeded || timed_out {
+                lost_packets.push(packet.clone());
+                false
             } else {
-                // Check for loss: either too many higher packets acked or timeout
-                let higher_acked_count = self.largest_acked.saturating_sub(packet.packet_number);
-                let timed_out =
-                    current_time - packet.sent_time > self.loss_timeout.as_micros() as u64;
-
-                if higher_acked_count >= self.loss_threshold || timed_out {
-                    lost_packets.push(packet.clone());
-                    false // Remove from in_flight
-                } else {
-                    true // Keep in in_flight
-                }
+                true
             }
         });
 
@@ -242,20 +649,22 @@ mod tests {
 
     #[test]
     fn test_header_encode_decode() {
-        let header = StpHeader::new(123, 456, 789);
+        let header = StpHeader::new(PacketType::Data, 42, 123, 456, 789);
         let mut buf = BytesMut::new();
         header.encode(&mut buf);
+        assert_eq!(buf.len(), StpHeader::SIZE);
 
         let decoded = StpHeader::decode(buf.freeze()).unwrap();
-        assert_eq!(header.packet_number, decoded.packet_number);
-        assert_eq!(header.latest_ack, decoded.latest_ack);
-        assert_eq!(header.ack_timestamp_echo, decoded.ack_timestamp_echo);
+        assert_eq!(header, decoded);
     }
 
     #[test]
     fn test_packet_encode_decode() {
         let payload = Bytes::from_static(b"hello world");
-        let packet = StpPacket::new(1, 0, 0, payload.clone());
+        let packet = StpPacket::new(
+            StpHeader::new(PacketType::Data, 1, 1, 0, 0),
+            payload.clone(),
+        );
 
         let encoded = packet.encode();
         let decoded = StpPacket::decode(encoded).unwrap();
@@ -263,4 +672,212 @@ mod tests {
         assert_eq!(packet.header.packet_number, decoded.header.packet_number);
         assert_eq!(packet.payload, decoded.payload);
     }
+
+    #[test]
+    fn test_ack_tracker_merges_ranges() {
+        let mut tracker = AckTracker::new();
+        for packet_number in [1, 2, 5, 4, 8] {
+            assert!(tracker.on_packet_received(packet_number));
+        }
+        assert!(!tracker.on_packet_received(4));
+        assert_eq!(tracker.frame().ranges, vec![8..=8, 4..=5, 1..=2]);
+
+        // Closing a gap joins the ranges around it
+        assert!(tracker.on_packet_received(3));
+        assert_eq!(tracker.frame().ranges, vec![8..=8, 1..=5]);
+
+        let frame = tracker.frame();
+        assert_eq!(AckFrame::decode(&frame.encode()), Some(frame));
+    }
+
+    #[test]
+    fn test_loss_recovery_tolerates_reordering() {
+        let mut recovery = LossRecovery::new();
+        for packet_number in 1..=6 {
+            recovery.on_packet_sent(InFlightPacket::new(packet_number, 100, Bytes::new()));
+        }
+
+        // Packet 2 is late but only 2 packets behind: neither acked nor lost yet
+        let frame = AckFrame {
+            ranges: vec![3..=4, 1..=1],
+        };
+        let (acked, lost) = recovery.on_ack_frame(&frame);
+        let numbers = |packets: Vec<InFlightPacket>| {
+            packets
+                .iter()
+                .map(|packet| packet.packet_number)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(numbers(acked), vec![1, 3, 4]);
+        assert!(lost.is_empty());
+
+        // Packet 2 arrives after all, but packet 5 is 3 packets behind the largest ACK
+        let frame = AckFrame {
+            ranges: vec![8..=8, 6..=6, 1..=4],
+        };
+        let (acked, lost) = recovery.on_ack_frame(&frame);
+        assert_eq!(numbers(acked), vec![2, 6]);
+        assert_eq!(numbers(lost), vec![5]);
+    }
+
+    #[test]
+    fn test_session_params_encode_decode() {
+        let params = SessionParams {
+            kind: SessionKind::CbrDownload,
+            payload_size: 1200,
+            bitrate: 2.5e6,
+            duration: Duration::from_secs(10),
+        };
+
+        let encoded = params.encode();
+        assert_eq!(SessionParams::decode(&encoded), Some(params));
+        assert_eq!(SessionParams::decode(&encoded[1..]), None);
+    }
+
+    #[test]
+    fn test_challenge_cookie_echoed_in_init() {
+        let params = SessionParams {
+            kind: SessionKind::Download,
+            payload_size: 1200,
+            bitrate: 0.0,
+            duration: Duration::from_secs(10),
+        };
+        let init = StpPacket::new(
+            StpHeader::new(PacketType::Init, 7, 1, 0, 0),
+            params.encode(),
+        );
+        assert_eq!(init.cookie(), None);
+        assert_eq!(
+            SessionParams::decode_init(&init.payload),
+            Some((params, None))
+        );
+
+        let challenge = StpPacket::challenge(&init, 0xc00c1e);
+        assert_eq!(challenge.header.packet_type, PacketType::InitAck);
+        assert_eq!(challenge.header.session_id, 7);
+        let cookie = challenge.cookie().unwrap();
+        assert_eq!(cookie, 0xc00c1e);
+
+        let echo = params.encode_with_cookie(cookie);
+        assert_eq!(
+            SessionParams::decode_init(&echo),
+            Some((params, Some(cookie)))
+        );
+        assert_eq!(SessionParams::decode_init(&echo[..echo.len() - 1]), None);
+
+        // The INIT-ACK accepting the session carries no cookie
+        let accept = StpPacket::reply(&init, PacketType::InitAck);
+        assert_eq!(accept.cookie(), None);
+    }
+
+    #[test]
+    fn test_session_params_rejects_invalid() {
+        let params = SessionParams {
+            kind: SessionKind::CbrDownload,
+            payload_size: MAX_PAYLOAD_SIZE,
+            bitrate: MAX_BITRATE,
+            duration: MAX_DURATION,
+        };
+        assert_eq!(SessionParams::decode(&params.encode()), Some(params));
+
+        let invalid = [
+            SessionParams {
+                payload_size: MAX_PAYLOAD_SIZE + 1,
+                ..params
+            },
+            SessionParams {
+                payload_size: u32::MAX as usize,
+                ..params
+            },
+            SessionParams {
+                bitrate: f64::NAN,
+                ..params
+            },
+            SessionParams {
+                bitrate: f64::INFINITY,
+                ..params
+            },
+            SessionParams {
+                bitrate: -1.0,
+                ..params
+            },
+            SessionParams {
+                bitrate: MAX_BITRATE * 2.0,
+                ..params
+            },
+            SessionParams {
+                bitrate: 0.0,
+                ..params
+            },
+            SessionParams {
+                duration: MAX_DURATION + Duration::from_millis(1),
+                ..params
+            },
+        ];
+        for params in invalid {
+            assert_eq!(SessionParams::decode(&params.encode()), None, "{params:?}");
+        }
+
+        // Only constant-bitrate tests need a bitrate
+        let download = SessionParams {
+            kind: SessionKind::Download,
+            bitrate: 0.0,
+            ..params
+        };
+        assert_eq!(SessionParams::decode(&download.encode()), Some(download));
+    }
+
+    #[test]
+    fn test_session_params_clamped() {
+        let params = SessionParams {
+            kind: SessionKind::CbrDownload,
+            payload_size: 1200,
+            bitrate: 40e9,
+            duration: Duration::from_secs(86_400),
+        };
+
+        let clamped = params.clamped(10e9, Duration::from_secs(3600));
+        assert_eq!(clamped.bitrate, 10e9);
+        assert_eq!(clamped.duration, Duration::from_secs(3600));
+        assert_eq!(clamped.payload_size, params.payload_size);
+
+        // Parameters within the limits are left alone
+        assert_eq!(clamped.clamped(20e9, Duration::from_secs(7200)), clamped);
+    }
+
+    #[test]
+    fn test_session_lifecycle() {
+        let addr = "127.0.0.1:5201".parse().unwrap();
+        let mut client = ConnectionState::new(addr, 7);
+        let mut server = ConnectionState::new(addr, 7);
+
+        let init = client.packet(PacketType::Init, Bytes::new());
+        assert_eq!(client.state, SessionState::Handshaking);
+        assert!(server.on_received(&init.header));
+        assert_eq!(server.state, SessionState::Established);
+
+        // Data can't arrive before the handshake completed
+        let data = server.packet(PacketType::Data, Bytes::new());
+        assert!(!client.on_received(&data.header));
+        let init_ack = server.packet(PacketType::InitAck, Bytes::new());
+        assert!(client.on_received(&init_ack.header));
+        assert!(client.on_received(&data.header));
+        assert_eq!(client.state, SessionState::Established);
+
+        // Packets of another session are dropped
+        let mut other = ConnectionState::new(addr, 8);
+        let stray = other.packet(PacketType::Init, Bytes::new());
+        assert!(!server.on_received(&stray.header));
+
+        let close = client.packet(PacketType::Close, Bytes::new());
+        assert_eq!(client.state, SessionState::Closing);
+        assert!(server.on_received(&close.header));
+        assert_eq!(server.state, SessionState::Closed);
+        let data = client.packet(PacketType::Data, Bytes::new());
+        assert!(!server.on_received(&data.header));
+
+        let close = server.packet(PacketType::Close, Bytes::new());
+        assert!(client.on_received(&close.header));
+        assert_eq!(client.state, SessionState::Closed);
+    }
 }
@@ -1,322 +1,891 @@
-use super::protocol::{ConnectionState, StpPacket};
+use super::cbr::{DatagramReceiver, cbr_pacer};
+use super::io::{DatagramSocket, MAX_DATAGRAM_SIZE, RecvBatch};
+use super::limits::SourceRateLimiter;
+use super::protocol::{
+    AckTracker, ConnectionState, PacketType, SessionKind, SessionParams, SessionState, StpHeader,
+    StpPacket, current_timestamp_micros,
+};
+use crate::performance::metrics::{ServerCounters, ServerMetrics};
+use crate::report::{SERVER_INTERVAL, ServerMeasurement};
 use crate::utils::format::{format_bytes, format_throughput};
 use bytes::Bytes;
 use colored::*;
 use eyre::Result;
 use parking_lot::Mutex;
 use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::time::Instant;
-use tokio::net::{ToSocketAddrs, UdpSocket};
+use tokio::net::UdpSocket;
 use tokio::time::Duration;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 // TODO: Is parking_lot for Mutex
 
+/// Server metrics for monitoring
+#[derive(Debug, Default)]
+pub struct UdpServerMetrics {
+    pub total_clients: AtomicU64,
+    /// Clients the server currently keeps state for
+    pub active_clients: AtomicUsize,
+    pub total_bytes_received: AtomicU64,
+    pub total_bytes_sent: AtomicU64,
+    pub packet_errors: AtomicU64,
+}
+
+impl UdpServerMetrics {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+}
+
+impl ServerMetrics for UdpServerMetrics {
+    fn counters(&self) -> ServerCounters {
+        ServerCounters {
+            total_connections: self.total_clients.load(Ordering::Relaxed),
+            active_connections: self.active_clients.load(Ordering::Relaxed) as u64,
+            bytes_received: self.total_bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.total_bytes_sent.load(Ordering::Relaxed),
+            errors: self.packet_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// How often expired sessions are evicted
+const REAPER_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time a new session is kept while waiting for the client to echo the cookie of its
+/// challenge. Its INIT is repeated every second, so this covers a few lost packets.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time a closed session is kept so a repeated CLOSE, sent since the reply was lost, can be
+/// answered again
+const CLOSED_SESSION_LINGER: Duration = Duration::from_secs(10);
+
+/// Maximum safe UDP payload size of download data (considering ethernet MTU minus IP/UDP
+/// headers)
+const MAX_UDP_PAYLOAD: usize = 1400;
+
+/// Download data packets sent for each packet of the client
+const BURST_PACKETS: usize = 10;
+
+/// Pause after each burst of download data, so the bursts don't flood the client
+const BURST_INTERVAL: Duration = Duration::from_millis(5);
+
+#[derive(Debug, Clone)]
+pub struct UdpServerConfig {
+    /// Bind address
+    pub bind_addr: SocketAddr,
+    /// Time after which a session the client stopped sending on is evicted
+    pub session_timeout: Duration,
+    /// Most sessions kept at once
+    pub max_sessions: usize,
+    /// Packets per second handled from each source IP address the session of the packet
+    /// isn't validated at, such as the INITs opening sessions
+    pub source_rate_limit: u32,
+    /// Highest bitrate in bits per second constant-bitrate downloads are streamed at.
+    /// Sessions asking for more are clamped to it.
+    pub max_bitrate: f64,
+    /// Longest test a session runs for. Sessions asking for more are clamped to it, which
+    /// also bounds how long constant-bitrate downloads keep their session.
+    pub max_test_duration: Duration,
+    /// Whether datagrams are sent and received one per syscall instead of in batches
+    pub standard_io: bool,
+    /// Metrics updated by the server
+    pub metrics: Arc<UdpServerMetrics>,
+}
+
+/// Sessions of the server, keyed by session ID
+type Sessions = Arc<Mutex<HashMap<u64, StpClientState>>>;
+
 /// STP Server for bandwidth measurement  
 pub struct StpServer {
-    socket: UdpSocket,
-    clients: Arc<Mutex<HashMap<std::net::SocketAddr, StpClientState>>>,
+    socket: Arc<DatagramSocket>,
+    sessions: Sessions,
+    metrics: Arc<UdpServerMetrics>,
+    session_timeout: Duration,
+    max_sessions: usize,
+    max_bitrate: f64,
+    max_test_duration: Duration,
+    limiter: Mutex<SourceRateLimiter>,
+    /// Packets refused by the rate limit since the reaper last reported them
+    rate_limited: AtomicU64,
+    /// Sessions refused since the reaper last reported them, as too many were open
+    sessions_refused: AtomicU64,
 }
 
 #[derive(Debug)]
 struct StpClientState {
     connection: ConnectionState,
+    /// Test requested by the client's INIT
+    params: SessionParams,
     start_time: Instant,
+    /// Time of the last packet received from the client
+    last_activity: Instant,
     total_bytes: u64,
     packets_received: u64,
     last_report: Instant,
-    local_packet_number: u64,
-    download_mode: bool,
-    download_payload_size: usize,
     download_start_time: Option<Instant>,
+    /// Start of the upload, set by the first data packet
+    upload_start_time: Option<Instant>,
+    /// Bytes received (upload) or sent (download) for the current test
+    measurement: ServerMeasurement,
+    /// Packets received from the client, reported back in ACK frames
+    acks: AckTracker,
+    /// Delivery of the upload datagrams
+    datagrams: DatagramReceiver,
+    /// Random key of the cookies challenging the addresses the session isn't validated at
+    cookie_key: RandomState,
 }
 
 impl StpClientState {
-    fn new(client_addr: std::net::SocketAddr) -> Self {
+    fn new(client_addr: SocketAddr, session_id: u64, params: SessionParams) -> Self {
         let now = Instant::now();
         Self {
-            connection: ConnectionState::new(client_addr),
+            connection: ConnectionState::new(client_addr, session_id),
+            params,
             start_time: now,
+            last_activity: now,
             total_bytes: 0,
             packets_received: 0,
             last_report: now,
-            local_packet_number: 0,
-            download_mode: false,
-            download_payload_size: 1024,
-            download_start_time: None,
+            download_start_time: params.kind.is_download().then_some(now),
+            upload_start_time: None,
+            measurement: ServerMeasurement {
+                connections: 1,
+                ..ServerMeasurement::new(SERVER_INTERVAL)
+            },
+            acks: AckTracker::new(),
+            datagrams: DatagramReceiver::new(),
+            cookie_key: RandomState::new(),
+        }
+    }
+
+    /// Whether the client proved it receives packets at `addr` by echoing a cookie from
+    /// there, so the server may send it data
+    fn is_validated(&self, addr: SocketAddr) -> bool {
+        self.connection.state != SessionState::Idle && self.connection.peer_addr == addr
+    }
+
+    /// Returns the cookie challenging `addr`. It only depends on the address, so challenges
+    /// sent to other addresses, spoofed or not, don't invalidate it.
+    fn cookie(&self, addr: SocketAddr) -> u64 {
+        self.cookie_key.hash_one(addr)
+    }
+
+    /// Whether the server is sending `kind` of download data to the client
+    fn is_sending(&self, kind: SessionKind) -> bool {
+        self.params.kind == kind && self.connection.state == SessionState::Established
+    }
+
+    /// Whether the session can be evicted: the client stopped sending for `timeout`, or
+    /// closed the session a while ago. The client of a constant-bitrate download only
+    /// listens, so the session is kept for the duration of the test in any case, which the
+    /// server limits to [`UdpServerConfig::max_test_duration`].
+    fn is_expired(&self, now: Instant, timeout: Duration) -> bool {
+        let idle = now.saturating_duration_since(self.last_activity);
+        match self.connection.state {
+            SessionState::Idle => idle >= HANDSHAKE_TIMEOUT.min(timeout),
+            SessionState::Closed => idle >= CLOSED_SESSION_LINGER.min(timeout),
+            _ if self.params.kind == SessionKind::CbrDownload => {
+                idle >= timeout
+                    && now.saturating_duration_since(self.start_time)
+                        >= self.params.duration + timeout
+            }
+            _ => idle >= timeout,
+        }
+    }
+
+    /// Logs the final statistics of the session, whose end `outcome` describes
+    fn log_final_stats(&self, outcome: &str) {
+        let duration = self.start_time.elapsed();
+        let final_mbps = if duration.as_secs_f64() > 0.0 {
+            (self.total_bytes as f64 * 8.0) / (duration.as_secs_f64() * 1_000_000.0)
+        } else {
+            0.0
+        };
+
+        info!(
+            "STP session {:016x} from {} {}: {} packets received, {} total in {:.2}s ({})",
+            self.connection.session_id,
+            self.connection.peer_addr.to_string().cyan(),
+            outcome,
+            self.packets_received,
+            format_bytes(self.total_bytes).yellow(),
+            duration.as_secs_f64(),
+            format_throughput(final_mbps).green()
+        );
+    }
+
+    /// Records bytes sent during a download
+    fn record_sent(&mut self, bytes: u64) {
+        if let Some(start) = self.download_start_time {
+            self.measurement.record(start.elapsed(), bytes);
         }
     }
 
-    fn next_packet_number(&mut self) -> u64 {
-        self.local_packet_number += 1;
-        self.local_packet_number
+    /// Records payload bytes received during an upload
+    fn record_received(&mut self, bytes: u64) {
+        let start = *self.upload_start_time.get_or_insert_with(Instant::now);
+        self.measurement.record(start.elapsed(), bytes);
+    }
+
+    /// Creates the CLOSE answering the client's, carrying the server-side measurement of the
+    /// test
+    fn close_packet(&mut self) -> Result<StpPacket> {
+        let datagrams = self.datagrams.stats();
+        self.measurement.datagrams = (datagrams.received > 0).then_some(datagrams);
+        let payload = serde_json::to_vec(&self.measurement)?;
+        Ok(self
+            .connection
+            .packet(PacketType::Close, Bytes::from(payload)))
     }
 }
 
+/// What to send in response to a packet, worked out while the sessions are locked
+#[derive(Debug, Default)]
+struct Response {
+    /// Packets to send back to the client
+    packets: Vec<Bytes>,
+    /// Payload size of the download data to send a burst of
+    download_burst: Option<usize>,
+    /// Constant-bitrate download to start streaming
+    cbr_download: Option<SessionParams>,
+}
+
 impl StpServer {
-    pub async fn new(addr: impl ToSocketAddrs) -> Result<Self> {
-        let socket = UdpSocket::bind(&addr).await?;
+    pub async fn new(config: UdpServerConfig) -> Result<Self> {
+        let socket = UdpSocket::bind(config.bind_addr).await?;
+        let socket = Arc::new(if config.standard_io {
+            DatagramSocket::standard(socket)
+        } else {
+            DatagramSocket::new(socket, true)
+        });
         Ok(Self {
             socket,
-            clients: Arc::new(Mutex::new(HashMap::new())),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            metrics: config.metrics,
+            session_timeout: config.session_timeout,
+            max_sessions: config.max_sessions,
+            max_bitrate: config.max_bitrate,
+            max_test_duration: config.max_test_duration,
+            limiter: Mutex::new(SourceRateLimiter::new(config.source_rate_limit)),
+            rate_limited: AtomicU64::new(0),
+            sessions_refused: AtomicU64::new(0),
         })
     }
 
+    /// Sends `data` to `addr`, counting it in the server metrics
+    async fn send_to(&self, data: &[u8], addr: SocketAddr) -> std::io::Result<usize> {
+        let result = self.socket.send_to(data, addr).await;
+        match &result {
+            Ok(sent) => self
+                .metrics
+                .total_bytes_sent
+                .fetch_add(*sent as u64, Ordering::Relaxed),
+            Err(_) => self.metrics.packet_errors.fetch_add(1, Ordering::Relaxed),
+        };
+        result
+    }
+
+    /// Sends `datagrams` to `addr` in as few syscalls as possible, counting them in the
+    /// server metrics
+    async fn send_batch_to(&self, datagrams: &[Bytes], addr: SocketAddr) -> std::io::Result<()> {
+        let result = self.socket.send_batch(datagrams, Some(addr)).await;
+        match &result {
+            Ok(()) => self.metrics.total_bytes_sent.fetch_add(
+                datagrams.iter().map(|datagram| datagram.len() as u64).sum(),
+                Ordering::Relaxed,
+            ),
+            Err(_) => self.metrics.packet_errors.fetch_add(1, Ordering::Relaxed),
+        };
+        result
+    }
+
     pub async fn run(&self) -> Result<()> {
         info!(
-            "UDP server listening on {}",
-            self.socket.local_addr()?.to_string().green()
+            "UDP server listening on {} ({})",
+            self.socket.local_addr()?.to_string().green(),
+            self.socket.path()
         );
 
-        let mut buffer = vec![0u8; 2048];
+        // Large enough for any datagram, since constant-bitrate tests choose their size
+        let mut batch = RecvBatch::new(&self.socket, MAX_DATAGRAM_SIZE);
 
         loop {
-            match self.socket.recv_from(&mut buffer).await {
-                Ok((size, client_addr)) => {
-                    debug!("Received {} bytes from {}", size, client_addr);
-                    let clients = self.clients.clone();
-                    let socket = &self.socket;
-                    let data = Bytes::copy_from_slice(&buffer[..size]);
-
-                    // Handle packet immediately (no need to spawn task for simple ACK)
-                    if let Err(e) = self
-                        .handle_stp_packet(socket, clients, client_addr, data)
-                        .await
-                    {
-                        error!("Error handling STP packet from {}: {}", client_addr, e);
+            match self.socket.recv_batch(&mut batch).await {
+                Ok(datagrams) => {
+                    let mut bursts = HashMap::new();
+                    for (data, client_addr) in datagrams {
+                        debug!("Received {} bytes from {}", data.len(), client_addr);
+                        self.metrics
+                            .total_bytes_received
+                            .fetch_add(data.len() as u64, Ordering::Relaxed);
+
+                        // Handle packet immediately (no need to spawn task for simple ACK)
+                        if let Err(e) = self.handle_stp_packet(client_addr, data, &mut bursts).await
+                        {
+                            self.metrics.packet_errors.fetch_add(1, Ordering::Relaxed);
+                            error!("Error handling STP packet from {}: {}", client_addr, e);
+                        }
                     }
+                    self.send_download_bursts(bursts).await;
                 }
                 Err(e) => {
+                    self.metrics.packet_errors.fetch_add(1, Ordering::Relaxed);
                     error!("STP receive error: {}", e);
                 }
             }
         }
     }
 
+    /// Handles a packet of the client at `client_addr`. Download sessions it clocks are
+    /// added to `bursts` along with the address and payload size of their data.
     async fn handle_stp_packet(
         &self,
-        socket: &UdpSocket,
-        clients: Arc<Mutex<HashMap<std::net::SocketAddr, StpClientState>>>,
-        client_addr: std::net::SocketAddr,
+        client_addr: SocketAddr,
         data: Bytes,
+        bursts: &mut HashMap<u64, (SocketAddr, usize)>,
     ) -> Result<()> {
-        if let Some(packet) = StpPacket::decode(data) {
-            let (ack_data, should_send_download_data, download_payload_size) = {
-                let mut clients_map = clients.lock();
-                let client_state = clients_map
-                    .entry(client_addr)
-                    .or_insert_with(|| StpClientState::new(client_addr));
-
-                // Check if this is a download command
-                if packet.payload.starts_with(b"DOWNLOAD") {
-                    client_state.download_mode = true;
-                    client_state.download_start_time = Some(Instant::now());
-
-                    // Parse payload size from DOWNLOAD:size format
-                    if let Ok(payload_str) = String::from_utf8(packet.payload.to_vec()) {
-                        info!("Received download command: '{}'", payload_str);
-                        if let Some(size_part) = payload_str.strip_prefix("DOWNLOAD:")
-                            && let Ok(size) = size_part.parse::<usize>()
-                        {
-                            client_state.download_payload_size = size;
-                            info!(
-                                "Client {} requested download mode with {} payload size",
-                                client_addr.to_string().cyan(),
-                                crate::utils::format::format_bytes(size).yellow()
-                            );
-                        } else {
-                            info!(
-                                "Client {} requested download mode (payload_str: '{}'), using default 1024 bytes",
-                                client_addr.to_string().cyan(),
-                                payload_str
-                            );
-                        }
-                    } else {
-                        info!(
-                            "Client {} requested download mode (invalid UTF-8), using default 1024 bytes",
-                            client_addr.to_string().cyan()
-                        );
-                    }
+        let arrival = current_timestamp_micros();
+        let Some(packet) = StpPacket::decode(data) else {
+            debug!("Dropping malformed packet from {}", client_addr);
+            return Ok(());
+        };
+
+        // Latency probes are answered without any session state
+        if packet.header.packet_type == PacketType::Ping {
+            let pong = StpPacket::reply(&packet, PacketType::Pong);
+            self.send_to(&pong.encode(), client_addr).await?;
+            return Ok(());
+        }
+
+        let response =
+            self.process_packet(&mut self.sessions.lock(), client_addr, &packet, arrival)?;
+
+        // Send replies without holding the lock
+        for reply in &response.packets {
+            self.send_to(reply, client_addr).await?;
+        }
+
+        if let Some(params) = response.cbr_download {
+            tokio::spawn(send_cbr_stream(
+                self.socket.clone(),
+                self.sessions.clone(),
+                self.metrics.clone(),
+                packet.header.session_id,
+                params,
+            ));
+        }
+
+        // If in download mode, send download data packets
+        if let Some(download_payload_size) = response.download_burst {
+            bursts.insert(
+                packet.header.session_id,
+                (client_addr, download_payload_size),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Sends a burst of download data to each of `bursts`, the sessions clocked by the
+    /// packets of a batch, so batching the packets of the clients doesn't multiply the
+    /// bursts and pauses
+    async fn send_download_bursts(&self, bursts: HashMap<u64, (SocketAddr, usize)>) {
+        if bursts.is_empty() {
+            return;
+        }
+
+        for (session_id, (client_addr, download_payload_size)) in bursts {
+            debug!("Sending download data to client {}", client_addr);
+
+            // Payloads larger than the maximum UDP size are fragmented into several packets
+            let fragments: Vec<usize> = (0..download_payload_size)
+                .step_by(MAX_UDP_PAYLOAD)
+                .map(|offset| MAX_UDP_PAYLOAD.min(download_payload_size - offset))
+                .collect();
+
+            // Send a burst of packets to maintain throughput, in as few syscalls as possible
+            let mut burst = Vec::new();
+            let mut burst_bytes = 0u64;
+            'burst: for _ in 0..BURST_PACKETS {
+                for fragment_size in &fragments {
+                    let payload = Bytes::from(vec![0u8; *fragment_size]);
+                    let Some(download_packet) = self.data_packet(session_id, payload) else {
+                        break 'burst; // Session closed
+                    };
+                    burst.push(download_packet.encode());
+                    burst_bytes += *fragment_size as u64;
                 }
+            }
 
-                // Check if this is a ping packet for latency measurement
-                let is_ping = packet.payload.starts_with(b"PING");
-                if is_ping {
-                    info!("Client {} sent ping packet", client_addr.to_string().cyan());
+            match self.send_batch_to(&burst, client_addr).await {
+                Ok(()) => {
+                    debug!(
+                        "Sent {} download packets ({} bytes) to {}",
+                        burst.len(),
+                        burst_bytes,
+                        client_addr
+                    );
+                    if let Some(session) = self.sessions.lock().get_mut(&session_id) {
+                        session.record_sent(burst_bytes);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to send download packets to {}: {}", client_addr, e);
                 }
+            }
+        }
 
-                // Update connection state
-                client_state.connection.update_from_received(&packet.header);
-                client_state.total_bytes += packet.payload.len() as u64;
-                client_state.packets_received += 1;
-
-                // Report progress periodically
-                if client_state.last_report.elapsed() >= Duration::from_secs(2) {
-                    let elapsed = client_state.start_time.elapsed();
-                    let current_mbps = if elapsed.as_secs_f64() > 0.0 {
-                        (client_state.total_bytes as f64 * 8.0)
-                            / (elapsed.as_secs_f64() * 1_000_000.0)
-                    } else {
-                        0.0
-                    };
+        tokio::time::sleep(BURST_INTERVAL).await;
+    }
 
-                    info!(
-                        "STP {}: {} packets, {} received, {} throughput",
-                        client_addr.to_string().cyan(),
-                        client_state.packets_received,
-                        format_bytes(client_state.total_bytes).yellow(),
-                        format_throughput(current_mbps).green()
+    /// Evicts expired sessions every [`REAPER_INTERVAL`], logging their final statistics
+    /// along with the packets refused since the last run
+    async fn reap_sessions(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(REAPER_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            let now = Instant::now();
+
+            let expired: Vec<StpClientState> = {
+                let mut sessions = self.sessions.lock();
+                let expired_ids: Vec<u64> = sessions
+                    .iter()
+                    .filter(|(_, session)| session.is_expired(now, self.session_timeout))
+                    .map(|(session_id, _)| *session_id)
+                    .collect();
+                expired_ids
+                    .iter()
+                    .filter_map(|session_id| sessions.remove(session_id))
+                    .collect()
+            };
+            self.limiter.lock().prune(now);
+
+            for session in expired {
+                // Closed sessions were already logged and are no longer active
+                if session.connection.state == SessionState::Closed {
+                    debug!(
+                        "Dropped closed session {:016x}",
+                        session.connection.session_id
                     );
+                    continue;
+                }
 
-                    client_state.last_report = Instant::now();
+                self.metrics.active_clients.fetch_sub(1, Ordering::Relaxed);
+                if session.connection.state == SessionState::Idle {
+                    debug!(
+                        "Dropped session {:016x} whose client never echoed the cookie",
+                        session.connection.session_id
+                    );
+                    continue;
                 }
+                let idle = now.saturating_duration_since(session.last_activity);
+                session.log_final_stats(&format!(
+                    "expired after {:.0}s without packets",
+                    idle.as_secs_f64()
+                ));
+            }
 
-                // Handle connection teardown (empty payload could indicate end)
-                if packet.payload.is_empty() && client_state.packets_received > 100 {
-                    // This might be a termination signal
-                    let duration = client_state.start_time.elapsed();
-                    let final_mbps = if duration.as_secs_f64() > 0.0 {
-                        (client_state.total_bytes as f64 * 8.0)
-                            / (duration.as_secs_f64() * 1_000_000.0)
-                    } else {
-                        0.0
-                    };
+            let rate_limited = self.rate_limited.swap(0, Ordering::Relaxed);
+            if rate_limited > 0 {
+                warn!("Dropped {rate_limited} packets of sources over the rate limit");
+            }
+            let sessions_refused = self.sessions_refused.swap(0, Ordering::Relaxed);
+            if sessions_refused > 0 {
+                warn!(
+                    "Refused {sessions_refused} sessions since {} were open",
+                    self.max_sessions
+                );
+            }
+        }
+    }
+
+    /// Creates the next download data packet of a session, unless it is no longer sending
+    fn data_packet(&self, session_id: u64, payload: Bytes) -> Option<StpPacket> {
+        let mut sessions = self.sessions.lock();
+        let session = sessions.get_mut(&session_id)?;
+        session
+            .is_sending(SessionKind::Download)
+            .then(|| session.connection.packet(PacketType::Data, payload))
+    }
+
+    /// Advances the session `packet` belongs to, opening it for an INIT, and works out the
+    /// response
+    fn process_packet(
+        &self,
+        sessions: &mut HashMap<u64, StpClientState>,
+        client_addr: SocketAddr,
+        packet: &StpPacket,
+        arrival: u64,
+    ) -> Result<Response> {
+        let header = &packet.header;
+        let mut response = Response::default();
+
+        // Packets from addresses their session isn't validated at may come from spoofed
+        // addresses, so each source only gets a few of them handled
+        let validated = sessions
+            .get(&header.session_id)
+            .is_some_and(|session| session.is_validated(client_addr));
+        if !validated && !self.limiter.lock().allow(client_addr.ip(), Instant::now()) {
+            self.rate_limited.fetch_add(1, Ordering::Relaxed);
+            debug!(
+                "Dropping {:?} packet of rate-limited {}",
+                header.packet_type, client_addr
+            );
+            return Ok(response);
+        }
+
+        // A new session, whose INIT carries the parameters of its test
+        if header.packet_type == PacketType::Init && !sessions.contains_key(&header.session_id) {
+            if sessions.len() >= self.max_sessions {
+                self.sessions_refused.fetch_add(1, Ordering::Relaxed);
+                debug!("Refusing session from {}: too many sessions", client_addr);
+                response
+                    .packets
+                    .push(StpPacket::reply(packet, PacketType::Reset).encode());
+                return Ok(response);
+            }
+
+            let Some((requested, _)) = SessionParams::decode_init(&packet.payload) else {
+                info!(
+                    "Refusing session from {}: invalid test parameters",
+                    client_addr.to_string().cyan()
+                );
+                response
+                    .packets
+                    .push(StpPacket::reply(packet, PacketType::Reset).encode());
+                return Ok(response);
+            };
+
+            // The client's parameters are only trusted up to the server's limits
+            let params = requested.clamped(self.max_bitrate, self.max_test_duration);
+            if params != requested {
+                info!(
+                    "Limiting session of {} to {} for {:.0}s",
+                    client_addr.to_string().cyan(),
+                    format_throughput(params.bitrate / 1_000_000.0),
+                    params.duration.as_secs_f64()
+                );
+            }
+
+            self.metrics.active_clients.fetch_add(1, Ordering::Relaxed);
+            sessions.insert(
+                header.session_id,
+                StpClientState::new(client_addr, header.session_id, params),
+            );
+        }
+
+        let Some(session) = sessions.get_mut(&header.session_id) else {
+            // Tell the client its session is unknown, unless it is aborting it anyway
+            if header.packet_type != PacketType::Reset {
+                debug!(
+                    "Resetting unknown session {:016x} of {}",
+                    header.session_id, client_addr
+                );
+                response
+                    .packets
+                    .push(StpPacket::reply(packet, PacketType::Reset).encode());
+            }
+            return Ok(response);
+        };
+
+        // Nothing but a challenge is sent to an address the client didn't prove it receives
+        // packets at, so the server can't be used to flood spoofed addresses. The client
+        // proves it by echoing the cookie in an INIT.
+        if !session.is_validated(client_addr) {
+            let echoed = (header.packet_type == PacketType::Init)
+                .then(|| SessionParams::decode_init(&packet.payload))
+                .flatten()
+                .and_then(|(_, cookie)| cookie);
+            let cookie = session.cookie(client_addr);
+            if echoed != Some(cookie) {
+                debug!(
+                    "Challenging {} for session {:016x}",
+                    client_addr, header.session_id
+                );
+                response
+                    .packets
+                    .push(StpPacket::challenge(packet, cookie).encode());
+                return Ok(response);
+            }
+
+            // The client's NAT may have rebound it to another address
+            if session.connection.peer_addr != client_addr {
+                info!(
+                    "Session {:016x} moved from {} to {}",
+                    header.session_id,
+                    session.connection.peer_addr.to_string().cyan(),
+                    client_addr.to_string().cyan()
+                );
+                session.connection.peer_addr = client_addr;
+            }
+        }
+
+        let previous_state = session.connection.state;
+        if !session.connection.on_received(header) {
+            debug!(
+                "Dropping {:?} packet of session {:016x} in state {:?}",
+                header.packet_type, header.session_id, previous_state
+            );
+            return Ok(response);
+        }
 
+        session.last_activity = Instant::now();
+        session.acks.on_packet_received(header.packet_number);
+        session.total_bytes += packet.payload.len() as u64;
+        session.packets_received += 1;
+
+        // Report progress periodically
+        if session.last_report.elapsed() >= Duration::from_secs(2) {
+            let elapsed = session.start_time.elapsed();
+            let current_mbps = if elapsed.as_secs_f64() > 0.0 {
+                (session.total_bytes as f64 * 8.0) / (elapsed.as_secs_f64() * 1_000_000.0)
+            } else {
+                0.0
+            };
+
+            info!(
+                "STP {}: {} packets, {} received, {} throughput",
+                client_addr.to_string().cyan(),
+                session.packets_received,
+                format_bytes(session.total_bytes).yellow(),
+                format_throughput(current_mbps).green()
+            );
+
+            session.last_report = Instant::now();
+        }
+
+        match header.packet_type {
+            PacketType::Init => {
+                if previous_state == SessionState::Idle {
+                    self.metrics.total_clients.fetch_add(1, Ordering::Relaxed);
+                    info!(
+                        "Client {} opened session {:016x}: {:?} with {} payloads",
+                        client_addr.to_string().cyan(),
+                        header.session_id,
+                        session.params.kind,
+                        format_bytes(session.params.payload_size).yellow()
+                    );
+                }
+                if previous_state == SessionState::Idle
+                    && session.params.kind == SessionKind::CbrDownload
+                {
                     info!(
-                        "STP session from {} completed: {} packets received, {} total in {:.2}s ({})",
+                        "Client {} requested a constant-bitrate download of {} payloads at {} for {:.2}s",
                         client_addr.to_string().cyan(),
-                        client_state.packets_received,
-                        format_bytes(client_state.total_bytes).yellow(),
-                        duration.as_secs_f64(),
-                        format_throughput(final_mbps).green()
+                        format_bytes(session.params.payload_size).yellow(),
+                        format_throughput(session.params.bitrate / 1_000_000.0).green(),
+                        session.params.duration.as_secs_f64()
                     );
+                    response.cbr_download = Some(session.params);
                 }
 
-                // Prepare ACK
-                let ack_packet_number = client_state.next_packet_number();
-                let ack_packet = StpPacket::ack_only(
-                    ack_packet_number,
-                    packet.header.packet_number, // ACK this packet
-                    packet.header.timestamp,     // Echo the timestamp
+                // Answered again if the INIT is repeated since the INIT-ACK was lost
+                let init_ack = session.connection.packet(PacketType::InitAck, Bytes::new());
+                response.packets.push(init_ack.encode());
+            }
+            PacketType::Data if !session.params.kind.is_download() => {
+                session.record_received(packet.payload.len() as u64);
+                session.datagrams.on_datagram(header, arrival);
+
+                // Constant-bitrate data isn't acknowledged
+                if session.params.kind == SessionKind::Upload {
+                    let ack = StpPacket::ack(
+                        header.session_id,
+                        session.connection.next_packet_number(),
+                        &session.acks.frame(),
+                        header.timestamp, // Echo the timestamp
+                    );
+                    response.packets.push(ack.encode());
+                }
+            }
+            PacketType::Close => {
+                if previous_state != SessionState::Closed {
+                    self.metrics.active_clients.fetch_sub(1, Ordering::Relaxed);
+                    session.log_final_stats("completed");
+                }
+
+                // The session is kept a while so a repeated CLOSE can be answered again
+                info!(
+                    "Sending results to {}: {} in {:.2}s",
+                    client_addr.to_string().cyan(),
+                    format_bytes(session.measurement.bytes).yellow(),
+                    session.measurement.duration.as_secs_f64()
                 );
+                response.packets.push(session.close_packet()?.encode());
+            }
+            PacketType::Reset => {
+                if previous_state != SessionState::Closed {
+                    self.metrics.active_clients.fetch_sub(1, Ordering::Relaxed);
+                    session.log_final_stats("was reset by the client");
+                }
+                sessions.remove(&header.session_id);
+                return Ok(response);
+            }
+            _ => {}
+        }
+
+        // Download data is clocked by the packets of the client
+        if session.is_sending(SessionKind::Download) {
+            response.download_burst = Some(session.params.payload_size);
+        }
 
+        Ok(response)
+    }
+}
+
+/// Sends the datagrams of a constant-bitrate download until the duration of the session is
+/// over or the client closes the session. The bitrate and duration of `params` were clamped
+/// to the server's limits when the session was opened.
+async fn send_cbr_stream(
+    socket: Arc<DatagramSocket>,
+    sessions: Sessions,
+    metrics: Arc<UdpServerMetrics>,
+    session_id: u64,
+    params: SessionParams,
+) {
+    let payload = Bytes::from(vec![0u8; params.payload_size]);
+    let mut pacer = cbr_pacer(params.bitrate, StpHeader::SIZE + payload.len());
+    let start = Instant::now();
+    let mut sent_datagrams = 0;
+
+    while start.elapsed() < params.duration {
+        pacer.wait(StpHeader::SIZE + payload.len()).await;
+
+        // The client may have moved to another address in the meantime
+        let next = sessions.lock().get_mut(&session_id).and_then(|session| {
+            session.is_sending(SessionKind::CbrDownload).then(|| {
                 (
-                    ack_packet.encode(),
-                    client_state.download_mode,
-                    client_state.download_payload_size,
+                    session.connection.packet(PacketType::Data, payload.clone()),
+                    session.connection.peer_addr,
                 )
-            }; // Lock is dropped here
-
-            // Send ACK without holding the lock
-            socket.send_to(&ack_data, client_addr).await?;
-
-            // If in download mode, send download data packets
-            if should_send_download_data {
-                info!("Sending download data to client {}", client_addr);
-
-                // Send a burst of packets to maintain throughput
-                // Maximum safe UDP packet size (considering ethernet MTU minus IP/UDP headers)
-                const MAX_UDP_PAYLOAD: usize = 1400;
-
-                for _ in 0..10 {
-                    // If payload is larger than max UDP size, fragment it
-                    if download_payload_size <= MAX_UDP_PAYLOAD {
-                        // Single packet
-                        let download_data = vec![0u8; download_payload_size];
-                        let payload = Bytes::from(download_data);
-
-                        // Get next packet number for download data
-                        let packet_number = {
-                            let mut clients_map = clients.lock();
-                            if let Some(client_state) = clients_map.get_mut(&client_addr) {
-                                client_state.next_packet_number()
-                            } else {
-                                break; // Client disconnected
-                            }
-                        };
-
-                        let download_packet = StpPacket::new(
-                            packet_number,
-                            0, // No ACK needed for download data
-                            0, // No timestamp echo
-                            payload,
-                        );
-
-                        match socket.send_to(&download_packet.encode(), client_addr).await {
-                            Ok(_) => {
-                                debug!(
-                                    "Sent download packet {} ({} bytes) to {}",
-                                    packet_number, download_payload_size, client_addr
-                                );
-                            }
-                            Err(e) => {
-                                error!("Failed to send download packet to {}: {}", client_addr, e);
-                                break; // Stop if send fails
-                            }
-                        }
-                    } else {
-                        // Fragment large payload into multiple packets
-                        let mut remaining_bytes = download_payload_size;
-                        let mut _fragment_number = 0;
-
-                        while remaining_bytes > 0 {
-                            let fragment_size = std::cmp::min(remaining_bytes, MAX_UDP_PAYLOAD);
-                            let fragment_data = vec![0u8; fragment_size];
-                            let payload = Bytes::from(fragment_data);
-
-                            // Get next packet number for each fragment
-                            let packet_number = {
-                                let mut clients_map = clients.lock();
-                                if let Some(client_state) = clients_map.get_mut(&client_addr) {
-                                    client_state.next_packet_number()
-                                } else {
-                                    break; // Client disconnected
-                                }
-                            };
-
-                            let download_packet = StpPacket::new(
-                                packet_number,
-                                0, // No ACK needed for download data
-                                0, // No timestamp echo
-                                payload,
-                            );
-
-                            match socket.send_to(&download_packet.encode(), client_addr).await {
-                                Ok(_) => {
-                                    debug!(
-                                        "Sent download fragment {} ({} bytes) to {}",
-                                        packet_number, fragment_size, client_addr
-                                    );
-                                }
-                                Err(e) => {
-                                    error!(
-                                        "Failed to send download fragment to {}: {}",
-                                        client_addr, e
-                                    );
-                                    break; // Stop if send fails
-                                }
-                            }
-
-                            remaining_bytes -= fragment_size;
-                            _fragment_number += 1;
-
-                            // Small delay between fragments
-                            tokio::time::sleep(Duration::from_micros(50)).await;
-                        }
-                    }
-
-                    // Small delay between packet bursts
-                    tokio::time::sleep(Duration::from_micros(500)).await;
+            })
+        });
+        let Some((packet, client_addr)) = next else {
+            break;
+        };
+
+        match socket.send_to(&packet.encode(), client_addr).await {
+            Ok(sent) => {
+                sent_datagrams += 1;
+                metrics
+                    .total_bytes_sent
+                    .fetch_add(sent as u64, Ordering::Relaxed);
+                if let Some(session) = sessions.lock().get_mut(&session_id) {
+                    session.record_sent(payload.len() as u64);
                 }
             }
+            Err(e) => {
+                metrics.packet_errors.fetch_add(1, Ordering::Relaxed);
+                error!(
+                    "Failed to send constant-bitrate datagram to {}: {}",
+                    client_addr, e
+                );
+                break;
+            }
         }
-
-        Ok(())
     }
+
+    info!(
+        "Constant-bitrate download of session {:016x} finished: {} datagrams in {:.2}s",
+        session_id,
+        sent_datagrams,
+        start.elapsed().as_secs_f64()
+    );
 }
 
-pub async fn run_udp_server(addr: impl ToSocketAddrs) -> Result<()> {
-    let server = StpServer::new(addr).await?;
+pub async fn run_udp_server(config: UdpServerConfig) -> Result<()> {
+    let server = Arc::new(StpServer::new(config).await?);
+    tokio::spawn(server.clone().reap_sessions());
     server.run().await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::protocol::StpHeader;
+    use super::*;
+
+    async fn test_server() -> StpServer {
+        StpServer::new(UdpServerConfig {
+            bind_addr: "127.0.0.1:0".parse().unwrap(),
+            session_timeout: Duration::from_secs(30),
+            max_sessions: 16,
+            source_rate_limit: 100,
+            max_bitrate: 10e9,
+            max_test_duration: Duration::from_secs(3600),
+            standard_io: true,
+            metrics: UdpServerMetrics::new(),
+        })
+        .await
+        .unwrap()
+    }
+
+    fn packet(packet_type: PacketType, packet_number: u64, payload: Bytes) -> StpPacket {
+        StpPacket::new(StpHeader::new(packet_type, 7, packet_number, 0, 0), payload)
+    }
+
+    /// Returns the cookie of the only packet of `response`, if it is a challenge
+    fn challenge_cookie(response: &Response) -> Option<u64> {
+        assert_eq!(response.packets.len(), 1);
+        StpPacket::decode(response.packets[0].clone())?.cookie()
+    }
+
+    #[tokio::test]
+    async fn test_data_only_sent_to_validated_address() {
+        let server = test_server().await;
+        let mut sessions = HashMap::new();
+        let client: SocketAddr = "192.0.2.1:4000".parse().unwrap();
+        let other: SocketAddr = "198.51.100.1:4000".parse().unwrap();
+        let params = SessionParams {
+            kind: SessionKind::Download,
+            payload_size: 1200,
+            bitrate: 0.0,
+            duration: Duration::from_secs(10),
+        };
+
+        // An INIT, maybe from a spoofed address, is only answered with a challenge
+        let init = packet(PacketType::Init, 1, params.encode());
+        let response = server
+            .process_packet(&mut sessions, client, &init, 0)
+            .unwrap();
+        let cookie = challenge_cookie(&response).unwrap();
+        assert!(response.download_burst.is_none());
+
+        // Echoing the cookie from another address doesn't validate either
+        let echo = packet(PacketType::Init, 2, params.encode_with_cookie(cookie));
+        let response = server
+            .process_packet(&mut sessions, other, &echo, 0)
+            .unwrap();
+        assert!(challenge_cookie(&response).is_some());
+        assert!(response.download_burst.is_none());
+
+        let response = server
+            .process_packet(&mut sessions, client, &echo, 0)
+            .unwrap();
+        assert_eq!(challenge_cookie(&response), None);
+        assert_eq!(response.download_burst, Some(params.payload_size));
+        assert_eq!(sessions[&7].connection.state, SessionState::Established);
+
+        // A packet of the session from another address doesn't move it without an echo
+        let ack = packet(PacketType::Ack, 3, Bytes::new());
+        let response = server
+            .process_packet(&mut sessions, other, &ack, 0)
+            .unwrap();
+        let cookie = challenge_cookie(&response).unwrap();
+        assert!(response.download_burst.is_none());
+        assert_eq!(sessions[&7].connection.peer_addr, client);
+
+        let echo = packet(PacketType::Init, 4, params.encode_with_cookie(cookie));
+        let response = server
+            .process_packet(&mut sessions, other, &echo, 0)
+            .unwrap();
+        assert_eq!(response.download_burst, Some(params.payload_size));
+        assert_eq!(sessions[&7].connection.peer_addr, other);
+    }
+}
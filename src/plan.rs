@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::Utc;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::performance::run_client_test;
+use crate::report::{Bitrate, StepReport, SuiteReport, TestConfig, Thresholds};
+use crate::utils::output::{StreamEvent, emit, status};
+use crate::{BitrateMode, ClientMode, CongestionAlgorithm, TargetBitrate, TestType, UdpMode};
+
+/// A sequence of client tests that run one after another and share a single report.
+/// Options of `defaults` apply to every step that doesn't set them itself.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestPlan {
+    pub name: Option<String>,
+    /// Labels of the suite report, e.g. `{ site = "lab" }`
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Tags of the suite report
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub defaults: PlanStep,
+    pub steps: Vec<PlanStep>,
+}
+
+/// Options of a single step of a test plan. Unset options fall back to the defaults of
+/// the plan, and then to the defaults of the `client` command.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlanStep {
+    /// Name of the step in the report. Defaults to the protocol and test type.
+    pub name: Option<String>,
+    pub protocol: Option<ClientMode>,
+    pub server: Option<String>,
+    pub port: Option<u16>,
+    /// Test duration in seconds
+    pub duration: Option<u64>,
+    pub connections: Option<usize>,
+    #[serde(rename = "type")]
+    pub test_type: Option<TestType>,
+    pub sizes: Option<Vec<usize>>,
+    pub chunk_size: Option<usize>,
+    /// Interval in seconds between throughput reports
+    pub interval: Option<f64>,
+    /// Rate uploads are limited to, e.g. `"50M"`
+    pub bitrate: Option<Bitrate>,
+    pub bitrate_mode: Option<BitrateMode>,
+    /// How UDP steps send datagrams. `cbr` requires a bitrate.
+    pub udp_mode: Option<UdpMode>,
+    /// Congestion control algorithm of UDP uploads in `stp` mode
+    pub cc: Option<CongestionAlgorithm>,
+    pub thresholds: Option<Thresholds>,
+}
+
+/// A step with all options resolved, ready to run
+#[derive(Debug, Clone)]
+pub struct ResolvedStep {
+    pub name: String,
+    pub config: TestConfig,
+    pub thresholds: Thresholds,
+}
+
+#[derive(Debug, Error)]
+pub enum PlanError {
+    #[error("Plan has no steps")]
+    NoSteps,
+    #[error("Step {0} has no protocol and the plan has no default protocol")]
+    MissingProtocol(usize),
+    #[error("Step {step} has an invalid report interval {interval}: {reason}")]
+    InvalidInterval {
+        step: usize,
+        interval: f64,
+        reason: String,
+    },
+    #[error("Step {0} uses the constant-bitrate UDP mode without a bitrate")]
+    MissingBitrate(usize),
+}
+
+impl PlanStep {
+    /// Returns this step with unset options taken from `defaults`. The name is not
+    /// inherited, since it identifies the step.
+    pub fn or(self, defaults: PlanStep) -> PlanStep {
+        PlanStep {
+            name: self.name,
+            protocol: self.protocol.or(defaults.protocol),
+            server: self.server.or(defaults.server),
+            port: self.port.or(defaults.port),
+            duration: self.duration.or(defaults.duration),
+            connections: self.connections.or(defaults.connections),
+            test_type: self.test_type.or(defaults.test_type),
+            sizes: self.sizes.or(defaults.sizes),
+            chunk_size: self.chunk_size.or(defaults.chunk_size),
+            interval: self.interval.or(defaults.interval),
+            bitrate: self.bitrate.or(defaults.bitrate),
+            bitrate_mode: self.bitrate_mode.or(defaults.bitrate_mode),
+            udp_mode: self.udp_mode.or(defaults.udp_mode),
+            cc: self.cc.or(defaults.cc),
+            thresholds: match (self.thresholds, defaults.thresholds) {
+                (Some(thresholds), Some(defaults)) => Some(thresholds.or(defaults)),
+                (thresholds, defaults) => thresholds.or(defaults),
+            },
+        }
+    }
+}
+
+impl TestPlan {
+    /// Resolves the options of every step, so mistakes in the plan surface before
+    /// anything runs. Steps are numbered from 1 in errors.
+    pub fn resolve(&self) -> Result<Vec<ResolvedStep>, PlanError> {
+        if self.steps.is_empty() {
+            return Err(PlanError::NoSteps);
+        }
+
+        let mut steps = Vec::with_capacity(self.steps.len());
+        for (index, step) in self.steps.iter().enumerate() {
+            let number = index + 1;
+            let step = step.clone().or(self.defaults.clone());
+
+            let mode = step.protocol.ok_or(PlanError::MissingProtocol(number))?;
+            let test_type = step.test_type.unwrap_or_default();
+            let interval = step.interval.unwrap_or(1.0);
+            let interval =
+                Duration::try_from_secs_f64(interval).map_err(|e| PlanError::InvalidInterval {
+                    step: number,
+                    interval,
+                    reason: e.to_string(),
+                })?;
+
+            let udp_mode = step.udp_mode.unwrap_or_default();
+            if udp_mode == UdpMode::Cbr && step.bitrate.is_none() {
+                return Err(PlanError::MissingBitrate(number));
+            }
+
+            steps.push(ResolvedStep {
+                name: step.name.unwrap_or_else(|| format!("{mode} {test_type}")),
+                config: TestConfig::new(
+                    mode,
+                    step.server.unwrap_or_else(|| "127.0.0.1".to_string()),
+                    step.port,
+                    step.duration.unwrap_or(10),
+                    step.connections.unwrap_or(1),
+                    test_type,
+                    step.sizes.unwrap_or_default(),
+                    step.chunk_size,
+                    interval,
+                )
+                .with_bitrate(step.bitrate.map(|bitrate| TargetBitrate {
+                    bitrate,
+                    mode: step.bitrate_mode.unwrap_or_default(),
+                }))
+                .with_udp_mode(udp_mode)
+                .with_congestion_control(step.cc.unwrap_or_default()),
+                thresholds: step.thresholds.unwrap_or_default(),
+            });
+        }
+
+        Ok(steps)
+    }
+}
+
+/// Runs every step of `plan` in order. A step that fails to run doesn't stop the
+/// remaining steps; its error is recorded in the report instead.
+pub async fn run_plan(plan: &TestPlan) -> Result<SuiteReport, PlanError> {
+    let steps = plan.resolve()?;
+    Ok(run_steps(
+        plan.name.clone(),
+        plan.labels.clone(),
+        plan.tags.clone(),
+        steps,
+        Duration::ZERO,
+    )
+    .await)
+}
+
+/// Runs `steps` in order, waiting `pause` between consecutive steps, and collects
+/// their reports into a suite report
+pub async fn run_steps(
+    name: Option<String>,
+    labels: BTreeMap<String, String>,
+    tags: Vec<String>,
+    steps: Vec<ResolvedStep>,
+    pause: Duration,
+) -> SuiteReport {
+    let start_time = Utc::now();
+    let total = steps.len();
+
+    let mut reports = Vec::with_capacity(total);
+    for (index, step) in steps.into_iter().enumerate() {
+        if index > 0 && !pause.is_zero() {
+            tokio::time::sleep(pause).await;
+        }
+
+        status!(
+            "{}",
+            format!("Step {}/{total}: {}", index + 1, step.name)
+                .blue()
+                .bold()
+        );
+
+        let (report, error) = match run_client_test(step.config.clone()).await {
+            Ok(mut report) => {
+                if !step.thresholds.is_empty() {
+                    report.thresholds = Some(step.thresholds.evaluate(&report.result));
+                }
+                emit(&StreamEvent::Report { report: &report });
+                (Some(report), None)
+            }
+            Err(e) => {
+                status!("{}", format!("Step {} failed: {e}", index + 1).red());
+                (None, Some(e.to_string()))
+            }
+        };
+
+        reports.push(StepReport {
+            name: step.name,
+            config: step.config,
+            report,
+            error,
+        });
+    }
+
+    SuiteReport::new(name, labels, tags, start_time, reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::Bitrate;
+
+    #[test]
+    fn test_resolve_steps() {
+        let plan: TestPlan = toml::from_str(
+            r#"
+            name = "nightly"
+
+            [defaults]
+            server = "10.0.0.2"
+            duration = 5
+            thresholds = { min_download = "100Mbps", max_loss = "1%" }
+
+            [[steps]]
+            protocol = "tcp"
+            type = "download"
+            connections = 4
+            thresholds = { min_download = "1Gbps" }
+
+            [[steps]]
+            name = "HTTP/3 upload"
+            protocol = "http3"
+            type = "upload"
+            sizes = [1024, 65536]
+            "#,
+        )
+        .unwrap();
+
+        let steps = plan.resolve().unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].name, "tcp download");
+        assert_eq!(steps[1].name, "HTTP/3 upload");
+        assert_eq!(steps[0].thresholds.min_download, Some(Bitrate(1e9)));
+        assert!(steps[0].thresholds.max_loss.is_some());
+
+        let TestConfig::Tcp(tcp) = &steps[0].config else {
+            panic!("expected a TCP step");
+        };
+        assert_eq!(tcp.server, "10.0.0.2");
+        assert_eq!(tcp.parallel_connections, 4);
+        assert_eq!(tcp.duration, Duration::from_secs(5));
+
+        let TestConfig::Http(http) = &steps[1].config else {
+            panic!("expected an HTTP step");
+        };
+        assert_eq!(http.server_url, "https://10.0.0.2:8443");
+        assert_eq!(http.payload_sizes.len(), 2);
+    }
+
+    #[test]
+    fn test_resolve_missing_protocol() {
+        let plan: TestPlan = toml::from_str("[[steps]]\ntype = \"download\"").unwrap();
+        assert!(matches!(plan.resolve(), Err(PlanError::MissingProtocol(1))));
+    }
+
+    #[test]
+    fn test_resolve_cbr_requires_bitrate() {
+        let plan: TestPlan =
+            toml::from_str("[[steps]]\nprotocol = \"udp\"\nudp_mode = \"cbr\"").unwrap();
+        assert!(matches!(plan.resolve(), Err(PlanError::MissingBitrate(1))));
+    }
+}
@@ -0,0 +1,308 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::report::*;
+
+const HEADER: &[&str] = &[
+    "protocol",
+    "server",
+    "test_type",
+    "connections",
+    "phase",
+    "payload_size",
+    "kind",
+    "connection",
+    "index",
+    "start_s",
+    "end_s",
+    "duration_s",
+    "bytes",
+    "throughput_bps",
+    "rtt_ms",
+    "errors",
+    "retransmits",
+    "error",
+];
+
+/// A single row of the CSV export. Columns that don't apply to a sample are left empty.
+#[derive(Debug, Default)]
+struct Row {
+    phase: &'static str,
+    payload_size: Option<usize>,
+    /// `probe` for latency probes, `interval` for interval buckets and `error` for steps of a
+    /// suite that failed to run
+    kind: &'static str,
+    /// Connection of an interval bucket. Empty for buckets of all connections combined.
+    connection: Option<usize>,
+    index: usize,
+    start: Option<Duration>,
+    end: Option<Duration>,
+    duration: Option<Duration>,
+    bytes: Option<u64>,
+    throughput_bps: Option<f64>,
+    rtt_ms: Option<f64>,
+    errors: Option<u64>,
+    retransmits: Option<u64>,
+    error: Option<String>,
+}
+
+/// Writes `report` as a single tidy CSV table with one row per sample: latency probes and
+/// interval buckets (overall and per connection). Individual reads and writes aren't
+/// exported, so throughput is only as fine-grained as the report interval.
+/// Every row repeats the configuration of the test so rows can be filtered on their own.
+pub fn write_csv<W: Write>(report: &TestReport, writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "{}", HEADER.join(","))?;
+    write_report_rows(writer, &config_columns(&report.config), report)
+}
+
+/// Writes the rows of every step of `report` as a single CSV table like [`write_csv`], with
+/// an additional `step` column first. Steps that failed to run get a single `error` row.
+pub fn write_suite_csv<W: Write>(report: &SuiteReport, writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "step,{}", HEADER.join(","))?;
+    for step in &report.steps {
+        let prefix: Vec<_> = std::iter::once(step.name.clone())
+            .chain(config_columns(&step.config))
+            .collect();
+        if let Some(report) = &step.report {
+            write_report_rows(writer, &prefix, report)?;
+        }
+        if let Some(error) = &step.error {
+            write_row(
+                writer,
+                &prefix,
+                Row {
+                    kind: "error",
+                    error: Some(error.clone()),
+                    ..Default::default()
+                },
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Protocol, server, test type and connections of a test, which start every row
+fn config_columns(config: &TestConfig) -> [String; 4] {
+    let (server, test_type, connections) = match config {
+        TestConfig::Tcp(config) => (
+            format!("{}:{}", config.server, config.port),
+            config.test_type,
+            config.parallel_connections,
+        ),
+        TestConfig::Udp(config) => (
+            format!("{}:{}", config.server, config.port),
+            config.test_type,
+            config.parallel_streams,
+        ),
+        TestConfig::Http(config) => (
+            config.server_url.clone(),
+            config.test_type,
+            config.parallel_connections,
+        ),
+    };
+    [
+        config.protocol().to_string(),
+        server,
+        test_type.to_string(),
+        connections.to_string(),
+    ]
+}
+
+fn write_report_rows<W: Write>(
+    writer: &mut W,
+    prefix: &[String],
+    report: &TestReport,
+) -> io::Result<()> {
+    let mut write_row = |row: Row| write_row(writer, prefix, row);
+
+    match &report.result {
+        TestResult::Simple(result) => {
+            write_throughput_rows(&mut write_row, "throughput", None, result)?
+        }
+        TestResult::Network(result) => {
+            if let Some(latency) = &result.latency {
+                for (index, probe) in latency.measurements.iter().enumerate() {
+                    write_row(Row {
+                        phase: "latency",
+                        kind: "probe",
+                        index,
+                        start: Some(probe.elapsed_time),
+                        rtt_ms: probe.rtt_ms,
+                        error: probe.rtt_ms.is_none().then(|| "dropped".to_string()),
+                        ..Default::default()
+                    })?;
+                }
+            }
+            for (size, result) in &result.download {
+                write_throughput_rows(&mut write_row, "download", Some(*size), result)?;
+            }
+            for (size, result) in &result.upload {
+                write_throughput_rows(&mut write_row, "upload", Some(*size), result)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_row<W: Write>(writer: &mut W, prefix: &[String], row: Row) -> io::Result<()> {
+    let secs = |d: Option<Duration>| d.map(|d| format!("{:.6}", d.as_secs_f64()));
+    let fields = [
+        Some(row.phase.to_string()),
+        row.payload_size.map(|s| s.to_string()),
+        Some(row.kind.to_string()),
+        row.connection.map(|c| c.to_string()),
+        Some(row.index.to_string()),
+        secs(row.start),
+        secs(row.end),
+        secs(row.duration),
+        row.bytes.map(|b| b.to_string()),
+        row.throughput_bps.map(|t| format!("{t:.3}")),
+        row.rtt_ms.map(|r| format!("{r:.3}")),
+        row.errors.map(|e| e.to_string()),
+        row.retransmits.map(|r| r.to_string()),
+        row.error,
+    ];
+
+    let line = prefix
+        .iter()
+        .cloned()
+        .chain(fields.into_iter().map(Option::unwrap_or_default))
+        .map(|field| escape_csv(&field))
+        .collect::<Vec<_>>()
+        .join(",");
+    writeln!(writer, "{line}")
+}
+
+fn write_throughput_rows(
+    write_row: &mut impl FnMut(Row) -> io::Result<()>,
+    phase: &'static str,
+    payload_size: Option<usize>,
+    result: &ThroughputResult,
+) -> io::Result<()> {
+    let series = std::iter::once((None, &result.intervals)).chain(
+        result
+            .connection_intervals
+            .iter()
+            .map(|(connection, intervals)| (Some(*connection), intervals)),
+    );
+    for (connection, intervals) in series {
+        for (index, interval) in intervals.iter().enumerate() {
+            write_row(Row {
+                phase,
+                payload_size,
+                kind: "interval",
+                connection,
+                index,
+                start: Some(interval.start),
+                end: Some(interval.end),
+                duration: Some(interval.end.saturating_sub(interval.start)),
+                bytes: Some(interval.bytes),
+                throughput_bps: Some(interval.throughput * 8.0),
+                errors: Some(interval.errors.into()),
+                retransmits: Some(interval.retransmits),
+                ..Default::default()
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Quotes `field` if it contains characters with a special meaning in CSV
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestType;
+    use chrono::Utc;
+
+    #[test]
+    fn test_csv_rows() {
+        let interval = ThroughputInterval {
+            start: Duration::ZERO,
+            end: Duration::from_secs(1),
+            bytes: 1000,
+            throughput: 1000.0,
+            errors: 1,
+            retransmits: 2,
+        };
+        let mut result = NetworkTestResult::new_udp();
+        result.add_download(
+            1024,
+            ThroughputResult {
+                totals: [ThroughputMeasurement::new(1000, Duration::from_millis(500))]
+                    .iter()
+                    .collect(),
+                total_duration: Duration::from_secs(1),
+                intervals: vec![interval.clone()],
+                connection_intervals: [(0, vec![interval])].into(),
+                timestamp: Utc::now(),
+            },
+            None,
+        );
+        let config = UdpTestConfig::new(
+            "127.0.0.1".to_string(),
+            Some(5201),
+            1,
+            1,
+            TestType::Download,
+            [1024],
+            Duration::from_secs(1),
+        );
+        let report = TestReport::from((Utc::now(), config, result));
+
+        let mut buffer = Vec::new();
+        write_csv(&report, &mut buffer).unwrap();
+        let csv = String::from_utf8(buffer).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], HEADER.join(","));
+        assert_eq!(
+            lines[1],
+            "udp,127.0.0.1:5201,download,1,download,1024,interval,,0,0.000000,1.000000,1.000000,1000,8000.000,,1,2,"
+        );
+        assert!(lines[2].contains(",interval,0,0,"));
+    }
+
+    #[test]
+    fn test_suite_csv_error_row() {
+        let config = TcpTestConfig::new(
+            "127.0.0.1".to_string(),
+            Some(5201),
+            1,
+            1,
+            TestType::Download,
+            [1024],
+            Duration::from_secs(1),
+        );
+        let step = StepReport {
+            name: "Run, 1".to_string(),
+            config: config.into(),
+            report: None,
+            error: Some("Connection refused".to_string()),
+        };
+        let suite = SuiteReport::new(None, Default::default(), Vec::new(), Utc::now(), vec![step]);
+
+        let mut buffer = Vec::new();
+        write_suite_csv(&suite, &mut buffer).unwrap();
+        let csv = String::from_utf8(buffer).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], format!("step,{}", HEADER.join(",")));
+        assert_eq!(
+            lines[1],
+            "\"Run, 1\",tcp,127.0.0.1:5201,download,1,,,error,,0,,,,,,,,,Connection refused"
+        );
+    }
+}
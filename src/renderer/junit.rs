@@ -0,0 +1,185 @@
+use std::io::{self, Write};
+
+use chrono::{DateTime, Utc};
+
+use crate::report::{SuiteReport, TestReport};
+
+use super::test_cases::{TestCase, suite_name, test_cases};
+
+/// A `<testsuite>` element: the test cases of a single report
+struct Suite<'a> {
+    name: String,
+    timestamp: DateTime<Utc>,
+    version: &'a str,
+    cases: Vec<TestCase>,
+    /// Error that stopped the suite from running, reported as an errored test case
+    error: Option<&'a str>,
+}
+
+impl Suite<'_> {
+    fn failures(&self) -> usize {
+        self.cases
+            .iter()
+            .filter(|case| !case.failures.is_empty())
+            .count()
+    }
+
+    fn errors(&self) -> usize {
+        usize::from(self.error.is_some())
+    }
+
+    fn tests(&self) -> usize {
+        self.cases.len() + self.errors()
+    }
+
+    fn time(&self) -> f64 {
+        self.cases.iter().map(|case| case.time.as_secs_f64()).sum()
+    }
+}
+
+/// Writes `report` as a JUnit XML document with a test case per phase of the test
+pub fn write_junit<W: Write>(report: &TestReport, writer: &mut W) -> io::Result<()> {
+    let suite = Suite {
+        name: suite_name(report),
+        timestamp: report.start_time,
+        version: &report.version,
+        cases: test_cases(report),
+        error: None,
+    };
+    write_testsuites(writer, &[suite])
+}
+
+/// Writes `report` as a JUnit XML document with a test suite per step of the plan.
+/// Steps that failed to run become a suite with a single errored test case.
+pub fn write_suite_junit<W: Write>(report: &SuiteReport, writer: &mut W) -> io::Result<()> {
+    let suites: Vec<_> = report
+        .steps
+        .iter()
+        .map(|step| Suite {
+            name: step.name.clone(),
+            timestamp: step
+                .report
+                .as_ref()
+                .map_or(report.start_time, |report| report.start_time),
+            version: &report.version,
+            cases: step.report.as_ref().map(test_cases).unwrap_or_default(),
+            error: step.error.as_deref(),
+        })
+        .collect();
+    write_testsuites(writer, &suites)
+}
+
+fn write_testsuites<W: Write>(writer: &mut W, suites: &[Suite]) -> io::Result<()> {
+    let tests: usize = suites.iter().map(Suite::tests).sum();
+    let failures: usize = suites.iter().map(Suite::failures).sum();
+    let errors: usize = suites.iter().map(Suite::errors).sum();
+    let time: f64 = suites.iter().map(Suite::time).sum();
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<testsuites name="speed-cli" tests="{tests}" failures="{failures}" errors="{errors}" time="{time:.3}">"#
+    )?;
+    for suite in suites {
+        write_testsuite(writer, suite)?;
+    }
+    writeln!(writer, "</testsuites>")
+}
+
+fn write_testsuite<W: Write>(writer: &mut W, suite: &Suite) -> io::Result<()> {
+    let name = escape_xml(&suite.name);
+
+    writeln!(
+        writer,
+        r#"  <testsuite name="{name}" tests="{}" failures="{}" errors="{}" skipped="0" time="{:.3}" timestamp="{}">"#,
+        suite.tests(),
+        suite.failures(),
+        suite.errors(),
+        suite.time(),
+        suite.timestamp.format("%Y-%m-%dT%H:%M:%S")
+    )?;
+    writeln!(writer, "    <properties>")?;
+    writeln!(
+        writer,
+        r#"      <property name="version" value="{}"/>"#,
+        escape_xml(suite.version)
+    )?;
+    writeln!(writer, "    </properties>")?;
+
+    if let Some(error) = suite.error {
+        writeln!(
+            writer,
+            r#"    <testcase classname="{name}" name="{name}" time="0.000">"#
+        )?;
+        writeln!(
+            writer,
+            r#"      <error message="{}" type="error"/>"#,
+            escape_xml(error)
+        )?;
+        writeln!(writer, "    </testcase>")?;
+    }
+
+    for case in &suite.cases {
+        write!(
+            writer,
+            r#"    <testcase classname="{name}" name="{}" time="{:.3}">"#,
+            escape_xml(&case.name),
+            case.time.as_secs_f64()
+        )?;
+        writeln!(writer)?;
+
+        if !case.failures.is_empty() {
+            writeln!(
+                writer,
+                r#"      <failure message="{}" type="failure">{}</failure>"#,
+                escape_xml(&case.failures.join("; ")),
+                escape_xml(&case.failures.join("\n"))
+            )?;
+        }
+        if !case.summary.is_empty() {
+            let summary = case
+                .summary
+                .iter()
+                .map(|(key, value)| format!("{key}: {value}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            writeln!(
+                writer,
+                "      <system-out>{}</system-out>",
+                escape_xml(&summary)
+            )?;
+        }
+
+        writeln!(writer, "    </testcase>")?;
+    }
+
+    writeln!(writer, "  </testsuite>")
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!(
+            escape_xml(r#"<a href="x">Tom & 'Jerry'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &apos;Jerry&apos;&lt;/a&gt;"
+        );
+    }
+}
@@ -3,6 +3,17 @@ use crate::report::*;
 use crate::utils::types::TestType;
 use std::io::{self, Write};
 
+mod csv;
+mod junit;
+mod prometheus;
+mod tap;
+mod test_cases;
+
+pub use csv::*;
+pub use junit::*;
+pub use prometheus::*;
+pub use tap::*;
+
 // TODO: Expand amount of graphs in HTML
 // TODO: Ensure correctness and performance of HTML generation from huge reports (10GB+)
 
@@ -193,7 +204,29 @@ impl ToHtml for TestReport {
             writer,
             r#"
         </div>
-    </div>
+"#
+        )?;
+
+        if let Some(thresholds) = &self.thresholds {
+            write!(
+                writer,
+                r#"
+        <div class="section">
+            <h2 class="section-title">Thresholds</h2>
+            "#
+            )?;
+            thresholds.write_html(writer)?;
+            write!(
+                writer,
+                r#"
+        </div>
+"#
+            )?;
+        }
+
+        write!(
+            writer,
+            r#"    </div>
 </body>
 </html>"#
         )
@@ -313,14 +346,26 @@ impl ToHtml for TestReport {
             <h2 class="section-title">Results</h2>
             {}
         </div>
-    </div>
+{}    </div>
 </body>
 </html>"#,
             self.version,
             self.start_time.format("%Y-%m-%d %H:%M:%S UTC"),
             self.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
             self.config.to_html(),
-            self.result.to_html()
+            self.result.to_html(),
+            self.thresholds
+                .as_ref()
+                .map(|thresholds| format!(
+                    r#"
+        <div class="section">
+            <h2 class="section-title">Thresholds</h2>
+            {}
+        </div>
+"#,
+                    thresholds.to_html()
+                ))
+                .unwrap_or_default()
         )
     }
 }
@@ -561,6 +606,14 @@ impl ToHtml for ThroughputResult {
                         <strong>Average Throughput:</strong> 
                         <span style="color: #6f42c1;">{}</span>
                     </div>
+                    <div style="display: flex; justify-content: space-between;">
+                        <strong>Intervals:</strong> 
+                        <span style="color: #6c757d;">{}</span>
+                    </div>
+                    <div style="display: flex; justify-content: space-between;">
+                        <strong>Retransmits:</strong> 
+                        <span style="color: #dc3545;">{}</span>
+                    </div>
                     <div style="display: flex; justify-content: space-between;">
                         <strong>Measurements:</strong> 
                         <span style="color: #6c757d;">{}</span>
@@ -574,7 +627,9 @@ impl ToHtml for ThroughputResult {
             format_bytes_u64(self.bytes_transferred()),
             self.total_duration.as_secs_f64(),
             format_throughput(self.avg_throughput()),
-            self.measurements.len(),
+            interval_summary(self),
+            self.total_retransmits(),
+            self.totals.count(),
             self.timestamp.format("%Y-%m-%d %H:%M:%S UTC")
         )
     }
@@ -596,6 +651,14 @@ impl ToHtml for ThroughputResult {
                         <strong>Average Throughput:</strong> 
                         <span style="color: #6f42c1;">{}</span>
                     </div>
+                    <div style="display: flex; justify-content: space-between;">
+                        <strong>Intervals:</strong> 
+                        <span style="color: #6c757d;">{}</span>
+                    </div>
+                    <div style="display: flex; justify-content: space-between;">
+                        <strong>Retransmits:</strong> 
+                        <span style="color: #dc3545;">{}</span>
+                    </div>
                     <div style="display: flex; justify-content: space-between;">
                         <strong>Measurements:</strong> 
                         <span style="color: #6c757d;">{}</span>
@@ -609,12 +672,27 @@ impl ToHtml for ThroughputResult {
             format_bytes_u64(self.bytes_transferred()),
             self.total_duration.as_secs_f64(),
             format_throughput(self.avg_throughput()),
-            self.measurements.len(),
+            interval_summary(self),
+            self.total_retransmits(),
+            self.totals.count(),
             self.timestamp.format("%Y-%m-%d %H:%M:%S UTC")
         )
     }
 }
 
+/// Summarizes the per-interval series of a throughput result on one line
+fn interval_summary(result: &ThroughputResult) -> String {
+    match result.interval_throughput_range() {
+        Some((min, max)) => format!(
+            "{} (min {}, max {})",
+            result.intervals.len(),
+            format_throughput(min * 8.0),
+            format_throughput(max * 8.0)
+        ),
+        None => "-".to_string(),
+    }
+}
+
 // Implementation for NetworkTestResult
 impl ToHtml for NetworkTestResult {
     fn write_html<W: Write>(&self, writer: &mut W) -> io::Result<()> {
@@ -637,6 +715,101 @@ impl ToHtml for NetworkTestResult {
             write!(writer, r#"</div>"#)?;
         }
 
+        // Latency under load
+        if let Some(loaded) = &self.loaded_latency {
+            write!(
+                writer,
+                r#"<div class="result-section" style="margin-bottom: 30px;">
+                    <h3 style="color: #28a745; border-bottom: 2px solid #e9ecef; padding-bottom: 10px;">{}Latency Under Load</h3>
+                    <table style="width: 100%; border-collapse: collapse;">
+                        <tr><th style="text-align: left;">Link</th><th style="text-align: left;">Average RTT</th><th style="text-align: left;">Increase</th></tr>"#,
+                protocol_prefix
+            )?;
+            let format_ms =
+                |rtt: Option<f64>| rtt.map_or("-".to_string(), |rtt| format!("{rtt:.2} ms"));
+            if let Some(idle) = &self.latency {
+                write!(
+                    writer,
+                    r#"<tr><td>Idle</td><td>{}</td><td>-</td></tr>"#,
+                    format_ms(idle.avg_rtt())
+                )?;
+            }
+            for (direction, latency) in loaded.directions() {
+                let increase = self
+                    .latency_increase(latency)
+                    .map_or("-".to_string(), |increase| format!("{increase:+.2} ms"));
+                write!(
+                    writer,
+                    r#"<tr><td>During {direction}</td><td>{}</td><td>{increase}</td></tr>"#,
+                    format_ms(latency.avg_rtt())
+                )?;
+            }
+            write!(writer, r#"</table>"#)?;
+            if let Some(grade) = self.bufferbloat_grade() {
+                write!(
+                    writer,
+                    r#"<p><strong>Bufferbloat Grade:</strong> {grade}</p>"#
+                )?;
+            }
+            write!(writer, r#"</div>"#)?;
+        }
+
+        // Responsiveness
+        if let Some(responsiveness) = &self.responsiveness {
+            write!(
+                writer,
+                r#"<div class="result-section" style="margin-bottom: 30px;">
+                    <h3 style="color: #28a745; border-bottom: 2px solid #e9ecef; padding-bottom: 10px;">{}Responsiveness</h3>
+                    <table style="width: 100%; border-collapse: collapse;">
+                        <tr><th style="text-align: left;">Direction</th><th style="text-align: left;">RPM</th><th style="text-align: left;">Goodput</th><th style="text-align: left;">Connections</th><th style="text-align: left;">Stable</th></tr>"#,
+                protocol_prefix
+            )?;
+            for (direction, phase) in responsiveness.directions() {
+                write!(
+                    writer,
+                    r#"<tr><td>{direction}</td><td>{:.0}</td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+                    phase.rpm,
+                    format_throughput(phase.goodput_bps),
+                    phase.load_connections,
+                    if phase.stable { "yes" } else { "no" }
+                )?;
+            }
+            write!(writer, r#"</table>"#)?;
+            if let Some(rpm) = responsiveness.rpm() {
+                write!(
+                    writer,
+                    r#"<p><strong>Responsiveness:</strong> {rpm:.0} RPM</p>"#
+                )?;
+            }
+            write!(writer, r#"</div>"#)?;
+        }
+
+        // Datagram delivery of UDP tests
+        if !self.datagrams.is_empty() {
+            write!(
+                writer,
+                r#"<div class="result-section" style="margin-bottom: 30px;">
+                    <h3 style="color: #28a745; border-bottom: 2px solid #e9ecef; padding-bottom: 10px;">{}Datagram Delivery</h3>
+                    <table style="width: 100%; border-collapse: collapse;">
+                        <tr><th style="text-align: left;">Direction</th><th style="text-align: left;">Payload Size</th><th style="text-align: left;">Lost</th><th style="text-align: left;">Jitter</th><th style="text-align: left;">Out of Order</th><th style="text-align: left;">Duplicates</th></tr>"#,
+                protocol_prefix
+            )?;
+            for (direction, size, stats) in self.datagrams.directions() {
+                write!(
+                    writer,
+                    r#"<tr><td>{direction}</td><td>{}</td><td>{}/{} ({:.2}%)</td><td>{:.3} ms</td><td>{}</td><td>{}</td></tr>"#,
+                    format_bytes_usize(size),
+                    stats.lost,
+                    stats.received + stats.lost,
+                    stats.loss_percent(),
+                    stats.jitter_ms(),
+                    stats.out_of_order,
+                    stats.duplicates
+                )?;
+            }
+            write!(writer, r#"</table></div>"#)?;
+        }
+
         // Download results
         if !self.download.is_empty() {
             write!(
@@ -683,6 +856,49 @@ impl ToHtml for NetworkTestResult {
             write!(writer, r#"</div></div>"#)?;
         }
 
+        // Server-side results
+        for (direction, results, note) in [
+            (
+                "Download (sent)",
+                &self.server.download,
+                self.server_download_note(),
+            ),
+            ("Upload (received)", &self.server.upload, None),
+        ] {
+            if results.is_empty() {
+                if let Some(note) = note {
+                    write!(
+                        writer,
+                        r#"<div class="result-section" style="margin-bottom: 30px;">
+                            <h3 style="color: #28a745; border-bottom: 2px solid #e9ecef; padding-bottom: 10px;">{}Server-side {} Results</h3>
+                            <p style="color: #6c757d;">{}</p>
+                        </div>"#,
+                        protocol_prefix, direction, note
+                    )?;
+                }
+                continue;
+            }
+            write!(
+                writer,
+                r#"<div class="result-section" style="margin-bottom: 30px;">
+                    <h3 style="color: #28a745; border-bottom: 2px solid #e9ecef; padding-bottom: 10px;">{}Server-side {} Results</h3>
+                    <div style="display: grid; gap: 20px;">"#,
+                protocol_prefix, direction
+            )?;
+            for (size, result) in results {
+                write!(
+                    writer,
+                    r#"<div>
+                        <h4 style="color: #007acc; margin-bottom: 10px;">Payload Size: {}</h4>
+                        <div style="margin-left: 20px;">"#,
+                    format_bytes_usize(*size)
+                )?;
+                result.write_html(writer)?;
+                write!(writer, r#"</div></div>"#)?;
+            }
+            write!(writer, r#"</div></div>"#)?;
+        }
+
         Ok(())
     }
 
@@ -752,10 +968,160 @@ impl ToHtml for NetworkTestResult {
             ));
         }
 
+        // Server-side results
+        for (direction, results, note) in [
+            (
+                "Download (sent)",
+                &self.server.download,
+                self.server_download_note(),
+            ),
+            ("Upload (received)", &self.server.upload, None),
+        ] {
+            if results.is_empty() {
+                if let Some(note) = note {
+                    html.push_str(&format!(
+                        r#"<div class="result-section" style="margin-bottom: 30px;">
+                            <h3 style="color: #28a745; border-bottom: 2px solid #e9ecef; padding-bottom: 10px;">{}Server-side {} Results</h3>
+                            <p style="color: #6c757d;">{}</p>
+                        </div>"#,
+                        protocol_prefix,
+                        direction,
+                        note
+                    ));
+                }
+                continue;
+            }
+            html.push_str(&format!(
+                r#"<div class="result-section" style="margin-bottom: 30px;">
+                    <h3 style="color: #28a745; border-bottom: 2px solid #e9ecef; padding-bottom: 10px;">{}Server-side {} Results</h3>
+                    <div style="display: grid; gap: 20px;">{}</div>
+                </div>"#,
+                protocol_prefix,
+                direction,
+                results
+                    .iter()
+                    .map(|(size, result)| format!(
+                        r#"<div>
+                            <h4 style="color: #007acc; margin-bottom: 10px;">Payload Size: {}</h4>
+                            <div style="margin-left: 20px;">{}</div>
+                        </div>"#,
+                        format_bytes_usize(*size),
+                        result.to_html()
+                    ))
+                    .collect::<Vec<_>>()
+                    .join("")
+            ));
+        }
+
         html
     }
 }
 
+// Implementation for ServerMeasurement
+impl ToHtml for ServerMeasurement {
+    fn write_html<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let intervals = match self.interval_throughput_range() {
+            Some((min, max)) => format!(
+                "{} x {:.2}s (min {}, max {})",
+                self.intervals.len(),
+                self.interval.as_secs_f64(),
+                format_throughput(min * 8.0),
+                format_throughput(max * 8.0)
+            ),
+            None => "-".to_string(),
+        };
+
+        write!(
+            writer,
+            r#"<div class="result-card" style="background-color: #f8f9fa; padding: 20px; border-radius: 6px; border-left: 4px solid #17a2b8;">
+                <h3 style="color: #17a2b8; margin-top: 0;">Server-side Measurement</h3>
+                <div style="display: grid; gap: 15px;">
+                    <div style="display: flex; justify-content: space-between;">
+                        <strong>Data Transferred:</strong> 
+                        <span style="color: #007acc;">{}</span>
+                    </div>
+                    <div style="display: flex; justify-content: space-between;">
+                        <strong>Duration:</strong> 
+                        <span style="color: #fd7e14;">{:.2}s</span>
+                    </div>
+                    <div style="display: flex; justify-content: space-between;">
+                        <strong>Average Throughput:</strong> 
+                        <span style="color: #6f42c1;">{}</span>
+                    </div>
+                    <div style="display: flex; justify-content: space-between;">
+                        <strong>Connections:</strong> 
+                        <span style="color: #6c757d;">{}</span>
+                    </div>
+                    <div style="display: flex; justify-content: space-between;">
+                        <strong>Intervals:</strong> 
+                        <span style="color: #6c757d;">{}</span>
+                    </div>
+                </div>
+            </div>"#,
+            format_bytes_u64(self.bytes),
+            self.duration.as_secs_f64(),
+            format_throughput(self.avg_throughput() * 8.0),
+            self.connections,
+            intervals
+        )
+    }
+}
+
+// Implementation for ThresholdEvaluation
+impl ToHtml for ThresholdEvaluation {
+    fn write_html<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let (color, summary) = if self.passed() {
+            (
+                "#28a745",
+                format!("All {} thresholds passed", self.checks.len()),
+            )
+        } else {
+            (
+                "#dc3545",
+                format!(
+                    "{} of {} thresholds violated",
+                    self.violations().count(),
+                    self.checks.len()
+                ),
+            )
+        };
+
+        write!(
+            writer,
+            r#"<div class="result-card" style="background-color: #f8f9fa; padding: 20px; border-radius: 6px; border-left: 4px solid {color};">
+                <h3 style="color: {color}; margin-top: 0;">{summary}</h3>
+                <div style="display: grid; gap: 10px;">"#
+        )?;
+
+        for check in &self.checks {
+            let (color, mark) = if check.passed {
+                ("#28a745", "✓")
+            } else {
+                ("#dc3545", "✗")
+            };
+            write!(
+                writer,
+                r#"
+                    <div style="display: flex; justify-content: space-between;">
+                        <strong>{mark} {} {}:</strong>
+                        <span><span style="color: {color};">{}</span> <span style="color: #6c757d;">(limit {})</span></span>
+                    </div>"#,
+                check.phase,
+                check.metric,
+                check.describe_actual(),
+                check.describe_limit()
+            )?;
+        }
+
+        write!(
+            writer,
+            r#"
+                </div>
+            </div>"#
+        )
+    }
+}
+
 // Implementation for LatencyResult
 impl ToHtml for LatencyResult {
     fn write_html<W: Write>(&self, writer: &mut W) -> io::Result<()> {
@@ -1039,6 +1405,411 @@ impl ToHtml for ThroughputMeasurement {
     }
 }
 
+// Implementation for ReportComparison
+impl ToHtml for ReportComparison {
+    fn write_html<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(
+            writer,
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Speed CLI Report Comparison</title>
+    <style>
+        body {{
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            line-height: 1.6;
+            margin: 0;
+            padding: 20px;
+            background-color: #f5f5f5;
+        }}
+        .container {{
+            max-width: 1200px;
+            margin: 0 auto;
+            background-color: white;
+            border-radius: 8px;
+            box-shadow: 0 2px 10px rgba(0,0,0,0.1);
+            padding: 30px;
+        }}
+        .header {{
+            text-align: center;
+            border-bottom: 3px solid #007acc;
+            padding-bottom: 20px;
+            margin-bottom: 30px;
+        }}
+        .header h1 {{
+            color: #007acc;
+            margin: 0;
+            font-size: 2.5em;
+        }}
+        .section-title {{
+            color: #495057;
+            border-bottom: 2px solid #e9ecef;
+            padding-bottom: 10px;
+            margin: 30px 0 20px 0;
+            font-size: 1.5em;
+            font-weight: 600;
+        }}
+        table {{
+            width: 100%;
+            border-collapse: collapse;
+        }}
+        th, td {{
+            padding: 8px 12px;
+            border-bottom: 1px solid #e9ecef;
+            text-align: right;
+        }}
+        th:first-child, td:first-child {{
+            text-align: left;
+            font-weight: 600;
+            color: #495057;
+        }}
+        th {{
+            background-color: #f8f9fa;
+        }}
+        .delta {{
+            font-size: 0.85em;
+            margin-left: 6px;
+        }}
+        .improvement {{ color: #28a745; }}
+        .regression {{ color: #dc3545; }}
+        .unchanged {{ color: #6c757d; }}
+    </style>
+</head>
+<body>
+    <div class="container">
+        <div class="header">
+            <h1>═══ Speed CLI Report Comparison ═══</h1>
+        </div>
+"#
+        )?;
+
+        let mut header = String::from("<tr><th>Metric</th>");
+        for (i, report) in self.reports.iter().enumerate() {
+            header.push_str(&format!(
+                "<th>{}{}<br><small>{}</small></th>",
+                report.label,
+                if i == 0 { " (baseline)" } else { "" },
+                report.start_time.format("%Y-%m-%d %H:%M:%S UTC")
+            ));
+        }
+        header.push_str("</tr>");
+
+        for section in &self.sections {
+            write!(
+                writer,
+                r#"
+        <h2 class="section-title">{}</h2>
+        <table>
+            {header}"#,
+                section.name
+            )?;
+
+            for metric in &section.metrics {
+                write!(writer, "\n            <tr><td>{}</td>", metric.name)?;
+                for (value, delta) in metric.values.iter().zip(&metric.deltas) {
+                    let value = value.map_or("-".to_string(), |v| metric.unit.format(v));
+                    match delta {
+                        Some(delta) => {
+                            let class = match delta.verdict {
+                                Verdict::Improvement => "improvement",
+                                Verdict::Regression => "regression",
+                                Verdict::Unchanged => "unchanged",
+                            };
+                            let text = match delta.percent {
+                                Some(percent) => format!("{percent:+.1}%"),
+                                None => format!("{:+.2}", delta.absolute),
+                            };
+                            write!(
+                                writer,
+                                r#"<td>{value}<span class="delta {class}">{text}</span></td>"#
+                            )?;
+                        }
+                        None => write!(writer, "<td>{value}</td>")?,
+                    }
+                }
+                write!(writer, "</tr>")?;
+            }
+
+            write!(writer, "\n        </table>")?;
+        }
+
+        write!(
+            writer,
+            r#"
+    </div>
+</body>
+</html>"#
+        )
+    }
+}
+
+// Implementation for SuiteReport
+impl ToHtml for SuiteReport {
+    fn write_html<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(
+            writer,
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Speed CLI Suite Report</title>
+    <style>
+        body {{
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            line-height: 1.6;
+            margin: 0;
+            padding: 20px;
+            background-color: #f5f5f5;
+        }}
+        .container {{
+            max-width: 1200px;
+            margin: 0 auto;
+            background-color: white;
+            border-radius: 8px;
+            box-shadow: 0 2px 10px rgba(0,0,0,0.1);
+            padding: 30px;
+        }}
+        .header {{
+            text-align: center;
+            border-bottom: 3px solid #007acc;
+            padding-bottom: 20px;
+            margin-bottom: 30px;
+        }}
+        .header h1 {{
+            color: #007acc;
+            margin: 0;
+            font-size: 2.5em;
+        }}
+        .meta-info {{
+            display: grid;
+            grid-template-columns: repeat(auto-fit, minmax(250px, 1fr));
+            gap: 15px;
+            margin-bottom: 30px;
+            padding: 20px;
+            background-color: #f8f9fa;
+            border-radius: 6px;
+        }}
+        .meta-item {{
+            display: flex;
+            justify-content: space-between;
+        }}
+        .meta-label {{
+            font-weight: 600;
+            color: #495057;
+        }}
+        .meta-value {{
+            color: #007acc;
+            font-weight: 500;
+        }}
+        .section {{
+            margin-bottom: 30px;
+        }}
+        .section-title {{
+            color: #495057;
+            border-bottom: 2px solid #e9ecef;
+            padding-bottom: 10px;
+            margin-bottom: 20px;
+            font-size: 1.5em;
+            font-weight: 600;
+        }}
+        .config-card {{
+            background-color: #f8f9fa;
+            padding: 20px;
+            border-radius: 6px;
+            border-left: 4px solid #007acc;
+            margin-bottom: 20px;
+        }}
+        table {{
+            width: 100%;
+            border-collapse: collapse;
+        }}
+        th, td {{
+            padding: 8px 12px;
+            border-bottom: 1px solid #e9ecef;
+            text-align: left;
+        }}
+        th {{
+            background-color: #f8f9fa;
+        }}
+        .passed {{ color: #28a745; font-weight: 600; }}
+        .violated {{ color: #fd7e14; font-weight: 600; }}
+        .failed {{ color: #dc3545; font-weight: 600; }}
+    </style>
+</head>
+<body>
+    <div class="container">
+        <div class="header">
+            <h1>═══ Speed CLI Suite Report ═══</h1>
+        </div>
+
+        <div class="meta-info">"#
+        )?;
+
+        let labels = self
+            .labels
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let meta = [
+            ("Suite", self.name.clone()),
+            ("Run ID", Some(self.run_id.clone())),
+            ("Host", Some(self.host.to_string())),
+            ("Version", Some(self.version.clone())),
+            (
+                "Start Time",
+                Some(self.start_time.format("%Y-%m-%d %H:%M:%S UTC").to_string()),
+            ),
+            (
+                "Report Time",
+                Some(self.timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string()),
+            ),
+            ("Labels", (!labels.is_empty()).then_some(labels)),
+            (
+                "Tags",
+                (!self.tags.is_empty()).then(|| self.tags.join(", ")),
+            ),
+        ];
+        for (label, value) in meta {
+            if let Some(value) = value {
+                write!(
+                    writer,
+                    r#"
+            <div class="meta-item">
+                <span class="meta-label">{label}:</span>
+                <span class="meta-value">{value}</span>
+            </div>"#
+                )?;
+            }
+        }
+
+        write!(
+            writer,
+            r#"
+        </div>
+
+        <div class="section">
+            <h2 class="section-title">Summary</h2>
+            <table>
+                <tr><th>#</th><th>Step</th><th>Outcome</th></tr>"#
+        )?;
+        for (index, step) in self.steps.iter().enumerate() {
+            let (class, outcome) = if step.error.is_some() {
+                ("failed", "Failed")
+            } else if !step.thresholds_passed() {
+                ("violated", "Thresholds violated")
+            } else {
+                ("passed", "Passed")
+            };
+            write!(
+                writer,
+                r#"
+                <tr><td>{}</td><td>{}</td><td class="{class}">{outcome}</td></tr>"#,
+                index + 1,
+                step.name
+            )?;
+        }
+        write!(
+            writer,
+            r#"
+            </table>
+        </div>
+"#
+        )?;
+
+        if !self.statistics.is_empty() {
+            write!(
+                writer,
+                r#"
+        <div class="section">
+            <h2 class="section-title">Statistics</h2>
+            <table>
+                <tr><th>Metric</th><th>Mean</th><th>95% CI</th><th>Std Dev</th><th>Min</th><th>Max</th><th>Runs</th></tr>"#
+            )?;
+            for statistic in &self.statistics {
+                let unit = statistic.unit;
+                let summary = &statistic.summary;
+                let ci95 = summary.ci95.map_or("-".to_string(), |ci95| {
+                    format!(
+                        "{} – {}",
+                        unit.format(summary.mean - ci95),
+                        unit.format(summary.mean + ci95)
+                    )
+                });
+                write!(
+                    writer,
+                    r#"
+                <tr><td>{}</td><td>{}</td><td>{ci95}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+                    statistic.name(),
+                    unit.format(summary.mean),
+                    unit.format(summary.stddev),
+                    unit.format(summary.min),
+                    unit.format(summary.max),
+                    summary.samples
+                )?;
+            }
+            write!(
+                writer,
+                r#"
+            </table>
+        </div>
+"#
+            )?;
+        }
+
+        for (index, step) in self.steps.iter().enumerate() {
+            write!(
+                writer,
+                r#"
+        <div class="section">
+            <h2 class="section-title">Step {}: {}</h2>
+            <div class="config-card">
+                "#,
+                index + 1,
+                step.name
+            )?;
+            step.config.write_html(writer)?;
+            write!(
+                writer,
+                r#"
+            </div>
+            "#
+            )?;
+
+            match (&step.report, &step.error) {
+                (Some(report), _) => {
+                    report.result.write_html(writer)?;
+                    if let Some(thresholds) = &report.thresholds {
+                        thresholds.write_html(writer)?;
+                    }
+                }
+                (None, error) => write!(
+                    writer,
+                    r#"<p class="failed">Error: {}</p>"#,
+                    error.as_deref().unwrap_or("unknown")
+                )?,
+            }
+
+            write!(
+                writer,
+                r#"
+        </div>
+"#
+            )?;
+        }
+
+        write!(
+            writer,
+            r#"    </div>
+</body>
+</html>"#
+        )
+    }
+}
+
 // Implementation for TestType
 impl ToHtml for TestType {
     fn write_html<W: Write>(&self, writer: &mut W) -> io::Result<()> {
@@ -1052,6 +1823,8 @@ impl ToHtml for TestType {
             TestType::Bidirectional => "bidirectional".to_string(),
             TestType::Simultaneous => "simultaneous".to_string(),
             TestType::LatencyOnly => "latency-only".to_string(),
+            TestType::LatencyUnderLoad => "latency-under-load".to_string(),
+            TestType::Responsiveness => "responsiveness".to_string(),
         }
     }
 }
@@ -0,0 +1,408 @@
+use std::io::{self, Write};
+
+use indexmap::IndexMap;
+
+use crate::report::*;
+use crate::utils::prometheus::{MetricType, write_header, write_sample};
+
+/// RTT quantiles exported for the latency phase
+const QUANTILES: [f64; 6] = [0.0, 0.5, 0.9, 0.95, 0.99, 1.0];
+
+/// Name, help and value of a per-phase throughput metric family
+type ThroughputFamily = (&'static str, &'static str, fn(&ThroughputResult) -> f64);
+
+const THROUGHPUT_FAMILIES: [ThroughputFamily; 5] = [
+    (
+        "speed_cli_throughput_bytes_per_second",
+        "Average throughput of the phase",
+        |result| result.avg_throughput(),
+    ),
+    (
+        "speed_cli_transferred_bytes",
+        "Bytes transferred during the phase",
+        |result| result.bytes_transferred() as f64,
+    ),
+    (
+        "speed_cli_duration_seconds",
+        "Duration of the phase",
+        |result| result.total_duration.as_secs_f64(),
+    ),
+    (
+        "speed_cli_errors",
+        "Failed measurements during the phase",
+        |result| result.total_errors() as f64,
+    ),
+    (
+        "speed_cli_retransmits",
+        "TCP retransmits during the phase",
+        |result| result.total_retransmits() as f64,
+    ),
+];
+
+/// Name, help and value of a per-phase datagram delivery metric family
+type DatagramFamily = (&'static str, &'static str, fn(&DatagramStats) -> f64);
+
+const DATAGRAM_FAMILIES: [DatagramFamily; 2] = [
+    (
+        "speed_cli_datagram_loss_ratio",
+        "Ratio of datagrams lost in UDP tests",
+        |stats| stats.loss_percent() / 100.0,
+    ),
+    (
+        "speed_cli_datagram_jitter_seconds",
+        "Interarrival jitter (RFC 3550) of UDP tests",
+        |stats| stats.jitter.as_secs_f64(),
+    ),
+];
+
+/// Labels of a sample
+type Labels = Vec<(&'static str, String)>;
+
+/// A metric family and the samples collected for it so far
+struct Family {
+    help: &'static str,
+    samples: Vec<(Labels, f64)>,
+}
+
+/// Samples of one or more reports grouped by metric family, since the text exposition
+/// format only allows each family to appear once
+#[derive(Default)]
+struct Families(IndexMap<&'static str, Family>);
+
+impl Families {
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for (name, family) in &self.0 {
+            write_header(writer, name, family.help, MetricType::Gauge)?;
+            for (labels, value) in &family.samples {
+                let labels: Vec<_> = labels.iter().map(|(k, v)| (*k, v.as_str())).collect();
+                write_sample(writer, name, &labels, *value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Collects the samples of a single report, labeled with its step if it belongs to a suite
+struct Samples<'a> {
+    families: &'a mut Families,
+    step: Option<&'a str>,
+}
+
+impl Samples<'_> {
+    fn add(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+        value: f64,
+    ) {
+        let labels = self
+            .step
+            .map(|step| ("step", step))
+            .into_iter()
+            .chain(labels.iter().copied())
+            .map(|(key, value)| (key, value.to_string()))
+            .collect();
+        self.families
+            .0
+            .entry(name)
+            .or_insert_with(|| Family {
+                help,
+                samples: Vec::new(),
+            })
+            .samples
+            .push((labels, value));
+    }
+}
+
+/// Writes `report` in the Prometheus text exposition format, e.g. for the textfile
+/// collector of node_exporter. Every value is a gauge labeled with the protocol, and
+/// throughput values additionally with the direction and payload size of their phase.
+pub fn write_prometheus<W: Write>(report: &TestReport, writer: &mut W) -> io::Result<()> {
+    let mut families = Families::default();
+    collect_samples(
+        report,
+        &mut Samples {
+            families: &mut families,
+            step: None,
+        },
+    );
+    families.write(writer)
+}
+
+/// Writes the samples of every step of `report` like [`write_prometheus`], additionally
+/// labeled with the name of the step. Whether each step ran at all is reported as
+/// `speed_cli_step_completed`.
+pub fn write_suite_prometheus<W: Write>(report: &SuiteReport, writer: &mut W) -> io::Result<()> {
+    let mut families = Families::default();
+    for step in &report.steps {
+        let mut samples = Samples {
+            families: &mut families,
+            step: Some(&step.name),
+        };
+        samples.add(
+            "speed_cli_step_completed",
+            "Whether the step ran (1) or failed to run (0)",
+            &[("protocol", step.config.protocol())],
+            if step.error.is_none() { 1.0 } else { 0.0 },
+        );
+        if let Some(report) = &step.report {
+            collect_samples(report, &mut samples);
+        }
+    }
+    families.write(writer)
+}
+
+fn collect_samples(report: &TestReport, samples: &mut Samples) {
+    let protocol = report.config.protocol();
+
+    let mut phases = Vec::new();
+    let mut latency = None;
+    match &report.result {
+        TestResult::Simple(result) => phases.push(("throughput", String::new(), result)),
+        TestResult::Network(result) => {
+            latency = result.latency.as_ref();
+            for (size, result) in &result.download {
+                phases.push(("download", size.to_string(), result));
+            }
+            for (size, result) in &result.upload {
+                phases.push(("upload", size.to_string(), result));
+            }
+        }
+    }
+
+    for (name, help, value) in THROUGHPUT_FAMILIES {
+        for (direction, payload_size, result) in &phases {
+            let labels = [
+                ("protocol", protocol),
+                ("direction", direction),
+                ("payload_size", payload_size.as_str()),
+            ];
+            samples.add(name, help, &labels, value(result));
+        }
+    }
+
+    if let Some(latency) = latency {
+        let labels = [("protocol", protocol)];
+
+        // RTTs are only available if at least one probe came back
+        if latency.successful_count() > 0 {
+            for quantile in QUANTILES {
+                if let Some(rtt) = latency.percentile_rtt(quantile * 100.0) {
+                    let quantile = quantile.to_string();
+                    let labels = [("protocol", protocol), ("quantile", quantile.as_str())];
+                    samples.add(
+                        "speed_cli_latency_rtt_seconds",
+                        "Quantiles of the RTT",
+                        &labels,
+                        rtt / 1000.0,
+                    );
+                }
+            }
+
+            let rtt_families = [
+                (
+                    "speed_cli_latency_rtt_average_seconds",
+                    "Average RTT",
+                    latency.avg_rtt(),
+                ),
+                (
+                    "speed_cli_latency_jitter_seconds",
+                    "Jitter (standard deviation of the RTT)",
+                    latency.jitter(),
+                ),
+            ];
+            for (name, help, rtt) in rtt_families {
+                if let Some(rtt) = rtt {
+                    samples.add(name, help, &labels, rtt / 1000.0);
+                }
+            }
+        }
+
+        samples.add(
+            "speed_cli_latency_loss_ratio",
+            "Ratio of dropped probes",
+            &labels,
+            latency.loss_percent() / 100.0,
+        );
+    }
+
+    if let TestResult::Network(result) = &report.result
+        && let Some(loaded) = &result.loaded_latency
+    {
+        let loaded_families = [
+            (
+                "speed_cli_loaded_latency_rtt_average_seconds",
+                "Average RTT while the link was saturated",
+                false,
+            ),
+            (
+                "speed_cli_latency_increase_seconds",
+                "Increase of the average RTT under load over the idle RTT",
+                true,
+            ),
+        ];
+        for (name, help, increase) in loaded_families {
+            for (direction, latency) in loaded.directions() {
+                let rtt = if increase {
+                    result.latency_increase(latency)
+                } else {
+                    latency.avg_rtt()
+                };
+                if let Some(rtt) = rtt {
+                    let labels = [("protocol", protocol), ("direction", direction)];
+                    samples.add(name, help, &labels, rtt / 1000.0);
+                }
+            }
+        }
+    }
+
+    if let TestResult::Network(result) = &report.result
+        && let Some(responsiveness) = &result.responsiveness
+    {
+        for (direction, phase) in responsiveness.directions() {
+            let labels = [("protocol", protocol), ("direction", direction)];
+            samples.add(
+                "speed_cli_responsiveness_rpm",
+                "Round-trips per minute while the link was saturated",
+                &labels,
+                phase.rpm,
+            );
+        }
+    }
+
+    if let TestResult::Network(result) = &report.result {
+        for (name, help, value) in DATAGRAM_FAMILIES {
+            for (direction, size, stats) in result.datagrams.directions() {
+                let size = size.to_string();
+                let labels = [
+                    ("protocol", protocol),
+                    ("direction", direction),
+                    ("payload_size", size.as_str()),
+                ];
+                samples.add(name, help, &labels, value(stats));
+            }
+        }
+    }
+
+    if let Some(thresholds) = &report.thresholds {
+        samples.add(
+            "speed_cli_thresholds_passed",
+            "Whether the result met all thresholds (1) or not (0)",
+            &[("protocol", protocol)],
+            if thresholds.passed() { 1.0 } else { 0.0 },
+        );
+    }
+
+    samples.add(
+        "speed_cli_report_timestamp_seconds",
+        "Time the report was created",
+        &[("protocol", protocol)],
+        report.timestamp.timestamp() as f64,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestType;
+    use chrono::Utc;
+    use std::time::Duration;
+
+    fn report() -> TestReport {
+        let mut result = NetworkTestResult::new_tcp();
+        result.latency = Some(LatencyResult {
+            measurements: vec![
+                LatencyMeasurement {
+                    rtt_ms: Some(10.0),
+                    elapsed_time: Duration::ZERO,
+                },
+                LatencyMeasurement {
+                    rtt_ms: None,
+                    elapsed_time: Duration::from_secs(1),
+                },
+            ],
+            timestamp: Utc::now(),
+        });
+        result.add_download(
+            1024,
+            ThroughputResult {
+                totals: [ThroughputMeasurement::new(1000, Duration::from_secs(1))]
+                    .iter()
+                    .collect(),
+                total_duration: Duration::from_secs(1),
+                intervals: Vec::new(),
+                connection_intervals: Default::default(),
+                timestamp: Utc::now(),
+            },
+            None,
+        );
+        let config = TcpTestConfig::new(
+            "127.0.0.1".to_string(),
+            None,
+            1,
+            1,
+            TestType::Bidirectional,
+            [1024],
+            Duration::from_secs(1),
+        );
+        TestReport::from((Utc::now(), config, result))
+    }
+
+    #[test]
+    fn test_prometheus_samples() {
+        let mut buffer = Vec::new();
+        write_prometheus(&report(), &mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+
+        assert!(text.contains("# TYPE speed_cli_throughput_bytes_per_second gauge\n"));
+        assert!(text.contains(
+            "speed_cli_throughput_bytes_per_second{protocol=\"tcp\",direction=\"download\",payload_size=\"1024\"} 1000\n"
+        ));
+        assert!(
+            text.contains(
+                "speed_cli_latency_rtt_seconds{protocol=\"tcp\",quantile=\"0.99\"} 0.01\n"
+            )
+        );
+        assert!(text.contains("speed_cli_latency_loss_ratio{protocol=\"tcp\"} 0.5\n"));
+        assert!(!text.contains("speed_cli_thresholds_passed"));
+    }
+
+    #[test]
+    fn test_suite_prometheus_families() {
+        let report = report();
+        let step = |name: &str, error: Option<&str>| StepReport {
+            name: name.to_string(),
+            config: report.config.clone(),
+            report: error.is_none().then(|| report.clone()),
+            error: error.map(str::to_string),
+        };
+        let suite = SuiteReport::new(
+            None,
+            Default::default(),
+            Vec::new(),
+            Utc::now(),
+            vec![
+                step("Run 1", None),
+                step("Run 2", None),
+                step("Run 3", Some("refused")),
+            ],
+        );
+
+        let mut buffer = Vec::new();
+        write_suite_prometheus(&suite, &mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+
+        assert_eq!(
+            text.matches("# TYPE speed_cli_throughput_bytes_per_second gauge\n")
+                .count(),
+            1
+        );
+        for step in ["Run 1", "Run 2"] {
+            assert!(text.contains(&format!(
+                "speed_cli_throughput_bytes_per_second{{step=\"{step}\",protocol=\"tcp\",direction=\"download\",payload_size=\"1024\"}} 1000\n"
+            )));
+        }
+        assert!(text.contains("speed_cli_step_completed{step=\"Run 3\",protocol=\"tcp\"} 0\n"));
+    }
+}
@@ -0,0 +1,83 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::report::{SuiteReport, TestReport};
+
+use super::test_cases::{TestCase, suite_name, test_cases};
+
+/// Writes `report` as a TAP version 13 stream with a test point per phase of the test.
+/// Failures and measurements are attached as YAML diagnostics.
+pub fn write_tap<W: Write>(report: &TestReport, writer: &mut W) -> io::Result<()> {
+    let suite = suite_name(report);
+    let points: Vec<_> = test_cases(report)
+        .into_iter()
+        .map(|case| (format!("{suite} {}", case.name), case))
+        .collect();
+    write_test_points(writer, &points)
+}
+
+/// Writes `report` as a single TAP version 13 stream with the test points of every step of
+/// the plan, prefixed by the name of the step. Steps that failed to run become a failed
+/// test point with the error as its failure.
+pub fn write_suite_tap<W: Write>(report: &SuiteReport, writer: &mut W) -> io::Result<()> {
+    let mut points = Vec::new();
+    for step in &report.steps {
+        if let Some(error) = &step.error {
+            let case = TestCase {
+                name: step.name.clone(),
+                time: Duration::ZERO,
+                failures: vec![error.clone()],
+                summary: Vec::new(),
+            };
+            points.push((step.name.clone(), case));
+        }
+        if let Some(report) = &step.report {
+            let suite = suite_name(report);
+            for case in test_cases(report) {
+                points.push((format!("{}: {suite} {}", step.name, case.name), case));
+            }
+        }
+    }
+    write_test_points(writer, &points)
+}
+
+/// Writes a test point for each test case, with its description
+fn write_test_points<W: Write>(writer: &mut W, points: &[(String, TestCase)]) -> io::Result<()> {
+    writeln!(writer, "TAP version 13")?;
+    writeln!(writer, "1..{}", points.len())?;
+
+    for (i, (description, case)) in points.iter().enumerate() {
+        let status = if case.failures.is_empty() {
+            "ok"
+        } else {
+            "not ok"
+        };
+        // `#` starts a directive in TAP, so it can't appear in descriptions
+        writeln!(
+            writer,
+            "{status} {} - {}",
+            i + 1,
+            description.replace('#', "")
+        )?;
+
+        writeln!(writer, "  ---")?;
+        writeln!(writer, "  duration_ms: {}", case.time.as_millis())?;
+        for (key, value) in &case.summary {
+            writeln!(writer, "  {key}: {}", yaml_string(value))?;
+        }
+        if !case.failures.is_empty() {
+            writeln!(writer, "  failures:")?;
+            for failure in &case.failures {
+                writeln!(writer, "    - {}", yaml_string(failure))?;
+            }
+        }
+        writeln!(writer, "  ...")?;
+    }
+
+    Ok(())
+}
+
+/// Quotes `value` as a YAML string
+fn yaml_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
@@ -0,0 +1,195 @@
+use std::time::Duration;
+
+use crate::report::*;
+use crate::utils::format::format_bytes;
+
+use super::format_throughput;
+
+/// A phase of a test report as a pass/fail test case, for CI-oriented formats (JUnit, TAP)
+#[derive(Debug, Clone)]
+pub(super) struct TestCase {
+    pub name: String,
+    pub time: Duration,
+    /// Reasons the test case failed. Empty if it passed.
+    pub failures: Vec<String>,
+    /// Human-readable summary of the measurements of the phase
+    pub summary: Vec<(&'static str, String)>,
+}
+
+/// Name of the suite the test cases of `report` belong to, e.g. "speed-cli.tcp"
+pub(super) fn suite_name(report: &TestReport) -> String {
+    let protocol = match &report.config {
+        TestConfig::Tcp(_) => "tcp",
+        TestConfig::Udp(_) => "udp",
+        TestConfig::Http(_) => "http",
+    };
+    format!("speed-cli.{protocol}")
+}
+
+/// Returns a test case for each phase of the report (latency, and download and upload of
+/// each payload size). Phases fail on connection failures and on threshold violations.
+pub(super) fn test_cases(report: &TestReport) -> Vec<TestCase> {
+    let mut cases = Vec::new();
+
+    match &report.result {
+        TestResult::Simple(result) => cases.push(throughput_case("Throughput", result)),
+        TestResult::Network(result) => {
+            if let Some(latency) = &result.latency {
+                cases.push(latency_case(latency));
+            }
+            for (size, result) in &result.download {
+                cases.push(throughput_case(
+                    &throughput_phase("Download", *size),
+                    result,
+                ));
+            }
+            for (size, result) in &result.upload {
+                cases.push(throughput_case(&throughput_phase("Upload", *size), result));
+            }
+        }
+    }
+
+    if let Some(thresholds) = &report.thresholds {
+        for check in thresholds.violations() {
+            let failure = format!(
+                "{} is {}, expected {}",
+                check.metric,
+                check.describe_actual(),
+                check.describe_limit()
+            );
+
+            // Thresholds of phases that did not run get a test case of their own
+            match cases.iter_mut().find(|case| case.name == check.phase) {
+                Some(case) => case.failures.push(failure),
+                None => cases.push(TestCase {
+                    name: check.phase.clone(),
+                    time: Duration::ZERO,
+                    failures: vec![failure],
+                    summary: Vec::new(),
+                }),
+            }
+        }
+    }
+
+    cases
+}
+
+fn latency_case(latency: &LatencyResult) -> TestCase {
+    let mut failures = Vec::new();
+    if latency.count() > 0 && latency.successful_count() == 0 {
+        failures.push(format!(
+            "All {} latency probes were dropped",
+            latency.count()
+        ));
+    }
+
+    let format_rtt = |rtt: Option<f64>| rtt.map_or("-".to_string(), |rtt| format!("{rtt:.2} ms"));
+    TestCase {
+        name: "Latency".to_string(),
+        time: latency
+            .measurements
+            .iter()
+            .map(|measurement| measurement.elapsed_time)
+            .max()
+            .unwrap_or_default(),
+        failures,
+        summary: vec![
+            ("probes", latency.count().to_string()),
+            ("average_rtt", format_rtt(latency.avg_rtt())),
+            ("p99_rtt", format_rtt(latency.percentile_rtt(99.0))),
+            ("packet_loss", format!("{:.2}%", latency.loss_percent())),
+        ],
+    }
+}
+
+fn throughput_case(name: &str, result: &ThroughputResult) -> TestCase {
+    let mut failures = Vec::new();
+    let errors = result.total_errors();
+    if errors > 0 {
+        let mut distribution: Vec<_> = result.error_distribution().into_iter().collect();
+        distribution.sort();
+        let distribution = distribution
+            .iter()
+            .map(|(error, count)| format!("{error}: {count}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        failures.push(format!(
+            "{errors} of {} measurements failed ({distribution})",
+            result.totals.count()
+        ));
+    }
+
+    TestCase {
+        name: name.to_string(),
+        time: result.total_duration,
+        failures,
+        summary: vec![
+            ("bytes", format_bytes(result.bytes_transferred())),
+            (
+                "average_throughput",
+                format_throughput(result.avg_throughput() * 8.0),
+            ),
+            ("measurements", result.totals.count().to_string()),
+            ("errors", errors.to_string()),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_failures_per_phase() {
+        let mut result = NetworkTestResult::new_tcp();
+        result.add_upload(
+            1024,
+            ThroughputResult {
+                totals: [
+                    ThroughputMeasurement::new(1000, Duration::from_secs(1)),
+                    ThroughputMeasurement::Failure {
+                        error: ConnectionError::Timeout("read".to_string()),
+                        duration: Duration::from_secs(1),
+                        retry_count: 0,
+                    },
+                ]
+                .iter()
+                .collect(),
+                total_duration: Duration::from_secs(1),
+                intervals: Vec::new(),
+                connection_intervals: Default::default(),
+                timestamp: Utc::now(),
+            },
+            None,
+        );
+        let config = TcpTestConfig::new(
+            "127.0.0.1".to_string(),
+            None,
+            1,
+            1,
+            Default::default(),
+            [1024],
+            Duration::from_secs(1),
+        );
+        let mut report = TestReport::from((Utc::now(), config, result));
+        report.thresholds = Some(
+            Thresholds {
+                min_download: Some(Bitrate(1e6)),
+                ..Default::default()
+            }
+            .evaluate(&report.result),
+        );
+
+        let cases = test_cases(&report);
+        assert_eq!(suite_name(&report), "speed-cli.tcp");
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[0].name, "Upload (1 KiB)");
+        assert_eq!(
+            cases[0].failures,
+            vec!["1 of 2 measurements failed (Timeout: 1)"]
+        );
+        assert_eq!(cases[1].name, "Download");
+        assert_eq!(cases[1].failures.len(), 1);
+    }
+}
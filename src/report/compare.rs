@@ -0,0 +1,617 @@
+use std::fmt::{self, Display, Formatter};
+
+use chrono::{DateTime, Utc};
+use colored::*;
+use humansize::{BaseUnit, DECIMAL, format_size};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::report::{LatencyResult, SuiteReport, TestReport, TestResult, ThroughputResult};
+use crate::utils::format::format_bytes;
+
+/// Relative changes smaller than this (in percent) are not considered significant
+const NOISE_THRESHOLD_PERCENT: f64 = 1.0;
+
+/// Unit of a compared metric, used for formatting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricUnit {
+    Milliseconds,
+    BitsPerSecond,
+    Percent,
+    Count,
+    RoundTripsPerMinute,
+}
+
+impl MetricUnit {
+    pub fn format(&self, value: f64) -> String {
+        match self {
+            MetricUnit::Milliseconds => format!("{value:.2} ms"),
+            MetricUnit::BitsPerSecond => format_size(
+                value.max(0.0) as u64,
+                DECIMAL.base_unit(BaseUnit::Bit).suffix("/s"),
+            ),
+            MetricUnit::Percent => format!("{value:.2}%"),
+            MetricUnit::Count => format!("{value:.0}"),
+            MetricUnit::RoundTripsPerMinute => format!("{value:.0} RPM"),
+        }
+    }
+}
+
+/// Whether a change of a metric is an improvement or a regression
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Improvement,
+    Regression,
+    Unchanged,
+}
+
+/// Change of a metric relative to the baseline report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricDelta {
+    pub absolute: f64,
+    /// Relative change in percent, if the baseline value is not zero
+    pub percent: Option<f64>,
+    pub verdict: Verdict,
+}
+
+impl MetricDelta {
+    fn new(baseline: f64, value: f64, higher_is_better: bool) -> Self {
+        let absolute = value - baseline;
+        let percent = (baseline != 0.0).then(|| absolute / baseline.abs() * 100.0);
+
+        let significant = match percent {
+            Some(percent) => percent.abs() >= NOISE_THRESHOLD_PERCENT,
+            None => absolute != 0.0,
+        };
+        let verdict = if !significant {
+            Verdict::Unchanged
+        } else if (absolute > 0.0) == higher_is_better {
+            Verdict::Improvement
+        } else {
+            Verdict::Regression
+        };
+
+        Self {
+            absolute,
+            percent,
+            verdict,
+        }
+    }
+}
+
+/// A single metric across all compared reports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricComparison {
+    pub name: String,
+    pub unit: MetricUnit,
+    pub higher_is_better: bool,
+    /// Value of the metric in each report, if the report has it
+    pub values: Vec<Option<f64>>,
+    /// Change of each value relative to the first (baseline) report
+    pub deltas: Vec<Option<MetricDelta>>,
+}
+
+impl MetricComparison {
+    fn new(name: &str, unit: MetricUnit, higher_is_better: bool, values: Vec<Option<f64>>) -> Self {
+        let deltas = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| match (values[0], value) {
+                (Some(baseline), Some(value)) if i > 0 => {
+                    Some(MetricDelta::new(baseline, *value, higher_is_better))
+                }
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            name: name.to_string(),
+            unit,
+            higher_is_better,
+            values,
+            deltas,
+        }
+    }
+}
+
+/// A group of related metrics, e.g. the download results of one payload size
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparisonSection {
+    pub name: String,
+    pub metrics: Vec<MetricComparison>,
+}
+
+/// Identifies one of the compared reports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparedReport {
+    /// Label of the report, usually its file name
+    pub label: String,
+    pub start_time: DateTime<Utc>,
+    pub version: String,
+}
+
+/// Side-by-side comparison of two or more reports against the first one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportComparison {
+    pub reports: Vec<ComparedReport>,
+    pub sections: Vec<ComparisonSection>,
+}
+
+impl ReportComparison {
+    /// Compares `reports` (labelled) against the first one, lining up matching sections
+    pub fn new(reports: &[(String, TestReport)]) -> Self {
+        Self::from_sections(
+            reports
+                .iter()
+                .map(|(label, report)| {
+                    (
+                        ComparedReport {
+                            label: label.clone(),
+                            start_time: report.start_time,
+                            version: report.version.clone(),
+                        },
+                        report_metrics(&report.result),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Compares suite reports (labelled) against the first one, lining up the sections of
+    /// steps with the same name. Steps that failed to run have no sections.
+    pub fn new_suites(reports: &[(String, SuiteReport)]) -> Self {
+        Self::from_sections(
+            reports
+                .iter()
+                .map(|(label, suite)| {
+                    let sections = suite
+                        .steps
+                        .iter()
+                        .filter_map(|step| Some((&step.name, step.report.as_ref()?)))
+                        .flat_map(|(name, report)| {
+                            report_metrics(&report.result).into_iter().map(
+                                move |(section, metrics)| (format!("{name}: {section}"), metrics),
+                            )
+                        })
+                        .collect();
+                    (
+                        ComparedReport {
+                            label: label.clone(),
+                            start_time: suite.start_time,
+                            version: suite.version.clone(),
+                        },
+                        sections,
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn from_sections(reports: Vec<(ComparedReport, Vec<Section>)>) -> Self {
+        // Sections keep the order in which they first appear across the reports
+        let mut sections: IndexMap<String, Vec<(&str, MetricUnit, bool)>> = IndexMap::new();
+        let mut values: IndexMap<(String, &str), Vec<Option<f64>>> = IndexMap::new();
+
+        let count = reports.len();
+        let mut compared = Vec::with_capacity(count);
+        for (index, (report, report_sections)) in reports.into_iter().enumerate() {
+            compared.push(report);
+            for (section, metrics) in report_sections {
+                let section_metrics = sections.entry(section.clone()).or_default();
+                for (name, unit, higher_is_better, value) in metrics {
+                    if !section_metrics.iter().any(|(n, _, _)| *n == name) {
+                        section_metrics.push((name, unit, higher_is_better));
+                    }
+                    values
+                        .entry((section.clone(), name))
+                        .or_insert_with(|| vec![None; count])[index] = value;
+                }
+            }
+        }
+
+        let sections = sections
+            .into_iter()
+            .map(|(section, metrics)| ComparisonSection {
+                metrics: metrics
+                    .into_iter()
+                    .map(|(name, unit, higher_is_better)| {
+                        let values = values
+                            .get(&(section.clone(), name))
+                            .cloned()
+                            .unwrap_or_default();
+                        MetricComparison::new(name, unit, higher_is_better, values)
+                    })
+                    .collect(),
+                name: section,
+            })
+            .collect();
+
+        Self {
+            reports: compared,
+            sections,
+        }
+    }
+
+    /// Returns true if any metric regressed compared to the baseline
+    pub fn has_regressions(&self) -> bool {
+        self.sections
+            .iter()
+            .flat_map(|section| &section.metrics)
+            .flat_map(|metric| metric.deltas.iter().flatten())
+            .any(|delta| delta.verdict == Verdict::Regression)
+    }
+}
+
+type Metric = (&'static str, MetricUnit, bool, Option<f64>);
+
+/// Name of a section and its metrics
+type Section = (String, Vec<Metric>);
+
+/// Extracts the comparable metrics of a result, grouped by section
+fn report_metrics(result: &TestResult) -> Vec<Section> {
+    match result {
+        TestResult::Simple(result) => vec![("Throughput".to_string(), throughput_metrics(result))],
+        TestResult::Network(result) => {
+            let mut sections = Vec::new();
+            if let Some(latency) = &result.latency {
+                sections.push(("Latency".to_string(), latency_metrics(latency)));
+            }
+            if let Some(loaded) = &result.loaded_latency {
+                for (direction, latency) in loaded.directions() {
+                    sections.push((
+                        format!("Loaded Latency ({direction})"),
+                        latency_metrics(latency),
+                    ));
+                }
+            }
+            if let Some(responsiveness) = &result.responsiveness {
+                for (direction, phase) in responsiveness.directions() {
+                    sections.push((
+                        format!("Responsiveness ({direction})"),
+                        vec![
+                            (
+                                "RPM",
+                                MetricUnit::RoundTripsPerMinute,
+                                true,
+                                Some(phase.rpm),
+                            ),
+                            (
+                                "Goodput",
+                                MetricUnit::BitsPerSecond,
+                                true,
+                                Some(phase.goodput_bps),
+                            ),
+                        ],
+                    ));
+                }
+            }
+            for (direction, size, stats) in result.datagrams.directions() {
+                sections.push((
+                    format!("Datagrams ({direction}, {})", format_bytes(size)),
+                    vec![
+                        (
+                            "Loss",
+                            MetricUnit::Percent,
+                            false,
+                            Some(stats.loss_percent()),
+                        ),
+                        (
+                            "Jitter",
+                            MetricUnit::Milliseconds,
+                            false,
+                            Some(stats.jitter_ms()),
+                        ),
+                    ],
+                ));
+            }
+            for (size, download) in &result.download {
+                sections.push((
+                    format!("Download ({})", format_bytes(*size)),
+                    throughput_metrics(download),
+                ));
+            }
+            for (size, upload) in &result.upload {
+                sections.push((
+                    format!("Upload ({})", format_bytes(*size)),
+                    throughput_metrics(upload),
+                ));
+            }
+            sections
+        }
+    }
+}
+
+fn latency_metrics(latency: &LatencyResult) -> Vec<Metric> {
+    vec![
+        (
+            "Average RTT",
+            MetricUnit::Milliseconds,
+            false,
+            latency.avg_rtt(),
+        ),
+        (
+            "Min RTT",
+            MetricUnit::Milliseconds,
+            false,
+            latency.min_rtt(),
+        ),
+        (
+            "P50 RTT",
+            MetricUnit::Milliseconds,
+            false,
+            latency.percentile_rtt(50.0),
+        ),
+        (
+            "P90 RTT",
+            MetricUnit::Milliseconds,
+            false,
+            latency.percentile_rtt(90.0),
+        ),
+        (
+            "P95 RTT",
+            MetricUnit::Milliseconds,
+            false,
+            latency.percentile_rtt(95.0),
+        ),
+        (
+            "P99 RTT",
+            MetricUnit::Milliseconds,
+            false,
+            latency.percentile_rtt(99.0),
+        ),
+        (
+            "Max RTT",
+            MetricUnit::Milliseconds,
+            false,
+            latency.max_rtt(),
+        ),
+        ("Jitter", MetricUnit::Milliseconds, false, latency.jitter()),
+        (
+            "Packet Loss",
+            MetricUnit::Percent,
+            false,
+            Some(latency.loss_percent()),
+        ),
+    ]
+}
+
+fn throughput_metrics(result: &ThroughputResult) -> Vec<Metric> {
+    vec![
+        (
+            "Average Throughput",
+            MetricUnit::BitsPerSecond,
+            true,
+            Some(result.avg_throughput() * 8.0),
+        ),
+        (
+            "Success Rate",
+            MetricUnit::Percent,
+            true,
+            Some(result.request_success_rate() * 100.0),
+        ),
+        (
+            "Errors",
+            MetricUnit::Count,
+            false,
+            Some(result.total_errors() as f64),
+        ),
+        (
+            "Retransmits",
+            MetricUnit::Count,
+            false,
+            Some(result.total_retransmits() as f64),
+        ),
+    ]
+}
+
+impl Display for MetricDelta {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let text = match self.percent {
+            Some(percent) => format!("{percent:+.1}%"),
+            None => format!("{:+.2}", self.absolute),
+        };
+        match self.verdict {
+            Verdict::Improvement => write!(f, "{}", text.green()),
+            Verdict::Regression => write!(f, "{}", text.red()),
+            Verdict::Unchanged => write!(f, "{}", text.dimmed()),
+        }
+    }
+}
+
+impl Display for ReportComparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}",
+            "═══ Speed CLI Report Comparison ═══".bright_cyan().bold()
+        )?;
+        for (i, report) in self.reports.iter().enumerate() {
+            writeln!(
+                f,
+                "  {} {} ({}){}",
+                format!("[{}]", i + 1).bright_white().bold(),
+                report.label.cyan(),
+                report
+                    .start_time
+                    .format("%Y-%m-%d %H:%M:%S UTC")
+                    .to_string()
+                    .yellow(),
+                if i == 0 { " - baseline" } else { "" }
+            )?;
+        }
+
+        for section in &self.sections {
+            writeln!(f)?;
+            writeln!(f, "{}", section.name.bright_white().bold().underline())?;
+            for metric in &section.metrics {
+                write!(f, "  {:<20}", metric.name.bright_blue().bold())?;
+                for (value, delta) in metric.values.iter().zip(&metric.deltas) {
+                    let value = value.map_or("-".to_string(), |v| metric.unit.format(v));
+                    match delta {
+                        Some(delta) => {
+                            // The delta is padded separately since color codes break alignment
+                            let text = delta.to_string();
+                            let width = 10 + text.len() - console_width(&text).min(text.len());
+                            write!(f, " {value:>14} {text:<width$}")?;
+                        }
+                        None => write!(f, " {value:>14} {:<10}", "")?,
+                    }
+                }
+                writeln!(f)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the number of visible characters in `text`, ignoring ANSI escape sequences
+fn console_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+    for c in text.chars() {
+        match (in_escape, c) {
+            (false, '\x1b') => in_escape = true,
+            (true, 'm') => in_escape = false,
+            (false, _) => width += 1,
+            _ => {}
+        }
+    }
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{
+        LatencyMeasurement, NetworkTestResult, StepReport, TcpTestConfig, ThroughputMeasurement,
+    };
+    use std::time::Duration;
+
+    fn report(rtt_ms: f64, bytes: u64) -> TestReport {
+        let mut result = NetworkTestResult::new_tcp();
+        result.latency = Some(LatencyResult {
+            measurements: vec![LatencyMeasurement {
+                rtt_ms: Some(rtt_ms),
+                elapsed_time: Duration::ZERO,
+            }],
+            timestamp: Utc::now(),
+        });
+        result.add_download(
+            1024,
+            ThroughputResult {
+                totals: [ThroughputMeasurement::new(bytes, Duration::from_secs(1))]
+                    .iter()
+                    .collect(),
+                total_duration: Duration::from_secs(1),
+                intervals: Vec::new(),
+                connection_intervals: Default::default(),
+                timestamp: Utc::now(),
+            },
+            None,
+        );
+        let config = TcpTestConfig::new(
+            "127.0.0.1".to_string(),
+            None,
+            1,
+            1,
+            Default::default(),
+            [1024],
+            Duration::from_secs(1),
+        );
+        TestReport::from((Utc::now(), config, result))
+    }
+
+    #[test]
+    fn test_delta_verdicts() {
+        let faster = MetricDelta::new(100.0, 150.0, true);
+        assert_eq!(faster.verdict, Verdict::Improvement);
+        assert_eq!(faster.percent, Some(50.0));
+
+        let slower = MetricDelta::new(10.0, 12.0, false);
+        assert_eq!(slower.verdict, Verdict::Regression);
+
+        let noise = MetricDelta::new(1000.0, 1005.0, true);
+        assert_eq!(noise.verdict, Verdict::Unchanged);
+
+        let from_zero = MetricDelta::new(0.0, 3.0, false);
+        assert_eq!(from_zero.percent, None);
+        assert_eq!(from_zero.verdict, Verdict::Regression);
+    }
+
+    #[test]
+    fn test_compare_lines_up_sections() {
+        let comparison = ReportComparison::new(&[
+            ("before.json".to_string(), report(10.0, 1000)),
+            ("after.json".to_string(), report(20.0, 2000)),
+        ]);
+
+        let names: Vec<_> = comparison
+            .sections
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Latency", "Download (1 KiB)"]);
+
+        let rtt = &comparison.sections[0].metrics[0];
+        assert_eq!(rtt.values, vec![Some(10.0), Some(20.0)]);
+        assert_eq!(rtt.deltas[1].as_ref().unwrap().verdict, Verdict::Regression);
+
+        let throughput = &comparison.sections[1].metrics[0];
+        assert_eq!(throughput.values, vec![Some(8000.0), Some(16000.0)]);
+        assert_eq!(
+            throughput.deltas[1].as_ref().unwrap().verdict,
+            Verdict::Improvement
+        );
+        assert!(comparison.has_regressions());
+    }
+
+    #[test]
+    fn test_compare_suites_by_step() {
+        let suite = |rtt_ms: f64, udp_failed: bool| {
+            let step = |name: &str, failed: bool| {
+                let report = report(rtt_ms, 1000);
+                StepReport {
+                    name: name.to_string(),
+                    config: report.config.clone(),
+                    error: failed.then(|| "Connection refused".to_string()),
+                    report: (!failed).then_some(report),
+                }
+            };
+            SuiteReport::new(
+                None,
+                Default::default(),
+                Vec::new(),
+                Utc::now(),
+                vec![step("tcp", false), step("udp", udp_failed)],
+            )
+        };
+
+        let comparison = ReportComparison::new_suites(&[
+            ("before.json".to_string(), suite(10.0, false)),
+            ("after.json".to_string(), suite(10.0, true)),
+        ]);
+
+        let names: Vec<_> = comparison
+            .sections
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "tcp: Latency",
+                "tcp: Download (1 KiB)",
+                "udp: Latency",
+                "udp: Download (1 KiB)"
+            ]
+        );
+        assert_eq!(
+            comparison.sections[2].metrics[0].values,
+            vec![Some(10.0), None]
+        );
+        assert!(!comparison.has_regressions());
+    }
+}
@@ -5,15 +5,16 @@ use colored::*;
 use indexmap::IndexSet;
 use serde::{Deserialize, Serialize};
 
-use crate::constants::DEFAULT_CHUNK_SIZE;
+use crate::constants::{DEFAULT_CHUNK_SIZE, DEFAULT_REPORT_INTERVAL};
 use crate::utils::format::format_bytes;
 use crate::{
-    TestType,
+    ClientMode, TestType,
     constants::{
         DEFAULT_HTTP_PAYLOAD_SIZES, DEFAULT_HTTP_PORT, DEFAULT_HTTPS_PORT,
         DEFAULT_TCP_PAYLOAD_SIZES, DEFAULT_TCP_PORT, DEFAULT_UDP_PAYLOAD_SIZES, DEFAULT_UDP_PORT,
     },
     performance::http::HttpVersion,
+    utils::types::{CongestionAlgorithm, TargetBitrate, UdpMode},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,6 +25,143 @@ pub enum TestConfig {
     Http(HttpTestConfig),
 }
 
+impl TestConfig {
+    /// Creates the configuration of a test using the protocol of `mode`
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<T>(
+        mode: ClientMode,
+        server: String,
+        port: Option<u16>,
+        duration: u64,
+        connections: usize,
+        test_type: TestType,
+        payload_sizes: T,
+        chunk_size: Option<usize>,
+        interval: Duration,
+    ) -> Self
+    where
+        T: IntoIterator<Item = usize>,
+    {
+        let http_version = match mode {
+            ClientMode::TCP => {
+                return TcpTestConfig::new(
+                    server,
+                    port,
+                    duration,
+                    connections,
+                    test_type,
+                    payload_sizes,
+                    interval,
+                )
+                .into();
+            }
+            ClientMode::UDP => {
+                return UdpTestConfig::new(
+                    server,
+                    port,
+                    duration,
+                    connections,
+                    test_type,
+                    payload_sizes,
+                    interval,
+                )
+                .into();
+            }
+            ClientMode::HTTP1 => HttpVersion::HTTP1,
+            ClientMode::H2C => HttpVersion::H2C,
+            ClientMode::HTTP2 => HttpVersion::HTTP2,
+            ClientMode::HTTP3 => HttpVersion::HTTP3,
+        };
+
+        HttpTestConfig::new(
+            server,
+            port,
+            duration,
+            connections,
+            test_type,
+            payload_sizes,
+            chunk_size,
+            http_version,
+            interval,
+        )
+        .into()
+    }
+
+    /// Short name of the protocol of the test, e.g. "tcp" or "h2c"
+    pub fn protocol(&self) -> &'static str {
+        match self {
+            TestConfig::Tcp(_) => "tcp",
+            TestConfig::Udp(_) => "udp",
+            TestConfig::Http(config) => match config.http_version {
+                HttpVersion::HTTP1 => "http1",
+                HttpVersion::H2C => "h2c",
+                HttpVersion::HTTP2 => "http2",
+                HttpVersion::HTTP3 => "http3",
+            },
+        }
+    }
+
+    /// Hostname or IP address of the server, without port or scheme
+    pub fn server(&self) -> String {
+        match self {
+            TestConfig::Tcp(config) => config.server.clone(),
+            TestConfig::Udp(config) => config.server.clone(),
+            TestConfig::Http(config) => url::Url::parse(&config.server_url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .unwrap_or_else(|| config.server_url.clone()),
+        }
+    }
+
+    /// Limits uploads to `bitrate`
+    pub fn with_bitrate(mut self, bitrate: Option<TargetBitrate>) -> Self {
+        match &mut self {
+            TestConfig::Tcp(config) => config.bitrate = bitrate,
+            TestConfig::Udp(config) => config.bitrate = bitrate,
+            TestConfig::Http(config) => config.bitrate = bitrate,
+        }
+        self
+    }
+
+    /// Sets how UDP tests send their datagrams. Other protocols are left unchanged.
+    pub fn with_udp_mode(mut self, mode: UdpMode) -> Self {
+        if let TestConfig::Udp(config) = &mut self {
+            config.mode = mode;
+        }
+        self
+    }
+
+    /// Sets the congestion control algorithm of UDP tests. Other protocols are left
+    /// unchanged.
+    pub fn with_congestion_control(mut self, algorithm: CongestionAlgorithm) -> Self {
+        if let TestConfig::Udp(config) = &mut self {
+            config.congestion_control = algorithm;
+        }
+        self
+    }
+
+    /// Makes UDP tests send and receive one datagram per syscall instead of in batches.
+    /// Other protocols are left unchanged.
+    pub fn with_udp_standard_io(mut self, standard_io: bool) -> Self {
+        if let TestConfig::Udp(config) = &mut self {
+            config.standard_io = standard_io;
+        }
+        self
+    }
+
+    pub fn test_type(&self) -> TestType {
+        match self {
+            TestConfig::Tcp(config) => config.test_type,
+            TestConfig::Udp(config) => config.test_type,
+            TestConfig::Http(config) => config.test_type,
+        }
+    }
+}
+
+fn default_report_interval() -> Duration {
+    DEFAULT_REPORT_INTERVAL
+}
+
 impl From<TcpTestConfig> for TestConfig {
     fn from(config: TcpTestConfig) -> Self {
         TestConfig::Tcp(config)
@@ -52,6 +190,12 @@ pub struct TcpTestConfig {
     pub test_type: TestType,
     /// Payload sizes to use for the test, in bytes. Note this doesn't make sense for TCP but included anyways.
     pub payload_sizes: IndexSet<usize>,
+    /// Width of the per-interval buckets in throughput results. Zero disables them.
+    #[serde(default = "default_report_interval")]
+    pub interval: Duration,
+    /// Rate uploads are limited to instead of sending as fast as possible
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<TargetBitrate>,
 }
 
 impl TcpTestConfig {
@@ -62,6 +206,7 @@ impl TcpTestConfig {
         parallel_connections: usize,
         test_type: TestType,
         payload_sizes: T,
+        interval: Duration,
     ) -> Self
     where
         T: IntoIterator<Item = usize>,
@@ -78,6 +223,8 @@ impl TcpTestConfig {
             } else {
                 payload_sizes
             },
+            interval,
+            bitrate: None,
         }
     }
 }
@@ -92,6 +239,21 @@ pub struct UdpTestConfig {
     pub test_type: TestType,
     /// Payload sizes to use for the test, in bytes.
     pub payload_sizes: IndexSet<usize>,
+    /// Width of the per-interval buckets in throughput results. Zero disables them.
+    #[serde(default = "default_report_interval")]
+    pub interval: Duration,
+    /// Rate uploads are limited to instead of sending as fast as possible
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<TargetBitrate>,
+    /// Whether datagrams are sent with congestion control or at a constant bitrate
+    #[serde(default)]
+    pub mode: UdpMode,
+    /// Congestion control algorithm of STP uploads
+    #[serde(default)]
+    pub congestion_control: CongestionAlgorithm,
+    /// Whether datagrams are sent and received one per syscall instead of in batches
+    #[serde(default)]
+    pub standard_io: bool,
 }
 
 impl UdpTestConfig {
@@ -102,6 +264,7 @@ impl UdpTestConfig {
         parallel_streams: usize,
         test_type: TestType,
         payload_sizes: T,
+        interval: Duration,
     ) -> Self
     where
         T: IntoIterator<Item = usize>,
@@ -118,6 +281,11 @@ impl UdpTestConfig {
             } else {
                 payload_sizes
             },
+            interval,
+            bitrate: None,
+            mode: UdpMode::default(),
+            congestion_control: CongestionAlgorithm::default(),
+            standard_io: false,
         }
     }
 }
@@ -136,9 +304,16 @@ pub struct HttpTestConfig {
     pub http_version: HttpVersion,
     /// Maximum chunk size for HTTP requests. This is effective only for HTTP/1.1 tests.
     pub chunk_size: usize,
+    /// Width of the per-interval buckets in throughput results. Zero disables them.
+    #[serde(default = "default_report_interval")]
+    pub interval: Duration,
+    /// Rate uploads are limited to instead of sending as fast as possible
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<TargetBitrate>,
 }
 
 impl HttpTestConfig {
+    #[allow(clippy::too_many_arguments)]
     pub fn new<T>(
         server: String,
         port: Option<u16>,
@@ -148,6 +323,7 @@ impl HttpTestConfig {
         payload_sizes: T,
         chunk_size: Option<usize>,
         http_version: HttpVersion,
+        interval: Duration,
     ) -> Self
     where
         T: IntoIterator<Item = usize>,
@@ -174,6 +350,8 @@ impl HttpTestConfig {
             payload_sizes,
             chunk_size: chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE),
             http_version,
+            interval,
+            bitrate: None,
         }
     }
 }
@@ -214,12 +392,26 @@ impl Display for TcpTestConfig {
             "Duration".bright_blue().bold(),
             format!("{}s", self.duration.as_secs()).magenta()
         )?;
+        writeln!(
+            f,
+            "  {}: {}",
+            "Report Interval".bright_blue().bold(),
+            format!("{:.2}s", self.interval.as_secs_f64()).magenta()
+        )?;
         writeln!(
             f,
             "  {}: {}",
             "Parallel Connections".bright_blue().bold(),
             self.parallel_connections.to_string().green()
         )?;
+        if let Some(bitrate) = &self.bitrate {
+            writeln!(
+                f,
+                "  {}: {}",
+                "Target Bitrate".bright_blue().bold(),
+                bitrate.to_string().green()
+            )?;
+        }
 
         let sizes: Vec<String> = self
             .payload_sizes
@@ -263,12 +455,40 @@ impl Display for UdpTestConfig {
             "Duration".bright_blue().bold(),
             format!("{}s", self.duration).magenta()
         )?;
+        writeln!(
+            f,
+            "  {}: {}",
+            "Report Interval".bright_blue().bold(),
+            format!("{:.2}s", self.interval.as_secs_f64()).magenta()
+        )?;
         writeln!(
             f,
             "  {}: {}",
             "Parallel Streams".bright_blue().bold(),
             self.parallel_streams.to_string().green()
         )?;
+        writeln!(
+            f,
+            "  {}: {}",
+            "UDP Mode".bright_blue().bold(),
+            self.mode.to_string().green()
+        )?;
+        if self.mode == UdpMode::Stp {
+            writeln!(
+                f,
+                "  {}: {}",
+                "Congestion Control".bright_blue().bold(),
+                self.congestion_control.to_string().green()
+            )?;
+        }
+        if let Some(bitrate) = &self.bitrate {
+            writeln!(
+                f,
+                "  {}: {}",
+                "Target Bitrate".bright_blue().bold(),
+                bitrate.to_string().green()
+            )?;
+        }
 
         let sizes: Vec<String> = self
             .payload_sizes
@@ -310,12 +530,26 @@ impl Display for HttpTestConfig {
             "Duration".bright_blue().bold(),
             format!("{}s", self.duration.as_secs()).magenta()
         )?;
+        writeln!(
+            f,
+            "  {}: {}",
+            "Report Interval".bright_blue().bold(),
+            format!("{:.2}s", self.interval.as_secs_f64()).magenta()
+        )?;
         writeln!(
             f,
             "  {}: {}",
             "Parallel Connections".bright_blue().bold(),
             self.parallel_connections.to_string().green()
         )?;
+        if let Some(bitrate) = &self.bitrate {
+            writeln!(
+                f,
+                "  {}: {}",
+                "Target Bitrate".bright_blue().bold(),
+                bitrate.to_string().green()
+            )?;
+        }
         writeln!(
             f,
             "  {}: {}",
@@ -3,15 +3,23 @@ use colored::*;
 use serde::{Deserialize, Serialize};
 use std::fmt::{self, Display, Formatter};
 
+mod compare;
 mod config;
 mod errors;
 mod measurement;
 mod result;
+mod statistics;
+mod suite;
+mod threshold;
 
+pub use compare::*;
 pub use config::*;
 pub use errors::*;
 pub use measurement::*;
 pub use result::*;
+pub use statistics::*;
+pub use suite::*;
+pub use threshold::*;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TestReport {
@@ -25,6 +33,9 @@ pub struct TestReport {
     pub timestamp: DateTime<Utc>,
     /// Version of speed-cli that generated this report
     pub version: String,
+    /// Pass/fail thresholds the result was checked against, if any were given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thresholds: Option<ThresholdEvaluation>,
 }
 
 impl TestReport {
@@ -40,6 +51,7 @@ impl TestReport {
             result,
             timestamp,
             version: env!("CARGO_PKG_VERSION").to_string(),
+            thresholds: None,
         }
     }
 }
@@ -57,6 +69,7 @@ where
             result: result.into(),
             timestamp: timestamp.into(),
             version: env!("CARGO_PKG_VERSION").to_string(),
+            thresholds: None,
         }
     }
 }
@@ -112,6 +125,12 @@ impl Display for TestReport {
         writeln!(f, "{}", "Results:".bright_white().bold().underline())?;
         write!(f, "{}", self.result)?;
 
+        if let Some(thresholds) = &self.thresholds {
+            writeln!(f)?;
+            writeln!(f, "{}", "Thresholds:".bright_white().bold().underline())?;
+            write!(f, "{thresholds}")?;
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,122 @@
+use std::fmt::{self, Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::report::{LatencyResult, NetworkTestResult};
+
+/// Latency sampled while a download or upload saturates the link
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoadedLatencyResult {
+    /// Latency during the download
+    pub download: Option<LatencyResult>,
+    /// Latency during the upload
+    pub upload: Option<LatencyResult>,
+}
+
+impl LoadedLatencyResult {
+    /// Loaded latency of each direction that was measured
+    pub fn directions(&self) -> impl Iterator<Item = (&'static str, &LatencyResult)> {
+        [("download", &self.download), ("upload", &self.upload)]
+            .into_iter()
+            .filter_map(|(direction, latency)| Some((direction, latency.as_ref()?)))
+    }
+}
+
+/// Grade of the latency increase under load, following the scale of common bufferbloat
+/// tests: A+ below 5 ms, A below 30 ms, B below 60 ms, C below 200 ms, D below 400 ms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum BufferbloatGrade {
+    #[serde(rename = "A+")]
+    APlus,
+    A,
+    B,
+    C,
+    D,
+    F,
+}
+
+impl BufferbloatGrade {
+    /// Grades an increase of the average RTT in milliseconds
+    pub fn from_increase(increase_ms: f64) -> Self {
+        match increase_ms {
+            x if x < 5.0 => BufferbloatGrade::APlus,
+            x if x < 30.0 => BufferbloatGrade::A,
+            x if x < 60.0 => BufferbloatGrade::B,
+            x if x < 200.0 => BufferbloatGrade::C,
+            x if x < 400.0 => BufferbloatGrade::D,
+            _ => BufferbloatGrade::F,
+        }
+    }
+}
+
+impl Display for BufferbloatGrade {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BufferbloatGrade::APlus => write!(f, "A+"),
+            BufferbloatGrade::A => write!(f, "A"),
+            BufferbloatGrade::B => write!(f, "B"),
+            BufferbloatGrade::C => write!(f, "C"),
+            BufferbloatGrade::D => write!(f, "D"),
+            BufferbloatGrade::F => write!(f, "F"),
+        }
+    }
+}
+
+impl NetworkTestResult {
+    /// Returns how much the average RTT of `loaded` exceeds the idle average RTT, in
+    /// milliseconds
+    pub fn latency_increase(&self, loaded: &LatencyResult) -> Option<f64> {
+        Some(loaded.avg_rtt()? - self.latency.as_ref()?.avg_rtt()?)
+    }
+
+    /// Returns the grade of the worst latency increase under load, if latency was
+    /// measured both idle and under load
+    pub fn bufferbloat_grade(&self) -> Option<BufferbloatGrade> {
+        self.loaded_latency
+            .as_ref()?
+            .directions()
+            .filter_map(|(_, loaded)| self.latency_increase(loaded))
+            .map(BufferbloatGrade::from_increase)
+            .max()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::LatencyMeasurement;
+    use chrono::Utc;
+    use std::time::Duration;
+
+    fn latency(rtts: &[f64]) -> LatencyResult {
+        LatencyResult {
+            measurements: rtts
+                .iter()
+                .map(|&rtt| LatencyMeasurement {
+                    rtt_ms: Some(rtt),
+                    elapsed_time: Duration::ZERO,
+                })
+                .collect(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_grade_uses_worst_direction() {
+        let mut result = NetworkTestResult::new_tcp();
+        result.latency = Some(latency(&[10.0, 12.0]));
+        result.loaded_latency = Some(LoadedLatencyResult {
+            download: Some(latency(&[20.0, 30.0])),
+            upload: Some(latency(&[80.0, 100.0])),
+        });
+
+        let download = result.loaded_latency.as_ref().unwrap().download.as_ref();
+        assert_eq!(result.latency_increase(download.unwrap()), Some(14.0));
+        assert_eq!(result.bufferbloat_grade(), Some(BufferbloatGrade::C));
+        assert_eq!(
+            BufferbloatGrade::from_increase(2.0),
+            BufferbloatGrade::APlus
+        );
+        assert_eq!(BufferbloatGrade::from_increase(500.0), BufferbloatGrade::F);
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use colored::Colorize as _;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+/// Delivery of a sequence-numbered datagram stream, as seen by its receiver
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct DatagramStats {
+    /// Datagrams received, not counting duplicates
+    pub received: u64,
+    /// Datagrams missing from the sequence up to the highest one received
+    pub lost: u64,
+    /// Datagrams received more than once
+    pub duplicates: u64,
+    /// Datagrams received after one with a higher sequence number
+    pub out_of_order: u64,
+    /// Interarrival jitter as defined by RFC 3550
+    pub jitter: Duration,
+}
+
+impl DatagramStats {
+    /// Returns the share of datagrams lost in percent
+    pub fn loss_percent(&self) -> f64 {
+        let expected = self.received + self.lost;
+        if expected == 0 {
+            return 0.0;
+        }
+
+        self.lost as f64 / expected as f64 * 100.0
+    }
+
+    /// Adds the datagrams of another stream of the same test. The jitter becomes the mean
+    /// of both, weighted by the datagrams received.
+    pub fn merge(&mut self, other: &DatagramStats) {
+        let received = self.received + other.received;
+        if received > 0 {
+            self.jitter = Duration::from_secs_f64(
+                (self.jitter.as_secs_f64() * self.received as f64
+                    + other.jitter.as_secs_f64() * other.received as f64)
+                    / received as f64,
+            );
+        }
+        self.received = received;
+        self.lost += other.lost;
+        self.duplicates += other.duplicates;
+        self.out_of_order += other.out_of_order;
+    }
+
+    /// Returns the jitter in milliseconds
+    pub fn jitter_ms(&self) -> f64 {
+        self.jitter.as_secs_f64() * 1000.0
+    }
+}
+
+impl Display for DatagramStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "  {}: {}/{} ({})",
+            "Lost".bright_green().bold(),
+            self.lost.to_string().red(),
+            (self.received + self.lost).to_string().white(),
+            format!("{:.2}%", self.loss_percent()).yellow()
+        )?;
+        writeln!(
+            f,
+            "  {}: {}",
+            "Jitter".bright_green().bold(),
+            format!("{:.3} ms", self.jitter_ms()).cyan()
+        )?;
+        writeln!(
+            f,
+            "  {}: {}",
+            "Out of Order".bright_green().bold(),
+            self.out_of_order.to_string().white()
+        )?;
+        writeln!(
+            f,
+            "  {}: {}",
+            "Duplicates".bright_green().bold(),
+            self.duplicates.to_string().white()
+        )
+    }
+}
+
+/// Datagram delivery of UDP tests, keyed by payload size like the
+/// throughput results
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DatagramResult {
+    /// Datagrams the client received during download tests
+    pub download: IndexMap<usize, DatagramStats>,
+    /// Datagrams the server received during upload tests
+    pub upload: IndexMap<usize, DatagramStats>,
+}
+
+impl DatagramResult {
+    pub fn is_empty(&self) -> bool {
+        self.download.is_empty() && self.upload.is_empty()
+    }
+
+    /// Statistics of each direction and payload size that was measured
+    pub fn directions(&self) -> impl Iterator<Item = (&'static str, usize, &DatagramStats)> {
+        [("download", &self.download), ("upload", &self.upload)]
+            .into_iter()
+            .flat_map(|(direction, results)| {
+                results
+                    .iter()
+                    .map(move |(size, stats)| (direction, *size, stats))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loss_percent() {
+        let stats = DatagramStats {
+            received: 990,
+            lost: 10,
+            ..Default::default()
+        };
+        assert!((stats.loss_percent() - 1.0).abs() < 1e-9);
+        assert_eq!(DatagramStats::default().loss_percent(), 0.0);
+    }
+
+    #[test]
+    fn test_merge_weights_jitter() {
+        let mut stats = DatagramStats {
+            received: 300,
+            lost: 3,
+            jitter: Duration::from_millis(1),
+            ..Default::default()
+        };
+        stats.merge(&DatagramStats {
+            received: 100,
+            lost: 1,
+            jitter: Duration::from_millis(5),
+            ..Default::default()
+        });
+
+        assert_eq!((stats.received, stats.lost), (400, 4));
+        assert_eq!(stats.jitter, Duration::from_millis(2));
+    }
+}
@@ -39,6 +39,16 @@ impl LatencyResult {
             .count()
     }
 
+    /// Returns the percentage (0.0 to 100.0) of dropped measurements
+    pub fn loss_percent(&self) -> f64 {
+        let total_count = self.count();
+        if total_count == 0 {
+            return 0.0;
+        }
+
+        (self.dropped_count() as f64 / total_count as f64) * 100.0
+    }
+
     /// Returns average RTT. If no measurements, returns 0.0
     pub fn avg_rtt(&self) -> Option<f64> {
         let mut sum = 0.0;
@@ -1,15 +1,24 @@
 use serde::{Deserialize, Serialize};
 use std::fmt::{self, Display, Formatter};
 
+pub use bufferbloat::*;
+pub use datagram::*;
 pub use latency::*;
 pub use network::*;
+pub use responsiveness::*;
+pub use server::*;
 pub use throughput::*;
 
+mod bufferbloat;
+mod datagram;
 mod latency;
 mod network;
+mod responsiveness;
+mod server;
 mod throughput;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
 pub enum TestResult {
     Simple(ThroughputResult),
     Network(NetworkTestResult),
@@ -5,19 +5,38 @@ use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    report::{LatencyResult, ThroughputResult},
+    report::{
+        BufferbloatGrade, DatagramResult, LatencyResult, LoadedLatencyResult, ResponsivenessResult,
+        ServerMeasurement, ServerSideResult, ThroughputResult,
+    },
     utils::format::format_bytes,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkTestResult {
+    /// Latency of the idle link
     pub latency: Option<LatencyResult>,
+    /// Latency while the link was saturated, for latency-under-load tests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loaded_latency: Option<LoadedLatencyResult>,
+    /// Round-trips per minute under working conditions, for responsiveness tests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub responsiveness: Option<ResponsivenessResult>,
     /// Map of download results by payload size
     pub download: IndexMap<usize, ThroughputResult>,
     /// Map of upload results by payload size
     pub upload: IndexMap<usize, ThroughputResult>,
     /// Protocol type for display purposes
     pub protocol: NetworkProtocol,
+    /// Measurements reported back by the server, if it supports it
+    #[serde(default, skip_serializing_if = "ServerSideResult::is_empty")]
+    pub server: ServerSideResult,
+    /// Loss and jitter of the datagrams, for UDP tests
+    #[serde(default, skip_serializing_if = "DatagramResult::is_empty")]
+    pub datagrams: DatagramResult,
+    /// How the client sent and received datagrams, for UDP tests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub udp_io: Option<UdpIoPath>,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -27,31 +46,103 @@ pub enum NetworkProtocol {
     Udp,
 }
 
+/// Syscalls UDP datagrams were sent and received with, from slowest to fastest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UdpIoPath {
+    /// One `send`/`recv` per datagram
+    Standard,
+    /// Batches of datagrams with `sendmmsg`/`recvmmsg`
+    Batched,
+    /// Batches with UDP segmentation offload (GSO/GRO)
+    Offload,
+}
+
+impl Display for UdpIoPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            UdpIoPath::Standard => write!(f, "one syscall per datagram"),
+            UdpIoPath::Batched => write!(f, "sendmmsg/recvmmsg"),
+            UdpIoPath::Offload => write!(f, "sendmmsg/recvmmsg with GSO/GRO"),
+        }
+    }
+}
+
 impl NetworkTestResult {
     pub fn new_http() -> Self {
         Self {
             latency: None,
+            loaded_latency: None,
+            responsiveness: None,
             download: IndexMap::new(),
             upload: IndexMap::new(),
             protocol: NetworkProtocol::Http,
+            server: ServerSideResult::default(),
+            datagrams: DatagramResult::default(),
+            udp_io: None,
         }
     }
 
     pub fn new_tcp() -> Self {
         Self {
             latency: None,
+            loaded_latency: None,
+            responsiveness: None,
             download: IndexMap::new(),
             upload: IndexMap::new(),
             protocol: NetworkProtocol::Tcp,
+            server: ServerSideResult::default(),
+            datagrams: DatagramResult::default(),
+            udp_io: None,
         }
     }
 
     pub fn new_udp() -> Self {
         Self {
             latency: None,
+            loaded_latency: None,
+            responsiveness: None,
             download: IndexMap::new(),
             upload: IndexMap::new(),
             protocol: NetworkProtocol::Udp,
+            server: ServerSideResult::default(),
+            datagrams: DatagramResult::default(),
+            udp_io: None,
+        }
+    }
+
+    /// Adds a download result along with the server's view of it, if available
+    pub fn add_download(
+        &mut self,
+        payload_size: usize,
+        result: ThroughputResult,
+        server: Option<ServerMeasurement>,
+    ) {
+        self.download.insert(payload_size, result);
+        if let Some(server) = server {
+            self.server.download.insert(payload_size, server);
+        }
+    }
+
+    /// Returns why the report has no server-side download results, if the server can't
+    /// measure downloads at all
+    pub fn server_download_note(&self) -> Option<&'static str> {
+        (matches!(self.protocol, NetworkProtocol::Http)
+            && !self.download.is_empty()
+            && self.server.download.is_empty())
+        .then_some("not measured, HTTP servers only report the uploads they receive")
+    }
+
+    /// Adds an upload result along with the server's view of it, if available
+    pub fn add_upload(
+        &mut self,
+        payload_size: usize,
+        result: ThroughputResult,
+        server: Option<ServerMeasurement>,
+    ) {
+        self.upload.insert(payload_size, result);
+        if let Some(server) = server {
+            self.server.upload.insert(payload_size, server);
         }
     }
 }
@@ -64,6 +155,15 @@ impl Display for NetworkTestResult {
             NetworkProtocol::Udp => "UDP ",
         };
 
+        if let Some(udp_io) = self.udp_io {
+            writeln!(
+                f,
+                "  {}: {}",
+                "UDP I/O".bright_green().bold(),
+                udp_io.to_string().cyan()
+            )?;
+        }
+
         // Display latency if available
         if let Some(latency) = &self.latency {
             writeln!(
@@ -77,6 +177,72 @@ impl Display for NetworkTestResult {
             writeln!(f)?;
         }
 
+        // Display latency under load if available
+        if let Some(loaded) = &self.loaded_latency {
+            writeln!(
+                f,
+                "  {}",
+                format!("{}Latency Under Load:", protocol_prefix)
+                    .bright_green()
+                    .bold()
+            )?;
+            if let Some(idle) = self.latency.as_ref().and_then(|latency| latency.avg_rtt()) {
+                writeln!(
+                    f,
+                    "    {}: {}",
+                    "Idle RTT".bright_blue().bold(),
+                    format!("{idle:.2} ms").cyan()
+                )?;
+            }
+            for (direction, latency) in loaded.directions() {
+                let label = match direction {
+                    "download" => "Download RTT",
+                    _ => "Upload RTT",
+                };
+                let Some(rtt) = latency.avg_rtt() else {
+                    writeln!(f, "    {}: {}", label.bright_blue().bold(), "n/a".red())?;
+                    continue;
+                };
+                write!(
+                    f,
+                    "    {}: {}",
+                    label.bright_blue().bold(),
+                    format!("{rtt:.2} ms").cyan()
+                )?;
+                if let Some(increase) = self.latency_increase(latency) {
+                    write!(f, " ({})", format!("{increase:+.2} ms").yellow())?;
+                }
+                writeln!(f)?;
+            }
+            if let Some(grade) = self.bufferbloat_grade() {
+                let grade = match grade {
+                    BufferbloatGrade::APlus | BufferbloatGrade::A => grade.to_string().green(),
+                    BufferbloatGrade::B | BufferbloatGrade::C => grade.to_string().yellow(),
+                    BufferbloatGrade::D | BufferbloatGrade::F => grade.to_string().red(),
+                };
+                writeln!(
+                    f,
+                    "    {}: {}",
+                    "Bufferbloat Grade".bright_blue().bold(),
+                    grade.bold()
+                )?;
+            }
+            writeln!(f)?;
+        }
+
+        // Display responsiveness if available
+        if let Some(responsiveness) = &self.responsiveness {
+            writeln!(
+                f,
+                "  {}",
+                format!("{}Responsiveness:", protocol_prefix)
+                    .bright_green()
+                    .bold()
+            )?;
+            write!(f, "{responsiveness}")?;
+            writeln!(f)?;
+        }
+
         // Display download results
         if !self.download.is_empty() {
             writeln!(
@@ -125,6 +291,78 @@ impl Display for NetworkTestResult {
             }
         }
 
+        // Display datagram delivery of UDP tests
+        for (direction, results) in [
+            ("Download", &self.datagrams.download),
+            ("Upload", &self.datagrams.upload),
+        ] {
+            if results.is_empty() {
+                continue;
+            }
+            writeln!(
+                f,
+                "  {}",
+                format!("{}{} Datagram Results:", protocol_prefix, direction)
+                    .bright_green()
+                    .bold()
+            )?;
+            for (size, stats) in results {
+                writeln!(
+                    f,
+                    "    {} ({}):",
+                    "Payload Size".bright_blue(),
+                    format_bytes(*size).yellow()
+                )?;
+                let stats_str = format!("{stats}");
+                for line in stats_str.lines() {
+                    writeln!(f, "    {line}")?;
+                }
+            }
+        }
+
+        // Display server-side results
+        for (direction, results, note) in [
+            (
+                "Download (sent)",
+                &self.server.download,
+                self.server_download_note(),
+            ),
+            ("Upload (received)", &self.server.upload, None),
+        ] {
+            if results.is_empty() {
+                if let Some(note) = note {
+                    writeln!(
+                        f,
+                        "  {}: {}",
+                        format!("{}Server-side {} Results", protocol_prefix, direction)
+                            .bright_green()
+                            .bold(),
+                        note.yellow()
+                    )?;
+                }
+                continue;
+            }
+            writeln!(
+                f,
+                "  {}",
+                format!("{}Server-side {} Results:", protocol_prefix, direction)
+                    .bright_green()
+                    .bold()
+            )?;
+            for (size, result) in results {
+                writeln!(
+                    f,
+                    "    {} ({}):",
+                    "Payload Size".bright_blue(),
+                    format_bytes(*size).yellow()
+                )?;
+                let result_str = format!("{result}");
+                for line in result_str.lines() {
+                    writeln!(f, "    {line}")?;
+                }
+            }
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,116 @@
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use colored::Colorize as _;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::format::format_throughput;
+
+/// Result of a responsiveness test following the IETF "Responsiveness under Working
+/// Conditions" methodology, in round-trips per minute (RPM)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResponsivenessResult {
+    /// Responsiveness while downloads saturated the link
+    pub download: Option<ResponsivenessPhase>,
+    /// Responsiveness while uploads saturated the link
+    pub upload: Option<ResponsivenessPhase>,
+}
+
+/// Responsiveness measured in one direction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponsivenessPhase {
+    /// Round-trips per minute under working conditions
+    pub rpm: f64,
+    /// Aggregate goodput of the load-generating connections, in bits per second
+    pub goodput_bps: f64,
+    /// Load-generating connections open at the end of the phase
+    pub load_connections: usize,
+    /// Whether goodput and responsiveness stabilized before the phase timed out
+    pub stable: bool,
+    /// Time the phase ran for
+    pub duration: Duration,
+    /// Trimmed mean of the TCP connect times of foreign probes, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp_connect_ms: Option<f64>,
+    /// Trimmed mean of the TLS (or QUIC) handshake times of foreign probes, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_handshake_ms: Option<f64>,
+    /// Trimmed mean of the request/response times of foreign probes, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_foreign_ms: Option<f64>,
+    /// Trimmed mean of the request/response times of self probes, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_self_ms: Option<f64>,
+    /// Number of probes on new connections
+    pub foreign_probes: usize,
+    /// Number of probes on the load-generating connections
+    pub self_probes: usize,
+}
+
+impl ResponsivenessResult {
+    /// Responsiveness of each direction that was measured
+    pub fn directions(&self) -> impl Iterator<Item = (&'static str, &ResponsivenessPhase)> {
+        [("download", &self.download), ("upload", &self.upload)]
+            .into_iter()
+            .filter_map(|(direction, phase)| Some((direction, phase.as_ref()?)))
+    }
+
+    /// Returns the RPM of the least responsive direction
+    pub fn rpm(&self) -> Option<f64> {
+        self.directions()
+            .map(|(_, phase)| phase.rpm)
+            .reduce(f64::min)
+    }
+}
+
+impl Display for ResponsivenessResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (direction, phase) in self.directions() {
+            let label = match direction {
+                "download" => "Downlink Responsiveness",
+                _ => "Uplink Responsiveness",
+            };
+            write!(
+                f,
+                "    {}: {}",
+                label.bright_blue().bold(),
+                format!("{:.0} RPM", phase.rpm).cyan().bold()
+            )?;
+            if !phase.stable {
+                write!(f, " {}", "(not stable)".yellow())?;
+            }
+            writeln!(f)?;
+            writeln!(
+                f,
+                "      {}: {} over {} connections in {:.1}s",
+                "Goodput".bright_blue(),
+                format_throughput(phase.goodput_bps / 1_000_000.0).cyan(),
+                phase.load_connections,
+                phase.duration.as_secs_f64()
+            )?;
+            for (name, value) in [
+                ("TCP Connect", phase.tcp_connect_ms),
+                ("TLS Handshake", phase.tls_handshake_ms),
+                ("HTTP (foreign)", phase.http_foreign_ms),
+                ("HTTP (self)", phase.http_self_ms),
+            ] {
+                if let Some(value) = value {
+                    writeln!(
+                        f,
+                        "      {}: {}",
+                        name.bright_blue(),
+                        format!("{value:.2} ms").cyan()
+                    )?;
+                }
+            }
+            writeln!(
+                f,
+                "      {}: {} foreign, {} self",
+                "Probes".bright_blue(),
+                phase.foreign_probes,
+                phase.self_probes
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt;
+use std::time::Duration;
+
+use colored::*;
+use humansize::{BINARY, BaseUnit, DECIMAL, format_size};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::report::DatagramStats;
+
+/// Default width of the per-interval buckets recorded by the servers
+pub const SERVER_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Share of an interval the measurement must cover for its last interval to count as full
+const FULL_INTERVAL_FRACTION: f64 = 0.95;
+
+/// Measurements taken by the server for a single test.
+/// For uploads these are the bytes the server actually received, for downloads the bytes it sent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerMeasurement {
+    /// Total number of bytes transferred
+    pub bytes: u64,
+    /// Time from the start of the test to the last byte transferred. HTTP servers only see
+    /// the requests, so for HTTP uploads the test starts when the server began reading the
+    /// first request body.
+    pub duration: Duration,
+    /// Width of each interval in `intervals`
+    pub interval: Duration,
+    /// Bytes transferred during each interval, starting at the beginning of the test
+    pub intervals: Vec<u64>,
+    /// Number of connections (or streams) that contributed to the measurement
+    pub connections: u32,
+    /// Delivery of the datagrams received during UDP uploads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datagrams: Option<DatagramStats>,
+}
+
+impl ServerMeasurement {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            bytes: 0,
+            duration: Duration::ZERO,
+            interval,
+            intervals: Vec::new(),
+            connections: 0,
+            datagrams: None,
+        }
+    }
+
+    /// Records `bytes` transferred at `offset` since the start of the test
+    pub fn record(&mut self, offset: Duration, bytes: u64) {
+        self.bytes += bytes;
+        self.duration = self.duration.max(offset);
+
+        if self.interval.is_zero() {
+            return;
+        }
+        let index = (offset.as_nanos() / self.interval.as_nanos()) as usize;
+        if index >= self.intervals.len() {
+            self.intervals.resize(index + 1, 0);
+        }
+        self.intervals[index] += bytes;
+    }
+
+    /// Merges another measurement of the same test (e.g. from another connection) into this one
+    pub fn merge(&mut self, other: &ServerMeasurement) {
+        self.bytes += other.bytes;
+        self.duration = self.duration.max(other.duration);
+        self.connections += other.connections;
+        match (&mut self.datagrams, &other.datagrams) {
+            (Some(datagrams), Some(other)) => datagrams.merge(other),
+            (None, Some(other)) => self.datagrams = Some(*other),
+            _ => {}
+        }
+
+        if self.intervals.len() < other.intervals.len() {
+            self.intervals.resize(other.intervals.len(), 0);
+        }
+        for (total, bytes) in self.intervals.iter_mut().zip(&other.intervals) {
+            *total += bytes;
+        }
+    }
+
+    /// Returns the average throughput in bytes per second
+    pub fn avg_throughput(&self) -> f64 {
+        if self.duration.is_zero() {
+            return 0.0;
+        }
+
+        self.bytes as f64 / self.duration.as_secs_f64()
+    }
+
+    /// Returns the lowest and highest per-interval throughput in bytes per second.
+    /// The last interval is skipped if the measurement ended well before it was over, since it
+    /// is then only partially filled.
+    pub fn interval_throughput_range(&self) -> Option<(f64, f64)> {
+        if self.interval.is_zero() || self.intervals.is_empty() {
+            return None;
+        }
+
+        let secs = self.interval.as_secs_f64();
+        let last_start = secs * (self.intervals.len() - 1) as f64;
+        let last_secs = (self.duration.as_secs_f64() - last_start).min(secs);
+        let full = if last_secs >= secs * FULL_INTERVAL_FRACTION {
+            self.intervals.len()
+        } else {
+            self.intervals.len() - 1
+        };
+
+        self.intervals[..full]
+            .iter()
+            .enumerate()
+            .map(|(index, bytes)| {
+                // The last interval may end slightly before its nominal end
+                let secs = if index + 1 == self.intervals.len() {
+                    last_secs
+                } else {
+                    secs
+                };
+                *bytes as f64 / secs
+            })
+            .fold(None, |range, rate| match range {
+                None => Some((rate, rate)),
+                Some((min, max)) => Some((f64::min(min, rate), f64::max(max, rate))),
+            })
+    }
+}
+
+impl fmt::Display for ServerMeasurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bits = DECIMAL.base_unit(BaseUnit::Bit).suffix("/s");
+
+        writeln!(
+            f,
+            "  {}: {}",
+            "Data Transferred".bright_green().bold(),
+            format_size(self.bytes, BINARY).cyan()
+        )?;
+        writeln!(
+            f,
+            "  {}: {}",
+            "Duration".bright_green().bold(),
+            format!("{:.2}s", self.duration.as_secs_f64()).yellow()
+        )?;
+        writeln!(
+            f,
+            "  {}: {}",
+            "Average Throughput".bright_green().bold(),
+            format_size((self.avg_throughput() * 8.0) as u64, bits).magenta()
+        )?;
+        writeln!(
+            f,
+            "  {}: {}",
+            "Connections".bright_green().bold(),
+            self.connections.to_string().white()
+        )?;
+        if let Some((min, max)) = self.interval_throughput_range() {
+            writeln!(
+                f,
+                "  {}: {} x {:.2}s (min {}, max {})",
+                "Intervals".bright_green().bold(),
+                self.intervals.len(),
+                self.interval.as_secs_f64(),
+                format_size((min * 8.0) as u64, bits).magenta(),
+                format_size((max * 8.0) as u64, bits).magenta()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Server-side measurements, keyed by payload size like the client-side results
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerSideResult {
+    /// Bytes the server sent during download tests
+    pub download: IndexMap<usize, ServerMeasurement>,
+    /// Bytes the server received during upload tests
+    pub upload: IndexMap<usize, ServerMeasurement>,
+}
+
+impl ServerSideResult {
+    pub fn is_empty(&self) -> bool {
+        self.download.is_empty() && self.upload.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_buckets_by_interval() {
+        let mut measurement = ServerMeasurement::new(Duration::from_secs(1));
+        measurement.record(Duration::from_millis(100), 10);
+        measurement.record(Duration::from_millis(900), 20);
+        measurement.record(Duration::from_millis(2500), 5);
+
+        assert_eq!(measurement.bytes, 35);
+        assert_eq!(measurement.duration, Duration::from_millis(2500));
+        assert_eq!(measurement.intervals, vec![30, 0, 5]);
+    }
+
+    #[test]
+    fn test_merge_connections() {
+        let mut a = ServerMeasurement::new(Duration::from_secs(1));
+        a.connections = 1;
+        a.record(Duration::from_millis(500), 100);
+
+        let mut b = ServerMeasurement::new(Duration::from_secs(1));
+        b.connections = 1;
+        b.record(Duration::from_millis(1500), 50);
+
+        a.merge(&b);
+        assert_eq!(a.bytes, 150);
+        assert_eq!(a.connections, 2);
+        assert_eq!(a.duration, Duration::from_millis(1500));
+        assert_eq!(a.intervals, vec![100, 50]);
+    }
+
+    #[test]
+    fn test_interval_range_keeps_full_last_interval() {
+        let mut measurement = ServerMeasurement::new(Duration::from_secs(1));
+        measurement.record(Duration::from_millis(500), 1000);
+        measurement.record(Duration::from_millis(1500), 2000);
+        measurement.duration = Duration::from_secs(2);
+        assert_eq!(
+            measurement.interval_throughput_range(),
+            Some((1000.0, 2000.0))
+        );
+
+        // Ending halfway through the second interval leaves only the first one
+        measurement.duration = Duration::from_millis(1500);
+        assert_eq!(
+            measurement.interval_throughput_range(),
+            Some((1000.0, 1000.0))
+        );
+    }
+}
@@ -6,20 +6,259 @@ use humansize::{BINARY, BaseUnit, DECIMAL, format_size};
 use num_format::{Locale, ToFormattedString};
 use serde::{Deserialize, Serialize};
 
-use crate::report::{ConnectionError, ThroughputMeasurement};
-use std::collections::HashMap;
+use crate::report::{ConnectionError, ThroughputMeasurement, jain_fairness_index};
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "ThroughputResultRepr")]
 pub struct ThroughputResult {
-    /// Throughput measurements
-    pub measurements: Vec<ThroughputMeasurement>,
+    /// Totals of the reads/writes of the test
+    pub totals: ThroughputTotals,
     /// Total duration of the test
     pub total_duration: Duration,
+    /// Per-interval time series of the test, relative to its start
+    #[serde(default)]
+    pub intervals: Vec<ThroughputInterval>,
+    /// Per-interval time series of each connection, keyed by connection index
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub connection_intervals: BTreeMap<usize, Vec<ThroughputInterval>>,
 
     pub timestamp: DateTime<Utc>,
 }
 
+/// Serialized form of [`ThroughputResult`], also accepting reports that stored every
+/// measurement instead of their totals
+#[derive(Deserialize)]
+struct ThroughputResultRepr {
+    #[serde(default)]
+    totals: Option<ThroughputTotals>,
+    #[serde(default)]
+    measurements: Vec<ThroughputMeasurement>,
+    total_duration: Duration,
+    #[serde(default)]
+    intervals: Vec<ThroughputInterval>,
+    #[serde(default)]
+    connection_intervals: BTreeMap<usize, Vec<ThroughputInterval>>,
+    timestamp: DateTime<Utc>,
+}
+
+impl From<ThroughputResultRepr> for ThroughputResult {
+    fn from(repr: ThroughputResultRepr) -> Self {
+        Self {
+            totals: repr
+                .totals
+                .unwrap_or_else(|| repr.measurements.iter().collect()),
+            total_duration: repr.total_duration,
+            intervals: repr.intervals,
+            connection_intervals: repr.connection_intervals,
+            timestamp: repr.timestamp,
+        }
+    }
+}
+
+/// Totals of the measurements of a throughput test, accumulated as they complete rather
+/// than keeping every read or write
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ThroughputTotals {
+    /// Number of bytes transferred by the successful reads/writes
+    pub bytes: u64,
+    /// Number of successful reads/writes
+    pub successes: u64,
+    /// Number of failed reads/writes
+    pub failures: u64,
+    /// Number of retry attempts before the failures
+    pub retries: u64,
+    /// Number of failures of each type
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<String, u64>,
+}
+
+impl ThroughputTotals {
+    /// Adds `measurement` to the totals
+    pub fn record(&mut self, measurement: &ThroughputMeasurement) {
+        match measurement {
+            ThroughputMeasurement::Success { bytes, .. } => {
+                self.bytes += bytes;
+                self.successes += 1;
+            }
+            ThroughputMeasurement::Failure {
+                error, retry_count, ..
+            } => {
+                let error_type = match error {
+                    ConnectionError::ConnectionFailed(_) => "Connection Failed",
+                    ConnectionError::TransferFailed(_) => "Transfer Failed",
+                    ConnectionError::Timeout(_) => "Timeout",
+                    ConnectionError::Unknown(_) => "Unknown",
+                };
+                *self.errors.entry(error_type.to_string()).or_insert(0) += 1;
+                self.failures += 1;
+                self.retries += u64::from(*retry_count);
+            }
+        }
+    }
+
+    /// Returns the number of measurements recorded
+    pub fn count(&self) -> u64 {
+        self.successes + self.failures
+    }
+}
+
+impl<'a> FromIterator<&'a ThroughputMeasurement> for ThroughputTotals {
+    fn from_iter<I: IntoIterator<Item = &'a ThroughputMeasurement>>(iter: I) -> Self {
+        let mut totals = Self::default();
+        for measurement in iter {
+            totals.record(measurement);
+        }
+        totals
+    }
+}
+
+/// Aggregated measurements for a single interval of a throughput test
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThroughputInterval {
+    /// Offset of the start of the interval from the start of the test
+    pub start: Duration,
+    /// Offset of the end of the interval from the start of the test
+    pub end: Duration,
+    /// Number of bytes transferred during the interval
+    pub bytes: u64,
+    /// Throughput during the interval in bytes per second
+    pub throughput: f64,
+    /// Number of failed reads/writes during the interval
+    pub errors: u32,
+    /// Number of retransmitted packets (or segments) during the interval
+    pub retransmits: u64,
+}
+
+impl ThroughputInterval {
+    fn empty(start: Duration, end: Duration) -> Self {
+        Self {
+            start,
+            end,
+            bytes: 0,
+            throughput: 0.0,
+            errors: 0,
+            retransmits: 0,
+        }
+    }
+}
+
+/// Shortest share of an interval the last interval of a test may cover before it is merged
+/// into the one before it, since a few milliseconds of data make for a meaningless rate
+pub const MIN_FINAL_INTERVAL_FRACTION: f64 = 0.25;
+
+/// Buckets measurements into fixed-width intervals relative to the start of a test.
+/// A zero interval disables bucketing.
+#[derive(Debug, Clone)]
+pub struct IntervalSeries {
+    interval: Duration,
+    intervals: Vec<ThroughputInterval>,
+}
+
+impl IntervalSeries {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            intervals: Vec::new(),
+        }
+    }
+
+    /// Returns the width of each interval
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns the interval at `index`, if anything has been recorded up to it
+    pub fn get(&self, index: usize) -> Option<&ThroughputInterval> {
+        self.intervals.get(index)
+    }
+
+    /// Returns the interval containing `offset`, creating any missing intervals before it
+    fn bucket(&mut self, offset: Duration) -> Option<&mut ThroughputInterval> {
+        if self.interval.is_zero() {
+            return None;
+        }
+
+        let index = (offset.as_nanos() / self.interval.as_nanos()) as usize;
+        while self.intervals.len() <= index {
+            let start = self.interval * self.intervals.len() as u32;
+            self.intervals
+                .push(ThroughputInterval::empty(start, start + self.interval));
+        }
+        self.intervals.get_mut(index)
+    }
+
+    /// Records a measurement completed at `offset` since the start of the test
+    pub fn record(&mut self, offset: Duration, measurement: &ThroughputMeasurement) {
+        if let Some(bucket) = self.bucket(offset) {
+            match measurement {
+                ThroughputMeasurement::Success { bytes, .. } => bucket.bytes += bytes,
+                ThroughputMeasurement::Failure { .. } => bucket.errors += 1,
+            }
+        }
+    }
+
+    /// Records retransmissions observed at `offset` since the start of the test
+    pub fn record_retransmits(&mut self, offset: Duration, count: u64) {
+        if let Some(bucket) = self.bucket(offset) {
+            bucket.retransmits += count;
+        }
+    }
+
+    /// Returns whether the last interval of a test that ran for `total_duration` is shorter
+    /// than [`MIN_FINAL_INTERVAL_FRACTION`] of an interval, so that it is merged into the one
+    /// before it
+    pub fn merges_final_interval(&self, total_duration: Duration) -> bool {
+        if self.interval.is_zero() {
+            return false;
+        }
+
+        let count = total_duration.as_nanos().div_ceil(self.interval.as_nanos());
+        if count < 2 {
+            return false;
+        }
+        let last = total_duration - self.interval * (count - 1) as u32;
+        last.as_secs_f64() < self.interval.as_secs_f64() * MIN_FINAL_INTERVAL_FRACTION
+    }
+
+    /// Completes the series for a test that ran for `total_duration`.
+    /// The last interval is truncated to the end of the test, or merged into the one before
+    /// it if it would be very short (see [`Self::merges_final_interval`]).
+    pub fn finish(mut self, total_duration: Duration) -> Vec<ThroughputInterval> {
+        if self.interval.is_zero() {
+            return Vec::new();
+        }
+
+        // Cover the whole test even if nothing was recorded towards its end
+        if !total_duration.is_zero() {
+            self.bucket(total_duration.saturating_sub(Duration::from_nanos(1)));
+        }
+
+        if self.merges_final_interval(total_duration)
+            && let Some(tail) = self.intervals.pop()
+            && let Some(last) = self.intervals.last_mut()
+        {
+            last.end = tail.end;
+            last.bytes += tail.bytes;
+            last.errors += tail.errors;
+            last.retransmits += tail.retransmits;
+        }
+
+        for interval in &mut self.intervals {
+            interval.end = interval.end.min(total_duration.max(interval.start));
+            let secs = (interval.end - interval.start).as_secs_f64();
+            interval.throughput = if secs > 0.0 {
+                interval.bytes as f64 / secs
+            } else {
+                0.0
+            };
+        }
+
+        self.intervals
+    }
+}
+
 impl fmt::Display for ThroughputResult {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(
@@ -96,14 +335,51 @@ impl fmt::Display for ThroughputResult {
             }
         }
 
+        let total_retransmits = self.total_retransmits();
+        if total_retransmits > 0 {
+            writeln!(
+                f,
+                "  {}: {}",
+                "Retransmits".bright_green().bold(),
+                total_retransmits.to_formatted_string(&Locale::en).yellow()
+            )?;
+        }
+        if let Some((min, max)) = self.interval_throughput_range() {
+            let bits = DECIMAL.base_unit(BaseUnit::Bit).suffix("/s");
+            writeln!(
+                f,
+                "  {}: {} (min {}, max {})",
+                "Intervals".bright_green().bold(),
+                self.intervals.len(),
+                format_size((min * 8.0) as u64, bits).magenta(),
+                format_size((max * 8.0) as u64, bits).magenta()
+            )?;
+        }
+
+        if let Some(fairness) = self.fairness_index() {
+            let bits = DECIMAL.base_unit(BaseUnit::Bit).suffix("/s");
+            writeln!(
+                f,
+                "  {}: {} (fairness index {})",
+                "Connections".bright_green().bold(),
+                self.connection_intervals.len(),
+                format!("{fairness:.3}").yellow()
+            )?;
+            for (connection, throughput) in self.connection_throughputs() {
+                writeln!(
+                    f,
+                    "    {}: {}",
+                    format!("#{connection}").bright_yellow(),
+                    format_size((throughput * 8.0) as u64, bits).magenta()
+                )?;
+            }
+        }
+
         writeln!(
             f,
             "  {}: {}",
             "Measurements".bright_green().bold(),
-            self.measurements
-                .len()
-                .to_formatted_string(&Locale::en)
-                .white()
+            self.totals.count().to_formatted_string(&Locale::en).white()
         )?;
         writeln!(
             f,
@@ -122,13 +398,7 @@ impl fmt::Display for ThroughputResult {
 impl ThroughputResult {
     /// Returns total number of bytes transferred
     pub fn bytes_transferred(&self) -> u64 {
-        self.measurements
-            .iter()
-            .map(|m| match m {
-                ThroughputMeasurement::Success { bytes, .. } => *bytes,
-                ThroughputMeasurement::Failure { .. } => 0,
-            })
-            .sum()
+        self.totals.bytes
     }
 
     /// Returns the average throughput in bytes per second
@@ -140,19 +410,43 @@ impl ThroughputResult {
         (self.bytes_transferred() as f64) / self.total_duration.as_secs_f64()
     }
 
+    /// Returns the average throughput of each connection in bytes per second, computed from
+    /// its per-interval series
+    pub fn connection_throughputs(&self) -> BTreeMap<usize, f64> {
+        if self.total_duration.is_zero() {
+            return BTreeMap::new();
+        }
+
+        self.connection_intervals
+            .iter()
+            .map(|(connection, intervals)| {
+                let bytes: u64 = intervals.iter().map(|interval| interval.bytes).sum();
+                (
+                    *connection,
+                    bytes as f64 / self.total_duration.as_secs_f64(),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns Jain's fairness index of the throughput of the connections, if there are
+    /// several
+    pub fn fairness_index(&self) -> Option<f64> {
+        let throughputs: Vec<f64> = self.connection_throughputs().into_values().collect();
+        if throughputs.len() < 2 {
+            return None;
+        }
+
+        jain_fairness_index(&throughputs)
+    }
+
     /// Returns the connection success rate as a percentage (0.0 to 1.0)
     pub fn connection_success_rate(&self) -> f64 {
-        if self.measurements.is_empty() {
+        if self.totals.count() == 0 {
             return 0.0;
         }
 
-        let successful_connections = self
-            .measurements
-            .iter()
-            .filter(|m| matches!(m, ThroughputMeasurement::Success { .. }))
-            .count();
-
-        successful_connections as f64 / self.measurements.len() as f64
+        self.totals.successes as f64 / self.totals.count() as f64
     }
 
     /// Returns the request success rate as a percentage (0.0 to 1.0)
@@ -162,25 +456,10 @@ impl ThroughputResult {
     }
 
     /// Returns retry statistics: (total_retries, successful_after_retry, failed_after_retry)
-    pub fn retry_statistics(&self) -> (u32, u32, u32) {
-        let mut total_retries = 0;
-        let successful_after_retry = 0;
-        let mut failed_after_retry = 0;
-
-        for measurement in &self.measurements {
-            match measurement {
-                ThroughputMeasurement::Success { .. } => {
-                    // For successful measurements, we assume no retries were needed
-                    // This could be enhanced if success measurements tracked retry count
-                }
-                ThroughputMeasurement::Failure { retry_count, .. } => {
-                    total_retries += retry_count;
-                    failed_after_retry += 1;
-                }
-            }
-        }
-
-        (total_retries, successful_after_retry, failed_after_retry)
+    pub fn retry_statistics(&self) -> (u64, u64, u64) {
+        // Successful measurements don't track retries, so every retry counts towards a
+        // failure
+        (self.totals.retries, 0, self.totals.failures)
     }
 
     /// Returns the success rate after retries (0.0 to 1.0)
@@ -195,30 +474,161 @@ impl ThroughputResult {
     }
 
     /// Returns error distribution by type
-    pub fn error_distribution(&self) -> HashMap<String, u32> {
-        let mut distribution = HashMap::new();
+    pub fn error_distribution(&self) -> HashMap<String, u64> {
+        self.totals
+            .errors
+            .iter()
+            .map(|(error_type, count)| (error_type.clone(), *count))
+            .collect()
+    }
 
-        for measurement in &self.measurements {
-            if let ThroughputMeasurement::Failure { error, .. } = measurement {
-                let error_type = match error {
-                    ConnectionError::ConnectionFailed(_) => "Connection Failed",
-                    ConnectionError::TransferFailed(_) => "Transfer Failed",
-                    ConnectionError::Timeout(_) => "Timeout",
-                    ConnectionError::Unknown(_) => "Unknown",
-                };
+    /// Returns the total number of errors
+    pub fn total_errors(&self) -> u64 {
+        self.totals.failures
+    }
+
+    /// Returns the total number of retransmissions across all intervals
+    pub fn total_retransmits(&self) -> u64 {
+        self.intervals.iter().map(|i| i.retransmits).sum()
+    }
 
-                *distribution.entry(error_type.to_string()).or_insert(0) += 1;
+    /// Returns the lowest and highest per-interval throughput in bytes per second.
+    /// The last interval is skipped if it is shorter than the others.
+    pub fn interval_throughput_range(&self) -> Option<(f64, f64)> {
+        let full = match self.intervals.split_last() {
+            Some((last, rest))
+                if rest
+                    .first()
+                    .is_some_and(|first| last.end - last.start < first.end - first.start) =>
+            {
+                rest
             }
-        }
+            _ => &self.intervals[..],
+        };
 
-        distribution
+        full.iter()
+            .map(|i| i.throughput)
+            .fold(None, |range, rate| match range {
+                None => Some((rate, rate)),
+                Some((min, max)) => Some((f64::min(min, rate), f64::max(max, rate))),
+            })
     }
+}
 
-    /// Returns the total number of errors
-    pub fn total_errors(&self) -> u32 {
-        self.measurements
-            .iter()
-            .filter(|m| matches!(m, ThroughputMeasurement::Failure { .. }))
-            .count() as u32
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interval_series_buckets_measurements() {
+        let mut series = IntervalSeries::new(Duration::from_secs(1));
+        let ok = |bytes| ThroughputMeasurement::new(bytes, Duration::from_millis(1));
+        series.record(Duration::from_millis(100), &ok(100));
+        series.record(Duration::from_millis(999), &ok(50));
+        series.record(
+            Duration::from_millis(1200),
+            &ThroughputMeasurement::new_error(
+                ConnectionError::Unknown("reset".to_string()),
+                Duration::ZERO,
+                0,
+            ),
+        );
+        series.record_retransmits(Duration::from_millis(1500), 3);
+        series.record(Duration::from_millis(2100), &ok(25));
+
+        let intervals = series.finish(Duration::from_millis(2500));
+        assert_eq!(intervals.len(), 3);
+        assert_eq!(intervals[0].bytes, 150);
+        assert_eq!(intervals[0].throughput, 150.0);
+        assert_eq!(intervals[1].bytes, 0);
+        assert_eq!(intervals[1].errors, 1);
+        assert_eq!(intervals[1].retransmits, 3);
+        assert_eq!(intervals[2].start, Duration::from_secs(2));
+        assert_eq!(intervals[2].end, Duration::from_millis(2500));
+        assert_eq!(intervals[2].throughput, 50.0);
+    }
+
+    #[test]
+    fn test_interval_series_covers_idle_tail() {
+        let mut series = IntervalSeries::new(Duration::from_secs(1));
+        series.record(
+            Duration::from_millis(10),
+            &ThroughputMeasurement::new(10, Duration::ZERO),
+        );
+
+        let intervals = series.finish(Duration::from_secs(3));
+        assert_eq!(intervals.len(), 3);
+        assert_eq!(intervals[2].end, Duration::from_secs(3));
+        assert_eq!(intervals[2].bytes, 0);
+    }
+
+    #[test]
+    fn test_interval_series_merges_short_final_interval() {
+        let mut series = IntervalSeries::new(Duration::from_secs(1));
+        let ok = |bytes| ThroughputMeasurement::new(bytes, Duration::from_millis(1));
+        series.record(Duration::from_millis(500), &ok(100));
+        series.record(Duration::from_millis(1500), &ok(100));
+        series.record(Duration::from_millis(2500), &ok(100));
+        series.record(Duration::from_millis(3050), &ok(15));
+
+        let intervals = series.clone().finish(Duration::from_millis(3150));
+        assert_eq!(intervals.len(), 3);
+        assert_eq!(intervals[2].start, Duration::from_secs(2));
+        assert_eq!(intervals[2].end, Duration::from_millis(3150));
+        assert_eq!(intervals[2].bytes, 115);
+
+        // Longer final intervals are kept as they are
+        let intervals = series.finish(Duration::from_millis(3500));
+        assert_eq!(intervals.len(), 4);
+        assert_eq!(intervals[3].bytes, 15);
+    }
+
+    #[test]
+    fn test_totals_of_legacy_measurements() {
+        let measurements = [
+            ThroughputMeasurement::new(100, Duration::from_secs(1)),
+            ThroughputMeasurement::new(50, Duration::from_secs(1)),
+        ];
+        let json = serde_json::json!({
+            "measurements": measurements,
+            "total_duration": Duration::from_secs(1),
+            "timestamp": Utc::now(),
+        });
+        let result: ThroughputResult = serde_json::from_value(json).unwrap();
+        assert_eq!(result.bytes_transferred(), 150);
+        assert_eq!(result.totals.count(), 2);
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert!(json.get("measurements").is_none());
+        let result: ThroughputResult = serde_json::from_value(json).unwrap();
+        assert_eq!(result.totals.successes, 2);
+    }
+
+    #[test]
+    fn test_totals_count_errors() {
+        let totals: ThroughputTotals = [
+            ThroughputMeasurement::new(100, Duration::from_secs(1)),
+            ThroughputMeasurement::new_error(
+                ConnectionError::Timeout("read".to_string()),
+                Duration::ZERO,
+                2,
+            ),
+        ]
+        .iter()
+        .collect();
+        assert_eq!(totals.bytes, 100);
+        assert_eq!(totals.failures, 1);
+        assert_eq!(totals.retries, 2);
+        assert_eq!(totals.errors["Timeout"], 1);
+    }
+
+    #[test]
+    fn test_interval_series_disabled() {
+        let mut series = IntervalSeries::new(Duration::ZERO);
+        series.record(
+            Duration::from_millis(10),
+            &ThroughputMeasurement::new(10, Duration::ZERO),
+        );
+        assert!(series.finish(Duration::from_secs(1)).is_empty());
     }
 }
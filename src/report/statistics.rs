@@ -0,0 +1,317 @@
+use std::fmt::{self, Display, Formatter};
+
+use colored::*;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use super::{MetricUnit, TestReport, TestResult};
+
+/// 97.5% quantiles of Student's t distribution for 1 to 30 degrees of freedom,
+/// i.e. the multipliers of the standard error for a two-sided 95% confidence interval
+const T_QUANTILES: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+
+/// 97.5% quantile of the normal distribution, used beyond 30 degrees of freedom
+const Z_QUANTILE: f64 = 1.960;
+
+/// Summary statistics of a sample of values
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub samples: usize,
+    pub mean: f64,
+    /// Sample standard deviation. Zero for a single sample.
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Half-width of the 95% confidence interval of the mean, if there are at least two samples
+    pub ci95: Option<f64>,
+}
+
+impl Summary {
+    /// Summarizes `values`. Returns `None` if there are none.
+    pub fn new(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+
+        let samples = values.len();
+        let mean = statistical::mean(values);
+        let (stddev, ci95) = if samples > 1 {
+            let stddev = statistical::standard_deviation(values, Some(mean));
+            let t = T_QUANTILES.get(samples - 2).copied().unwrap_or(Z_QUANTILE);
+            (stddev, Some(t * stddev / (samples as f64).sqrt()))
+        } else {
+            (0.0, None)
+        };
+
+        Some(Self {
+            samples,
+            mean,
+            stddev,
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            ci95,
+        })
+    }
+}
+
+/// Jain's fairness index of the shares of `values`: 1 when all are equal, down to 1/n when a
+/// single one has everything. Returns `None` if there are no values or all are zero.
+pub fn jain_fairness_index(values: &[f64]) -> Option<f64> {
+    let sum: f64 = values.iter().sum();
+    let sum_of_squares: f64 = values.iter().map(|value| value * value).sum();
+    if sum_of_squares == 0.0 {
+        return None;
+    }
+
+    Some(sum * sum / (values.len() as f64 * sum_of_squares))
+}
+
+/// Summary of a metric across repeated runs of the same test
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepeatStatistic {
+    pub protocol: String,
+    /// download, upload or latency
+    pub direction: String,
+    /// Payload size of throughput metrics
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_size: Option<usize>,
+    pub metric: String,
+    pub unit: MetricUnit,
+    pub summary: Summary,
+}
+
+/// Value of a metric taken from a single test report
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub protocol: &'static str,
+    pub direction: &'static str,
+    pub payload_size: Option<usize>,
+    pub metric: &'static str,
+    pub unit: MetricUnit,
+    pub value: f64,
+}
+
+impl Sample {
+    /// Takes the throughput and latency values of `report`. Values the report lacks
+    /// (e.g. RTTs when all probes were dropped) are left out.
+    pub fn from_report(report: &TestReport) -> Vec<Self> {
+        let protocol = report.config.protocol();
+        let mut samples = Vec::new();
+        let mut add = |direction, payload_size, metric, unit, value: Option<f64>| {
+            if let Some(value) = value {
+                samples.push(Self {
+                    protocol,
+                    direction,
+                    payload_size,
+                    metric,
+                    unit,
+                    value,
+                });
+            }
+        };
+
+        match &report.result {
+            TestResult::Simple(result) => add(
+                "throughput",
+                None,
+                "Throughput",
+                MetricUnit::BitsPerSecond,
+                Some(result.avg_throughput() * 8.0),
+            ),
+            TestResult::Network(result) => {
+                if let Some(latency) = &result.latency {
+                    let unit = MetricUnit::Milliseconds;
+                    add("latency", None, "Average RTT", unit, latency.avg_rtt());
+                    add(
+                        "latency",
+                        None,
+                        "P99 RTT",
+                        unit,
+                        latency.percentile_rtt(99.0),
+                    );
+                    add("latency", None, "Jitter", unit, latency.jitter());
+                    add(
+                        "latency",
+                        None,
+                        "Packet Loss",
+                        MetricUnit::Percent,
+                        Some(latency.loss_percent()),
+                    );
+                }
+                if let Some(loaded) = &result.loaded_latency {
+                    let unit = MetricUnit::Milliseconds;
+                    for (direction, latency) in loaded.directions() {
+                        add(direction, None, "Loaded RTT", unit, latency.avg_rtt());
+                        let increase = result.latency_increase(latency);
+                        add(direction, None, "Latency Increase", unit, increase);
+                    }
+                }
+                if let Some(responsiveness) = &result.responsiveness {
+                    let unit = MetricUnit::RoundTripsPerMinute;
+                    for (direction, phase) in responsiveness.directions() {
+                        add(direction, None, "RPM", unit, Some(phase.rpm));
+                    }
+                }
+                for (direction, size, stats) in result.datagrams.directions() {
+                    let loss = Some(stats.loss_percent());
+                    add(
+                        direction,
+                        Some(size),
+                        "Datagram Loss",
+                        MetricUnit::Percent,
+                        loss,
+                    );
+                    let jitter = Some(stats.jitter_ms());
+                    add(
+                        direction,
+                        Some(size),
+                        "Datagram Jitter",
+                        MetricUnit::Milliseconds,
+                        jitter,
+                    );
+                }
+                for (direction, results) in
+                    [("download", &result.download), ("upload", &result.upload)]
+                {
+                    for (size, result) in results {
+                        add(
+                            direction,
+                            Some(*size),
+                            "Throughput",
+                            MetricUnit::BitsPerSecond,
+                            Some(result.avg_throughput() * 8.0),
+                        );
+                    }
+                }
+            }
+        }
+
+        samples
+    }
+}
+
+impl RepeatStatistic {
+    /// Summarizes the throughput and latency of `reports`, per protocol, direction and
+    /// payload size
+    pub fn from_reports<'a>(reports: impl IntoIterator<Item = &'a TestReport>) -> Vec<Self> {
+        let samples: Vec<_> = reports.into_iter().flat_map(Sample::from_report).collect();
+        Self::from_samples(&samples)
+    }
+
+    /// Summarizes `samples`, per protocol, direction, payload size and metric
+    pub fn from_samples<'a>(samples: impl IntoIterator<Item = &'a Sample>) -> Vec<Self> {
+        type Key = (
+            &'static str,
+            &'static str,
+            Option<usize>,
+            &'static str,
+            MetricUnit,
+        );
+        let mut values: IndexMap<Key, Vec<f64>> = IndexMap::new();
+        for sample in samples {
+            values
+                .entry((
+                    sample.protocol,
+                    sample.direction,
+                    sample.payload_size,
+                    sample.metric,
+                    sample.unit,
+                ))
+                .or_default()
+                .push(sample.value);
+        }
+
+        values
+            .into_iter()
+            .filter_map(
+                |((protocol, direction, payload_size, metric, unit), values)| {
+                    Some(Self {
+                        protocol: protocol.to_string(),
+                        direction: direction.to_string(),
+                        payload_size,
+                        metric: metric.to_string(),
+                        unit,
+                        summary: Summary::new(&values)?,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// Describes what was measured, e.g. "tcp download (1 KiB) Throughput"
+    pub fn name(&self) -> String {
+        match self.payload_size {
+            Some(size) => format!(
+                "{} {} ({}) {}",
+                self.protocol,
+                self.direction,
+                crate::utils::format::format_bytes(size),
+                self.metric
+            ),
+            None => format!("{} {} {}", self.protocol, self.direction, self.metric),
+        }
+    }
+}
+
+impl Display for RepeatStatistic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let summary = &self.summary;
+        let unit = self.unit;
+        write!(
+            f,
+            "{}: {}",
+            self.name().bright_white().bold(),
+            unit.format(summary.mean).green()
+        )?;
+        if let Some(ci95) = summary.ci95 {
+            write!(
+                f,
+                " (95% CI {} – {})",
+                unit.format(summary.mean - ci95),
+                unit.format(summary.mean + ci95)
+            )?;
+        }
+        write!(
+            f,
+            ", stddev {}, min {}, max {}, n={}",
+            unit.format(summary.stddev),
+            unit.format(summary.min),
+            unit.format(summary.max),
+            summary.samples
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let summary = Summary::new(&[10.0, 12.0, 14.0]).unwrap();
+        assert_eq!(summary.samples, 3);
+        assert_eq!(summary.mean, 12.0);
+        assert_eq!(summary.stddev, 2.0);
+        assert_eq!((summary.min, summary.max), (10.0, 14.0));
+        // t(0.975, 2) * 2 / sqrt(3)
+        assert!((summary.ci95.unwrap() - 4.968).abs() < 1e-3);
+
+        let single = Summary::new(&[5.0]).unwrap();
+        assert_eq!(single.stddev, 0.0);
+        assert_eq!(single.ci95, None);
+        assert!(Summary::new(&[]).is_none());
+    }
+
+    #[test]
+    fn test_jain_fairness_index() {
+        assert_eq!(jain_fairness_index(&[5.0, 5.0, 5.0, 5.0]), Some(1.0));
+        assert_eq!(jain_fairness_index(&[8.0, 0.0, 0.0, 0.0]), Some(0.25));
+        // (1 + 3)^2 / (2 * (1 + 9))
+        assert_eq!(jain_fairness_index(&[1.0, 3.0]), Some(0.8));
+        assert_eq!(jain_fairness_index(&[0.0, 0.0]), None);
+    }
+}
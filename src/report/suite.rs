@@ -0,0 +1,300 @@
+use chrono::{DateTime, Utc};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+use super::{RepeatStatistic, TestConfig, TestReport};
+use crate::utils::host;
+
+/// Report of a session of tests (e.g. the steps of a test plan) with metadata shared by
+/// all of them, so the whole session can be archived and compared as a single file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuiteReport {
+    /// Name of the suite, if it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Unique ID of the session
+    pub run_id: String,
+    /// Machine the tests ran on
+    pub host: HostInfo,
+    /// Arbitrary key-value pairs describing the session (e.g. site=lab, link=10g)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Tags to find the session by later (e.g. nightly, v1.2.0)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Start time of the first step
+    pub start_time: DateTime<Utc>,
+    /// Time the last step completed
+    pub timestamp: DateTime<Utc>,
+    /// Version of speed-cli that generated this report
+    pub version: String,
+    /// Steps in the order they ran
+    pub steps: Vec<StepReport>,
+    /// Summary of the steps, if they were repeated runs of the same test
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub statistics: Vec<RepeatStatistic>,
+}
+
+/// Machine a suite of tests ran on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Operating system (e.g. linux, macos, windows)
+    pub os: String,
+    /// Kernel release, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_release: Option<String>,
+    /// CPU architecture (e.g. x86_64, aarch64)
+    pub arch: String,
+    /// Number of logical CPUs
+    pub cpus: usize,
+}
+
+impl HostInfo {
+    /// Describes the machine this process runs on
+    pub fn current() -> Self {
+        Self {
+            hostname: host::hostname(),
+            os: std::env::consts::OS.to_string(),
+            os_release: host::os_release(),
+            arch: std::env::consts::ARCH.to_string(),
+            cpus: num_cpus::get(),
+        }
+    }
+}
+
+impl Display for HostInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(hostname) = &self.hostname {
+            write!(f, "{hostname} (")?;
+        }
+        write!(f, "{}", self.os)?;
+        if let Some(release) = &self.os_release {
+            write!(f, " {release}")?;
+        }
+        write!(f, ", {}, {} CPUs", self.arch, self.cpus)?;
+        if self.hostname.is_some() {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns a random ID in the format of a version 4 UUID
+fn new_run_id() -> String {
+    let bits = rand::random::<u128>() & !(0xf << 76) & !(0x3 << 62) | (0x4 << 76) | (0x2 << 62);
+    let hex = format!("{bits:032x}");
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Contents of a results file: the report of a single test or of a suite of tests
+#[derive(Debug, Clone)]
+pub enum ReportFile {
+    Test(Box<TestReport>),
+    Suite(Box<SuiteReport>),
+}
+
+impl Display for ReportFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReportFile::Test(report) => report.fmt(f),
+            ReportFile::Suite(report) => report.fmt(f),
+        }
+    }
+}
+
+/// Outcome of a single step of a suite
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepReport {
+    pub name: String,
+    pub config: TestConfig,
+    /// Report of the test. Missing if the step failed to run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report: Option<TestReport>,
+    /// Error that stopped the step, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SuiteReport {
+    /// Creates the report of a session that ran on this machine and has just completed
+    pub fn new(
+        name: Option<String>,
+        labels: BTreeMap<String, String>,
+        tags: Vec<String>,
+        start_time: DateTime<Utc>,
+        steps: Vec<StepReport>,
+    ) -> Self {
+        Self {
+            name,
+            run_id: new_run_id(),
+            host: HostInfo::current(),
+            labels,
+            tags,
+            start_time,
+            timestamp: Utc::now(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            steps,
+            statistics: Vec::new(),
+        }
+    }
+
+    /// Steps that failed to run
+    pub fn failed_steps(&self) -> impl Iterator<Item = &StepReport> {
+        self.steps.iter().filter(|step| step.error.is_some())
+    }
+
+    /// Steps that ran but violated their thresholds
+    pub fn violating_steps(&self) -> impl Iterator<Item = &StepReport> {
+        self.steps.iter().filter(|step| !step.thresholds_passed())
+    }
+}
+
+impl StepReport {
+    /// Returns whether the step passed its thresholds. Steps without thresholds or
+    /// that failed to run have nothing to violate.
+    pub fn thresholds_passed(&self) -> bool {
+        self.report
+            .as_ref()
+            .and_then(|report| report.thresholds.as_ref())
+            .is_none_or(|thresholds| thresholds.passed())
+    }
+}
+
+impl Display for SuiteReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}",
+            "═══ Speed CLI Suite Report ═══".bright_cyan().bold()
+        )?;
+        if let Some(name) = &self.name {
+            writeln!(f, "{}: {}", "Suite".bright_white().bold(), name.green())?;
+        }
+        writeln!(
+            f,
+            "{}: {}",
+            "Run ID".bright_white().bold(),
+            self.run_id.green()
+        )?;
+        writeln!(
+            f,
+            "{}: {}",
+            "Host".bright_white().bold(),
+            self.host.to_string().green()
+        )?;
+        if !self.labels.is_empty() {
+            let labels = self
+                .labels
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(f, "{}: {}", "Labels".bright_white().bold(), labels.green())?;
+        }
+        if !self.tags.is_empty() {
+            writeln!(
+                f,
+                "{}: {}",
+                "Tags".bright_white().bold(),
+                self.tags.join(", ").green()
+            )?;
+        }
+        writeln!(
+            f,
+            "{}: {}",
+            "Version".bright_white().bold(),
+            self.version.green()
+        )?;
+        writeln!(
+            f,
+            "{}: {}",
+            "Start Time".bright_white().bold(),
+            self.start_time
+                .format("%Y-%m-%d %H:%M:%S UTC")
+                .to_string()
+                .yellow()
+        )?;
+        writeln!(
+            f,
+            "{}: {}",
+            "Report Time".bright_white().bold(),
+            self.timestamp
+                .format("%Y-%m-%d %H:%M:%S UTC")
+                .to_string()
+                .yellow()
+        )?;
+
+        for (index, step) in self.steps.iter().enumerate() {
+            writeln!(f)?;
+            writeln!(
+                f,
+                "{}",
+                format!("─── Step {}: {} ───", index + 1, step.name)
+                    .bright_cyan()
+                    .bold()
+            )?;
+            match (&step.report, &step.error) {
+                (Some(report), _) => write!(f, "{report}")?,
+                (None, error) => {
+                    writeln!(f, "{}", "Configuration:".bright_white().bold().underline())?;
+                    write!(f, "{}", step.config)?;
+                    writeln!(
+                        f,
+                        "{}: {}",
+                        "Error".red().bold(),
+                        error.as_deref().unwrap_or("unknown")
+                    )?;
+                }
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(f, "{}", "Summary:".bright_white().bold().underline())?;
+        for step in &self.steps {
+            let outcome = if step.error.is_some() {
+                "FAILED".red().bold()
+            } else if !step.thresholds_passed() {
+                "VIOLATED".yellow().bold()
+            } else {
+                "PASSED".green().bold()
+            };
+            writeln!(f, "  {outcome:<8} {}", step.name)?;
+        }
+
+        if !self.statistics.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "{}", "Statistics:".bright_white().bold().underline())?;
+            for statistic in &self.statistics {
+                writeln!(f, "  {statistic}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_id_format() {
+        let id = new_run_id();
+        let groups: Vec<_> = id.split('-').map(str::len).collect();
+        assert_eq!(groups, vec![8, 4, 4, 4, 12]);
+        assert_eq!(&id[14..15], "4");
+        assert!(matches!(&id[19..20], "8" | "9" | "a" | "b"));
+        assert_ne!(id, new_run_id());
+    }
+}
@@ -0,0 +1,490 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use colored::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::report::{LatencyResult, MetricUnit, TestResult, ThroughputResult};
+use crate::utils::format::format_bytes;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ThresholdParseError {
+    #[error("invalid number in threshold '{0}'")]
+    InvalidNumber(String),
+    #[error("unknown unit '{unit}' in threshold '{value}'")]
+    UnknownUnit { value: String, unit: String },
+}
+
+/// Splits a threshold such as `500Mbps` or `0.5 %` into its number and unit
+fn split_unit(value: &str) -> Result<(f64, &str), ThresholdParseError> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    // Signs are not part of the number, so negative thresholds are rejected here too
+    let number: f64 = number
+        .parse()
+        .map_err(|_| ThresholdParseError::InvalidNumber(value.to_string()))?;
+    Ok((number, unit.trim()))
+}
+
+/// Threshold values are given as strings with units in thresholds files and on the command
+/// line, but numbers in the base unit are accepted too.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawThreshold {
+    Number(f64),
+    Text(String),
+}
+
+/// Bit rate in bits per second, parsed from values such as `500Mbps`, `1.5 Gbit/s` or `100MB/s`
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(try_from = "RawThreshold", into = "f64")]
+pub struct Bitrate(pub f64);
+
+impl FromStr for Bitrate {
+    type Err = ThresholdParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, unit) = split_unit(s)?;
+
+        // A capital B means bytes, everything else is in bits
+        let (prefix, bytes) = if let Some(prefix) = unit
+            .strip_suffix("B/s")
+            .or_else(|| unit.strip_suffix("Bps"))
+        {
+            (prefix, true)
+        } else {
+            let lower = unit.to_ascii_lowercase();
+            let prefix_len = ["bit/s", "bps", "b/s"]
+                .iter()
+                .find_map(|suffix| lower.strip_suffix(suffix))
+                .unwrap_or(&lower)
+                .len();
+            (&unit[..prefix_len], false)
+        };
+
+        let multiplier = match prefix.to_ascii_lowercase().as_str() {
+            "" => 1.0,
+            "k" => 1e3,
+            "m" => 1e6,
+            "g" => 1e9,
+            "t" => 1e12,
+            _ => {
+                return Err(ThresholdParseError::UnknownUnit {
+                    value: s.to_string(),
+                    unit: unit.to_string(),
+                });
+            }
+        };
+
+        let bits = if bytes { 8.0 } else { 1.0 };
+        Ok(Bitrate(number * multiplier * bits))
+    }
+}
+
+/// Latency in milliseconds, parsed from values such as `20ms`, `1.5s` or `500us`
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(try_from = "RawThreshold", into = "f64")]
+pub struct Latency(pub f64);
+
+impl FromStr for Latency {
+    type Err = ThresholdParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, unit) = split_unit(s)?;
+        let multiplier = match unit {
+            "" | "ms" => 1.0,
+            "s" => 1e3,
+            "us" | "µs" => 1e-3,
+            _ => {
+                return Err(ThresholdParseError::UnknownUnit {
+                    value: s.to_string(),
+                    unit: unit.to_string(),
+                });
+            }
+        };
+        Ok(Latency(number * multiplier))
+    }
+}
+
+/// Percentage from 0 to 100, parsed from values such as `0.5%` or `0.5`
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(try_from = "RawThreshold", into = "f64")]
+pub struct Percent(pub f64);
+
+impl FromStr for Percent {
+    type Err = ThresholdParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match split_unit(s)? {
+            (number, "" | "%") if number <= 100.0 => Ok(Percent(number)),
+            (_, "" | "%") => Err(ThresholdParseError::InvalidNumber(s.to_string())),
+            (_, unit) => Err(ThresholdParseError::UnknownUnit {
+                value: s.to_string(),
+                unit: unit.to_string(),
+            }),
+        }
+    }
+}
+
+macro_rules! impl_threshold_conversions {
+    ($($ty:ident),*) => {$(
+        impl TryFrom<RawThreshold> for $ty {
+            type Error = ThresholdParseError;
+
+            fn try_from(raw: RawThreshold) -> Result<Self, Self::Error> {
+                match raw {
+                    RawThreshold::Number(number) => format!("{number}").parse(),
+                    RawThreshold::Text(text) => text.parse(),
+                }
+            }
+        }
+
+        impl From<$ty> for f64 {
+            fn from(value: $ty) -> Self {
+                value.0
+            }
+        }
+    )*};
+}
+impl_threshold_conversions!(Bitrate, Latency, Percent);
+
+/// Pass/fail limits that a test report is checked against
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Thresholds {
+    /// Minimum average download throughput of every payload size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_download: Option<Bitrate>,
+    /// Minimum average upload throughput of every payload size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_upload: Option<Bitrate>,
+    /// Maximum average round-trip time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_latency: Option<Latency>,
+    /// Maximum 99th percentile round-trip time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_p99_latency: Option<Latency>,
+    /// Maximum share of dropped latency probes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_loss: Option<Percent>,
+}
+
+impl Thresholds {
+    /// Returns true if no threshold is set
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns these thresholds with unset values taken from `other`
+    pub fn or(self, other: Thresholds) -> Thresholds {
+        Thresholds {
+            min_download: self.min_download.or(other.min_download),
+            min_upload: self.min_upload.or(other.min_upload),
+            max_latency: self.max_latency.or(other.max_latency),
+            max_p99_latency: self.max_p99_latency.or(other.max_p99_latency),
+            max_loss: self.max_loss.or(other.max_loss),
+        }
+    }
+
+    /// Checks the result of a test against every threshold that is set.
+    /// A threshold fails if the metric it refers to was not measured.
+    pub fn evaluate(&self, result: &TestResult) -> ThresholdEvaluation {
+        let mut checks = Vec::new();
+
+        match result {
+            TestResult::Simple(result) => {
+                if let Some(min) = self.min_download {
+                    checks.push(throughput_check("Throughput", result, min));
+                }
+            }
+            TestResult::Network(result) => {
+                for (direction, results, min) in [
+                    ("Download", &result.download, self.min_download),
+                    ("Upload", &result.upload, self.min_upload),
+                ] {
+                    let Some(min) = min else { continue };
+                    if results.is_empty() {
+                        checks.push(ThresholdCheck::new(
+                            direction,
+                            "Average Throughput",
+                            MetricUnit::BitsPerSecond,
+                            false,
+                            min.0,
+                            None,
+                        ));
+                    }
+                    for (size, result) in results {
+                        checks.push(throughput_check(
+                            &throughput_phase(direction, *size),
+                            result,
+                            min,
+                        ));
+                    }
+                }
+
+                self.check_latency(result.latency.as_ref(), &mut checks);
+            }
+        }
+
+        ThresholdEvaluation {
+            thresholds: self.clone(),
+            checks,
+        }
+    }
+
+    fn check_latency(&self, latency: Option<&LatencyResult>, checks: &mut Vec<ThresholdCheck>) {
+        let limits = [
+            (
+                "Average RTT",
+                self.max_latency.map(|l| l.0),
+                MetricUnit::Milliseconds,
+            ),
+            (
+                "P99 RTT",
+                self.max_p99_latency.map(|l| l.0),
+                MetricUnit::Milliseconds,
+            ),
+            (
+                "Packet Loss",
+                self.max_loss.map(|p| p.0),
+                MetricUnit::Percent,
+            ),
+        ];
+
+        for (metric, limit, unit) in limits {
+            let Some(limit) = limit else { continue };
+            let actual = latency.and_then(|latency| match metric {
+                "Average RTT" => latency.avg_rtt(),
+                "P99 RTT" => latency.percentile_rtt(99.0),
+                _ => (latency.count() > 0).then(|| latency.loss_percent()),
+            });
+            checks.push(ThresholdCheck::new(
+                "Latency", metric, unit, true, limit, actual,
+            ));
+        }
+    }
+}
+
+/// Name of a throughput phase as used in threshold checks, e.g. "Download (8 KiB)"
+pub fn throughput_phase(direction: &str, payload_size: usize) -> String {
+    format!("{direction} ({})", format_bytes(payload_size))
+}
+
+fn throughput_check(phase: &str, result: &ThroughputResult, min: Bitrate) -> ThresholdCheck {
+    ThresholdCheck::new(
+        phase,
+        "Average Throughput",
+        MetricUnit::BitsPerSecond,
+        false,
+        min.0,
+        Some(result.avg_throughput() * 8.0),
+    )
+}
+
+/// Outcome of checking a single metric against its threshold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThresholdCheck {
+    /// Phase of the test the metric belongs to (e.g. "Latency", "Download (8 KiB)")
+    pub phase: String,
+    pub metric: String,
+    pub unit: MetricUnit,
+    /// Whether `limit` is a maximum rather than a minimum
+    pub is_maximum: bool,
+    pub limit: f64,
+    /// Measured value, if the metric was measured at all
+    pub actual: Option<f64>,
+    pub passed: bool,
+}
+
+impl ThresholdCheck {
+    fn new(
+        phase: &str,
+        metric: &str,
+        unit: MetricUnit,
+        is_maximum: bool,
+        limit: f64,
+        actual: Option<f64>,
+    ) -> Self {
+        let passed = actual.is_some_and(|actual| {
+            if is_maximum {
+                actual <= limit
+            } else {
+                actual >= limit
+            }
+        });
+
+        Self {
+            phase: phase.to_string(),
+            metric: metric.to_string(),
+            unit,
+            is_maximum,
+            limit,
+            actual,
+            passed,
+        }
+    }
+
+    /// Describes the limit, e.g. "≥ 500 Mbit/s"
+    pub fn describe_limit(&self) -> String {
+        let op = if self.is_maximum { "≤" } else { "≥" };
+        format!("{op} {}", self.unit.format(self.limit))
+    }
+
+    /// Describes the measured value, or that the metric was not measured
+    pub fn describe_actual(&self) -> String {
+        self.actual.map_or("not measured".to_string(), |actual| {
+            self.unit.format(actual)
+        })
+    }
+}
+
+/// Thresholds a report was checked against and the outcome of each check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThresholdEvaluation {
+    pub thresholds: Thresholds,
+    pub checks: Vec<ThresholdCheck>,
+}
+
+impl ThresholdEvaluation {
+    /// Returns true if every check passed
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+
+    /// Returns the checks that failed
+    pub fn violations(&self) -> impl Iterator<Item = &ThresholdCheck> {
+        self.checks.iter().filter(|check| !check.passed)
+    }
+}
+
+impl Display for ThresholdCheck {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mark = if self.passed {
+            "✓".green().bold()
+        } else {
+            "✗".red().bold()
+        };
+        let actual = self.describe_actual();
+        write!(
+            f,
+            "{mark} {} {}: {} (limit {})",
+            self.phase.bright_blue(),
+            self.metric,
+            if self.passed {
+                actual.green()
+            } else {
+                actual.red()
+            },
+            self.describe_limit()
+        )
+    }
+}
+
+impl Display for ThresholdEvaluation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            writeln!(f, "  {check}")?;
+        }
+
+        let violations = self.violations().count();
+        if violations == 0 {
+            writeln!(
+                f,
+                "  {}",
+                format!("All {} thresholds passed", self.checks.len())
+                    .green()
+                    .bold()
+            )
+        } else {
+            writeln!(
+                f,
+                "  {}",
+                format!("{violations} of {} thresholds violated", self.checks.len())
+                    .red()
+                    .bold()
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{LatencyMeasurement, NetworkTestResult, ThroughputMeasurement};
+    use chrono::Utc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_thresholds() {
+        assert_eq!("500Mbps".parse::<Bitrate>().unwrap(), Bitrate(500e6));
+        assert_eq!("1.5 Gbit/s".parse::<Bitrate>().unwrap(), Bitrate(1.5e9));
+        assert_eq!("10MB/s".parse::<Bitrate>().unwrap(), Bitrate(80e6));
+        assert_eq!("1000".parse::<Bitrate>().unwrap(), Bitrate(1000.0));
+        assert!("5 furlongs".parse::<Bitrate>().is_err());
+
+        assert_eq!("20ms".parse::<Latency>().unwrap(), Latency(20.0));
+        assert_eq!("1.5s".parse::<Latency>().unwrap(), Latency(1500.0));
+        assert_eq!("0.5%".parse::<Percent>().unwrap(), Percent(0.5));
+        assert!("150%".parse::<Percent>().is_err());
+
+        let thresholds: Thresholds =
+            serde_json::from_str(r#"{"min_download": "1Gbps", "max_p99_latency": 20}"#).unwrap();
+        assert_eq!(thresholds.min_download, Some(Bitrate(1e9)));
+        assert_eq!(thresholds.max_p99_latency, Some(Latency(20.0)));
+        assert!(serde_json::from_str::<Thresholds>(r#"{"min_dowload": "1Gbps"}"#).is_err());
+    }
+
+    #[test]
+    fn test_evaluate_thresholds() {
+        let mut result = NetworkTestResult::new_tcp();
+        result.add_download(
+            1024,
+            ThroughputResult {
+                totals: [ThroughputMeasurement::new(
+                    1_000_000,
+                    Duration::from_secs(1),
+                )]
+                .iter()
+                .collect(),
+                total_duration: Duration::from_secs(1),
+                intervals: Vec::new(),
+                connection_intervals: Default::default(),
+                timestamp: Utc::now(),
+            },
+            None,
+        );
+        let probe = |rtt_ms| LatencyMeasurement {
+            rtt_ms,
+            elapsed_time: Duration::ZERO,
+        };
+        result.latency = Some(LatencyResult {
+            measurements: vec![probe(Some(10.0)), probe(None)],
+            timestamp: Utc::now(),
+        });
+
+        let thresholds = Thresholds {
+            min_download: Some(Bitrate(1e6)),
+            min_upload: Some(Bitrate(1e6)),
+            max_p99_latency: Some(Latency(20.0)),
+            max_loss: Some(Percent(10.0)),
+            ..Default::default()
+        };
+        let evaluation = thresholds.evaluate(&result.into());
+
+        let failed: Vec<_> = evaluation
+            .violations()
+            .map(|check| (check.phase.as_str(), check.metric.as_str()))
+            .collect();
+        assert_eq!(
+            failed,
+            vec![("Upload", "Average Throughput"), ("Latency", "Packet Loss")]
+        );
+        assert_eq!(evaluation.checks.len(), 4);
+        assert!(!evaluation.passed());
+    }
+}
@@ -2,13 +2,24 @@ use std::path::Path;
 use thiserror::Error;
 use tokio::io::{AsyncWriteExt, BufWriter};
 
-use crate::{renderer::ToHtml, report::TestReport};
+use crate::{
+    renderer::{
+        ToHtml, write_csv, write_junit, write_prometheus, write_suite_csv, write_suite_junit,
+        write_suite_prometheus, write_suite_tap, write_tap,
+    },
+    report::{ReportComparison, SuiteReport, TestReport},
+    utils::output::status,
+};
 
 #[derive(Debug, Error)]
 pub enum ExportError {
     IO(#[from] std::io::Error),
     Serde(#[from] serde_json::Error),
     Cbor(#[from] ciborium::ser::Error<std::io::Error>),
+    UnsupportedExtension {
+        extension: String,
+        supported: &'static [&'static str],
+    },
 }
 
 impl std::fmt::Display for ExportError {
@@ -17,17 +28,65 @@ impl std::fmt::Display for ExportError {
             ExportError::IO(e) => write!(f, "I/O error: {e}"),
             ExportError::Serde(e) => write!(f, "Serialization error: {e}"),
             ExportError::Cbor(e) => write!(f, "CBOR serialization error: {e}"),
+            ExportError::UnsupportedExtension {
+                extension,
+                supported,
+            } => {
+                let supported = supported
+                    .iter()
+                    .map(|ext| format!(".{ext}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(
+                    f,
+                    "Unsupported file extension \"{extension}\", expected one of {supported}"
+                )
+            }
         }
     }
 }
 
+/// Extensions a suite report can be exported to
+pub const SUITE_EXTENSIONS: &[&str] = &["json", "cbor", "html", "xml", "tap", "csv", "prom"];
+
+/// Extensions a comparison of reports can be exported to
+pub const COMPARISON_EXTENSIONS: &[&str] = &["json", "html"];
+
+impl ExportError {
+    fn unsupported_extension(filename: &Path, supported: &'static [&'static str]) -> Self {
+        ExportError::UnsupportedExtension {
+            extension: filename
+                .extension()
+                .map(|ext| ext.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            supported,
+        }
+    }
+}
+
+/// Fails if the file doesn't have one of the supported extensions, so that exports can be
+/// checked before running any test
+pub fn check_extension(
+    filename: &Path,
+    supported: &'static [&'static str],
+) -> Result<(), ExportError> {
+    match filename.extension() {
+        Some(ext) if supported.iter().any(|supported| ext == *supported) => Ok(()),
+        _ => Err(ExportError::unsupported_extension(filename, supported)),
+    }
+}
+
 pub async fn export_report(report: &TestReport, filename: &Path) -> Result<(), ExportError> {
     match filename.extension() {
         Some(ext) if ext == "html" => export_report_html(report, filename).await,
         Some(ext) if ext == "json" => export_report_json(report, filename).await,
         Some(ext) if ext == "cbor" => export_report_cbor(report, filename).await,
+        Some(ext) if ext == "xml" => export_report_junit(report, filename).await,
+        Some(ext) if ext == "tap" => export_report_tap(report, filename).await,
+        Some(ext) if ext == "csv" => export_report_csv(report, filename).await,
+        Some(ext) if ext == "prom" => export_report_prometheus(report, filename).await,
         _ => {
-            println!(
+            status!(
                 "No known extension detected in file path. Exporting to JSON format by default."
             );
 
@@ -72,3 +131,154 @@ pub async fn export_report_html(report: &TestReport, filename: &Path) -> Result<
 
     Ok(())
 }
+
+pub async fn export_report_junit(report: &TestReport, filename: &Path) -> Result<(), ExportError> {
+    let file = tokio::fs::File::create(filename).await?;
+    let mut writer = BufWriter::new(file);
+
+    let mut buffer = Vec::new();
+    write_junit(report, &mut buffer)?;
+    writer.write_all(&buffer).await?;
+
+    writer.flush().await?;
+    Ok(())
+}
+
+pub async fn export_report_tap(report: &TestReport, filename: &Path) -> Result<(), ExportError> {
+    let file = tokio::fs::File::create(filename).await?;
+    let mut writer = BufWriter::new(file);
+
+    let mut buffer = Vec::new();
+    write_tap(report, &mut buffer)?;
+    writer.write_all(&buffer).await?;
+
+    writer.flush().await?;
+    Ok(())
+}
+
+pub async fn export_report_csv(report: &TestReport, filename: &Path) -> Result<(), ExportError> {
+    let file = tokio::fs::File::create(filename).await?;
+    let mut writer = BufWriter::new(file);
+
+    let mut buffer = Vec::new();
+    write_csv(report, &mut buffer)?;
+    writer.write_all(&buffer).await?;
+
+    writer.flush().await?;
+    Ok(())
+}
+
+pub async fn export_report_prometheus(
+    report: &TestReport,
+    filename: &Path,
+) -> Result<(), ExportError> {
+    let file = tokio::fs::File::create(filename).await?;
+    let mut writer = BufWriter::new(file);
+
+    let mut buffer = Vec::new();
+    write_prometheus(report, &mut buffer)?;
+    writer.write_all(&buffer).await?;
+
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Exports a suite report as JSON, CBOR, HTML, JUnit XML, TAP, CSV or Prometheus depending on
+/// the extension of the file, failing for any other extension (see [`SUITE_EXTENSIONS`]).
+pub async fn export_suite_report(report: &SuiteReport, filename: &Path) -> Result<(), ExportError> {
+    let mut buffer = Vec::new();
+    match filename.extension() {
+        Some(ext) if ext == "json" => serde_json::to_writer_pretty(&mut buffer, report)?,
+        Some(ext) if ext == "html" => report.write_html(&mut buffer)?,
+        Some(ext) if ext == "cbor" => ciborium::into_writer(report, &mut buffer)?,
+        Some(ext) if ext == "xml" => write_suite_junit(report, &mut buffer)?,
+        Some(ext) if ext == "tap" => write_suite_tap(report, &mut buffer)?,
+        Some(ext) if ext == "csv" => write_suite_csv(report, &mut buffer)?,
+        Some(ext) if ext == "prom" => write_suite_prometheus(report, &mut buffer)?,
+        _ => {
+            return Err(ExportError::unsupported_extension(
+                filename,
+                SUITE_EXTENSIONS,
+            ));
+        }
+    }
+
+    let file = tokio::fs::File::create(filename).await?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(&buffer).await?;
+
+    writer.flush().await?;
+    Ok(())
+}
+
+pub async fn export_suite_report_html(
+    report: &SuiteReport,
+    filename: &Path,
+) -> Result<(), ExportError> {
+    let file = tokio::fs::File::create(filename).await?;
+    let mut writer = BufWriter::new(file);
+
+    let mut buffer = Vec::new();
+    report.write_html(&mut buffer)?;
+    writer.write_all(&buffer).await?;
+
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Exports a comparison of reports as JSON or HTML depending on the extension of the file,
+/// failing for any other extension (see [`COMPARISON_EXTENSIONS`]).
+pub async fn export_comparison(
+    comparison: &ReportComparison,
+    filename: &Path,
+) -> Result<(), ExportError> {
+    let buffer = match filename.extension() {
+        Some(ext) if ext == "json" => serde_json::to_vec_pretty(comparison)?,
+        Some(ext) if ext == "html" => comparison.to_html().into_bytes(),
+        _ => {
+            return Err(ExportError::unsupported_extension(
+                filename,
+                COMPARISON_EXTENSIONS,
+            ));
+        }
+    };
+
+    let file = tokio::fs::File::create(filename).await?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(&buffer).await?;
+
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_extension() {
+        assert!(check_extension(Path::new("suite.json"), SUITE_EXTENSIONS).is_ok());
+        assert!(check_extension(Path::new("out/suite.csv"), SUITE_EXTENSIONS).is_ok());
+
+        let error = check_extension(Path::new("suite.txt"), SUITE_EXTENSIONS).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unsupported file extension \"txt\", expected one of .json, .cbor, .html, .xml, .tap, .csv, .prom"
+        );
+        assert!(check_extension(Path::new("comparison"), COMPARISON_EXTENSIONS).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_export_comparison_rejects_unsupported_extension() {
+        let filename = std::env::temp_dir().join("speed-cli-comparison-test.tap");
+        let comparison = ReportComparison {
+            reports: Vec::new(),
+            sections: Vec::new(),
+        };
+        assert!(matches!(
+            export_comparison(&comparison, &filename).await,
+            Err(ExportError::UnsupportedExtension { .. })
+        ));
+        assert!(!filename.exists());
+    }
+}
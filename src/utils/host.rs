@@ -0,0 +1,39 @@
+/// Name and kernel release of the machine, as reported by `uname`
+#[cfg(unix)]
+fn uname() -> Option<(String, String)> {
+    use std::ffi::CStr;
+
+    // SAFETY: `utsname` is plain old data and `uname` fills it with NUL-terminated strings
+    let mut name: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut name) } != 0 {
+        return None;
+    }
+
+    let field = |field: &[libc::c_char]| {
+        unsafe { CStr::from_ptr(field.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    Some((field(&name.nodename), field(&name.release)))
+}
+
+#[cfg(not(unix))]
+fn uname() -> Option<(String, String)> {
+    None
+}
+
+/// Returns the hostname of the machine, if it can be determined
+pub fn hostname() -> Option<String> {
+    uname()
+        .map(|(hostname, _)| hostname)
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .filter(|hostname| !hostname.is_empty())
+}
+
+/// Returns the kernel release of the machine (e.g. "6.8.0-45-generic"), if known
+pub fn os_release() -> Option<String> {
+    uname()
+        .map(|(_, release)| release)
+        .filter(|release| !release.is_empty())
+}
@@ -1,6 +1,7 @@
 use std::path::Path;
 
-use crate::report::TestReport;
+use crate::plan::TestPlan;
+use crate::report::{ReportFile, SuiteReport, Thresholds};
 
 use thiserror::Error;
 #[derive(Debug, Error)]
@@ -11,16 +12,54 @@ pub enum ImportError {
     Serde(#[from] serde_json::Error),
     #[error("CBOR parsing error: {0}")]
     Cbor(#[from] ciborium::de::Error<std::io::Error>),
+    #[error("TOML parsing error: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("YAML parsing error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
 }
 
-/// Attempts to parse a file as JSON.
-pub async fn import_report_json(filename: &Path) -> Result<TestReport, ImportError> {
+/// Attempts to parse a file as a JSON test or suite report.
+pub async fn import_report_json(filename: &Path) -> Result<ReportFile, ImportError> {
     let content = tokio::fs::read_to_string(filename).await?;
-    Ok(serde_json::from_str(&content)?)
+    // Suite reports have required fields test reports lack, so they can't be confused
+    if let Ok(report) = serde_json::from_str::<SuiteReport>(&content) {
+        return Ok(ReportFile::Suite(Box::new(report)));
+    }
+    Ok(ReportFile::Test(Box::new(serde_json::from_str(&content)?)))
 }
 
-/// Attempts to parse a file as CBOR.
-pub async fn import_report_cbor(filename: &Path) -> Result<TestReport, ImportError> {
+/// Attempts to parse a file as a CBOR test or suite report.
+pub async fn import_report_cbor(filename: &Path) -> Result<ReportFile, ImportError> {
     let content = tokio::fs::read(filename).await?;
-    Ok(ciborium::from_reader(&content[..])?)
+    if let Ok(report) = ciborium::from_reader::<SuiteReport, _>(&content[..]) {
+        return Ok(ReportFile::Suite(Box::new(report)));
+    }
+    Ok(ReportFile::Test(Box::new(ciborium::from_reader(
+        &content[..],
+    )?)))
+}
+
+/// Parses a test or suite report as CBOR if it has a `.cbor` extension, and as JSON otherwise.
+pub async fn import_report(filename: &Path) -> Result<ReportFile, ImportError> {
+    match filename.extension().and_then(|ext| ext.to_str()) {
+        Some("cbor") => import_report_cbor(filename).await,
+        _ => import_report_json(filename).await,
+    }
+}
+
+/// Parses a thresholds file as JSON.
+pub async fn import_thresholds(filename: &Path) -> Result<Thresholds, ImportError> {
+    let content = tokio::fs::read_to_string(filename).await?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Parses a test plan as YAML if it has a `.yaml` or `.yml` extension, as JSON if it has a
+/// `.json` extension, and as TOML otherwise.
+pub async fn import_plan(filename: &Path) -> Result<TestPlan, ImportError> {
+    let content = tokio::fs::read_to_string(filename).await?;
+    match filename.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml" | "yml") => Ok(serde_yaml::from_str(&content)?),
+        Some("json") => Ok(serde_json::from_str(&content)?),
+        _ => Ok(toml::from_str(&content)?),
+    }
 }
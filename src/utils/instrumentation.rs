@@ -1,11 +1,20 @@
 use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
 
-use crate::report::{LatencyMeasurement, ThroughputMeasurement};
+use crate::OutputMode;
+use crate::report::{
+    LatencyMeasurement, ThroughputInterval, ThroughputMeasurement, ThroughputTotals,
+};
 use crate::utils::format::{format_bytes, format_throughput};
+use crate::utils::interval_table::{IntervalReporter, IntervalTable, TestIntervals};
+use crate::utils::output::{IntervalStream, StreamEvent, TestPhase, emit, output_mode};
+
+/// How long to wait after the end of an interval for its last events before reporting it
+const INTERVAL_REPORT_DELAY: Duration = Duration::from_millis(100);
 
 /// Test type for progress bar styling
 #[derive(Clone, Copy)]
@@ -15,8 +24,13 @@ pub enum ProgressBarType {
     Upload,
 }
 
-/// Creates a progress bar with appropriate styling for the test type
+/// Creates a progress bar with appropriate styling for the test type.
+/// The progress bar is hidden unless progress bars are the selected output mode.
 pub fn create_progress_bar(test_type: ProgressBarType, duration: Duration) -> ProgressBar {
+    if output_mode() != OutputMode::Progress {
+        return ProgressBar::hidden();
+    }
+
     let progress_bar = ProgressBar::new(duration.as_secs());
 
     let (color, test_name) = match test_type {
@@ -54,6 +68,7 @@ impl ToTitleCase for str {
 /// Statistics collector for latency measurements
 pub struct LatencyStatsCollector {
     pub measurements: Arc<std::sync::Mutex<Vec<LatencyMeasurement>>>,
+    pub start_time: Instant,
     pub collector_handle: JoinHandle<()>,
     pub progress_handle: JoinHandle<()>,
 }
@@ -72,6 +87,9 @@ impl LatencyStatsCollector {
         // Collector task
         let collector_handle = tokio::spawn(async move {
             while let Some(measurement) = rx.recv().await {
+                emit(&StreamEvent::Latency {
+                    measurement: &measurement,
+                });
                 if let Ok(mut measurements) = measurements_clone.lock() {
                     measurements.push(measurement);
                 }
@@ -117,6 +135,7 @@ impl LatencyStatsCollector {
         (
             Self {
                 measurements,
+                start_time,
                 collector_handle,
                 progress_handle,
             },
@@ -133,70 +152,153 @@ impl LatencyStatsCollector {
         progress_bar.finish_with_message(message);
 
         // Extract measurements with minimal lock time
+        let measurements = self.measurements.lock().unwrap().clone();
+
+        emit(&StreamEvent::PhaseComplete {
+            phase: TestPhase::Latency,
+            duration: self.start_time.elapsed(),
+            measurements: measurements.len(),
+            bytes: None,
+        });
+
+        measurements
+    }
+}
+
+/// Events reported to the throughput statistics collector by the test tasks.
+/// `connection` identifies the parallel connection (or stream) the event belongs to.
+#[derive(Debug, Clone)]
+/// `offset` is the time since the start of the test the event happened at, so that events are
+/// bucketed by when they happened rather than by when the collector got to them.
+pub enum ThroughputEvent {
+    /// A completed (or failed) read or write
+    Measurement {
+        connection: usize,
+        offset: Duration,
+        measurement: ThroughputMeasurement,
+    },
+    /// Number of retransmissions observed since the last report
+    Retransmits {
+        connection: usize,
+        offset: Duration,
+        count: u64,
+    },
+}
 
-        self.measurements.lock().unwrap().clone()
+/// Measurements of single-stream tests belong to the first connection
+impl From<(Duration, ThroughputMeasurement)> for ThroughputEvent {
+    fn from((offset, measurement): (Duration, ThroughputMeasurement)) -> Self {
+        ThroughputEvent::Measurement {
+            connection: 0,
+            offset,
+            measurement,
+        }
     }
 }
 
 /// Statistics collector for throughput measurements
 pub struct ThroughputStatsCollector {
-    pub measurements: Arc<std::sync::Mutex<Vec<ThroughputMeasurement>>>,
+    pub totals: Arc<std::sync::Mutex<ThroughputTotals>>,
+    pub intervals: Arc<std::sync::Mutex<TestIntervals>>,
+    pub reporter: Option<Arc<std::sync::Mutex<Box<dyn IntervalReporter + Send>>>>,
+    pub phase: TestPhase,
+    pub start_time: Instant,
     pub collector_handle: JoinHandle<()>,
     pub progress_handle: JoinHandle<()>,
 }
 
 impl ThroughputStatsCollector {
+    /// Creates a collector that also buckets measurements into `interval`-wide intervals
+    /// relative to `start_time`. Unless progress bars are shown, the intervals are reported
+    /// as they complete in the selected output mode instead.
     pub fn new(
         progress_bar: ProgressBar,
+        phase: TestPhase,
         start_time: Instant,
         duration: Duration,
-    ) -> (Self, mpsc::UnboundedSender<ThroughputMeasurement>) {
-        let (tx, mut rx) = mpsc::unbounded_channel::<ThroughputMeasurement>();
+        interval: Duration,
+    ) -> (Self, mpsc::UnboundedSender<ThroughputEvent>) {
+        let (tx, mut rx) = mpsc::unbounded_channel::<ThroughputEvent>();
 
-        let measurements = Arc::new(std::sync::Mutex::new(Vec::<ThroughputMeasurement>::new()));
-        let measurements_clone = measurements.clone();
+        let totals = Arc::new(std::sync::Mutex::new(ThroughputTotals::default()));
+        let totals_clone = totals.clone();
+        let intervals = Arc::new(std::sync::Mutex::new(TestIntervals::new(interval)));
+        let intervals_clone = intervals.clone();
+
+        let reporter: Option<Box<dyn IntervalReporter + Send>> = match output_mode() {
+            OutputMode::Progress => None,
+            OutputMode::Intervals => Some(Box::new(IntervalTable::new())),
+            OutputMode::JsonStream => Some(Box::new(IntervalStream::new(phase))),
+        };
+        let reporter = reporter.map(|reporter| Arc::new(std::sync::Mutex::new(reporter)));
 
         // Collector task
         let collector_handle = tokio::spawn(async move {
-            while let Some(measurement) = rx.recv().await {
-                if let Ok(mut measurements) = measurements_clone.lock() {
-                    measurements.push(measurement);
+            while let Some(event) = rx.recv().await {
+                match event {
+                    ThroughputEvent::Measurement {
+                        connection,
+                        offset,
+                        measurement,
+                    } => {
+                        if let Ok(mut intervals) = intervals_clone.lock() {
+                            intervals.record(connection, offset, &measurement);
+                        }
+                        if let Ok(mut totals) = totals_clone.lock() {
+                            totals.record(&measurement);
+                        }
+                    }
+                    ThroughputEvent::Retransmits {
+                        connection,
+                        offset,
+                        count,
+                    } => {
+                        if let Ok(mut intervals) = intervals_clone.lock() {
+                            intervals.record_retransmits(connection, offset, count);
+                        }
+                    }
                 }
             }
         });
 
         // Progress update task
-        let progress_handle = {
+        let progress_handle = if let Some(reporter) = reporter.clone() {
+            let intervals = intervals.clone();
+            tokio::spawn(async move {
+                while start_time.elapsed() < duration {
+                    let elapsed = start_time.elapsed().saturating_sub(INTERVAL_REPORT_DELAY);
+                    if let (Ok(mut reporter), Ok(intervals)) = (reporter.lock(), intervals.lock()) {
+                        reporter.report_completed(&intervals, elapsed);
+                    }
+
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            })
+        } else {
             let pb = progress_bar.clone();
-            let measurements_for_stats = measurements.clone();
+            let totals_for_stats = totals.clone();
             tokio::spawn(async move {
                 while start_time.elapsed() < duration {
                     let elapsed = start_time.elapsed().as_secs();
                     pb.set_position(elapsed);
 
                     // Calculate and display running average throughput (minimal overhead)
-                    if let Ok(measurements) = measurements_for_stats.try_lock()
-                        && !measurements.is_empty()
+                    if let Ok(totals) = totals_for_stats.try_lock()
+                        && totals.count() > 0
                     {
-                        let total_bytes: u64 = measurements
-                            .iter()
-                            .map(|m| match m {
-                                ThroughputMeasurement::Success { bytes, .. } => *bytes,
-                                ThroughputMeasurement::Failure { .. } => 0,
-                            })
-                            .sum();
+                        let total_bytes = totals.bytes;
                         let elapsed_secs = start_time.elapsed().as_secs_f64();
                         let throughput_mbps =
                             (total_bytes as f64 * 8.0) / (elapsed_secs * 1_000_000.0);
                         let throughput_bytes_per_sec = total_bytes as f64 / elapsed_secs;
-                        let requests_per_sec = measurements.len() as f64 / elapsed_secs;
+                        let requests_per_sec = totals.count() as f64 / elapsed_secs;
 
                         pb.set_message(format!(
                             "Avg: {} | {} | Req/s: {:.1} | Chunks: {}",
                             format_throughput(throughput_mbps),
                             format_bytes(throughput_bytes_per_sec as usize),
                             requests_per_sec,
-                            measurements.len()
+                            totals.count()
                         ));
                     }
 
@@ -209,7 +311,11 @@ impl ThroughputStatsCollector {
 
         (
             Self {
-                measurements,
+                totals,
+                intervals,
+                reporter,
+                phase,
+                start_time,
                 collector_handle,
                 progress_handle,
             },
@@ -217,16 +323,90 @@ impl ThroughputStatsCollector {
         )
     }
 
+    /// Waits for all events to be processed and returns the totals of the measurements along
+    /// with the per-interval series of the whole test and of each connection.
     pub async fn finish(
         self,
         progress_bar: ProgressBar,
         message: String,
-    ) -> Vec<ThroughputMeasurement> {
+    ) -> (
+        ThroughputTotals,
+        Vec<ThroughputInterval>,
+        BTreeMap<usize, Vec<ThroughputInterval>>,
+    ) {
         let _ = tokio::join!(self.collector_handle, self.progress_handle);
         progress_bar.finish_with_message(message);
 
-        // Extract measurements with minimal lock time
+        let total_duration = self.start_time.elapsed();
+        let intervals = self.intervals.lock().unwrap();
+        if let Some(reporter) = &self.reporter {
+            reporter
+                .lock()
+                .unwrap()
+                .report_final(&intervals, total_duration);
+        }
+        let connection_intervals = intervals.finish_connections(total_duration);
+        let intervals = intervals.finish(total_duration);
+
+        let totals = self.totals.lock().unwrap().clone();
+
+        emit(&StreamEvent::PhaseComplete {
+            phase: self.phase,
+            duration: total_duration,
+            measurements: totals.count() as usize,
+            bytes: Some(totals.bytes),
+        });
+
+        (totals, intervals, connection_intervals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_events_bucketed_by_offset() {
+        // Start the test in the past so that every event arrives well after it happened
+        let start_time = Instant::now() - Duration::from_secs(2);
+        let (collector, tx) = ThroughputStatsCollector::new(
+            ProgressBar::hidden(),
+            TestPhase::Download { payload_size: 1024 },
+            start_time,
+            Duration::ZERO,
+            Duration::from_secs(1),
+        );
+
+        let events = [
+            (0, Duration::from_millis(100), 1000),
+            (1, Duration::from_millis(900), 500),
+            (0, Duration::from_millis(1500), 2000),
+        ];
+        for (connection, offset, bytes) in events {
+            let measurement = ThroughputMeasurement::new(bytes, Duration::from_millis(1));
+            tx.send(ThroughputEvent::Measurement {
+                connection,
+                offset,
+                measurement,
+            })
+            .unwrap();
+        }
+        tx.send(ThroughputEvent::Retransmits {
+            connection: 0,
+            offset: Duration::from_millis(1200),
+            count: 3,
+        })
+        .unwrap();
+        drop(tx);
+
+        let (totals, intervals, connection_intervals) =
+            collector.finish(ProgressBar::hidden(), String::new()).await;
 
-        self.measurements.lock().unwrap().clone()
+        assert_eq!(totals.bytes, 3500);
+        assert_eq!(intervals[0].bytes, 1500);
+        assert_eq!(intervals[1].bytes, 2000);
+        assert_eq!(intervals[1].retransmits, 3);
+        assert_eq!(connection_intervals[&0][0].bytes, 1000);
+        assert_eq!(connection_intervals[&1][0].bytes, 500);
     }
 }
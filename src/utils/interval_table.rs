@@ -0,0 +1,292 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::time::Duration;
+
+use humansize::{BINARY, BaseUnit, DECIMAL, format_size};
+
+use crate::report::{IntervalSeries, ThroughputInterval, ThroughputMeasurement};
+
+const SEPARATOR: &str = "- - - - - - - - - - - - - - - - - - - - - - - - -";
+
+/// Interval series and totals of a single connection
+#[derive(Debug, Clone)]
+struct ConnectionIntervals {
+    series: IntervalSeries,
+    bytes: u64,
+    retransmits: u64,
+}
+
+/// Per-interval series for the whole test and for each of its connections
+#[derive(Debug, Clone)]
+pub struct TestIntervals {
+    total: IntervalSeries,
+    connections: BTreeMap<usize, ConnectionIntervals>,
+}
+
+impl TestIntervals {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            total: IntervalSeries::new(interval),
+            connections: BTreeMap::new(),
+        }
+    }
+
+    fn connection(&mut self, connection: usize) -> &mut ConnectionIntervals {
+        let interval = self.total.interval();
+        self.connections
+            .entry(connection)
+            .or_insert_with(|| ConnectionIntervals {
+                series: IntervalSeries::new(interval),
+                bytes: 0,
+                retransmits: 0,
+            })
+    }
+
+    /// Records a measurement of `connection` completed at `offset` since the start of the test
+    pub fn record(
+        &mut self,
+        connection: usize,
+        offset: Duration,
+        measurement: &ThroughputMeasurement,
+    ) {
+        self.total.record(offset, measurement);
+
+        let entry = self.connection(connection);
+        entry.series.record(offset, measurement);
+        if let ThroughputMeasurement::Success { bytes, .. } = measurement {
+            entry.bytes += bytes;
+        }
+    }
+
+    /// Records retransmissions of `connection` observed at `offset` since the start of the test
+    pub fn record_retransmits(&mut self, connection: usize, offset: Duration, count: u64) {
+        self.total.record_retransmits(offset, count);
+
+        let entry = self.connection(connection);
+        entry.series.record_retransmits(offset, count);
+        entry.retransmits += count;
+    }
+
+    /// Returns the width of each interval
+    pub fn interval(&self) -> Duration {
+        self.total.interval()
+    }
+
+    /// Completes the series of the whole test, see [`IntervalSeries::finish`]
+    pub fn finish(&self, total_duration: Duration) -> Vec<ThroughputInterval> {
+        self.total.clone().finish(total_duration)
+    }
+
+    /// Completes the series of each connection, see [`IntervalSeries::finish`]
+    pub fn finish_connections(
+        &self,
+        total_duration: Duration,
+    ) -> BTreeMap<usize, Vec<ThroughputInterval>> {
+        self.connections
+            .iter()
+            .map(|(connection, entry)| (*connection, entry.series.clone().finish(total_duration)))
+            .collect()
+    }
+}
+
+/// Reports the intervals of a throughput test while it is running
+pub trait IntervalReporter {
+    /// Reports every interval that ended at or before `elapsed`
+    fn report_completed(&mut self, intervals: &TestIntervals, elapsed: Duration);
+
+    /// Reports the remaining intervals of a test that ran for `total_duration`
+    fn report_final(&mut self, intervals: &TestIntervals, total_duration: Duration);
+}
+
+/// Prints an iperf-style table with one row per interval and connection, plus a SUM row
+/// when the test uses several connections.
+#[derive(Debug, Default)]
+pub struct IntervalTable {
+    /// Number of intervals already printed
+    printed: usize,
+}
+
+impl IntervalReporter for IntervalTable {
+    fn report_completed(&mut self, intervals: &TestIntervals, elapsed: Duration) {
+        self.print_completed(intervals, elapsed);
+    }
+
+    fn report_final(&mut self, intervals: &TestIntervals, total_duration: Duration) {
+        self.print_final(intervals, total_duration);
+    }
+}
+
+impl IntervalTable {
+    /// Creates the table and prints its header
+    pub fn new() -> Self {
+        let table = Self::default();
+        table.print_header();
+        table
+    }
+
+    pub fn print_header(&self) {
+        println!(
+            "[ ID] {:<17} {:>12} {:>16} {:>6}",
+            "Interval", "Transfer", "Bitrate", "Retr"
+        );
+    }
+
+    /// Prints every interval that ended at or before `elapsed`
+    pub fn print_completed(&mut self, intervals: &TestIntervals, elapsed: Duration) {
+        let width = intervals.total.interval();
+        if width.is_zero() {
+            return;
+        }
+
+        while width * (self.printed as u32 + 1) <= elapsed {
+            self.print_interval(intervals, self.printed..self.printed + 1, elapsed);
+            self.printed += 1;
+        }
+    }
+
+    /// Prints the remaining intervals of a test that ran for `total_duration`, including
+    /// the last partial one, followed by the totals of each connection. A very short last
+    /// interval is merged into the one before it like in [`IntervalSeries::finish`].
+    pub fn print_final(&mut self, intervals: &TestIntervals, total_duration: Duration) {
+        let width = intervals.total.interval();
+        if !width.is_zero() {
+            let count = total_duration.as_nanos().div_ceil(width.as_nanos()) as usize;
+            let merged = intervals.total.merges_final_interval(total_duration);
+            let reported = if merged { count - 1 } else { count };
+            while self.printed < reported {
+                let end = if self.printed + 1 == reported {
+                    count
+                } else {
+                    self.printed + 1
+                };
+                self.print_interval(intervals, self.printed..end, total_duration);
+                self.printed += 1;
+            }
+        }
+
+        // Tests with several connections already end each interval with a separator
+        if self.printed == 0 || intervals.connections.len() <= 1 {
+            println!("{SEPARATOR}");
+        }
+        self.print_header();
+        for (connection, entry) in &intervals.connections {
+            println!(
+                "{}",
+                format_row(
+                    &connection_id(*connection),
+                    Duration::ZERO,
+                    total_duration,
+                    entry.bytes,
+                    entry.retransmits
+                )
+            );
+        }
+        if intervals.connections.len() > 1 {
+            let (bytes, retransmits) =
+                intervals
+                    .connections
+                    .values()
+                    .fold((0, 0), |(bytes, retransmits), entry| {
+                        (bytes + entry.bytes, retransmits + entry.retransmits)
+                    });
+            println!(
+                "{}",
+                format_row("SUM", Duration::ZERO, total_duration, bytes, retransmits)
+            );
+        }
+    }
+
+    /// Prints the rows of the intervals in `indices` combined, truncated to `limit`
+    fn print_interval(&self, intervals: &TestIntervals, indices: Range<usize>, limit: Duration) {
+        let width = intervals.total.interval();
+        let start = width * indices.start as u32;
+        let end = (width * indices.end as u32).min(limit);
+
+        let totals = |series: &IntervalSeries| {
+            indices.clone().filter_map(|index| series.get(index)).fold(
+                (0, 0),
+                |(bytes, retransmits), interval| {
+                    (bytes + interval.bytes, retransmits + interval.retransmits)
+                },
+            )
+        };
+
+        for (connection, entry) in &intervals.connections {
+            let (bytes, retransmits) = totals(&entry.series);
+            println!(
+                "{}",
+                format_row(&connection_id(*connection), start, end, bytes, retransmits)
+            );
+        }
+        if intervals.connections.len() > 1 {
+            let (bytes, retransmits) = totals(&intervals.total);
+            println!("{}", format_row("SUM", start, end, bytes, retransmits));
+            println!("{SEPARATOR}");
+        }
+    }
+}
+
+/// Connections are numbered from 1 in the table
+fn connection_id(connection: usize) -> String {
+    (connection + 1).to_string()
+}
+
+fn format_row(id: &str, start: Duration, end: Duration, bytes: u64, retransmits: u64) -> String {
+    let secs = end.saturating_sub(start).as_secs_f64();
+    let bitrate = if secs > 0.0 {
+        bytes as f64 * 8.0 / secs
+    } else {
+        0.0
+    };
+
+    format!(
+        "[{:>3}] {:>6.2}-{:<6.2} sec {:>12} {:>16} {:>6}",
+        id,
+        start.as_secs_f64(),
+        end.as_secs_f64(),
+        format_size(bytes, BINARY),
+        format_size(
+            bitrate as u64,
+            DECIMAL.base_unit(BaseUnit::Bit).suffix("/s")
+        ),
+        retransmits
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_row() {
+        let row = format_row(
+            "1",
+            Duration::from_secs(1),
+            Duration::from_secs(2),
+            1024 * 1024,
+            3,
+        );
+        assert_eq!(
+            row,
+            "[  1]   1.00-2.00   sec        1 MiB      8.39 Mbit/s      3"
+        );
+    }
+
+    #[test]
+    fn test_intervals_per_connection() {
+        let mut intervals = TestIntervals::new(Duration::from_secs(1));
+        let measurement = ThroughputMeasurement::new(100, Duration::ZERO);
+        intervals.record(0, Duration::from_millis(100), &measurement);
+        intervals.record(1, Duration::from_millis(1100), &measurement);
+        intervals.record_retransmits(1, Duration::from_millis(1200), 2);
+
+        assert_eq!(intervals.connections[&0].bytes, 100);
+        assert_eq!(intervals.connections[&1].retransmits, 2);
+        assert_eq!(intervals.connections[&1].series.get(0).unwrap().bytes, 0);
+
+        let total = intervals.finish(Duration::from_secs(2));
+        assert_eq!(total.len(), 2);
+        assert_eq!(total[1].bytes, 100);
+        assert_eq!(total[1].retransmits, 2);
+    }
+}
@@ -1,8 +1,13 @@
 pub mod export;
 pub mod file;
 pub mod format;
+pub mod host;
 pub mod import;
 pub mod instrumentation;
+pub mod interval_table;
+pub mod output;
 pub mod progress;
+pub mod prometheus;
+pub mod socket;
 pub mod tls;
 pub mod types;
@@ -0,0 +1,168 @@
+use std::io::Write as _;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::OutputMode;
+use crate::report::{LatencyMeasurement, SuiteReport, TestConfig, TestReport, ThroughputInterval};
+use crate::utils::interval_table::{IntervalReporter, TestIntervals};
+
+static OUTPUT_MODE: OnceLock<OutputMode> = OnceLock::new();
+
+/// Sets the output mode for the rest of the process. Only the first call has an effect.
+pub fn set_output_mode(mode: OutputMode) {
+    let _ = OUTPUT_MODE.set(mode);
+}
+
+/// Returns the output mode selected on the command line
+pub fn output_mode() -> OutputMode {
+    OUTPUT_MODE.get().copied().unwrap_or_default()
+}
+
+/// Returns true if stdout is reserved for the NDJSON event stream
+pub fn is_json_stream() -> bool {
+    output_mode() == OutputMode::JsonStream
+}
+
+/// Prints human-readable status output to stdout, unless stdout carries the JSON event stream
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !$crate::utils::output::is_json_stream() {
+            println!($($arg)*);
+        }
+    };
+}
+pub(crate) use status;
+
+/// Phase of a test that events refer to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TestPhase {
+    Latency,
+    Download { payload_size: usize },
+    Upload { payload_size: usize },
+}
+
+/// Events written to stdout, one JSON object per line, in the JSON stream output mode
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum StreamEvent<'a> {
+    /// The client is about to start testing against the server
+    TestStarted {
+        start_time: DateTime<Utc>,
+        config: &'a TestConfig,
+    },
+    /// An interval of a throughput phase has completed
+    Interval {
+        phase: TestPhase,
+        #[serde(flatten)]
+        interval: &'a ThroughputInterval,
+    },
+    /// A single latency probe has completed (or was dropped)
+    Latency {
+        #[serde(flatten)]
+        measurement: &'a LatencyMeasurement,
+    },
+    /// A phase of the test has completed
+    PhaseComplete {
+        phase: TestPhase,
+        duration: Duration,
+        /// Number of raw measurements taken during the phase
+        measurements: usize,
+        /// Bytes transferred, for throughput phases
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bytes: Option<u64>,
+    },
+    /// The complete report of the test, or of a step of a test plan
+    Report { report: &'a TestReport },
+    /// The aggregate report of all steps of a test plan
+    SuiteReport { report: &'a SuiteReport },
+}
+
+/// Writes `event` as a single line to stdout if the JSON stream output mode is selected
+pub fn emit(event: &StreamEvent) {
+    if !is_json_stream() {
+        return;
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    match serde_json::to_writer(&mut stdout, event) {
+        Ok(()) => {
+            let _ = writeln!(stdout);
+            let _ = stdout.flush();
+        }
+        Err(e) => eprintln!("Error serializing event: {e}"),
+    }
+}
+
+/// Emits the intervals of a throughput phase as events once they complete
+pub struct IntervalStream {
+    phase: TestPhase,
+    /// Number of intervals already emitted
+    emitted: usize,
+}
+
+impl IntervalStream {
+    pub fn new(phase: TestPhase) -> Self {
+        Self { phase, emitted: 0 }
+    }
+
+    fn emit_new(&mut self, intervals: &[ThroughputInterval]) {
+        for interval in intervals.iter().skip(self.emitted) {
+            emit(&StreamEvent::Interval {
+                phase: self.phase,
+                interval,
+            });
+            self.emitted += 1;
+        }
+    }
+}
+
+impl IntervalReporter for IntervalStream {
+    fn report_completed(&mut self, intervals: &TestIntervals, elapsed: Duration) {
+        let width = intervals.interval();
+        if width.is_zero() {
+            return;
+        }
+
+        let completed = (elapsed.as_nanos() / width.as_nanos()) as usize;
+        if completed > self.emitted {
+            let finished = intervals.finish(width * completed as u32);
+            self.emit_new(&finished[..completed.min(finished.len())]);
+        }
+    }
+
+    fn report_final(&mut self, intervals: &TestIntervals, total_duration: Duration) {
+        self.emit_new(&intervals.finish(total_duration));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interval_event_is_flat() {
+        let interval = ThroughputInterval {
+            start: Duration::ZERO,
+            end: Duration::from_secs(1),
+            bytes: 1000,
+            throughput: 1000.0,
+            errors: 0,
+            retransmits: 2,
+        };
+        let event = StreamEvent::Interval {
+            phase: TestPhase::Upload { payload_size: 1024 },
+            interval: &interval,
+        };
+
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(value["event"], "interval");
+        assert_eq!(value["phase"]["kind"], "upload");
+        assert_eq!(value["phase"]["payload_size"], 1024);
+        assert_eq!(value["bytes"], 1000);
+        assert_eq!(value["retransmits"], 2);
+    }
+}
@@ -3,12 +3,19 @@ use std::future::Future;
 use std::time::Duration;
 use tokio::time::{Instant, interval};
 
+use crate::utils::output::is_json_stream;
+
 /// A wrapper that shows a count up indicator around an async function
-/// The indicator updates every second and shows elapsed time
+/// The indicator updates every second and shows elapsed time.
+/// Nothing is shown while stdout carries the JSON event stream.
 pub async fn with_progress_counter<F, T>(message: &str, future: F) -> T
 where
     F: Future<Output = T>,
 {
+    if is_json_stream() {
+        return future.await;
+    }
+
     let start_time = Instant::now();
     let mut interval = interval(Duration::from_secs(1));
 
@@ -0,0 +1,66 @@
+use std::io::{self, Write};
+
+/// Type of a metric family in the Prometheus text exposition format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+}
+
+/// Writes the `# HELP` and `# TYPE` lines that start a metric family
+pub fn write_header<W: Write>(
+    writer: &mut W,
+    name: &str,
+    help: &str,
+    metric_type: MetricType,
+) -> io::Result<()> {
+    let metric_type = match metric_type {
+        MetricType::Counter => "counter",
+        MetricType::Gauge => "gauge",
+    };
+    writeln!(writer, "# HELP {name} {}", help.replace('\\', r"\\"))?;
+    writeln!(writer, "# TYPE {name} {metric_type}")
+}
+
+/// Writes a single sample of a metric family
+pub fn write_sample<W: Write>(
+    writer: &mut W,
+    name: &str,
+    labels: &[(&str, &str)],
+    value: f64,
+) -> io::Result<()> {
+    write!(writer, "{name}")?;
+    if !labels.is_empty() {
+        let labels = labels
+            .iter()
+            .map(|(key, value)| format!("{key}=\"{}\"", escape_label_value(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        write!(writer, "{{{labels}}}")?;
+    }
+    writeln!(writer, " {value}")
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_sample() {
+        let mut buffer = Vec::new();
+        write_header(&mut buffer, "up", "Whether it is up", MetricType::Gauge).unwrap();
+        write_sample(&mut buffer, "up", &[("job", "a\"b")], 1.0).unwrap();
+        write_sample(&mut buffer, "up", &[], 0.5).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "# HELP up Whether it is up\n# TYPE up gauge\nup{job=\"a\\\"b\"} 1\nup 0.5\n"
+        );
+    }
+}
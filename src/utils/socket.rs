@@ -0,0 +1,28 @@
+use tokio::net::TcpStream;
+
+/// Returns the total number of segments retransmitted on a TCP connection so far.
+/// Only supported on Linux, returns `None` elsewhere or if the kernel refuses the query.
+#[cfg(target_os = "linux")]
+pub fn tcp_total_retransmits(stream: &TcpStream) -> Option<u32> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: `tcp_info` is plain old data and the kernel writes at most `len` bytes into it
+    let mut info: libc::tcp_info = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            &mut info as *mut libc::tcp_info as *mut libc::c_void,
+            &mut len,
+        )
+    };
+
+    (ret == 0).then_some(info.tcpi_total_retrans)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn tcp_total_retransmits(_stream: &TcpStream) -> Option<u32> {
+    None
+}
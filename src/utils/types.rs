@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+use crate::report::{Bitrate, MetricUnit};
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 #[clap(rename_all = "lowercase")]
 pub enum ClientMode {
     /// TCP
@@ -32,10 +35,125 @@ pub enum TestType {
     Simultaneous,
     /// Latency only
     #[clap(alias = "latency")]
+    #[serde(alias = "latency")]
     LatencyOnly,
+    /// Latency while a download and then an upload saturate the link (bufferbloat)
+    #[clap(alias = "bufferbloat")]
+    #[serde(alias = "bufferbloat")]
+    LatencyUnderLoad,
+    /// Round-trips per minute while the link is saturated (HTTP/2 and HTTP/3 only)
+    #[clap(alias = "rpm")]
+    #[serde(alias = "rpm")]
+    Responsiveness,
+}
+
+/// Whether a target bitrate applies to all connections together or to each of them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+#[clap(rename_all = "kebab-case")]
+pub enum BitrateMode {
+    /// The connections share the bitrate
+    #[default]
+    Aggregate,
+    /// Each connection sends at the bitrate
+    PerConnection,
+}
+
+/// How UDP tests send their datagrams
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+#[clap(rename_all = "kebab-case")]
+pub enum UdpMode {
+    /// STP with congestion control and retransmissions, to measure capacity
+    #[default]
+    Stp,
+    /// Constant bitrate without congestion control, to measure loss and jitter of a
+    /// fixed-rate stream like VoIP or video (iperf -u equivalent). Requires a bitrate.
+    Cbr,
+}
+
+impl fmt::Display for UdpMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UdpMode::Stp => write!(f, "stp"),
+            UdpMode::Cbr => write!(f, "cbr"),
+        }
+    }
+}
+
+/// Congestion control algorithm of STP uploads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+#[clap(rename_all = "lowercase")]
+pub enum CongestionAlgorithm {
+    /// BBR, modeling bandwidth and RTT
+    #[default]
+    Bbr,
+    /// CUBIC, the default of Linux
+    Cubic,
+    /// NewReno, halving the window on loss
+    NewReno,
+    /// Send without congestion control
+    None,
+}
+
+impl fmt::Display for CongestionAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CongestionAlgorithm::Bbr => write!(f, "bbr"),
+            CongestionAlgorithm::Cubic => write!(f, "cubic"),
+            CongestionAlgorithm::NewReno => write!(f, "newreno"),
+            CongestionAlgorithm::None => write!(f, "none"),
+        }
+    }
+}
+
+/// Rate an upload is limited to instead of sending as fast as possible
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TargetBitrate {
+    /// Bits per second
+    pub bitrate: Bitrate,
+    #[serde(default)]
+    pub mode: BitrateMode,
+}
+
+impl fmt::Display for TargetBitrate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bitrate = MetricUnit::BitsPerSecond.format(self.bitrate.0);
+        match self.mode {
+            BitrateMode::Aggregate => write!(f, "{bitrate}"),
+            BitrateMode::PerConnection => write!(f, "{bitrate} per connection"),
+        }
+    }
+}
+
+/// How the client reports progress while a test is running
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+#[clap(rename_all = "kebab-case")]
+pub enum OutputMode {
+    /// Interactive progress bars with a running average
+    #[default]
+    Progress,
+    /// iperf-style table with one row per interval and connection, suited for logs
+    Intervals,
+    /// Newline-delimited JSON events on stdout, for consumption by other tools
+    JsonStream,
 }
 
 use std::fmt;
+impl fmt::Display for ClientMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientMode::TCP => write!(f, "tcp"),
+            ClientMode::UDP => write!(f, "udp"),
+            ClientMode::HTTP1 => write!(f, "http1"),
+            ClientMode::H2C => write!(f, "h2c"),
+            ClientMode::HTTP2 => write!(f, "http2"),
+            ClientMode::HTTP3 => write!(f, "http3"),
+        }
+    }
+}
+
 impl fmt::Display for TestType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -44,6 +162,8 @@ impl fmt::Display for TestType {
             TestType::Bidirectional => write!(f, "bidirectional"),
             TestType::Simultaneous => write!(f, "simultaneous"),
             TestType::LatencyOnly => write!(f, "latency-only"),
+            TestType::LatencyUnderLoad => write!(f, "latency-under-load"),
+            TestType::Responsiveness => write!(f, "responsiveness"),
         }
     }
 }